@@ -0,0 +1,14 @@
+DROP TRIGGER thread_last_updated_at_after_thread_update;
+
+CREATE TRIGGER thread_last_updated_at_after_thread_update
+    AFTER UPDATE ON threads
+    FOR EACH ROW
+BEGIN
+    UPDATE threads
+    SET last_updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
+
+ALTER TABLE `threads` DROP COLUMN `archived`;
+
+ALTER TABLE `threads` DROP COLUMN `pinned`;
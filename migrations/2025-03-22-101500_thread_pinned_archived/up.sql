@@ -0,0 +1,16 @@
+ALTER TABLE `threads` ADD COLUMN `pinned` BOOLEAN NOT NULL DEFAULT 0;
+
+ALTER TABLE `threads` ADD COLUMN `archived` BOOLEAN NOT NULL DEFAULT 0;
+
+-- Only renaming a thread should change its position in the thread list,
+-- pinning or archiving it should not.
+DROP TRIGGER thread_last_updated_at_after_thread_update;
+
+CREATE TRIGGER thread_last_updated_at_after_thread_update
+    AFTER UPDATE OF title ON threads
+    FOR EACH ROW
+BEGIN
+    UPDATE threads
+    SET last_updated_at = CURRENT_TIMESTAMP
+    WHERE id = OLD.id;
+END;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS `thread_tags`;
+DROP TABLE IF EXISTS `tags`;
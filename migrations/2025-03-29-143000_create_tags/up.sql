@@ -0,0 +1,15 @@
+CREATE TABLE `tags` (
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name` TEXT NOT NULL UNIQUE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+
+CREATE TABLE `thread_tags` (
+	`thread_id` BIGINT NOT NULL,
+	`tag_id` BIGINT NOT NULL,
+
+	PRIMARY KEY(`thread_id`, `tag_id`),
+	FOREIGN KEY(`thread_id`) REFERENCES `threads`(`id`),
+	FOREIGN KEY(`tag_id`) REFERENCES `tags`(`id`)
+);
@@ -0,0 +1,42 @@
+CREATE TABLE `thread_tags_old` (
+	`thread_id` BIGINT NOT NULL,
+	`tag_id` BIGINT NOT NULL,
+
+	PRIMARY KEY(`thread_id`, `tag_id`),
+	FOREIGN KEY(`thread_id`) REFERENCES `threads`(`id`),
+	FOREIGN KEY(`tag_id`) REFERENCES `tags`(`id`)
+);
+
+INSERT INTO `thread_tags_old` (`thread_id`, `tag_id`)
+SELECT `thread_id`, `tag_id` FROM `thread_tags`;
+
+DROP TABLE `thread_tags`;
+
+ALTER TABLE `thread_tags_old` RENAME TO `thread_tags`;
+
+
+CREATE TABLE `messages_old` (
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`thread_id` BIGINT NOT NULL,
+	`content` TEXT NOT NULL,
+	`role` TEXT NOT NULL,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+	FOREIGN KEY(`thread_id`) REFERENCES `threads`(`id`)
+);
+
+INSERT INTO `messages_old` (`id`, `thread_id`, `content`, `role`, `created_at`)
+SELECT `id`, `thread_id`, `content`, `role`, `created_at` FROM `messages`;
+
+DROP TABLE `messages`;
+
+ALTER TABLE `messages_old` RENAME TO `messages`;
+
+CREATE TRIGGER thread_last_updated_at_after_new_message
+    AFTER INSERT ON messages
+    FOR EACH ROW
+BEGIN
+    UPDATE threads
+    SET last_updated_at = CURRENT_TIMESTAMP
+    WHERE id = NEW.thread_id;
+END;
@@ -0,0 +1,51 @@
+-- SQLite does not support altering foreign key constraints,
+-- so the tables referencing threads have to be recreated.
+-- Rows referencing threads that were already deleted are not copied over.
+
+CREATE TABLE `messages_new` (
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`thread_id` BIGINT NOT NULL,
+	`content` TEXT NOT NULL,
+	`role` TEXT NOT NULL,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+	FOREIGN KEY(`thread_id`) REFERENCES `threads`(`id`) ON DELETE CASCADE
+);
+
+INSERT INTO `messages_new` (`id`, `thread_id`, `content`, `role`, `created_at`)
+SELECT `id`, `thread_id`, `content`, `role`, `created_at`
+FROM `messages`
+WHERE `thread_id` IN (SELECT `id` FROM `threads`);
+
+DROP TABLE `messages`;
+
+ALTER TABLE `messages_new` RENAME TO `messages`;
+
+CREATE TRIGGER thread_last_updated_at_after_new_message
+    AFTER INSERT ON messages
+    FOR EACH ROW
+BEGIN
+    UPDATE threads
+    SET last_updated_at = CURRENT_TIMESTAMP
+    WHERE id = NEW.thread_id;
+END;
+
+
+CREATE TABLE `thread_tags_new` (
+	`thread_id` BIGINT NOT NULL,
+	`tag_id` BIGINT NOT NULL,
+
+	PRIMARY KEY(`thread_id`, `tag_id`),
+	FOREIGN KEY(`thread_id`) REFERENCES `threads`(`id`) ON DELETE CASCADE,
+	FOREIGN KEY(`tag_id`) REFERENCES `tags`(`id`) ON DELETE CASCADE
+);
+
+INSERT INTO `thread_tags_new` (`thread_id`, `tag_id`)
+SELECT `thread_id`, `tag_id`
+FROM `thread_tags`
+WHERE `thread_id` IN (SELECT `id` FROM `threads`)
+AND `tag_id` IN (SELECT `id` FROM `tags`);
+
+DROP TABLE `thread_tags`;
+
+ALTER TABLE `thread_tags_new` RENAME TO `thread_tags`;
@@ -0,0 +1 @@
+ALTER TABLE `threads` ADD COLUMN `deleted_at` TIMESTAMP;
@@ -0,0 +1,7 @@
+CREATE TABLE `drafts` (
+	`thread_id` INTEGER NOT NULL PRIMARY KEY,
+	`content` TEXT NOT NULL,
+	`updated_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+	FOREIGN KEY(`thread_id`) REFERENCES `threads`(`id`) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Messages are incomplete while their content is being streamed
+ALTER TABLE `messages` ADD COLUMN `complete` BOOLEAN NOT NULL DEFAULT TRUE;
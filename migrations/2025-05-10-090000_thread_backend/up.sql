@@ -0,0 +1,2 @@
+-- Backend used to generate the answers of the thread
+ALTER TABLE `threads` ADD COLUMN `backend` TEXT NOT NULL DEFAULT 'ollama';
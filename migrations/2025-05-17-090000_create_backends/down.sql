@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `threads` ADD COLUMN `backend` TEXT NOT NULL DEFAULT 'ollama';
+UPDATE `threads`
+SET `backend` = (SELECT `kind` FROM `backends` WHERE `backends`.`id` = `threads`.`backend_id`)
+WHERE `backend_id` IN (SELECT `id` FROM `backends`);
+ALTER TABLE `threads` DROP COLUMN `backend_id`;
+DROP TABLE `backends`;
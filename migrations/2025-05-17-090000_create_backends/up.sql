@@ -0,0 +1,20 @@
+CREATE TABLE `backends` (
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name` TEXT NOT NULL UNIQUE,
+	`kind` TEXT NOT NULL,
+	`base_url` TEXT NOT NULL,
+	`api_key` TEXT,
+	`timeout_seconds` INTEGER NOT NULL DEFAULT 60,
+	`default_model` TEXT,
+	`active` BOOLEAN NOT NULL DEFAULT FALSE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+INSERT INTO `backends` (`name`, `kind`, `base_url`, `active`)
+VALUES ('Local Ollama', 'ollama', 'http://localhost:11434', TRUE);
+
+-- Threads use the active backend until another one is selected for them.
+-- It is not a foreign key so that the column can be dropped when reverting this migration,
+-- the threads of a deleted backend are reset by the application.
+ALTER TABLE `threads` ADD COLUMN `backend_id` BIGINT;
+ALTER TABLE `threads` DROP COLUMN `backend`;
@@ -0,0 +1,3 @@
+ALTER TABLE `backends` DROP COLUMN `first_byte_timeout_seconds`;
+ALTER TABLE `backends` DROP COLUMN `connect_timeout_seconds`;
+ALTER TABLE `backends` RENAME COLUMN `idle_timeout_seconds` TO `timeout_seconds`;
@@ -0,0 +1,6 @@
+-- The timeout of whole generations becomes the maximum time between two chunks,
+-- so that long answers are not cut off.
+ALTER TABLE `backends` RENAME COLUMN `timeout_seconds` TO `idle_timeout_seconds`;
+ALTER TABLE `backends` ADD COLUMN `connect_timeout_seconds` INTEGER NOT NULL DEFAULT 10;
+-- Answers start once the model is loaded, which can take minutes on slow disks
+ALTER TABLE `backends` ADD COLUMN `first_byte_timeout_seconds` INTEGER NOT NULL DEFAULT 300;
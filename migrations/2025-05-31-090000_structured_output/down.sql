@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `messages` DROP COLUMN `format_error`;
+ALTER TABLE `threads` DROP COLUMN `response_format`;
@@ -0,0 +1,4 @@
+-- Format the answers of the thread must follow: `json`, a JSON schema, or NULL for free text
+ALTER TABLE `threads` ADD COLUMN `response_format` TEXT;
+-- Why the answer does not follow the format of its thread, NULL if it does
+ALTER TABLE `messages` ADD COLUMN `format_error` TEXT;
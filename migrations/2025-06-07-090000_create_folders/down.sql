@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE `threads` DROP COLUMN `folder_id`;
+DROP TABLE IF EXISTS `folders`;
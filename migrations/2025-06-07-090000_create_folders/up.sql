@@ -0,0 +1,10 @@
+CREATE TABLE `folders` (
+	`id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+	`name` TEXT NOT NULL UNIQUE,
+	`created_at` TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+-- Folder containing the thread, NULL if it is not in any folder.
+-- It is not a foreign key so that the column can be dropped when reverting this migration,
+-- the threads of a deleted folder are moved out of it by the application.
+ALTER TABLE `threads` ADD COLUMN `folder_id` BIGINT;
@@ -1,52 +1,327 @@
+pub mod backend;
 pub mod database;
 pub mod notification;
 pub mod ollama;
+pub mod openai;
 pub mod prompts;
+pub mod retry;
+pub mod structured_output;
+pub mod templates;
+pub mod think_tags;
 
-use anyhow::Result;
-use futures::Stream;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::future::{join_all, ready};
+use futures::stream::{self, Stream};
 use futures::StreamExt;
 
+use backend::{
+    build_backend, ChatBackend, ChatChunk, ChatOptions, CompletionChunk, CompletionPrompt,
+    KeepAlive, LoadedModel, SamplingOptions, Timeouts,
+};
+use database::models::Backend;
 use ollama::{
-    api::{chat, list_models, pull_model, version},
-    types::{Message as OllamaMessage, PullModelResponse, Role},
+    types::{ChatResponse, GenerateResponse, Message as OllamaMessage, PullModelResponse, Role},
+    OllamaBackend,
 };
 use prompts::THREAD_TITLE_PROMPT;
+use retry::{retry, RetryAttempt, RetryPolicy};
+use structured_output::ResponseFormat;
+use think_tags::ThinkTagParser;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct AssistantParameters {
+    pub backend: Arc<dyn ChatBackend>,
     pub model: Option<String>,
     pub temperature: f64,
     pub top_k: u64,
     pub top_p: f64,
     pub seed: u64,
+    /// How requests are sent again when the server cannot be reached.
+    pub retry_policy: RetryPolicy,
+    pub keep_alive: Option<KeepAlive>,
+    /// Format of the answers, set for threads with structured output.
+    pub response_format: Option<ResponseFormat>,
+}
+
+impl AssistantParameters {
+    /// Returns the settings sent along with chat requests.
+    pub fn chat_options(&self) -> ChatOptions {
+        ChatOptions {
+            keep_alive: self.keep_alive,
+            response_format: self.response_format.clone(),
+        }
+    }
+
+    pub fn sampling_options(&self) -> SamplingOptions {
+        SamplingOptions {
+            temperature: self.temperature,
+            top_k: self.top_k,
+            top_p: self.top_p,
+            seed: self.seed,
+        }
+    }
 }
 
 impl Default for AssistantParameters {
     fn default() -> Self {
         Self {
+            backend: Arc::new(OllamaBackend::default()),
             model: None,
             temperature: 0.5,
             top_k: 40,
             top_p: 0.9,
             seed: 42,
+            retry_policy: RetryPolicy::default(),
+            keep_alive: None,
+            response_format: None,
         }
     }
 }
 
+/// Timing and token statistics reported at the end of a generation.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationStats {
+    pub total_duration: Duration,
+    pub load_duration: Duration,
+    pub prompt_eval_count: u64,
+    pub eval_count: u64,
+    pub eval_duration: Duration,
+}
+
+impl GenerationStats {
+    pub fn tokens_per_second(&self) -> f64 {
+        let eval_seconds = self.eval_duration.as_secs_f64();
+        if eval_seconds == 0.0 {
+            return 0.0;
+        }
+        self.eval_count as f64 / eval_seconds
+    }
+}
+
+/// Part of a generated answer, without its `<think></think>` blocks.
+#[derive(Debug, Clone, Default)]
+pub struct AnswerChunk {
+    pub content: String,
+    /// Whether the content received before this chunk was reasoning and must be discarded,
+    /// which is only known once the closing tag of a think block opened by the chat template
+    /// is received.
+    pub discard_previous: bool,
+    /// Statistics of the generation, only reported with the last chunk.
+    pub stats: Option<GenerationStats>,
+}
+
+/// Removes the `<think></think>` blocks from the chunks of an answer.
+struct AnswerState {
+    think_tag_parser: ThinkTagParser,
+    done: bool,
+}
+
+impl AnswerState {
+    fn new() -> Self {
+        Self {
+            think_tag_parser: ThinkTagParser::new(),
+            done: false,
+        }
+    }
+
+    /// Handles a chunk, `None` meaning that the stream ended.
+    fn handle(&mut self, chunk: Option<Result<ChatChunk>>) -> Option<Result<AnswerChunk>> {
+        match chunk {
+            Some(Ok(chunk)) => {
+                tracing::debug!("chat chunk: {:?}", chunk);
+                let mut content = self.think_tag_parser.push(&chunk.message.content);
+                let discard_previous = self.think_tag_parser.take_discard();
+                if chunk.done {
+                    self.done = true;
+                    content += &self.think_tag_parser.finish();
+                }
+                Some(Ok(AnswerChunk {
+                    content,
+                    discard_previous,
+                    stats: chunk.stats,
+                }))
+            }
+            Some(Err(error)) => {
+                tracing::error!("Error while receiving chat response because of: {error}");
+                Some(Err(error))
+            }
+            // Text held back by the parser is flushed even if the last chunk never came
+            None if !self.done => {
+                self.done = true;
+                let content = self.think_tag_parser.finish();
+                (!content.is_empty()).then(|| {
+                    Ok(AnswerChunk {
+                        content,
+                        ..AnswerChunk::default()
+                    })
+                })
+            }
+            None => None,
+        }
+    }
+}
+
+impl From<&ChatResponse> for GenerationStats {
+    fn from(value: &ChatResponse) -> Self {
+        Self {
+            total_duration: Duration::from_nanos(value.total_duration.unwrap_or_default()),
+            load_duration: Duration::from_nanos(value.load_duration.unwrap_or_default()),
+            prompt_eval_count: value.prompt_eval_count.unwrap_or_default(),
+            eval_count: value.eval_count.unwrap_or_default(),
+            eval_duration: Duration::from_nanos(value.eval_duration.unwrap_or_default()),
+        }
+    }
+}
+
+impl From<&GenerateResponse> for GenerationStats {
+    fn from(value: &GenerateResponse) -> Self {
+        Self {
+            total_duration: Duration::from_nanos(value.total_duration.unwrap_or_default()),
+            load_duration: Duration::from_nanos(value.load_duration.unwrap_or_default()),
+            prompt_eval_count: value.prompt_eval_count.unwrap_or_default(),
+            eval_count: value.eval_count.unwrap_or_default(),
+            eval_duration: Duration::from_nanos(value.eval_duration.unwrap_or_default()),
+        }
+    }
+}
+
+/// Backend configured in the database.
+#[derive(Debug, Clone)]
+pub struct ConfiguredBackend {
+    pub config: Backend,
+    pub backend: Arc<dyn ChatBackend>,
+}
+
+/// Model that can be selected, along with the backend serving it.
+#[derive(Debug, Clone)]
+pub struct ModelChoice {
+    pub backend_id: i64,
+    pub backend_name: String,
+    pub backend: Arc<dyn ChatBackend>,
+    pub model: String,
+}
+
+impl ModelChoice {
+    pub fn is(&self, backend_id: i64, model: &str) -> bool {
+        self.backend_id == backend_id && self.model == model
+    }
+}
+
+/// Returns the names shown in model selections, prefixed with the name of their backend
+/// when models of several backends are listed.
+pub fn model_labels(models: &[ModelChoice]) -> Vec<String> {
+    let several_backends = models
+        .iter()
+        .any(|choice| choice.backend_id != models[0].backend_id);
+    models
+        .iter()
+        .map(|choice| match several_backends {
+            true => format!("{} / {}", choice.backend_name, choice.model),
+            false => choice.model.clone(),
+        })
+        .collect()
+}
+
 #[derive(Debug)]
 pub struct Assistant {
     parameters: AssistantParameters,
+    backends: Vec<ConfiguredBackend>,
+    /// Id of the backend in the parameters, `None` until the backends are loaded.
+    backend_id: Option<i64>,
 }
 
 impl Assistant {
     pub async fn new() -> Self {
         Assistant {
             parameters: AssistantParameters::default(),
+            backends: Vec::new(),
+            backend_id: None,
         }
     }
 
+    /// Replaces the configured backends, keeping the selected one if it still exists.
+    pub fn set_backends(&mut self, configs: Vec<Backend>) {
+        self.backends = configs
+            .into_iter()
+            .filter_map(|config| {
+                let backend = build_backend(
+                    &config.kind,
+                    &config.base_url,
+                    config.api_key.clone(),
+                    Timeouts::from_seconds(
+                        config.connect_timeout_seconds,
+                        config.first_byte_timeout_seconds,
+                        config.idle_timeout_seconds,
+                    ),
+                );
+                match backend {
+                    Ok(backend) => Some(ConfiguredBackend { config, backend }),
+                    Err(error) => {
+                        tracing::error!("Ignoring backend {config:?} because of: {error}");
+                        None
+                    }
+                }
+            })
+            .collect();
+        self.set_backend(self.backend_id);
+    }
+
+    pub fn backends(&self) -> &[ConfiguredBackend] {
+        &self.backends
+    }
+
+    pub fn backend_id(&self) -> Option<i64> {
+        self.backend_id
+    }
+
+    /// Returns the configuration of the selected backend.
+    pub fn backend_config(&self) -> Option<&Backend> {
+        self.configured_backend(self.backend_id)
+            .map(|configured_backend| &configured_backend.config)
+    }
+
+    /// Returns the name of the selected backend, to be shown to users.
+    pub fn backend_name(&self) -> String {
+        self.backend_config()
+            .map(|config| config.name.clone())
+            .unwrap_or_else(|| self.parameters.backend.kind().label().to_string())
+    }
+
+    fn configured_backend(&self, id: Option<i64>) -> Option<&ConfiguredBackend> {
+        self.backends
+            .iter()
+            .find(|configured_backend| Some(configured_backend.config.id) == id)
+    }
+
+    /// Selects the backend used to generate answers, `None` or an unknown backend
+    /// meaning the active one.
+    pub fn set_backend(&mut self, id: Option<i64>) {
+        let configured_backend = self.configured_backend(id).or_else(|| {
+            self.backends
+                .iter()
+                .find(|configured_backend| configured_backend.config.active)
+                .or(self.backends.first())
+        });
+        if let Some(configured_backend) = configured_backend.cloned() {
+            self.backend_id = Some(configured_backend.config.id);
+            self.parameters.backend = configured_backend.backend;
+        }
+    }
+
+    /// Whether models are pulled when selected, which is only supported by Ollama.
+    pub fn can_pull_models(&self) -> bool {
+        self.parameters.backend.can_pull_models()
+    }
+
+    /// Returns a copy of the parameters, used to generate answers without holding the assistant.
+    pub fn parameters(&self) -> AssistantParameters {
+        self.parameters.clone()
+    }
+
     pub fn set_model(&mut self, model: String) {
         self.parameters.model = Some(model);
     }
@@ -63,32 +338,93 @@ impl Assistant {
         self.parameters.top_p = value;
     }
 
+    pub fn set_keep_alive(&mut self, keep_alive: Option<KeepAlive>) {
+        self.parameters.keep_alive = keep_alive;
+    }
+
     pub fn reset_parameters(&mut self) {
         self.parameters = AssistantParameters {
+            backend: self.parameters.backend.clone(),
             model: self.parameters.model.clone(),
+            keep_alive: self.parameters.keep_alive,
             ..AssistantParameters::default()
         }
     }
 
-    pub async fn is_ollama_running(&self) -> bool {
-        version().await.is_ok()
+    pub async fn is_backend_running(&self) -> bool {
+        self.parameters.backend.health_check().await.is_ok()
     }
 
+    /// Returns the models of the selected backend.
     pub async fn list_models(&self) -> Result<Vec<String>> {
-        let response = list_models().await?;
-        let models = response
-            .models
-            .iter()
-            .map(move |item| item.name.clone())
-            .collect();
-        Ok(models)
+        let backend = &self.parameters.backend;
+        retry(
+            &self.parameters.retry_policy,
+            |_| {},
+            || backend.list_models(),
+        )
+        .await
+    }
+
+    /// Returns the models of the given backends, grouped by backend.
+    ///
+    /// Backends that cannot be reached are skipped. The backends are passed by value
+    /// so that their models can be listed without holding the assistant.
+    pub async fn list_all_models(backends: Vec<ConfiguredBackend>) -> Vec<ModelChoice> {
+        let model_lists = join_all(
+            backends
+                .iter()
+                .map(|configured_backend| configured_backend.backend.list_models()),
+        )
+        .await;
+        backends
+            .into_iter()
+            .zip(model_lists)
+            .flat_map(|(configured_backend, models)| {
+                let models = models.unwrap_or_else(|error| {
+                    tracing::warn!(
+                        "Listing models of backend {} failed because of: {error}",
+                        configured_backend.config.name
+                    );
+                    Vec::new()
+                });
+                models.into_iter().map(move |model| ModelChoice {
+                    backend_id: configured_backend.config.id,
+                    backend_name: configured_backend.config.name.clone(),
+                    backend: configured_backend.backend.clone(),
+                    model,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the model to use among the models of the selected backend:
+    /// its default model if it is available, otherwise the first one.
+    pub fn preferred_model(&self, models: &[String]) -> Option<String> {
+        let default_model = self
+            .backend_config()
+            .and_then(|config| config.default_model.clone());
+        match default_model {
+            // The models of a backend that cannot be reached are unknown
+            Some(model) if models.is_empty() || models.contains(&model) => Some(model),
+            _ => models.first().cloned(),
+        }
     }
 
+    /// Downloads a model with the backend of the given parameters, so that the assistant
+    /// is not held during the download.
     pub async fn pull_model(
-        &self,
+        parameters: &AssistantParameters,
         model: String,
     ) -> Result<impl Stream<Item = Result<PullModelResponse>>> {
-        let response_stream = pull_model(model).await?;
+        let backend = &parameters.backend;
+        // Pulling again resumes the download, but only failures to start it are retried
+        let response_stream = retry(
+            &parameters.retry_policy,
+            |_| {},
+            || backend.pull_model(model.clone()),
+        )
+        .await?;
         let pull_model_stream = response_stream.map(|response| match response {
             Ok(response) => {
                 tracing::debug!("pull model response: {:?}", response);
@@ -102,15 +438,44 @@ impl Assistant {
         Ok(pull_model_stream)
     }
 
-    fn remove_think_tags(&self, text: String) -> String {
-        let start_index = text.find("<think>").unwrap_or(0);
-        let end_index = text.find("</think>").map_or(0, |v| v + "</think>".len());
-        let before_part = &text[..start_index];
-        let after_part = &text[end_index..];
-        format!("{}{}", before_part, after_part)
+    /// Whether the model of the given parameters has to be loaded before answering,
+    /// `false` if the backend does not tell.
+    pub async fn model_needs_loading(parameters: &AssistantParameters) -> bool {
+        match &parameters.model {
+            Some(model) => parameters.backend.is_model_loaded(model.clone()).await == Some(false),
+            None => false,
+        }
+    }
+
+    /// Loads the model of the given parameters in memory, so that the first answer
+    /// does not wait for it. Does nothing if the backend does not load models.
+    pub async fn preload_model(parameters: &AssistantParameters) -> Result<()> {
+        match &parameters.model {
+            Some(model) if parameters.backend.can_manage_loaded_models() => {
+                parameters
+                    .backend
+                    .load_model(model.clone(), parameters.keep_alive)
+                    .await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the models in the memory of the backend of the given parameters.
+    pub async fn loaded_models(parameters: &AssistantParameters) -> Result<Vec<LoadedModel>> {
+        parameters.backend.loaded_models().await
+    }
+
+    pub async fn unload_model(parameters: &AssistantParameters, model: String) -> Result<()> {
+        parameters.backend.unload_model(model).await
     }
 
-    pub async fn generate_thread_title(&mut self, message: OllamaMessage) -> Result<String> {
+    pub async fn generate_thread_title(
+        mut parameters: AssistantParameters,
+        message: OllamaMessage,
+    ) -> Result<String> {
+        // Titles are plain text, whatever the format of the answers of the thread
+        parameters.response_format = None;
         let system_message = OllamaMessage {
             content: String::from(THREAD_TITLE_PROMPT),
             role: Role::System,
@@ -120,33 +485,842 @@ impl Assistant {
             role: Role::User,
         };
         let messages = vec![system_message, query_message];
-        let mut message_stream = self.generate_answer(messages).await?;
+        let mut message_stream = Self::generate_answer(parameters, messages).await?;
         let mut thread_title = String::new();
         while let Some(result) = message_stream.next().await {
-            let message = result?;
-            thread_title += &message.content;
+            let chunk = result?;
+            if chunk.discard_previous {
+                thread_title.clear();
+            }
+            thread_title += &chunk.content;
         }
-        // Remove <think></think> tags, if there are any
-        thread_title = self.remove_think_tags(thread_title);
-        thread_title = thread_title.replace("\n", "");
+        // <think></think> tags are already removed from the generated answer
+        thread_title = thread_title.replace("\n", "").trim().to_string();
         Ok(thread_title)
     }
 
+    /// Generates an answer with the given parameters.
+    ///
+    /// The parameters are passed by value so that several answers can be generated
+    /// concurrently without holding the assistant.
     pub async fn generate_answer(
-        &mut self,
+        parameters: AssistantParameters,
+        messages: Vec<OllamaMessage>,
+    ) -> Result<impl Stream<Item = Result<AnswerChunk>>> {
+        Self::generate_answer_reporting_retries(parameters, messages, |_| {}).await
+    }
+
+    /// Generates an answer with the given parameters, calling `on_retry` before sending
+    /// the request again when the server cannot be reached.
+    pub async fn generate_answer_reporting_retries(
+        parameters: AssistantParameters,
         messages: Vec<OllamaMessage>,
-    ) -> Result<impl Stream<Item = Result<OllamaMessage>>> {
-        let response_stream = chat(self.parameters.model.clone().unwrap(), messages, true).await?;
-        let generation_stream = response_stream.map(|chat_response| match chat_response {
-            Ok(chat_response) => {
-                tracing::debug!("chat response: {:?}", chat_response);
-                Ok(chat_response.message)
+        on_retry: impl FnMut(&RetryAttempt),
+    ) -> Result<impl Stream<Item = Result<AnswerChunk>>> {
+        let options = parameters.chat_options();
+        let model = parameters
+            .model
+            .ok_or_else(|| anyhow!("No model is selected"))?;
+        let generation_stream = Self::generate_answer_with_model(
+            parameters.backend,
+            model,
+            messages,
+            options,
+            &parameters.retry_policy,
+            on_retry,
+        )
+        .await?;
+        Ok(generation_stream)
+    }
+
+    /// Completes a prompt with the given parameters, as is rather than as a chat message.
+    ///
+    /// Unlike answers, completions keep any `<think></think>` tags, since the prompt
+    /// may well have opened them.
+    pub async fn complete(
+        parameters: AssistantParameters,
+        prompt: CompletionPrompt,
+    ) -> Result<impl Stream<Item = Result<CompletionChunk>>> {
+        let sampling = parameters.sampling_options();
+        let model = parameters
+            .model
+            .ok_or_else(|| anyhow!("No model is selected"))?;
+        let backend = parameters.backend;
+        let chunk_stream = retry(
+            &parameters.retry_policy,
+            |_| {},
+            || {
+                backend.complete(
+                    model.clone(),
+                    prompt.clone(),
+                    sampling,
+                    parameters.keep_alive,
+                )
+            },
+        )
+        .await?;
+        Ok(chunk_stream.map(|chunk| {
+            if let Err(error) = &chunk {
+                tracing::error!("Error while receiving completion because of: {error}");
             }
-            Err(error) => {
-                tracing::error!("Error while receiving chat response because of: {error}");
-                Err(error)
+            chunk
+        }))
+    }
+
+    /// Generates an answer with the given backend and model, independently of the selected ones.
+    ///
+    /// The request is only sent again if it failed before the answer started.
+    /// The statistics of the generation are returned along with the last chunk.
+    pub async fn generate_answer_with_model(
+        backend: Arc<dyn ChatBackend>,
+        model: String,
+        messages: Vec<OllamaMessage>,
+        options: ChatOptions,
+        retry_policy: &RetryPolicy,
+        on_retry: impl FnMut(&RetryAttempt),
+    ) -> Result<impl Stream<Item = Result<AnswerChunk>>> {
+        let chunk_stream = retry(retry_policy, on_retry, || {
+            backend.chat(model.clone(), messages.clone(), options.clone())
+        })
+        .await?;
+        let mut state = AnswerState::new();
+        let generation_stream = chunk_stream
+            .map(Some)
+            .chain(stream::once(ready(None)))
+            .filter_map(move |chunk| ready(state.handle(chunk)));
+        Ok(generation_stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use chrono::NaiveDateTime;
+    use serde_json::{json, Value};
+
+    use super::*;
+    use ollama::mock_server::{MockOllama, Reply};
+
+    fn backend(id: i64, default_model: Option<&str>, active: bool) -> Backend {
+        Backend {
+            id,
+            name: format!("Backend {id}"),
+            kind: String::from("ollama"),
+            base_url: String::from("http://localhost:11434"),
+            api_key: None,
+            connect_timeout_seconds: 10,
+            first_byte_timeout_seconds: 300,
+            idle_timeout_seconds: 60,
+            default_model: default_model.map(String::from),
+            active,
+            created_at: NaiveDateTime::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_selecting_backend_and_model() {
+        let mut assistant = Assistant::new().await;
+        assistant.set_backends(vec![
+            backend(1, Some("qwen3:8b"), false),
+            backend(2, Some("missing"), true),
+        ]);
+        assert_eq!(assistant.backend_id(), Some(2));
+
+        let models = vec![String::from("llama3.2:1b"), String::from("qwen3:8b")];
+        assert_eq!(
+            assistant.preferred_model(&models).as_deref(),
+            Some("llama3.2:1b")
+        );
+        assistant.set_backend(Some(1));
+        assert_eq!(
+            assistant.preferred_model(&models).as_deref(),
+            Some("qwen3:8b")
+        );
+        assert_eq!(assistant.preferred_model(&[]).as_deref(), Some("qwen3:8b"));
+
+        // The selected backend is kept unless it is deleted
+        assistant.set_backends(vec![backend(1, None, false), backend(2, None, true)]);
+        assert_eq!(assistant.backend_id(), Some(1));
+        assert_eq!(
+            assistant.preferred_model(&models).as_deref(),
+            Some("llama3.2:1b")
+        );
+        assert_eq!(assistant.preferred_model(&[]), None);
+        assistant.set_backends(vec![backend(2, None, true)]);
+        assert_eq!(assistant.backend_id(), Some(2));
+        assistant.set_backend(Some(3));
+        assert_eq!(assistant.backend_id(), Some(2));
+    }
+
+    /// Returns an assistant generating answers with the given server.
+    async fn mock_assistant(server: &MockOllama) -> Assistant {
+        let mut assistant = Assistant::new().await;
+        assistant.set_backends(vec![Backend {
+            base_url: server.base_url().to_string(),
+            idle_timeout_seconds: 1,
+            ..backend(1, None, true)
+        }]);
+        assistant.set_model(String::from("llama3.2:1b"));
+        assistant
+    }
+
+    fn user_message(content: &str) -> OllamaMessage {
+        OllamaMessage {
+            role: Role::User,
+            content: content.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer() {
+        let server = MockOllama::start().await;
+        // Chunks end in the middle of lines, characters and think tags
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["<think>A greeting", "</think>Hé", "llo!"]).split_every(7),
+        );
+        let assistant = mock_assistant(&server).await;
+
+        let message_stream =
+            Assistant::generate_answer(assistant.parameters(), vec![user_message("Hi")])
+                .await
+                .expect("Generating answer should work");
+        let contents: Vec<String> = message_stream
+            .map(|message| message.expect("Message should be valid").content)
+            .collect()
+            .await;
+        assert_eq!(contents.concat(), "Héllo!");
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/api/chat");
+        assert_eq!(requests[0].body["model"], "llama3.2:1b");
+        assert_eq!(requests[0].body["stream"], true);
+        assert_eq!(requests[0].body["messages"][0]["content"], "Hi");
+        assert!(requests[0].body.get("keep_alive").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer_with_think_block_opened_by_template() {
+        let server = MockOllama::start().await;
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["A greeting", " to answer</think>", "Hello!"]),
+        );
+        let assistant = mock_assistant(&server).await;
+
+        let chunks: Vec<AnswerChunk> =
+            Assistant::generate_answer(assistant.parameters(), vec![user_message("Hi")])
+                .await
+                .expect("Generating answer should work")
+                .map(|chunk| chunk.expect("Chunk should be valid"))
+                .collect()
+                .await;
+        let discards: Vec<bool> = chunks.iter().map(|chunk| chunk.discard_previous).collect();
+        assert_eq!(discards, [false, true, false, false]);
+        assert_eq!(chunks[1].content, "");
+        assert_eq!(chunks[2].content, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer_without_last_chunk() {
+        let server = MockOllama::start().await;
+        // A partial tag held back by the parser is kept when the stream ends early
+        server.reply(
+            "/api/chat",
+            Reply::json_lines(&[json!({
+                "model": "llama3.2:1b",
+                "created_at": "2025-01-01T00:00:00Z",
+                "message": { "role": "assistant", "content": "Hello <" },
+                "done": false,
+            })]),
+        );
+        let assistant = mock_assistant(&server).await;
+
+        assert_eq!(
+            collect_answer(assistant.parameters())
+                .await
+                .into_iter()
+                .map(|content| content.expect("Chunk should be valid"))
+                .collect::<Vec<_>>(),
+            ["Hello ", "<"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer_with_statistics() {
+        let server = MockOllama::start().await;
+        server.reply("/api/chat", Reply::chat(&["Hello", " there"]));
+        let assistant = mock_assistant(&server).await;
+
+        let generation_stream = Assistant::generate_answer_with_model(
+            assistant.parameters().backend,
+            String::from("qwen3:8b"),
+            vec![user_message("Hi")],
+            ChatOptions::default(),
+            &RetryPolicy::default(),
+            |_| {},
+        )
+        .await
+        .expect("Generating answer should work");
+        let results: Vec<_> = generation_stream.collect().await;
+        assert_eq!(results.len(), 3);
+        let chunk = results[2].as_ref().expect("Last chunk should be valid");
+        let stats = chunk
+            .stats
+            .as_ref()
+            .expect("Last chunk should have statistics");
+        assert_eq!(stats.eval_count, 2);
+        assert_eq!(stats.tokens_per_second(), 2.0);
+        assert_eq!(server.requests()[0].body["model"], "qwen3:8b");
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer_fails() {
+        let server = MockOllama::start().await;
+        server.reply(
+            "/api/chat",
+            Reply::error(
+                StatusCode::NOT_FOUND,
+                "model \"llama3.2:1b\" not found, try pulling it first",
+            ),
+        );
+        // The error is streamed when the model fails after the answer started
+        server.reply(
+            "/api/chat",
+            Reply::json_lines(&[
+                json!({
+                    "model": "llama3.2:1b",
+                    "created_at": "2025-01-01T00:00:00Z",
+                    "message": { "role": "assistant", "content": "Hel" },
+                    "done": false,
+                }),
+                json!({ "error": "an error was encountered while running the model" }),
+            ]),
+        );
+        let assistant = mock_assistant(&server).await;
+
+        let Err(error) =
+            Assistant::generate_answer(assistant.parameters(), vec![user_message("Hi")]).await
+        else {
+            panic!("Generating answer with an unknown model should fail");
+        };
+        assert_eq!(
+            error.to_string(),
+            "model \"llama3.2:1b\" not found, try pulling it first"
+        );
+
+        let message_stream =
+            Assistant::generate_answer(assistant.parameters(), vec![user_message("Hi")])
+                .await
+                .expect("Generating answer should start");
+        let results: Vec<_> = message_stream.collect().await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0]
+                .as_ref()
+                .expect("First chunk should be valid")
+                .content,
+            "Hel"
+        );
+        let error = results[1].as_ref().expect_err("Second chunk should fail");
+        assert_eq!(
+            error.to_string(),
+            "an error was encountered while running the model"
+        );
+    }
+
+    /// Returns parameters generating answers with the given server and timeouts.
+    fn mock_parameters(server: &MockOllama, first_byte: u64, idle: u64) -> AssistantParameters {
+        AssistantParameters {
+            backend: Arc::new(server.backend(Timeouts {
+                connect: Duration::from_secs(1),
+                first_byte: Duration::from_millis(first_byte),
+                idle: Duration::from_millis(idle),
+            })),
+            model: Some(String::from("llama3.2:1b")),
+            ..AssistantParameters::default()
+        }
+    }
+
+    async fn collect_answer(parameters: AssistantParameters) -> Vec<Result<String>> {
+        match Assistant::generate_answer(parameters, vec![user_message("Hi")]).await {
+            Ok(message_stream) => {
+                message_stream
+                    .map(|message| message.map(|message| message.content))
+                    .collect()
+                    .await
             }
+            Err(error) => vec![Err(error)],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generating_long_answer() {
+        let server = MockOllama::start().await;
+        // The answer takes much longer than the idle timeout, but chunks keep coming
+        let parts = ["Slow"; 12];
+        server.reply(
+            "/api/chat",
+            Reply::chat(&parts).with_delay(Duration::from_millis(50)),
+        );
+        server.reply(
+            "/api/pull",
+            Reply::json_lines(&vec![json!({ "status": "pulling 74701a8c35f6" }); 12])
+                .with_delay(Duration::from_millis(50)),
+        );
+        let parameters = mock_parameters(&server, 1000, 200);
+
+        let answer: Result<Vec<String>> = collect_answer(parameters.clone())
+            .await
+            .into_iter()
+            .collect();
+        assert_eq!(
+            answer.expect("Answer should be complete").concat(),
+            parts.concat()
+        );
+
+        let mut assistant = Assistant::new().await;
+        assistant.parameters = parameters;
+        let progress: Vec<_> =
+            Assistant::pull_model(&assistant.parameters, String::from("llama3.2:1b"))
+                .await
+                .expect("Pulling model should work")
+                .collect()
+                .await;
+        assert_eq!(progress.len(), 12);
+        assert!(progress.iter().all(Result::is_ok));
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer_with_idle_server() {
+        let server = MockOllama::start().await;
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["Hello", " there"]).with_pause(1, Duration::from_millis(600)),
+        );
+        // The server stops in the middle of a line
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["Hello", " there"])
+                .split_every(150)
+                .with_pause(1, Duration::from_millis(600)),
+        );
+        let parameters = mock_parameters(&server, 1000, 200);
+
+        for _ in 0..2 {
+            let results = collect_answer(parameters.clone()).await;
+            assert_eq!(results.len(), 2);
+            assert_eq!(
+                results[0].as_ref().expect("First chunk should be valid"),
+                "Hello"
+            );
+            let error = results[1].as_ref().expect_err("Idle server should fail");
+            assert_eq!(error.to_string(), "The server sent nothing for 200ms");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generating_answer_while_model_loads() {
+        let server = MockOllama::start().await;
+        server.reply("/api/ps", Reply::json(json!({ "models": [] })));
+        // Loading the model takes longer than the idle timeout, but not the first-byte one
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["Hello"]).with_pause(0, Duration::from_millis(400)),
+        );
+        server.reply(
+            "/api/ps",
+            Reply::json(json!({
+                "models": [{
+                    "name": "llama3.2:1b",
+                    "model": "llama3.2:1b",
+                    "size": 2_000_000_000u64,
+                    "size_vram": 2_000_000_000u64,
+                    "expires_at": "2025-01-01T00:05:00Z",
+                }],
+            })),
+        );
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["Hello"]).with_pause(0, Duration::from_millis(1500)),
+        );
+        let parameters = mock_parameters(&server, 1000, 200);
+
+        assert!(Assistant::model_needs_loading(&parameters).await);
+        let results = collect_answer(parameters.clone()).await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(Result::is_ok));
+
+        assert!(!Assistant::model_needs_loading(&parameters).await);
+        let results = collect_answer(parameters.clone()).await;
+        assert_eq!(results.len(), 1);
+        let error = results[0].as_ref().expect_err("Answer should not start");
+        assert_eq!(
+            error.to_string(),
+            "The server did not start answering within 1s"
+        );
+
+        // Whether the model is loaded is unknown when the server does not tell
+        assert!(!Assistant::model_needs_loading(&parameters).await);
+    }
+
+    #[tokio::test]
+    async fn test_retrying_answer_while_server_restarts() {
+        let server = MockOllama::start().await;
+        let unavailable = || {
+            Reply::error(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server busy, please try again",
+            )
+        };
+        server.reply("/api/chat", unavailable());
+        server.reply("/api/chat", Reply::chat(&["Hello"]));
+        for _ in 0..3 {
+            server.reply("/api/chat", unavailable());
+        }
+        let parameters = AssistantParameters {
+            retry_policy: RetryPolicy {
+                max_attempts: 3,
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(20),
+            },
+            ..mock_parameters(&server, 1000, 1000)
+        };
+
+        let mut retries = Vec::new();
+        let message_stream = Assistant::generate_answer_reporting_retries(
+            parameters.clone(),
+            vec![user_message("Hi")],
+            |retry_attempt| retries.push(retry_attempt.clone()),
+        )
+        .await
+        .expect("Generating answer should work once the server is back");
+        let contents: Vec<String> = message_stream
+            .map(|message| message.expect("Message should be valid").content)
+            .collect()
+            .await;
+        assert_eq!(contents.concat(), "Hello");
+        assert_eq!(retries.len(), 1);
+        assert_eq!(retries[0].attempt, 2);
+        assert_eq!(retries[0].error, "server busy, please try again");
+
+        // The request is sent at most 3 times
+        let results = collect_answer(parameters).await;
+        let error = results[0]
+            .as_ref()
+            .expect_err("Server should stay unavailable");
+        assert_eq!(error.to_string(), "server busy, please try again");
+        assert_eq!(server.requests().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_generating_thread_title() {
+        let server = MockOllama::start().await;
+        server.reply(
+            "/api/chat",
+            Reply::chat(&[
+                "<think>The user greets</think>\n",
+                "A friendly",
+                " greeting\n",
+            ])
+            .split_every(5),
+        );
+        let assistant = mock_assistant(&server).await;
+
+        let title =
+            Assistant::generate_thread_title(assistant.parameters(), user_message("Hello there"))
+                .await
+                .expect("Generating thread title should work");
+        assert_eq!(title, "A friendly greeting");
+
+        // Reasoning without opening tag is not part of the title
+        server.reply(
+            "/api/chat",
+            Reply::chat(&["The user greets", "</think>", "Greeting"]).split_every(5),
+        );
+        let title =
+            Assistant::generate_thread_title(assistant.parameters(), user_message("Hello there"))
+                .await
+                .expect("Generating thread title should work");
+        assert_eq!(title, "Greeting");
+
+        let messages = &server.requests()[0].body["messages"];
+        assert_eq!(messages[0]["content"], THREAD_TITLE_PROMPT);
+        assert_eq!(messages[1]["content"], "<query>Hello there</query>");
+    }
+
+    #[tokio::test]
+    async fn test_pulling_model() {
+        let server = MockOllama::start().await;
+        server.reply(
+            "/api/pull",
+            Reply::json_lines(&[
+                json!({ "status": "pulling manifest" }),
+                json!({ "status": "pulling 74701a8c35f6", "digest": "sha256:74701a8c35f6", "total": 100, "completed": 40 }),
+                json!({ "status": "pulling 74701a8c35f6", "digest": "sha256:74701a8c35f6", "total": 100, "completed": 100 }),
+                json!({ "status": "success" }),
+            ])
+            .split_every(16)
+            .with_delay(Duration::from_millis(1)),
+        );
+        server.reply(
+            "/api/pull",
+            Reply::json_lines(&[
+                json!({ "status": "pulling manifest" }),
+                json!({ "error": "pull model manifest: file does not exist" }),
+            ]),
+        );
+        let assistant = mock_assistant(&server).await;
+
+        let progress: Vec<(String, Option<u64>, Option<f64>)> =
+            Assistant::pull_model(&assistant.parameters, String::from("llama3.2:1b"))
+                .await
+                .expect("Pulling model should work")
+                .map(|response| {
+                    let response = response.expect("Pull response should be valid");
+                    let progress = response.progress();
+                    (response.status, response.completed, progress)
+                })
+                .collect()
+                .await;
+        assert_eq!(
+            progress,
+            vec![
+                (String::from("pulling manifest"), None, None),
+                (String::from("pulling 74701a8c35f6"), Some(40), Some(0.4)),
+                (String::from("pulling 74701a8c35f6"), Some(100), Some(1.0)),
+                (String::from("success"), None, None),
+            ]
+        );
+        assert_eq!(server.requests()[0].body["model"], "llama3.2:1b");
+
+        let results: Vec<_> = Assistant::pull_model(&assistant.parameters, String::from("missing"))
+            .await
+            .expect("Pulling model should start")
+            .collect()
+            .await;
+        let error = results[1]
+            .as_ref()
+            .expect_err("Pulling missing model should fail");
+        assert_eq!(
+            error.to_string(),
+            "pull model manifest: file does not exist"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generating_structured_answer() {
+        let server = MockOllama::start().await;
+        server.reply("/api/chat", Reply::chat(&["Greeting"]));
+        server.reply("/api/chat", Reply::chat(&["{\"greeting\":", " \"Hello\"}"]));
+        let assistant = mock_assistant(&server).await;
+        let schema = json!({
+            "type": "object",
+            "properties": { "greeting": { "type": "string" } },
         });
-        Ok(generation_stream)
+        let parameters = AssistantParameters {
+            response_format: Some(ResponseFormat::Schema(schema.clone())),
+            ..assistant.parameters()
+        };
+
+        let title = Assistant::generate_thread_title(parameters.clone(), user_message("Hi"))
+            .await
+            .expect("Generating thread title should work");
+        assert_eq!(title, "Greeting");
+        let contents: Vec<String> =
+            Assistant::generate_answer(parameters, vec![user_message("Hi")])
+                .await
+                .expect("Generating answer should work")
+                .map(|message| message.expect("Message should be valid").content)
+                .collect()
+                .await;
+        let format = ResponseFormat::Schema(schema.clone());
+        assert_eq!(
+            format.check_answer(&contents.concat()),
+            Ok(String::from("{\n  \"greeting\": \"Hello\"\n}"))
+        );
+
+        let requests = server.requests();
+        // Thread titles are plain text
+        assert!(requests[0].body.get("format").is_none());
+        assert_eq!(requests[1].body["format"], schema);
+    }
+
+    #[tokio::test]
+    async fn test_keeping_models_in_memory() {
+        let server = MockOllama::start().await;
+        let loaded = |reason: &str| {
+            Reply::json(json!({
+                "model": "llama3.2:1b",
+                "created_at": "2025-01-01T00:00:00Z",
+                "message": { "role": "assistant", "content": "" },
+                "done_reason": reason,
+                "done": true,
+            }))
+        };
+        server.reply("/api/chat", loaded("load"));
+        server.reply("/api/chat", Reply::chat(&["Hello"]));
+        server.reply(
+            "/api/ps",
+            Reply::json(json!({ "models": [
+                { "name": "llama3.2:1b", "model": "llama3.2:1b", "size": 2_500_000_000u64, "size_vram": 2_500_000_000u64 },
+                { "name": "qwen3:8b", "model": "qwen3:8b", "size": 6_000_000_000u64, "size_vram": 3_000_000_000u64 },
+                { "name": "mistral:7b", "model": "mistral:7b", "size": 4_100_000_000u64, "size_vram": 0 },
+            ] })),
+        );
+        server.reply("/api/chat", loaded("unload"));
+        let mut assistant = mock_assistant(&server).await;
+        assistant.set_keep_alive(Some(KeepAlive::Forever));
+        let parameters = assistant.parameters();
+
+        Assistant::preload_model(&parameters)
+            .await
+            .expect("Preloading model should work");
+        Assistant::generate_answer(parameters.clone(), vec![user_message("Hi")])
+            .await
+            .expect("Generating answer should work")
+            .collect::<Vec<_>>()
+            .await;
+        let loaded_models = Assistant::loaded_models(&parameters)
+            .await
+            .expect("Listing loaded models should work");
+        let memory_usages: Vec<String> = loaded_models
+            .iter()
+            .map(LoadedModel::memory_usage)
+            .collect();
+        assert_eq!(
+            memory_usages,
+            vec!["2.5 GB, 100% GPU", "6.0 GB, 50% GPU", "4.1 GB, CPU"]
+        );
+        Assistant::unload_model(&parameters, loaded_models[1].name.clone())
+            .await
+            .expect("Unloading model should work");
+
+        let requests = server.requests();
+        let bodies: Vec<(&str, &Value, &Value)> = requests
+            .iter()
+            .map(|request| {
+                (
+                    request.path.as_str(),
+                    &request.body["keep_alive"],
+                    &request.body["model"],
+                )
+            })
+            .collect();
+        assert_eq!(
+            bodies,
+            vec![
+                ("/api/chat", &json!(-1), &json!("llama3.2:1b")),
+                ("/api/chat", &json!(-1), &json!("llama3.2:1b")),
+                ("/api/ps", &Value::Null, &Value::Null),
+                ("/api/chat", &json!(0), &json!("qwen3:8b")),
+            ]
+        );
+        assert_eq!(requests[0].body["messages"], json!([]));
+        assert_eq!(requests[0].body["stream"], false);
+    }
+
+    #[test]
+    fn test_keep_alive_labels() {
+        let labels: Vec<String> = KeepAlive::CHOICES
+            .into_iter()
+            .map(KeepAlive::label)
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "Server default",
+                "5 minutes",
+                "30 minutes",
+                "1 hour",
+                "Forever"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checking_backend_and_listing_models() {
+        let server = MockOllama::start().await;
+        let assistant = mock_assistant(&server).await;
+        assert!(!assistant.is_backend_running().await);
+
+        server.reply("/api/version", Reply::json(json!({ "version": "0.6.5" })));
+        assert!(assistant.is_backend_running().await);
+
+        server.reply("/api/tags", Reply::tags(&["llama3.2:1b", "qwen3:8b"]));
+        assert_eq!(
+            assistant
+                .list_models()
+                .await
+                .expect("Listing models should work"),
+            vec![String::from("llama3.2:1b"), String::from("qwen3:8b")]
+        );
+
+        // Backends whose models cannot be listed are skipped
+        let other_server = MockOllama::start().await;
+        server.reply("/api/tags", Reply::tags(&["llama3.2:1b"]));
+        let mut backends = assistant.backends().to_vec();
+        backends.push(ConfiguredBackend {
+            config: backend(2, None, false),
+            backend: Arc::new(other_server.backend(Timeouts::default())),
+        });
+        let models = Assistant::list_all_models(backends).await;
+        assert_eq!(models.len(), 1);
+        assert!(models[0].is(1, "llama3.2:1b"));
+    }
+
+    #[tokio::test]
+    async fn test_completing_raw_prompt() {
+        let server = MockOllama::start().await;
+        server.reply(
+            "/api/generate",
+            Reply::generate(&["    return a", " + b\n"]),
+        );
+        let mut assistant = mock_assistant(&server).await;
+        assistant.set_temperature(0.2);
+        let prompt = CompletionPrompt {
+            prompt: String::from("def add(a, b):\n"),
+            suffix: Some(String::from("\nprint(add(1, 2))")),
+            template: None,
+            raw: true,
+        };
+
+        let chunks: Vec<CompletionChunk> = Assistant::complete(assistant.parameters(), prompt)
+            .await
+            .expect("Completing prompt should work")
+            .map(|chunk| chunk.expect("Receiving completion should work"))
+            .collect()
+            .await;
+        let completion: String = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(completion, "    return a + b\n");
+        let stats = chunks
+            .last()
+            .and_then(|chunk| chunk.stats.clone())
+            .expect("Last chunk should have statistics");
+        assert_eq!(stats.tokens_per_second(), 4.0);
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].body,
+            json!({
+                "model": "llama3.2:1b",
+                "prompt": "def add(a, b):\n",
+                "suffix": "\nprint(add(1, 2))",
+                "raw": true,
+                "stream": true,
+                "options": { "temperature": 0.2, "top_k": 40, "top_p": 0.9, "seed": 42 },
+            })
+        );
+
+        // Only Ollama servers complete raw prompts
+        let parameters = AssistantParameters {
+            backend: build_backend("openai", server.base_url(), None, Timeouts::default())
+                .expect("Building backend should work"),
+            ..assistant.parameters()
+        };
+        assert!(Assistant::complete(parameters, CompletionPrompt::default())
+            .await
+            .is_err());
     }
 }
@@ -0,0 +1,447 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use futures::{FutureExt, StreamExt};
+
+use super::ollama::types::{Message, PullModelResponse};
+use super::ollama::OllamaBackend;
+use super::openai::OpenAiBackend;
+use super::structured_output::ResponseFormat;
+use super::GenerationStats;
+
+/// Part of an answer streamed by a backend.
+#[derive(Debug, Clone, Default)]
+pub struct ChatChunk {
+    pub message: Message,
+    /// Whether this is the last chunk of the answer.
+    pub done: bool,
+    /// Statistics of the generation, only reported with the last chunk.
+    pub stats: Option<GenerationStats>,
+}
+
+/// Part of a completion streamed by a backend.
+#[derive(Debug, Clone, Default)]
+pub struct CompletionChunk {
+    pub text: String,
+    /// Whether this is the last chunk of the completion.
+    pub done: bool,
+    /// Statistics of the generation, only reported with the last chunk.
+    pub stats: Option<GenerationStats>,
+}
+
+pub type ChatStream = BoxStream<'static, Result<ChatChunk>>;
+pub type CompletionStream = BoxStream<'static, Result<CompletionChunk>>;
+pub type PullStream = BoxStream<'static, Result<PullModelResponse>>;
+pub type BytesStream = BoxStream<'static, Result<Vec<u8>>>;
+
+/// How long a model stays in memory after answering, so that the next answer starts quickly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAlive {
+    For(Duration),
+    Forever,
+}
+
+impl KeepAlive {
+    /// Durations that can be picked, `None` leaving it to the server.
+    pub const CHOICES: [Option<KeepAlive>; 5] = [
+        None,
+        Some(KeepAlive::For(Duration::from_secs(5 * 60))),
+        Some(KeepAlive::For(Duration::from_secs(30 * 60))),
+        Some(KeepAlive::For(Duration::from_secs(60 * 60))),
+        Some(KeepAlive::Forever),
+    ];
+
+    /// Returns the duration in seconds, negative meaning forever as for Ollama.
+    pub fn seconds(&self) -> i64 {
+        match self {
+            KeepAlive::For(duration) => duration.as_secs() as i64,
+            KeepAlive::Forever => -1,
+        }
+    }
+
+    pub fn label(keep_alive: Option<KeepAlive>) -> String {
+        match keep_alive {
+            None => String::from("Server default"),
+            Some(KeepAlive::Forever) => String::from("Forever"),
+            Some(KeepAlive::For(duration)) => match duration.as_secs() / 60 {
+                60 => String::from("1 hour"),
+                minutes if minutes > 0 && minutes % 60 == 0 => format!("{} hours", minutes / 60),
+                minutes => format!("{minutes} minutes"),
+            },
+        }
+    }
+}
+
+/// Settings of a chat request, besides the model and the messages.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChatOptions {
+    /// Ignored by servers that do not unload models.
+    pub keep_alive: Option<KeepAlive>,
+    /// Format the answer is constrained to, free text if `None`.
+    pub response_format: Option<ResponseFormat>,
+}
+
+/// Settings of the sampling of the next tokens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingOptions {
+    pub temperature: f64,
+    pub top_k: u64,
+    pub top_p: f64,
+    pub seed: u64,
+}
+
+/// Prompt completed as is, rather than as the messages of a chat.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompletionPrompt {
+    pub prompt: String,
+    /// Text after the completion, for models that fill in the middle.
+    pub suffix: Option<String>,
+    /// Prompt template replacing the one of the model.
+    pub template: Option<String>,
+    /// Whether the prompt is sent to the model without applying any template.
+    pub raw: bool,
+}
+
+/// Model loaded in the memory of a server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadedModel {
+    pub name: String,
+    /// Memory used by the model, in bytes.
+    pub size: u64,
+    /// Part of the memory that is on the GPU, in bytes.
+    pub size_vram: u64,
+}
+
+impl LoadedModel {
+    /// Describes the memory used by the model, e.g. "4.1 GB, 100% GPU".
+    pub fn memory_usage(&self) -> String {
+        let gigabytes = self.size as f64 / 1e9;
+        let processor = match self.size_vram {
+            0 => String::from("CPU"),
+            size_vram => format!(
+                "{:.0}% GPU",
+                100.0 * size_vram.min(self.size) as f64 / self.size.max(1) as f64
+            ),
+        };
+        format!("{gigabytes:.1} GB, {processor}")
+    }
+}
+
+/// Maximum durations waited for a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Waited to open a connection.
+    pub connect: Duration,
+    /// Waited for a streamed response to start, which includes loading the model.
+    pub first_byte: Duration,
+    /// Waited between two chunks of a streamed response, however long the whole response takes.
+    pub idle: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(10),
+            first_byte: Duration::from_secs(300),
+            idle: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Timeouts {
+    /// Returns the timeouts stored in the `backends` table, of at least one second.
+    pub fn from_seconds(connect: i64, first_byte: i64, idle: i64) -> Self {
+        let seconds = |value: i64| Duration::from_secs(value.max(1) as u64);
+        Self {
+            connect: seconds(connect),
+            first_byte: seconds(first_byte),
+            idle: seconds(idle),
+        }
+    }
+}
+
+/// Address and credentials of a server.
+#[derive(Clone)]
+pub struct Endpoint {
+    pub base_url: String,
+    /// Sent as a bearer token, for hosted servers or ones behind an authenticating proxy.
+    pub api_key: Option<String>,
+    pub timeouts: Timeouts,
+    client: reqwest::Client,
+}
+
+impl fmt::Debug for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The API key must not end up in logs
+        f.debug_struct("Endpoint")
+            .field("base_url", &self.base_url)
+            .field("timeouts", &self.timeouts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Endpoint {
+    pub fn new(base_url: &str, api_key: Option<String>, timeouts: Timeouts) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(timeouts.connect)
+            .build()
+            .expect("Building HTTP client should work");
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.filter(|api_key| !api_key.is_empty()),
+            timeouts,
+            client,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.with_api_key(self.client.get(format!("{}{path}", self.base_url)))
+    }
+
+    pub fn post(&self, path: &str) -> reqwest::RequestBuilder {
+        self.with_api_key(self.client.post(format!("{}{path}", self.base_url)))
+    }
+
+    fn with_api_key(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    /// Sends a request whose response is streamed, without limiting the duration of the response.
+    pub async fn send_streaming(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        match tokio::time::timeout(self.timeouts.first_byte, request.send()).await {
+            Ok(response) => Ok(response?),
+            Err(_) => Err(anyhow!(
+                "The server did not start answering within {:?}",
+                self.timeouts.first_byte
+            )),
+        }
+    }
+
+    /// Streams the body of a response, failing once the server sent nothing for too long.
+    ///
+    /// The stream ends after the first error.
+    pub fn body_stream(&self, response: reqwest::Response) -> BytesStream {
+        let timeouts = self.timeouts;
+        let body = response.bytes_stream().boxed();
+        stream::unfold(Some((body, true)), move |state| async move {
+            let (mut body, first_chunk) = state?;
+            let timeout = match first_chunk {
+                true => timeouts.first_byte,
+                false => timeouts.idle,
+            };
+            match tokio::time::timeout(timeout, body.next()).await {
+                Ok(Some(Ok(bytes))) => Some((Ok(bytes.to_vec()), Some((body, false)))),
+                Ok(Some(Err(error))) => Some((Err(error.into()), None)),
+                Ok(None) => None,
+                Err(_) if first_chunk => Some((
+                    Err(anyhow!(
+                        "The server did not start answering within {timeout:?}"
+                    )),
+                    None,
+                )),
+                Err(_) => Some((
+                    Err(anyhow!("The server sent nothing for {timeout:?}")),
+                    None,
+                )),
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Returns the error of a request that failed with the given response.
+///
+/// The message is made from the body of the response, while the status is kept as the cause
+/// so that failures of a restarting server can be told apart and retried.
+pub async fn status_error(
+    response: reqwest::Response,
+    message: impl FnOnce(String) -> String,
+) -> anyhow::Error {
+    let status_error = response.error_for_status_ref().err();
+    let text = match response.text().await {
+        Ok(text) => text,
+        Err(error) => return error.into(),
+    };
+    match status_error {
+        Some(status_error) => anyhow::Error::new(status_error).context(message(text)),
+        None => anyhow!(message(text)),
+    }
+}
+
+/// Kinds of servers that answers can be generated with.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackendKind {
+    #[default]
+    Ollama,
+    /// Server implementing the chat completions API of OpenAI,
+    /// such as llama.cpp server, vLLM or LM Studio.
+    OpenAi,
+}
+
+impl BackendKind {
+    pub const ALL: [BackendKind; 2] = [BackendKind::Ollama, BackendKind::OpenAi];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            BackendKind::Ollama => "Ollama",
+            BackendKind::OpenAi => "OpenAI-compatible",
+        }
+    }
+}
+
+impl TryFrom<String> for BackendKind {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match &*value {
+            "ollama" => Ok(BackendKind::Ollama),
+            "openai" => Ok(BackendKind::OpenAi),
+            _ => Err(format!(
+                "Could not convert string {value} to BackendKind enum"
+            )),
+        }
+    }
+}
+
+impl From<BackendKind> for &str {
+    fn from(val: BackendKind) -> Self {
+        match val {
+            BackendKind::Ollama => "ollama",
+            BackendKind::OpenAi => "openai",
+        }
+    }
+}
+
+/// Server generating answers.
+///
+/// The returned futures do not borrow the backend so that they can be run in the background.
+pub trait ChatBackend: fmt::Debug + Send + Sync {
+    fn kind(&self) -> BackendKind;
+
+    /// Streams the answer of a model to the given messages.
+    fn chat(
+        &self,
+        model: String,
+        messages: Vec<Message>,
+        options: ChatOptions,
+    ) -> BoxFuture<'static, Result<ChatStream>>;
+
+    /// Streams the completion of a prompt by a model.
+    fn complete(
+        &self,
+        model: String,
+        _prompt: CompletionPrompt,
+        _sampling: SamplingOptions,
+        _keep_alive: Option<KeepAlive>,
+    ) -> BoxFuture<'static, Result<CompletionStream>> {
+        let kind = self.kind();
+        async move {
+            Err(anyhow!(
+                "{} servers cannot complete prompts with model {model}",
+                kind.label()
+            ))
+        }
+        .boxed()
+    }
+
+    /// Returns the names of the models that can be used.
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>>>;
+
+    /// Fails if the server cannot be reached.
+    fn health_check(&self) -> BoxFuture<'static, Result<()>>;
+
+    /// Whether the model is in memory, `None` if the server does not tell.
+    ///
+    /// Answers only start once the model is loaded, which can take a while.
+    fn is_model_loaded(&self, _model: String) -> BoxFuture<'static, Option<bool>> {
+        async { None }.boxed()
+    }
+
+    /// Whether models can be loaded and unloaded with [`ChatBackend::load_model`]
+    /// and [`ChatBackend::unload_model`].
+    fn can_manage_loaded_models(&self) -> bool {
+        false
+    }
+
+    /// Returns the models in memory.
+    fn loaded_models(&self) -> BoxFuture<'static, Result<Vec<LoadedModel>>> {
+        let kind = self.kind();
+        async move {
+            Err(anyhow!(
+                "{} servers do not list loaded models",
+                kind.label()
+            ))
+        }
+        .boxed()
+    }
+
+    /// Loads a model in memory before it is used, keeping it there for the given duration.
+    fn load_model(
+        &self,
+        model: String,
+        _keep_alive: Option<KeepAlive>,
+    ) -> BoxFuture<'static, Result<()>> {
+        let kind = self.kind();
+        async move {
+            Err(anyhow!(
+                "{} servers cannot load model {model}",
+                kind.label()
+            ))
+        }
+        .boxed()
+    }
+
+    /// Removes a model from memory.
+    fn unload_model(&self, model: String) -> BoxFuture<'static, Result<()>> {
+        let kind = self.kind();
+        async move {
+            Err(anyhow!(
+                "{} servers cannot unload model {model}",
+                kind.label()
+            ))
+        }
+        .boxed()
+    }
+
+    /// Whether models can be downloaded with [`ChatBackend::pull_model`].
+    fn can_pull_models(&self) -> bool {
+        false
+    }
+
+    /// Streams the progress of the download of a model.
+    fn pull_model(&self, model: String) -> BoxFuture<'static, Result<PullStream>> {
+        let kind = self.kind();
+        async move {
+            Err(anyhow!(
+                "{} servers cannot pull model {model}",
+                kind.label()
+            ))
+        }
+        .boxed()
+    }
+}
+
+/// Creates a backend of the given kind, as stored in the `backends` table.
+pub fn build_backend(
+    kind: &str,
+    base_url: &str,
+    api_key: Option<String>,
+    timeouts: Timeouts,
+) -> Result<Arc<dyn ChatBackend>> {
+    let kind = BackendKind::try_from(kind.to_string()).map_err(|error| anyhow!(error))?;
+    let endpoint = Endpoint::new(base_url, api_key, timeouts);
+    Ok(match kind {
+        BackendKind::Ollama => Arc::new(OllamaBackend::new(endpoint)),
+        BackendKind::OpenAi => Arc::new(OpenAiBackend::new(endpoint)),
+    })
+}
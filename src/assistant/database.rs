@@ -1,29 +1,63 @@
+pub mod backup;
+pub mod encryption;
+pub mod message_writer;
 pub mod models;
+pub mod pool;
 pub mod schema;
 
 use std::fmt;
+use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
+use chrono::{Duration, Utc};
 use diesel::prelude::*;
 use diesel::sqlite::SqliteConnection;
 use diesel_async::async_connection_wrapper::AsyncConnectionWrapper;
+use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
 use diesel_async::{AsyncConnection, RunQueryDsl};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use home::home_dir;
 
 use super::notification::{DatabaseNotifier, DatabaseNotifierMessage};
 use super::ollama::types::Role;
 use super::prompts::ASSISTANT_SYSTEM_PROMPT;
 
-use self::models::{Message, NewMessage, NewThread, Thread};
-use self::schema::{messages, threads};
+use self::backup::BackupFile;
+use self::encryption::{DatabaseKey, EncryptionSettings};
+use self::models::{
+    Backend, Folder, Message, NewBackend, NewDraft, NewFolder, NewMessage, NewPromptTemplate,
+    NewTag, NewThread, PromptTemplate, Tag, Thread, ThreadTag,
+};
+use self::pool::ConnectionPool;
+use self::schema::{
+    backends, drafts, folders, messages, prompt_templates, tags, thread_tags, threads,
+};
 
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+/// Options used to filter the threads returned by [`Database::get_threads`].
+///
+/// A `None` value means that threads are not filtered on that attribute.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadFilterOptions {
+    pub pinned: Option<bool>,
+    pub archived: Option<bool>,
+    pub tag_id: Option<i64>,
+    pub folder_id: Option<i64>,
+    /// Return the threads in the trash instead of the other ones.
+    pub trashed: bool,
+}
+
+/// Maximum number of connections opened to the database.
+const POOL_SIZE: usize = 4;
+
+/// Suffixes of the files written next to the database before replacing it.
+const EXPORTED_DATABASE_SUFFIX: &str = ".export";
+const RESTORED_DATABASE_SUFFIX: &str = ".restore";
+
 pub struct Database {
     database_url: String,
-    connection: SyncConnectionWrapper<SqliteConnection>,
+    pool: ConnectionPool,
     pub notifier: DatabaseNotifier,
 }
 
@@ -34,42 +68,27 @@ impl fmt::Debug for Database {
 }
 
 impl Database {
-    pub async fn new(database_url: Option<String>) -> Result<Self> {
-        let database_url = match database_url {
-            Some(database_url) => database_url,
-            None => {
-                let mut database_path = match home_dir() {
-                    Some(path) if !path.as_os_str().is_empty() => path,
-                    _ => panic!("Unable to get home dir!"),
-                };
-                database_path.push(".pincer_chat");
-                database_path.push("database.db");
-
-                match database_path.into_os_string().into_string() {
-                    Ok(database_url) => database_url,
-                    Err(error) => panic!("Unable to get your home dir because of: {:?}", error),
-                }
-            }
-        };
-        let connection = Self::connect(&database_url).await?;
+    pub async fn new(database_url: String) -> Result<Self> {
+        recover_replacement(&database_url)?;
+        let pool = ConnectionPool::new(database_url.clone(), POOL_SIZE);
+        // Fail early if the database cannot be opened,
+        // an encrypted one can only be opened once it is unlocked
+        if EncryptionSettings::read(&database_url)?.is_none() {
+            drop(pool.get().await?);
+        }
         let instance = Self {
             database_url,
-            connection,
+            pool,
             notifier: DatabaseNotifier::new(),
         };
         Ok(instance)
     }
 
-    pub async fn connect(database_url: &str) -> Result<SyncConnectionWrapper<SqliteConnection>> {
-        let connection = SyncConnectionWrapper::<SqliteConnection>::establish(database_url).await?;
-        Ok(connection)
-    }
-
     pub async fn run_migrations(&self) -> Result<()> {
-        let connection = Self::connect(&self.database_url).await?;
+        let connection = self.pool.connect().await?;
         let mut async_wrapper: AsyncConnectionWrapper<SyncConnectionWrapper<SqliteConnection>> =
             AsyncConnectionWrapper::from(connection);
-        let _ = tokio::task::spawn_blocking(move || -> Result<()> {
+        tokio::task::spawn_blocking(move || -> Result<()> {
             match async_wrapper.run_pending_migrations(MIGRATIONS) {
                 Ok(_) => {
                     tracing::info!("Successfully applied migraitons");
@@ -81,16 +100,181 @@ impl Database {
                 }
             }
         })
+        .await?
+    }
+
+    pub fn is_encrypted(&self) -> Result<bool> {
+        Ok(EncryptionSettings::read(&self.database_url)?.is_some())
+    }
+
+    /// Derives the key of an encrypted database from its passphrase and checks that it opens it.
+    pub async fn unlock(&self, passphrase: &str) -> Result<()> {
+        let settings = EncryptionSettings::read(&self.database_url)?
+            .ok_or_else(|| anyhow!("Database is not encrypted"))?;
+        let key = derive_key(settings, passphrase).await?;
+        check_key(&self.database_url, &key).await?;
+        self.pool.set_key(Some(key));
+        tracing::info!("Unlocked database");
+        Ok(())
+    }
+
+    /// Encrypts a plaintext database with a key derived from the given passphrase.
+    pub async fn enable_encryption(&self, passphrase: &str) -> Result<()> {
+        if self.is_encrypted()? {
+            return Err(anyhow!("Database is already encrypted"));
+        }
+        let settings = EncryptionSettings::generate();
+        let key = derive_key(settings.clone(), passphrase).await?;
+        self.reencrypt(Some((settings, key))).await?;
+        tracing::info!("Encrypted database");
+        Ok(())
+    }
+
+    /// Re-encrypts the database with a key derived from a new passphrase.
+    pub async fn change_passphrase(&self, passphrase: &str, new_passphrase: &str) -> Result<()> {
+        self.check_passphrase(passphrase).await?;
+        let settings = EncryptionSettings::generate();
+        let key = derive_key(settings.clone(), new_passphrase).await?;
+        self.reencrypt(Some((settings, key))).await?;
+        tracing::info!("Changed database passphrase");
+        Ok(())
+    }
+
+    /// Decrypts the database, storing it as plaintext.
+    pub async fn disable_encryption(&self, passphrase: &str) -> Result<()> {
+        self.check_passphrase(passphrase).await?;
+        self.reencrypt(None).await?;
+        tracing::info!("Decrypted database");
+        Ok(())
+    }
+
+    async fn check_passphrase(&self, passphrase: &str) -> Result<()> {
+        let settings = EncryptionSettings::read(&self.database_url)?
+            .ok_or_else(|| anyhow!("Database is not encrypted"))?;
+        let key = derive_key(settings, passphrase).await?;
+        if self.pool.key().as_ref() != Some(&key) {
+            return Err(anyhow!("Wrong passphrase"));
+        }
+        Ok(())
+    }
+
+    /// Exports the content of the database to a new file encrypted with the given key,
+    /// or in plaintext if there is none, and replaces the database with it.
+    async fn reencrypt(&self, encryption: Option<(EncryptionSettings, DatabaseKey)>) -> Result<()> {
+        let _exclusive_access = self.pool.exclusive().await?;
+        let exported_database_url = format!("{}{EXPORTED_DATABASE_SUFFIX}", self.database_url);
+        let _ = std::fs::remove_file(&exported_database_url);
+
+        let mut connection = self.pool.connect().await?;
+        // Make sure that everything written in the write-ahead log gets exported
+        diesel::sql_query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&mut connection)
+            .await?;
+        let key_literal = match &encryption {
+            Some((_, key)) => key.sql_literal(),
+            None => String::from("''"),
+        };
+        diesel::sql_query(format!(
+            "ATTACH DATABASE '{}' AS exported KEY {key_literal}",
+            exported_database_url.replace('\'', "''")
+        ))
+        .execute(&mut connection)
         .await?;
+        let export_result = diesel::sql_query("SELECT sqlcipher_export('exported')")
+            .execute(&mut connection)
+            .await;
+        diesel::sql_query("DETACH DATABASE exported")
+            .execute(&mut connection)
+            .await?;
+        drop(connection);
+        if let Err(error) = export_result {
+            let _ = std::fs::remove_file(&exported_database_url);
+            return Err(anyhow!("Exporting database failed because of: {error}"));
+        }
+
+        let (settings, key) = encryption.unzip();
+        replace_database(
+            &exported_database_url,
+            &self.database_url,
+            settings.as_ref(),
+        )?;
+        self.pool.set_key(key);
+        Ok(())
+    }
+
+    /// Backs up the database into a new file, deleting the oldest backups.
+    pub async fn backup(&self) -> Result<BackupFile> {
+        let database_url = self.database_url.clone();
+        let key = self.pool.key();
+        let backup =
+            tokio::task::spawn_blocking(move || backup::create_backup(&database_url, key.as_ref()))
+                .await??;
+        tracing::info!("Backed up database to {}", backup.path.display());
+        Ok(backup)
+    }
+
+    /// Backs up the database unless the last backup is recent enough.
+    pub async fn backup_if_due(&self) -> Result<Option<BackupFile>> {
+        let last_backup = self.list_backups()?.into_iter().next();
+        let now = chrono::Local::now().naive_local();
+        match last_backup {
+            Some(backup) if now - backup.created_at < backup::BACKUP_INTERVAL => Ok(None),
+            _ => Ok(Some(self.backup().await?)),
+        }
+    }
+
+    pub fn list_backups(&self) -> Result<Vec<BackupFile>> {
+        backup::list_backups(&self.database_url)
+    }
+
+    /// Replaces the database with a backup after checking that it can be used.
+    ///
+    /// The passphrase of an encrypted backup is needed, and it becomes the one of the database.
+    pub async fn restore_backup(
+        &self,
+        backup_path: PathBuf,
+        passphrase: Option<&str>,
+    ) -> Result<()> {
+        let backup_url = backup_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Backup path {backup_path:?} is not valid UTF-8"))?
+            .to_string();
+        let settings = EncryptionSettings::read(&backup_url)?;
+        let key = match (&settings, passphrase) {
+            (Some(settings), Some(passphrase)) => {
+                Some(derive_key(settings.clone(), passphrase).await?)
+            }
+            (Some(_), None) => {
+                return Err(anyhow!("The backup is encrypted, enter its passphrase"))
+            }
+            (None, _) => None,
+        };
+        let validated_key = key.clone();
+        tokio::task::spawn_blocking(move || {
+            backup::validate_backup(&backup_path, validated_key.as_ref())
+        })
+        .await??;
+
+        let _exclusive_access = self.pool.exclusive().await?;
+        let restored_database_url = format!("{}{RESTORED_DATABASE_SUFFIX}", self.database_url);
+        std::fs::copy(&backup_url, &restored_database_url)?;
+        replace_database(
+            &restored_database_url,
+            &self.database_url,
+            settings.as_ref(),
+        )?;
+        self.pool.set_key(key);
+        tracing::info!("Restored database from backup {backup_url}");
         Ok(())
     }
 
-    pub async fn create_thread(&mut self, title: &str) -> Result<Thread> {
+    pub async fn create_thread(&self, title: &str) -> Result<Thread> {
+        let mut connection = self.pool.get().await?;
         let new_thread = NewThread { title };
         let inserted_thread = diesel::insert_into(threads::table)
             .values(&new_thread)
             .returning(Thread::as_returning())
-            .get_result(&mut self.connection)
+            .get_result(&mut *connection)
             .await?;
 
         // System Message
@@ -98,12 +282,13 @@ impl Database {
             thread_id: inserted_thread.id,
             content: ASSISTANT_SYSTEM_PROMPT,
             role: Role::System.into(),
+            complete: true,
         };
 
         diesel::insert_into(messages::table)
             .values(&system_message)
             .returning(Message::as_returning())
-            .get_result(&mut self.connection)
+            .get_result(&mut *connection)
             .await?;
 
         self.notifier
@@ -111,71 +296,260 @@ impl Database {
         Ok(inserted_thread)
     }
 
-    pub async fn update_thread_title(&mut self, id: i64, title: String) -> Result<()> {
+    pub async fn update_thread_title(&self, id: i64, title: String) -> Result<()> {
+        let mut connection = self.pool.get().await?;
         use self::schema::threads::dsl;
 
-        let updated_thread = diesel::update(dsl::threads.find(id))
+        diesel::update(dsl::threads.find(id))
             .set(dsl::title.eq(&*title))
-            .get_result(&mut self.connection)
+            .execute(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThreadTitle(id, title));
+        Ok(())
+    }
+
+    pub async fn set_thread_pinned(&self, id: i64, pinned: bool) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::pinned.eq(pinned))
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        Ok(())
+    }
+
+    /// Sets the backend used to generate the answers of a thread, `None` meaning the active one.
+    pub async fn set_thread_backend(&self, id: i64, backend_id: Option<i64>) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::backend_id.eq(backend_id))
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        Ok(())
+    }
+
+    /// Sets the format the answers of a thread must follow, as stored by
+    /// [`ResponseFormat`](super::structured_output::ResponseFormat), `None` for free text.
+    pub async fn set_thread_response_format(
+        &self,
+        id: i64,
+        response_format: Option<&str>,
+    ) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::response_format.eq(response_format))
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        Ok(())
+    }
+
+    pub async fn set_thread_archived(&self, id: i64, archived: bool) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::archived.eq(archived))
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        Ok(())
+    }
+
+    /// Moves the thread to the trash, from which it can still be restored.
+    pub async fn delete_thread(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::deleted_at.eq(Some(Utc::now().naive_utc())))
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        Ok(())
+    }
+
+    pub async fn restore_thread(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::deleted_at.eq(None::<chrono::NaiveDateTime>))
+            .get_result(&mut *connection)
             .await?;
         self.notifier
             .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
         Ok(())
     }
 
-    pub async fn delete_thread(&mut self, id: i64) -> Result<()> {
+    /// Permanently deletes the thread.
+    pub async fn purge_thread(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
         use self::schema::threads::dsl;
 
+        // Messages and tag assignments are deleted along with the thread
         diesel::delete(dsl::threads.filter(dsl::id.eq(id)))
-            .execute(&mut self.connection)
+            .execute(&mut *connection)
             .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::DeleteThread(id));
         Ok(())
     }
 
+    /// Permanently deletes the threads that have been in the trash
+    /// for longer than the given retention period.
+    ///
+    /// Returns the number of deleted threads.
+    pub async fn purge_deleted_threads(&self, retention: Duration) -> Result<usize> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let cutoff = Utc::now().naive_utc() - retention;
+        let purged_thread_ids: Vec<i64> =
+            diesel::delete(dsl::threads.filter(dsl::deleted_at.lt(cutoff)))
+                .returning(dsl::id)
+                .get_results(&mut *connection)
+                .await?;
+        for id in purged_thread_ids.iter() {
+            self.notifier
+                .notify(DatabaseNotifierMessage::DeleteThread(*id));
+        }
+        Ok(purged_thread_ids.len())
+    }
+
     #[allow(dead_code)]
-    pub async fn get_thread(&mut self, id: i64) -> Result<Thread> {
+    pub async fn get_thread(&self, id: i64) -> Result<Thread> {
+        let mut connection = self.pool.get().await?;
         use self::schema::threads::dsl;
 
-        let thread = dsl::threads.find(id).first(&mut self.connection).await?;
+        let thread = dsl::threads.find(id).first(&mut *connection).await?;
         Ok(thread)
     }
 
-    pub async fn get_threads(&mut self) -> Result<Vec<Thread>> {
-        let threads = schema::threads::table
+    pub async fn get_threads(&self, options: ThreadFilterOptions) -> Result<Vec<Thread>> {
+        let mut connection = self.pool.get().await?;
+        let mut query = schema::threads::table
             .select(Thread::as_select())
-            .order_by(threads::last_updated_at.desc())
-            .load(&mut self.connection)
+            .into_boxed();
+        if options.trashed {
+            query = query.filter(threads::deleted_at.is_not_null());
+        } else {
+            query = query.filter(threads::deleted_at.is_null());
+        }
+        if let Some(pinned) = options.pinned {
+            query = query.filter(threads::pinned.eq(pinned));
+        }
+        if let Some(archived) = options.archived {
+            query = query.filter(threads::archived.eq(archived));
+        }
+        if let Some(tag_id) = options.tag_id {
+            query = query.filter(
+                threads::id.eq_any(
+                    thread_tags::table
+                        .filter(thread_tags::tag_id.eq(tag_id))
+                        .select(thread_tags::thread_id),
+                ),
+            );
+        }
+        if let Some(folder_id) = options.folder_id {
+            query = query.filter(threads::folder_id.eq(folder_id));
+        }
+        let threads = query
+            .order_by((threads::pinned.desc(), threads::last_updated_at.desc()))
+            .load(&mut *connection)
             .await?;
         Ok(threads)
     }
 
-    pub async fn get_messages(&mut self, thread_id: i64) -> Result<Vec<Message>> {
+    /// Returns all the messages of a thread, oldest first.
+    pub async fn get_messages(&self, thread_id: i64) -> Result<Vec<Message>> {
+        let mut connection = self.pool.get().await?;
         let messages = schema::messages::table
             .filter(schema::messages::thread_id.eq(thread_id))
             .select(Message::as_select())
-            .load(&mut self.connection)
+            .order_by(schema::messages::id.asc())
+            .load(&mut *connection)
             .await?;
+        Ok(messages)
+    }
 
-        self.notifier
-            .notify(DatabaseNotifierMessage::GetThreadMessages(messages.clone()));
+    /// Returns at most `limit` messages of a thread sent before the message `before_id`,
+    /// or the most recent ones if it is `None`, oldest first.
+    pub async fn get_messages_page(
+        &self,
+        thread_id: i64,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>> {
+        let mut connection = self.pool.get().await?;
+        let mut query = schema::messages::table
+            .filter(schema::messages::thread_id.eq(thread_id))
+            .select(Message::as_select())
+            .into_boxed();
+        if let Some(before_id) = before_id {
+            query = query.filter(schema::messages::id.lt(before_id));
+        }
+        let mut messages = query
+            .order_by(schema::messages::id.desc())
+            .limit(limit)
+            .load(&mut *connection)
+            .await?;
+        messages.reverse();
+
+        let notifier_message = match before_id {
+            Some(_) => DatabaseNotifierMessage::GetOlderThreadMessages(thread_id, messages.clone()),
+            None => DatabaseNotifierMessage::GetThreadMessages(thread_id, messages.clone()),
+        };
+        self.notifier.notify(notifier_message);
         Ok(messages)
     }
 
     pub async fn create_message(
-        &mut self,
+        &self,
         thread_id: i64,
         content: String,
         role: Role,
     ) -> Result<Message> {
+        self.insert_message(thread_id, &content, role, true).await
+    }
+
+    /// Creates an empty message whose content is going to be streamed,
+    /// see [`MessageWriter`](message_writer::MessageWriter).
+    pub async fn create_partial_message(&self, thread_id: i64, role: Role) -> Result<Message> {
+        self.insert_message(thread_id, "", role, false).await
+    }
+
+    async fn insert_message(
+        &self,
+        thread_id: i64,
+        content: &str,
+        role: Role,
+        complete: bool,
+    ) -> Result<Message> {
+        let mut connection = self.pool.get().await?;
         let new_message = NewMessage {
             thread_id,
-            content: &content,
+            content,
             role: role.into(),
+            complete,
         };
         let inserted_message = diesel::insert_into(messages::table)
             .values(&new_message)
             .returning(Message::as_returning())
-            .get_result(&mut self.connection)
+            .get_result(&mut *connection)
             .await?;
 
         self.notifier.notify(DatabaseNotifierMessage::NewMessage(
@@ -184,135 +558,650 @@ impl Database {
         Ok(inserted_message)
     }
 
-    pub async fn update_message(&mut self, message_id: i64, content_update: String) -> Result<()> {
+    /// Appends content to a message without notifying the subscribers.
+    pub async fn append_to_message(&self, message_id: i64, content_update: &str) -> Result<()> {
+        let mut connection = self.pool.get().await?;
         use self::schema::messages::dsl::*;
 
         diesel::update(messages.find(message_id))
-            .set(content.eq(content.concat(&*content_update)))
-            .execute(&mut self.connection)
+            .set(content.eq(content.concat(content_update)))
+            .returning(id)
+            .get_result::<i64>(&mut *connection)
             .await?;
-        self.notifier
-            .notify(DatabaseNotifierMessage::UpdateMessage(content_update));
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use rand::distr::{Alphanumeric, SampleString};
-
-    use super::*;
-
-    struct TestDatabaseWrapper {
-        database_filepath: String,
-        pub database: Database,
+    /// Marks a message whose content was streamed as complete.
+    pub async fn complete_message(&self, message_id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        diesel::update(messages::table.find(message_id))
+            .set(messages::complete.eq(true))
+            .returning(messages::id)
+            .get_result::<i64>(&mut *connection)
+            .await?;
+        Ok(())
     }
 
-    impl TestDatabaseWrapper {
-        async fn setup() -> Self {
-            let mut temp_dir = std::env::temp_dir();
-            let database_filename: String =
-                Alphanumeric.sample_string(&mut rand::rng(), 16) + ".db";
-            temp_dir.push(database_filename);
-            let database_url = match temp_dir.into_os_string().into_string() {
-                Ok(database_url) => database_url,
-                Err(_) => panic!("Unable to get temporary dir for tests!"),
-            };
-            let database = Database::new(Some(database_url.clone()))
-                .await
-                .expect("Instantiating database should work");
-            database
-                .run_migrations()
-                .await
-                .expect("Migrations should work");
-            Self {
-                database_filepath: database_url,
-                database,
-            }
+    /// Keeps the content of messages whose streaming was interrupted, e.g. by a crash,
+    /// and deletes the ones that did not receive any content.
+    ///
+    /// Returns the number of recovered messages.
+    pub async fn recover_partial_messages(&self) -> Result<usize> {
+        let mut connection = self.pool.get().await?;
+        let incomplete_messages = messages::table.filter(messages::complete.eq(false));
+        let n_deleted = diesel::delete(incomplete_messages.filter(messages::content.eq("")))
+            .execute(&mut *connection)
+            .await?;
+        if n_deleted > 0 {
+            tracing::info!("Deleted {n_deleted} empty partial messages");
         }
+        let n_recovered = diesel::update(incomplete_messages)
+            .set(messages::complete.eq(true))
+            .execute(&mut *connection)
+            .await?;
+        Ok(n_recovered)
     }
 
-    impl Drop for TestDatabaseWrapper {
-        fn drop(&mut self) {
-            std::fs::remove_file(&*self.database_filepath)
-                .expect("Deleting database file should work");
-        }
+    /// Replaces the content of a message, which is then no longer flagged as not following
+    /// the format of its thread.
+    pub async fn edit_message(&self, message_id: i64, new_content: String) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        let edited_message = diesel::update(messages::table.find(message_id))
+            .set((
+                messages::content.eq(new_content),
+                messages::format_error.eq(None::<String>),
+            ))
+            .returning(Message::as_returning())
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::EditMessage(edited_message));
+        Ok(())
     }
 
-    #[tokio::test]
-    async fn test_creating_thread() {
-        let mut database_wrapper = TestDatabaseWrapper::setup().await;
-        let database = &mut database_wrapper.database;
-        let result = database.create_thread("Test Thread Title").await;
-        assert!(result.is_ok(), "Error: {}", result.err().unwrap());
-        let thread = result.unwrap();
-        assert!(thread.id > 0);
-        assert_eq!(thread.title, "Test Thread Title");
+    /// Flags an answer that does not follow the format of its thread.
+    pub async fn set_message_format_error(
+        &self,
+        message_id: i64,
+        format_error: &str,
+    ) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        let edited_message = diesel::update(messages::table.find(message_id))
+            .set(messages::format_error.eq(format_error))
+            .returning(Message::as_returning())
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::EditMessage(edited_message));
+        Ok(())
+    }
 
-        let messages = database
-            .get_messages(thread.id)
-            .await
-            .expect("Getting thread message should work");
-        assert_eq!(messages.len(), 1);
-        assert_eq!(messages[0].role, "system");
+    pub async fn delete_message(&self, message_id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        let thread_id = diesel::delete(messages::table.find(message_id))
+            .returning(messages::thread_id)
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier.notify(DatabaseNotifierMessage::DeleteMessage(
+            thread_id, message_id,
+        ));
+        Ok(())
     }
 
-    #[tokio::test]
-    async fn test_updating_thread_title() {
-        let mut database_wrapper = TestDatabaseWrapper::setup().await;
-        let database = &mut database_wrapper.database;
-        let result = database.create_thread("Test Thread Title").await;
-        assert!(result.is_ok());
-        let thread = result.unwrap();
-        assert!(thread.id > 0);
-        assert_eq!(thread.title, "Test Thread Title");
+    /// Returns the most recent prompts submitted by the user, oldest first.
+    pub async fn get_user_prompts(&self, limit: i64) -> Result<Vec<String>> {
+        let mut connection = self.pool.get().await?;
+        let role: &str = Role::User.into();
+        let mut prompts: Vec<String> = messages::table
+            .filter(messages::role.eq(role))
+            .select(messages::content)
+            .order_by(messages::id.desc())
+            .limit(limit)
+            .load(&mut *connection)
+            .await?;
+        prompts.reverse();
+        Ok(prompts)
+    }
 
-        database
-            .update_thread_title(thread.id, String::from("A Different Title"))
-            .await
-            .expect("Updating thread title should work");
+    /// Saves the unsent user input of a thread, or removes it if it is empty.
+    pub async fn save_draft(&self, thread_id: i64, content: &str) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        if content.is_empty() {
+            diesel::delete(drafts::table.find(thread_id))
+                .execute(&mut *connection)
+                .await?;
+            return Ok(());
+        }
+        let new_draft = NewDraft { thread_id, content };
+        diesel::replace_into(drafts::table)
+            .values(&new_draft)
+            .execute(&mut *connection)
+            .await?;
+        Ok(())
+    }
 
-        let thread = database
-            .get_thread(thread.id)
+    pub async fn get_draft(&self, thread_id: i64) -> Result<Option<String>> {
+        let mut connection = self.pool.get().await?;
+        let draft = drafts::table
+            .find(thread_id)
+            .select(drafts::content)
+            .first(&mut *connection)
             .await
-            .expect("Getting thread by id should work");
-        assert_eq!(thread.title, "A Different Title");
+            .optional()?;
+        Ok(draft)
     }
 
-    #[tokio::test]
-    async fn test_deleting_thread() {
-        let mut database_wrapper = TestDatabaseWrapper::setup().await;
-        let database = &mut database_wrapper.database;
-        let result = database.create_thread("Test Thread Title").await;
-        assert!(result.is_ok(), "Error: {}", result.err().unwrap());
-        let thread = result.unwrap();
-        assert!(thread.id > 0);
-        assert_eq!(thread.title, "Test Thread Title");
+    pub async fn create_tag(&self, name: &str) -> Result<Tag> {
+        let mut connection = self.pool.get().await?;
+        let new_tag = NewTag { name };
+        let inserted_tag = diesel::insert_into(tags::table)
+            .values(&new_tag)
+            .returning(Tag::as_returning())
+            .get_result(&mut *connection)
+            .await?;
 
-        let n_threads_before_delete = database
-            .get_threads()
-            .await
-            .expect("Getting all threads should work")
-            .len();
-        assert_eq!(n_threads_before_delete, 1);
+        self.notifier
+            .notify(DatabaseNotifierMessage::NewTag(inserted_tag.clone()));
+        Ok(inserted_tag)
+    }
 
-        database
-            .delete_thread(thread.id)
-            .await
-            .expect("Deleting thread should work");
+    pub async fn delete_tag(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        diesel::delete(tags::table.find(id))
+            .execute(&mut *connection)
+            .await?;
 
-        let n_threads_after_delete = database
-            .get_threads()
-            .await
-            .expect("Getting all threads should work")
-            .len();
-        assert_eq!(n_threads_after_delete, 0);
+        self.notifier.notify(DatabaseNotifierMessage::DeleteTag(id));
+        Ok(())
     }
 
-    #[tokio::test]
-    async fn test_deleting_multiple_threads() {
-        let mut database_wrapper = TestDatabaseWrapper::setup().await;
-        let database = &mut database_wrapper.database;
+    pub async fn get_tags(&self) -> Result<Vec<Tag>> {
+        let mut connection = self.pool.get().await?;
+        let tags = tags::table
+            .select(Tag::as_select())
+            .order_by(tags::name.asc())
+            .load(&mut *connection)
+            .await?;
+        Ok(tags)
+    }
+
+    pub async fn get_thread_tags(&self, thread_id: i64) -> Result<Vec<Tag>> {
+        let mut connection = self.pool.get().await?;
+        let tags = tags::table
+            .inner_join(thread_tags::table)
+            .filter(thread_tags::thread_id.eq(thread_id))
+            .select(Tag::as_select())
+            .order_by(tags::name.asc())
+            .load(&mut *connection)
+            .await?;
+        Ok(tags)
+    }
+
+    /// Returns the tag assignments of all threads.
+    pub async fn get_all_thread_tags(&self) -> Result<Vec<ThreadTag>> {
+        let mut connection = self.pool.get().await?;
+        let thread_tags = thread_tags::table
+            .select(ThreadTag::as_select())
+            .load(&mut *connection)
+            .await?;
+        Ok(thread_tags)
+    }
+
+    pub async fn add_tag_to_thread(&self, thread_id: i64, tag_id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        let thread_tag = ThreadTag { thread_id, tag_id };
+        diesel::insert_or_ignore_into(thread_tags::table)
+            .values(&thread_tag)
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+
+        let tags = self.get_thread_tags(thread_id).await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThreadTags(thread_id, tags));
+        Ok(())
+    }
+
+    pub async fn remove_tag_from_thread(&self, thread_id: i64, tag_id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        diesel::delete(thread_tags::table.find((thread_id, tag_id)))
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+
+        let tags = self.get_thread_tags(thread_id).await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThreadTags(thread_id, tags));
+        Ok(())
+    }
+
+    pub async fn create_folder(&self, name: &str) -> Result<Folder> {
+        let mut connection = self.pool.get().await?;
+        let new_folder = NewFolder { name };
+        let inserted_folder = diesel::insert_into(folders::table)
+            .values(&new_folder)
+            .returning(Folder::as_returning())
+            .get_result(&mut *connection)
+            .await?;
+
+        self.notifier
+            .notify(DatabaseNotifierMessage::NewFolder(inserted_folder.clone()));
+        Ok(inserted_folder)
+    }
+
+    /// Deletes a folder, the threads it contains are moved out of it.
+    pub async fn delete_folder(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        let updated_threads = diesel::update(threads::table.filter(threads::folder_id.eq(id)))
+            .set(threads::folder_id.eq(None::<i64>))
+            .returning(Thread::as_returning())
+            .get_results(&mut *connection)
+            .await?;
+        diesel::delete(folders::table.find(id))
+            .execute(&mut *connection)
+            .await?;
+
+        for updated_thread in updated_threads {
+            self.notifier
+                .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        }
+        self.notifier
+            .notify(DatabaseNotifierMessage::DeleteFolder(id));
+        Ok(())
+    }
+
+    pub async fn get_folders(&self) -> Result<Vec<Folder>> {
+        let mut connection = self.pool.get().await?;
+        let folders = folders::table
+            .select(Folder::as_select())
+            .order_by(folders::name.asc())
+            .load(&mut *connection)
+            .await?;
+        Ok(folders)
+    }
+
+    /// Moves a thread into a folder, or out of any folder if `folder_id` is `None`.
+    pub async fn set_thread_folder(&self, id: i64, folder_id: Option<i64>) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        use self::schema::threads::dsl;
+
+        let updated_thread = diesel::update(dsl::threads.find(id))
+            .set(dsl::folder_id.eq(folder_id))
+            .get_result(&mut *connection)
+            .await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateThread(updated_thread));
+        Ok(())
+    }
+
+    pub async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>> {
+        let mut connection = self.pool.get().await?;
+        let prompt_templates = prompt_templates::table
+            .select(PromptTemplate::as_select())
+            .order_by(prompt_templates::name.asc())
+            .load(&mut *connection)
+            .await?;
+        Ok(prompt_templates)
+    }
+
+    /// Saves prompt templates, replacing the content of the existing ones with the same name.
+    ///
+    /// Either all the templates are saved or none of them is.
+    pub async fn save_prompt_templates(
+        &self,
+        new_prompt_templates: &[NewPromptTemplate<'_>],
+    ) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        connection
+            .transaction::<_, anyhow::Error, _>(|connection| {
+                async move {
+                    for new_prompt_template in new_prompt_templates {
+                        diesel::insert_into(prompt_templates::table)
+                            .values(new_prompt_template)
+                            .on_conflict(prompt_templates::name)
+                            .do_update()
+                            .set(prompt_templates::content.eq(new_prompt_template.content))
+                            .execute(connection)
+                            .await?;
+                    }
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await?;
+        drop(connection);
+
+        let prompt_templates = self.get_prompt_templates().await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdatePromptTemplates(
+                prompt_templates,
+            ));
+        Ok(())
+    }
+
+    pub async fn delete_prompt_template(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        diesel::delete(prompt_templates::table.find(id))
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+
+        let prompt_templates = self.get_prompt_templates().await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdatePromptTemplates(
+                prompt_templates,
+            ));
+        Ok(())
+    }
+
+    pub async fn get_backends(&self) -> Result<Vec<Backend>> {
+        let mut connection = self.pool.get().await?;
+        let backends = backends::table
+            .select(Backend::as_select())
+            .order_by(backends::name.asc())
+            .load(&mut *connection)
+            .await?;
+        Ok(backends)
+    }
+
+    pub async fn create_backend(&self, new_backend: &NewBackend) -> Result<Backend> {
+        let mut connection = self.pool.get().await?;
+        let backend = diesel::insert_into(backends::table)
+            .values(new_backend)
+            .returning(Backend::as_returning())
+            .get_result(&mut *connection)
+            .await?;
+        drop(connection);
+
+        self.notify_backends().await?;
+        Ok(backend)
+    }
+
+    pub async fn update_backend(&self, id: i64, new_backend: &NewBackend) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        diesel::update(backends::table.find(id))
+            .set(new_backend)
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+
+        self.notify_backends().await
+    }
+
+    /// Changes the address of a backend, e.g. when its server does not run on the default host.
+    pub async fn set_backend_base_url(&self, id: i64, base_url: &str) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        let updated_rows = diesel::update(backends::table.find(id))
+            .set(backends::base_url.eq(base_url.trim()))
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+        if updated_rows == 0 {
+            return Err(anyhow!("Backend {id} does not exist"));
+        }
+
+        self.notify_backends().await
+    }
+
+    /// Makes a backend the one used at startup and for new threads.
+    pub async fn set_active_backend(&self, id: i64) -> Result<()> {
+        let mut connection = self.pool.get().await?;
+        // A single statement, so that there is always exactly one active backend
+        let updated_rows = diesel::update(backends::table)
+            .set(backends::active.eq(backends::id.eq(id)))
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+        if updated_rows == 0 {
+            return Err(anyhow!("There is no backend to activate"));
+        }
+
+        self.notify_backends().await
+    }
+
+    /// Deletes a backend, its threads going back to the active one.
+    ///
+    /// The last backend cannot be deleted. If the active backend is deleted,
+    /// the first remaining one becomes active.
+    pub async fn delete_backend(&self, id: i64) -> Result<()> {
+        let backends = self.get_backends().await?;
+        let Some(backend) = backends.iter().find(|backend| backend.id == id) else {
+            return Err(anyhow!("Backend {id} does not exist"));
+        };
+        if backends.len() == 1 {
+            return Err(anyhow!("The last backend cannot be deleted"));
+        }
+
+        let mut connection = self.pool.get().await?;
+        diesel::update(threads::table.filter(threads::backend_id.eq(id)))
+            .set(threads::backend_id.eq(None::<i64>))
+            .execute(&mut *connection)
+            .await?;
+        diesel::delete(backends::table.find(id))
+            .execute(&mut *connection)
+            .await?;
+        drop(connection);
+
+        if backend.active {
+            let remaining_backend = backends
+                .iter()
+                .find(|backend| backend.id != id)
+                .expect("Another backend should remain");
+            self.set_active_backend(remaining_backend.id).await
+        } else {
+            self.notify_backends().await
+        }
+    }
+
+    async fn notify_backends(&self) -> Result<()> {
+        let backends = self.get_backends().await?;
+        self.notifier
+            .notify(DatabaseNotifierMessage::UpdateBackends(backends));
+        Ok(())
+    }
+}
+
+/// Replaces the database file with another one encrypted with the given settings.
+///
+/// The settings are staged before the file is renamed, so that [`recover_replacement`]
+/// can make them match the database if the application stops in between.
+fn replace_database(
+    new_database_url: &str,
+    database_url: &str,
+    settings: Option<&EncryptionSettings>,
+) -> Result<()> {
+    EncryptionSettings::stage(settings, database_url)?;
+    // The write-ahead log of the previous file must not be applied to the new one
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{database_url}{suffix}"));
+    }
+    if let Err(error) = std::fs::rename(new_database_url, database_url) {
+        EncryptionSettings::discard_staged(database_url)?;
+        return Err(error.into());
+    }
+    EncryptionSettings::commit_staged(database_url)
+}
+
+/// Finishes or rolls back a replacement of the database interrupted after its settings were staged.
+fn recover_replacement(database_url: &str) -> Result<()> {
+    if !EncryptionSettings::is_staged(database_url) {
+        return Ok(());
+    }
+    let new_database_urls = [EXPORTED_DATABASE_SUFFIX, RESTORED_DATABASE_SUFFIX]
+        .map(|suffix| format!("{database_url}{suffix}"));
+    if new_database_urls
+        .iter()
+        .any(|new_database_url| std::path::Path::new(new_database_url).exists())
+    {
+        // The database was not replaced yet, so the current settings still apply
+        tracing::warn!("Discarding encryption settings of an interrupted database replacement");
+        for new_database_url in new_database_urls {
+            let _ = std::fs::remove_file(new_database_url);
+        }
+        EncryptionSettings::discard_staged(database_url)
+    } else {
+        tracing::warn!("Committing encryption settings of an interrupted database replacement");
+        EncryptionSettings::commit_staged(database_url)
+    }
+}
+
+/// Derives a key on a blocking thread, as it is purposely slow.
+async fn derive_key(settings: EncryptionSettings, passphrase: &str) -> Result<DatabaseKey> {
+    let passphrase = passphrase.to_string();
+    tokio::task::spawn_blocking(move || settings.derive_key(&passphrase)).await?
+}
+
+/// Fails if the key does not open the database.
+async fn check_key(database_url: &str, key: &DatabaseKey) -> Result<()> {
+    let mut connection = pool::connect(database_url, Some(key))
+        .await
+        .map_err(|_| anyhow!("Wrong passphrase"))?;
+    diesel::sql_query("SELECT count(*) FROM sqlite_master")
+        .execute(&mut connection)
+        .await
+        .map_err(|_| anyhow!("Wrong passphrase"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distr::{Alphanumeric, SampleString};
+
+    use super::*;
+
+    struct TestDatabaseWrapper {
+        database_filepath: String,
+        pub database: Database,
+    }
+
+    impl TestDatabaseWrapper {
+        async fn setup() -> Self {
+            let mut temp_dir = std::env::temp_dir();
+            let database_filename: String =
+                Alphanumeric.sample_string(&mut rand::rng(), 16) + ".db";
+            temp_dir.push(database_filename);
+            let database_url = match temp_dir.into_os_string().into_string() {
+                Ok(database_url) => database_url,
+                Err(_) => panic!("Unable to get temporary dir for tests!"),
+            };
+            let database = Database::new(database_url.clone())
+                .await
+                .expect("Instantiating database should work");
+            database
+                .run_migrations()
+                .await
+                .expect("Migrations should work");
+            Self {
+                database_filepath: database_url,
+                database,
+            }
+        }
+    }
+
+    /// Reverts all migrations applied after, and including, the given one.
+    async fn revert_migrations_down_to(database_url: &str, version: &'static str) {
+        let connection = pool::connect(database_url, None)
+            .await
+            .expect("Connecting to database should work");
+        let mut async_wrapper: AsyncConnectionWrapper<SyncConnectionWrapper<SqliteConnection>> =
+            AsyncConnectionWrapper::from(connection);
+        tokio::task::spawn_blocking(move || loop {
+            let reverted_version = async_wrapper
+                .revert_last_migration(MIGRATIONS)
+                .expect("Reverting last migration should work");
+            if reverted_version.to_string() == version {
+                break;
+            }
+        })
+        .await
+        .expect("Reverting migrations should not panic");
+    }
+
+    impl Drop for TestDatabaseWrapper {
+        fn drop(&mut self) {
+            std::fs::remove_file(&*self.database_filepath)
+                .expect("Deleting database file should work");
+            // Write-ahead log files only exist while connections are open
+            for suffix in ["-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{suffix}", self.database_filepath));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_creating_thread() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let result = database.create_thread("Test Thread Title").await;
+        assert!(result.is_ok(), "Error: {}", result.err().unwrap());
+        let thread = result.unwrap();
+        assert!(thread.id > 0);
+        assert_eq!(thread.title, "Test Thread Title");
+
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting thread message should work");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].role, "system");
+    }
+
+    #[tokio::test]
+    async fn test_updating_thread_title() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let result = database.create_thread("Test Thread Title").await;
+        assert!(result.is_ok());
+        let thread = result.unwrap();
+        assert!(thread.id > 0);
+        assert_eq!(thread.title, "Test Thread Title");
+
+        database
+            .update_thread_title(thread.id, String::from("A Different Title"))
+            .await
+            .expect("Updating thread title should work");
+
+        let thread = database
+            .get_thread(thread.id)
+            .await
+            .expect("Getting thread by id should work");
+        assert_eq!(thread.title, "A Different Title");
+    }
+
+    #[tokio::test]
+    async fn test_deleting_thread() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let result = database.create_thread("Test Thread Title").await;
+        assert!(result.is_ok(), "Error: {}", result.err().unwrap());
+        let thread = result.unwrap();
+        assert!(thread.id > 0);
+        assert_eq!(thread.title, "Test Thread Title");
+
+        let n_threads_before_delete = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting all threads should work")
+            .len();
+        assert_eq!(n_threads_before_delete, 1);
+
+        database
+            .delete_thread(thread.id)
+            .await
+            .expect("Deleting thread should work");
+
+        let n_threads_after_delete = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting all threads should work")
+            .len();
+        assert_eq!(n_threads_after_delete, 0);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_multiple_threads() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
         let mut thread_ids: Vec<i64> = Vec::new();
         for _ in 0..3 {
             let result = database.create_thread("Test Thread Title").await;
@@ -324,7 +1213,7 @@ mod tests {
         }
 
         let n_threads_before_delete = database
-            .get_threads()
+            .get_threads(ThreadFilterOptions::default())
             .await
             .expect("Getting all threads should work")
             .len();
@@ -337,11 +1226,1493 @@ mod tests {
                 .expect("Deleting thread should work");
 
             let n_threads_after_delete = database
-                .get_threads()
+                .get_threads(ThreadFilterOptions::default())
                 .await
                 .expect("Getting all threads should work")
                 .len();
             assert_eq!(n_threads_after_delete, n_threads_before_delete - i - 1);
         }
     }
+
+    #[tokio::test]
+    async fn test_pinning_thread() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let first_thread = database
+            .create_thread("First Thread")
+            .await
+            .expect("Creating thread should work");
+        let second_thread = database
+            .create_thread("Second Thread")
+            .await
+            .expect("Creating thread should work");
+        assert!(!first_thread.pinned);
+
+        database
+            .set_thread_pinned(first_thread.id, true)
+            .await
+            .expect("Pinning thread should work");
+
+        let thread = database
+            .get_thread(first_thread.id)
+            .await
+            .expect("Getting thread by id should work");
+        assert!(thread.pinned);
+        assert_eq!(thread.last_updated_at, first_thread.last_updated_at);
+
+        let threads = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting all threads should work");
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].id, first_thread.id);
+        assert_eq!(threads[1].id, second_thread.id);
+
+        let pinned_threads = database
+            .get_threads(ThreadFilterOptions {
+                pinned: Some(true),
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting pinned threads should work");
+        assert_eq!(pinned_threads.len(), 1);
+        assert_eq!(pinned_threads[0].id, first_thread.id);
+    }
+
+    #[tokio::test]
+    async fn test_archiving_thread() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let first_thread = database
+            .create_thread("First Thread")
+            .await
+            .expect("Creating thread should work");
+        let second_thread = database
+            .create_thread("Second Thread")
+            .await
+            .expect("Creating thread should work");
+        assert!(!first_thread.archived);
+
+        database
+            .set_thread_archived(first_thread.id, true)
+            .await
+            .expect("Archiving thread should work");
+
+        let active_threads = database
+            .get_threads(ThreadFilterOptions {
+                archived: Some(false),
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting active threads should work");
+        assert_eq!(active_threads.len(), 1);
+        assert_eq!(active_threads[0].id, second_thread.id);
+
+        let archived_threads = database
+            .get_threads(ThreadFilterOptions {
+                archived: Some(true),
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting archived threads should work");
+        assert_eq!(archived_threads.len(), 1);
+        assert_eq!(archived_threads[0].id, first_thread.id);
+
+        database
+            .set_thread_archived(first_thread.id, false)
+            .await
+            .expect("Unarchiving thread should work");
+        let n_threads = database
+            .get_threads(ThreadFilterOptions {
+                archived: Some(false),
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting active threads should work")
+            .len();
+        assert_eq!(n_threads, 2);
+    }
+
+    #[tokio::test]
+    async fn test_creating_and_deleting_tags() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let work_tag = database
+            .create_tag("work")
+            .await
+            .expect("Creating tag should work");
+        assert!(work_tag.id > 0);
+        assert_eq!(work_tag.name, "work");
+        database
+            .create_tag("personal")
+            .await
+            .expect("Creating tag should work");
+
+        let result = database.create_tag("work").await;
+        assert!(result.is_err(), "Tag names should be unique");
+
+        let tags = database.get_tags().await.expect("Getting tags should work");
+        let tag_names: Vec<&str> = tags.iter().map(|tag| &*tag.name).collect();
+        assert_eq!(tag_names, vec!["personal", "work"]);
+
+        database
+            .delete_tag(work_tag.id)
+            .await
+            .expect("Deleting tag should work");
+        let tags = database.get_tags().await.expect("Getting tags should work");
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "personal");
+    }
+
+    #[tokio::test]
+    async fn test_tagging_threads() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let first_thread = database
+            .create_thread("First Thread")
+            .await
+            .expect("Creating thread should work");
+        let second_thread = database
+            .create_thread("Second Thread")
+            .await
+            .expect("Creating thread should work");
+        let tag = database
+            .create_tag("work")
+            .await
+            .expect("Creating tag should work");
+
+        database
+            .add_tag_to_thread(first_thread.id, tag.id)
+            .await
+            .expect("Tagging thread should work");
+        // Tagging a thread twice should be a no-op
+        database
+            .add_tag_to_thread(first_thread.id, tag.id)
+            .await
+            .expect("Tagging thread twice should work");
+
+        let thread_tags = database
+            .get_thread_tags(first_thread.id)
+            .await
+            .expect("Getting thread tags should work");
+        assert_eq!(thread_tags.len(), 1);
+        assert_eq!(thread_tags[0].id, tag.id);
+        assert_eq!(
+            database
+                .get_all_thread_tags()
+                .await
+                .expect("Getting all thread tags should work")
+                .len(),
+            1
+        );
+
+        let tagged_threads = database
+            .get_threads(ThreadFilterOptions {
+                tag_id: Some(tag.id),
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting tagged threads should work");
+        assert_eq!(tagged_threads.len(), 1);
+        assert_eq!(tagged_threads[0].id, first_thread.id);
+
+        database
+            .remove_tag_from_thread(first_thread.id, tag.id)
+            .await
+            .expect("Untagging thread should work");
+        let thread_tags = database
+            .get_thread_tags(first_thread.id)
+            .await
+            .expect("Getting thread tags should work");
+        assert!(thread_tags.is_empty());
+
+        database
+            .add_tag_to_thread(second_thread.id, tag.id)
+            .await
+            .expect("Tagging thread should work");
+        database
+            .delete_tag(tag.id)
+            .await
+            .expect("Deleting tag should work");
+        let thread_tags = database
+            .get_all_thread_tags()
+            .await
+            .expect("Getting all thread tags should work");
+        assert!(thread_tags.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_moving_threads_to_folders() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let first_thread = database
+            .create_thread("First Thread")
+            .await
+            .expect("Creating thread should work");
+        let second_thread = database
+            .create_thread("Second Thread")
+            .await
+            .expect("Creating thread should work");
+        let work_folder = database
+            .create_folder("Work")
+            .await
+            .expect("Creating folder should work");
+        database
+            .create_folder("Personal")
+            .await
+            .expect("Creating folder should work");
+        let result = database.create_folder("Work").await;
+        assert!(result.is_err(), "Folder names should be unique");
+        let folders = database
+            .get_folders()
+            .await
+            .expect("Getting folders should work");
+        let folder_names: Vec<&str> = folders.iter().map(|folder| &*folder.name).collect();
+        assert_eq!(folder_names, vec!["Personal", "Work"]);
+
+        database
+            .set_thread_folder(first_thread.id, Some(work_folder.id))
+            .await
+            .expect("Moving thread to folder should work");
+        let threads_in_folder = database
+            .get_threads(ThreadFilterOptions {
+                folder_id: Some(work_folder.id),
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting threads in folder should work");
+        assert_eq!(threads_in_folder.len(), 1);
+        assert_eq!(threads_in_folder[0].id, first_thread.id);
+        // Moving a thread does not change its position in the thread list
+        assert_eq!(
+            threads_in_folder[0].last_updated_at,
+            first_thread.last_updated_at
+        );
+
+        database
+            .delete_folder(work_folder.id)
+            .await
+            .expect("Deleting folder should work");
+        let threads = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting threads should work");
+        assert_eq!(threads.len(), 2);
+        assert!(threads.iter().all(|thread| thread.folder_id.is_none()));
+        assert_eq!(
+            database
+                .get_thread(second_thread.id)
+                .await
+                .expect("Getting thread should work")
+                .folder_id,
+            None
+        );
+        assert_eq!(
+            database
+                .get_folders()
+                .await
+                .expect("Getting folders should work")
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_deleting_thread_deletes_its_messages_and_tags() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let other_thread = database
+            .create_thread("Other Thread Title")
+            .await
+            .expect("Creating thread should work");
+        database
+            .create_message(thread.id, String::from("Hello"), Role::User)
+            .await
+            .expect("Creating message should work");
+        let tag = database
+            .create_tag("work")
+            .await
+            .expect("Creating tag should work");
+        database
+            .add_tag_to_thread(thread.id, tag.id)
+            .await
+            .expect("Tagging thread should work");
+
+        database
+            .purge_thread(thread.id)
+            .await
+            .expect("Purging thread should work");
+
+        let n_messages = schema::messages::table
+            .filter(schema::messages::thread_id.eq(thread.id))
+            .count()
+            .get_result::<i64>(
+                &mut *database
+                    .pool
+                    .get()
+                    .await
+                    .expect("Getting a connection should work"),
+            )
+            .await
+            .expect("Counting messages should work");
+        assert_eq!(n_messages, 0);
+        let thread_tags = database
+            .get_all_thread_tags()
+            .await
+            .expect("Getting all thread tags should work");
+        assert!(thread_tags.is_empty());
+
+        // Other threads are left untouched
+        let other_messages = database
+            .get_messages(other_thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(other_messages.len(), 1);
+        let tags = database.get_tags().await.expect("Getting tags should work");
+        assert_eq!(tags.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_creating_message_for_missing_thread_fails() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let result = database
+            .create_message(42, String::from("Hello"), Role::User)
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failing_migration_returns_error() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        revert_migrations_down_to(&database_wrapper.database_filepath, "20250607090000").await;
+        // The reverted migration cannot create the table again
+        diesel::sql_query("CREATE TABLE folders (id INTEGER PRIMARY KEY)")
+            .execute(
+                &mut *database_wrapper
+                    .database
+                    .pool
+                    .get()
+                    .await
+                    .expect("Getting a connection should work"),
+            )
+            .await
+            .expect("Creating table should work");
+
+        assert!(database_wrapper.database.run_migrations().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migration_removes_orphaned_messages() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        // Revert the migration that adds cascading deletes
+        revert_migrations_down_to(&database_wrapper.database_filepath, "20250405091000").await;
+
+        // Raw queries are used because the schema differs from the current one
+        let database = &mut database_wrapper.database;
+        for query in [
+            "INSERT INTO threads (id, title) VALUES (1, 'Deleted Thread')",
+            "INSERT INTO threads (id, title) VALUES (2, 'Other Thread')",
+            "INSERT INTO messages (thread_id, content, role) VALUES (1, 'Hello', 'user')",
+            "INSERT INTO messages (thread_id, content, role) VALUES (2, 'Hello', 'user')",
+            // Simulate a thread deleted before foreign keys were enforced
+            "PRAGMA foreign_keys = OFF",
+            "DELETE FROM threads WHERE id = 1",
+        ] {
+            diesel::sql_query(query)
+                .execute(
+                    &mut *database
+                        .pool
+                        .get()
+                        .await
+                        .expect("Getting a connection should work"),
+                )
+                .await
+                .expect("Executing query should work");
+        }
+        let n_messages = schema::messages::table
+            .count()
+            .get_result::<i64>(
+                &mut *database
+                    .pool
+                    .get()
+                    .await
+                    .expect("Getting a connection should work"),
+            )
+            .await
+            .expect("Counting messages should work");
+        assert_eq!(n_messages, 2);
+
+        database
+            .run_migrations()
+            .await
+            .expect("Migrations should work");
+
+        let messages = database
+            .get_messages(2)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages.len(), 1);
+        let n_messages = schema::messages::table
+            .count()
+            .get_result::<i64>(
+                &mut *database
+                    .pool
+                    .get()
+                    .await
+                    .expect("Getting a connection should work"),
+            )
+            .await
+            .expect("Counting messages should work");
+        assert_eq!(n_messages, 1);
+    }
+
+    #[tokio::test]
+    async fn test_restoring_deleted_thread() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        assert!(thread.deleted_at.is_none());
+
+        database
+            .delete_thread(thread.id)
+            .await
+            .expect("Deleting thread should work");
+
+        let trashed_threads = database
+            .get_threads(ThreadFilterOptions {
+                trashed: true,
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting trashed threads should work");
+        assert_eq!(trashed_threads.len(), 1);
+        assert_eq!(trashed_threads[0].id, thread.id);
+        assert!(trashed_threads[0].deleted_at.is_some());
+        // Messages of deleted threads are kept until the thread is purged
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages.len(), 1);
+
+        database
+            .restore_thread(thread.id)
+            .await
+            .expect("Restoring thread should work");
+
+        let threads = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting all threads should work");
+        assert_eq!(threads.len(), 1);
+        assert!(threads[0].deleted_at.is_none());
+        let n_trashed_threads = database
+            .get_threads(ThreadFilterOptions {
+                trashed: true,
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting trashed threads should work")
+            .len();
+        assert_eq!(n_trashed_threads, 0);
+    }
+
+    #[tokio::test]
+    async fn test_purging_deleted_threads_after_retention_period() {
+        use self::schema::threads::dsl;
+
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let old_thread = database
+            .create_thread("Old Thread")
+            .await
+            .expect("Creating thread should work");
+        let recent_thread = database
+            .create_thread("Recent Thread")
+            .await
+            .expect("Creating thread should work");
+        let active_thread = database
+            .create_thread("Active Thread")
+            .await
+            .expect("Creating thread should work");
+
+        database
+            .delete_thread(old_thread.id)
+            .await
+            .expect("Deleting thread should work");
+        database
+            .delete_thread(recent_thread.id)
+            .await
+            .expect("Deleting thread should work");
+        diesel::update(dsl::threads.find(old_thread.id))
+            .set(dsl::deleted_at.eq(Some(Utc::now().naive_utc() - Duration::days(40))))
+            .execute(
+                &mut *database
+                    .pool
+                    .get()
+                    .await
+                    .expect("Getting a connection should work"),
+            )
+            .await
+            .expect("Updating deletion date should work");
+
+        let n_purged = database
+            .purge_deleted_threads(Duration::days(30))
+            .await
+            .expect("Purging threads should work");
+        assert_eq!(n_purged, 1);
+
+        let trashed_threads = database
+            .get_threads(ThreadFilterOptions {
+                trashed: true,
+                ..ThreadFilterOptions::default()
+            })
+            .await
+            .expect("Getting trashed threads should work");
+        assert_eq!(trashed_threads.len(), 1);
+        assert_eq!(trashed_threads[0].id, recent_thread.id);
+        assert!(database.get_thread(old_thread.id).await.is_err());
+        let messages = database
+            .get_messages(old_thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert!(messages.is_empty());
+
+        let threads = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting all threads should work");
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].id, active_thread.id);
+    }
+
+    #[tokio::test]
+    async fn test_saving_drafts() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let other_thread = database
+            .create_thread("Other Thread Title")
+            .await
+            .expect("Creating thread should work");
+
+        let draft = database
+            .get_draft(thread.id)
+            .await
+            .expect("Getting draft should work");
+        assert!(draft.is_none());
+
+        database
+            .save_draft(thread.id, "Unfinished\nprompt")
+            .await
+            .expect("Saving draft should work");
+        database
+            .save_draft(thread.id, "Unfinished\nprompt, continued")
+            .await
+            .expect("Saving draft twice should work");
+        let draft = database
+            .get_draft(thread.id)
+            .await
+            .expect("Getting draft should work");
+        assert_eq!(draft.as_deref(), Some("Unfinished\nprompt, continued"));
+        let other_draft = database
+            .get_draft(other_thread.id)
+            .await
+            .expect("Getting draft should work");
+        assert!(other_draft.is_none());
+
+        database
+            .save_draft(thread.id, "")
+            .await
+            .expect("Clearing draft should work");
+        let draft = database
+            .get_draft(thread.id)
+            .await
+            .expect("Getting draft should work");
+        assert!(draft.is_none());
+
+        database
+            .save_draft(other_thread.id, "Draft")
+            .await
+            .expect("Saving draft should work");
+        database
+            .purge_thread(other_thread.id)
+            .await
+            .expect("Purging thread should work");
+        let other_draft = database
+            .get_draft(other_thread.id)
+            .await
+            .expect("Getting draft should work");
+        assert!(other_draft.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_getting_user_prompts() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        for content in ["First", "Second", "Third"] {
+            database
+                .create_message(thread.id, String::from(content), Role::User)
+                .await
+                .expect("Creating message should work");
+            database
+                .create_message(thread.id, String::from("Answer"), Role::Assistant)
+                .await
+                .expect("Creating message should work");
+        }
+
+        let prompts = database
+            .get_user_prompts(2)
+            .await
+            .expect("Getting user prompts should work");
+        assert_eq!(prompts, vec!["Second", "Third"]);
+    }
+
+    #[tokio::test]
+    async fn test_saving_and_deleting_prompt_templates() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+
+        database
+            .save_prompt_templates(&[
+                NewPromptTemplate {
+                    name: "review",
+                    content: "Review this diff:\n{{diff}}",
+                },
+                NewPromptTemplate {
+                    name: "tests",
+                    content: "Write tests for {{code}}",
+                },
+            ])
+            .await
+            .expect("Saving prompt templates should work");
+        database
+            .save_prompt_templates(&[NewPromptTemplate {
+                name: "review",
+                content: "Review this {{language}} diff:\n{{diff}}",
+            }])
+            .await
+            .expect("Saving prompt template with existing name should work");
+
+        let prompt_templates = database
+            .get_prompt_templates()
+            .await
+            .expect("Getting prompt templates should work");
+        assert_eq!(prompt_templates.len(), 2);
+        assert_eq!(prompt_templates[0].name, "review");
+        assert_eq!(
+            prompt_templates[0].content,
+            "Review this {{language}} diff:\n{{diff}}"
+        );
+        assert_eq!(prompt_templates[1].name, "tests");
+
+        database
+            .delete_prompt_template(prompt_templates[0].id)
+            .await
+            .expect("Deleting prompt template should work");
+        let prompt_templates = database
+            .get_prompt_templates()
+            .await
+            .expect("Getting prompt templates should work");
+        assert_eq!(prompt_templates.len(), 1);
+        assert_eq!(prompt_templates[0].name, "tests");
+    }
+
+    #[tokio::test]
+    async fn test_saving_prompt_templates_is_atomic() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        diesel::sql_query(
+            "CREATE TRIGGER reject_broken_template BEFORE INSERT ON prompt_templates
+            WHEN NEW.name = 'broken'
+            BEGIN
+                SELECT RAISE(ABORT, 'broken template');
+            END",
+        )
+        .execute(
+            &mut *database
+                .pool
+                .get()
+                .await
+                .expect("Getting a connection should work"),
+        )
+        .await
+        .expect("Creating trigger should work");
+
+        let result = database
+            .save_prompt_templates(&[
+                NewPromptTemplate {
+                    name: "review",
+                    content: "Review this diff:\n{{diff}}",
+                },
+                NewPromptTemplate {
+                    name: "broken",
+                    content: "Fails to be saved",
+                },
+            ])
+            .await;
+        assert!(result.is_err(), "Saving broken template should fail");
+        let prompt_templates = database
+            .get_prompt_templates()
+            .await
+            .expect("Getting prompt templates should work");
+        assert!(
+            prompt_templates.is_empty(),
+            "No template should be saved when one of them fails"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_editing_and_deleting_messages() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let message = database
+            .create_message(thread.id, String::from("Original"), Role::User)
+            .await
+            .expect("Creating message should work");
+        let other_message = database
+            .create_message(thread.id, String::from("Answer"), Role::Assistant)
+            .await
+            .expect("Creating message should work");
+
+        database
+            .edit_message(message.id, String::from("Edited"))
+            .await
+            .expect("Editing message should work");
+        database
+            .delete_message(other_message.id)
+            .await
+            .expect("Deleting message should work");
+
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        // The system message is created along with the thread
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].id, message.id);
+        assert_eq!(messages[1].content, "Edited");
+    }
+
+    #[tokio::test]
+    async fn test_updating_missing_message_fails() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+        database
+            .append_to_message(message.id, "Partial")
+            .await
+            .expect("Updating message should work");
+
+        database
+            .purge_thread(thread.id)
+            .await
+            .expect("Purging thread should work");
+        let result = database.append_to_message(message.id, " answer").await;
+        assert!(result.is_err());
+        let result = database.complete_message(message.id).await;
+        assert!(result.is_err());
+        let result = database.delete_message(message.id).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_message_writer_buffers_content() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+        let mut writer = message_writer::MessageWriter::new(thread.id, message.id);
+
+        writer
+            .push(database, String::from("Hello"))
+            .await
+            .expect("Pushing content should work");
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages[1].content, "");
+        assert!(!messages[1].complete);
+
+        // Content above the flush size is written right away
+        let long_content = "a".repeat(2048);
+        writer
+            .push(database, long_content.clone())
+            .await
+            .expect("Pushing content should work");
+        writer
+            .push(database, String::from(" world"))
+            .await
+            .expect("Pushing content should work");
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages[1].content, format!("Hello{long_content}"));
+
+        writer
+            .finish(database)
+            .await
+            .expect("Finishing message should work");
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages[1].content, format!("Hello{long_content} world"));
+        assert!(messages[1].complete);
+    }
+
+    #[tokio::test]
+    async fn test_message_writer_flushes_stalled_stream() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+        let mut writer = message_writer::MessageWriter::new(thread.id, message.id);
+
+        let mut stream = Box::pin(futures::StreamExt::chain(
+            futures::stream::iter(["Hello"]),
+            futures::stream::once(async {
+                tokio::time::sleep(std::time::Duration::from_millis(700)).await;
+                " world"
+            }),
+        ));
+        let chunk = writer
+            .next_chunk(database, &mut stream)
+            .await
+            .expect("Waiting for chunk should work");
+        assert_eq!(chunk, Some("Hello"));
+        writer
+            .push(database, String::from("Hello"))
+            .await
+            .expect("Pushing content should work");
+
+        // The buffered content is written while the stream stalls
+        let chunk = writer
+            .next_chunk(database, &mut stream)
+            .await
+            .expect("Waiting for chunk should work");
+        assert_eq!(chunk, Some(" world"));
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages[1].content, "Hello");
+        assert!(!messages[1].complete);
+    }
+
+    #[tokio::test]
+    async fn test_encrypting_database() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        let database_url = database_wrapper.database_filepath.clone();
+        let thread = database
+            .create_thread("Secret Thread")
+            .await
+            .expect("Creating thread should work");
+        assert!(!database
+            .is_encrypted()
+            .expect("Checking encryption should work"));
+
+        database
+            .enable_encryption("passphrase")
+            .await
+            .expect("Encrypting database should work");
+        assert!(database
+            .is_encrypted()
+            .expect("Checking encryption should work"));
+        let header = std::fs::read(&database_url).expect("Reading database file should work");
+        assert!(!header.starts_with(b"SQLite format 3"));
+        assert_eq!(
+            database
+                .get_thread(thread.id)
+                .await
+                .expect("Getting thread should work")
+                .title,
+            "Secret Thread"
+        );
+
+        // Another instance can only read the database once unlocked
+        let other_database = Database::new(database_url.clone())
+            .await
+            .expect("Instantiating database should work");
+        assert!(other_database.get_thread(thread.id).await.is_err());
+        assert!(other_database.unlock("wrong passphrase").await.is_err());
+        other_database
+            .unlock("passphrase")
+            .await
+            .expect("Unlocking database should work");
+        assert!(other_database.get_thread(thread.id).await.is_ok());
+        drop(other_database);
+
+        assert!(database
+            .change_passphrase("wrong passphrase", "new passphrase")
+            .await
+            .is_err());
+        database
+            .change_passphrase("passphrase", "new passphrase")
+            .await
+            .expect("Changing passphrase should work");
+        assert!(database.get_thread(thread.id).await.is_ok());
+
+        database
+            .disable_encryption("new passphrase")
+            .await
+            .expect("Decrypting database should work");
+        assert!(!database
+            .is_encrypted()
+            .expect("Checking encryption should work"));
+        let header = std::fs::read(&database_url).expect("Reading database file should work");
+        assert!(header.starts_with(b"SQLite format 3"));
+        assert!(database.get_thread(thread.id).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_recovering_interrupted_database_replacement() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database_url = database_wrapper.database_filepath.clone();
+
+        // Stopped before the database file was replaced
+        let exported_database_url = format!("{database_url}{EXPORTED_DATABASE_SUFFIX}");
+        std::fs::write(&exported_database_url, b"").expect("Writing file should work");
+        EncryptionSettings::stage(Some(&EncryptionSettings::generate()), &database_url)
+            .expect("Staging settings should work");
+        let database = Database::new(database_url.clone())
+            .await
+            .expect("Instantiating database should work");
+        assert!(!database
+            .is_encrypted()
+            .expect("Checking encryption should work"));
+        assert!(!EncryptionSettings::is_staged(&database_url));
+        assert!(!std::path::Path::new(&exported_database_url).exists());
+
+        // Stopped after the database file was replaced
+        EncryptionSettings::stage(Some(&EncryptionSettings::generate()), &database_url)
+            .expect("Staging settings should work");
+        let database = Database::new(database_url.clone())
+            .await
+            .expect("Instantiating database should work");
+        assert!(database
+            .is_encrypted()
+            .expect("Checking encryption should work"));
+        assert!(!EncryptionSettings::is_staged(&database_url));
+        EncryptionSettings::remove(&database_url).expect("Removing settings should work");
+    }
+
+    #[tokio::test]
+    async fn test_backing_up_and_restoring_database() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        let backed_up_thread = database
+            .create_thread("Backed Up Thread")
+            .await
+            .expect("Creating thread should work");
+
+        let backup = database
+            .backup_if_due()
+            .await
+            .expect("Backing up database should work")
+            .expect("A backup should be made when there is none");
+        assert!(database
+            .backup_if_due()
+            .await
+            .expect("Backing up database should work")
+            .is_none());
+        let new_thread = database
+            .create_thread("New Thread")
+            .await
+            .expect("Creating thread should work");
+
+        database
+            .restore_backup(backup.path.clone(), None)
+            .await
+            .expect("Restoring backup should work");
+        assert!(database.get_thread(backed_up_thread.id).await.is_ok());
+        assert!(database.get_thread(new_thread.id).await.is_err());
+
+        // Only the most recent backups are kept
+        for _ in 0..backup::MAX_BACKUPS {
+            database
+                .backup()
+                .await
+                .expect("Backing up database should work");
+        }
+        let backups = database
+            .list_backups()
+            .expect("Listing backups should work");
+        assert_eq!(backups.len(), backup::MAX_BACKUPS);
+        assert!(!backups.contains(&backup));
+        assert!(!backup.path.exists());
+
+        for backup in backups {
+            std::fs::remove_file(backup.path).expect("Deleting backup should work");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_restoring_backup_from_newer_version_fails() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        let thread = database
+            .create_thread("Thread")
+            .await
+            .expect("Creating thread should work");
+        diesel::sql_query(
+            "INSERT INTO __diesel_schema_migrations (version) VALUES ('29991231000000')",
+        )
+        .execute(
+            &mut *database
+                .pool
+                .get()
+                .await
+                .expect("Getting a connection should work"),
+        )
+        .await
+        .expect("Inserting migration version should work");
+        let backup = database
+            .backup()
+            .await
+            .expect("Backing up database should work");
+        database
+            .delete_thread(thread.id)
+            .await
+            .expect("Deleting thread should work");
+
+        let result = database.restore_backup(backup.path.clone(), None).await;
+        assert!(result.is_err());
+        // The database is left untouched
+        assert!(database
+            .get_thread(thread.id)
+            .await
+            .expect("Getting thread should work")
+            .deleted_at
+            .is_some());
+
+        std::fs::remove_file(backup.path).expect("Deleting backup should work");
+    }
+
+    fn new_backend(name: &str, kind: &str) -> NewBackend {
+        NewBackend {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            base_url: String::from("http://localhost:8080"),
+            api_key: None,
+            connect_timeout_seconds: 10,
+            first_byte_timeout_seconds: 300,
+            idle_timeout_seconds: 60,
+            default_model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_managing_backends() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        let backends = database
+            .get_backends()
+            .await
+            .expect("Getting backends should work");
+        assert_eq!(backends.len(), 1);
+        assert_eq!(backends[0].kind, "ollama");
+        assert!(backends[0].active);
+        let ollama_id = backends[0].id;
+
+        let backend = database
+            .create_backend(&new_backend("GPU server", "openai"))
+            .await
+            .expect("Creating backend should work");
+        assert!(!backend.active);
+        database
+            .create_backend(&new_backend("GPU server", "ollama"))
+            .await
+            .expect_err("Creating backend with existing name should fail");
+
+        let mut updated_backend = new_backend("GPU server", "openai");
+        updated_backend.api_key = Some(String::from("secret"));
+        updated_backend.default_model = Some(String::from("qwen3:8b"));
+        database
+            .update_backend(backend.id, &updated_backend)
+            .await
+            .expect("Updating backend should work");
+        database
+            .set_active_backend(backend.id)
+            .await
+            .expect("Activating backend should work");
+        let backends = database
+            .get_backends()
+            .await
+            .expect("Getting backends should work");
+        assert_eq!(backends[0].api_key.as_deref(), Some("secret"));
+        assert_eq!(backends[0].default_model.as_deref(), Some("qwen3:8b"));
+        assert!(backends[0].active);
+        assert!(!backends[1].active);
+
+        database
+            .set_backend_base_url(ollama_id, " http://192.168.1.20:11434 ")
+            .await
+            .expect("Changing backend address should work");
+        let backends = database
+            .get_backends()
+            .await
+            .expect("Getting backends should work");
+        assert_eq!(backends[1].base_url, "http://192.168.1.20:11434");
+        database
+            .set_backend_base_url(-1, "http://localhost:11434")
+            .await
+            .expect_err("Changing address of missing backend should fail");
+
+        database
+            .delete_backend(backend.id)
+            .await
+            .expect("Deleting backend should work");
+        let backends = database
+            .get_backends()
+            .await
+            .expect("Getting backends should work");
+        assert_eq!(backends.len(), 1);
+        assert_eq!(backends[0].id, ollama_id);
+        assert!(backends[0].active);
+        database
+            .delete_backend(ollama_id)
+            .await
+            .expect_err("Deleting last backend should fail");
+    }
+
+    #[tokio::test]
+    async fn test_setting_thread_backend() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        let thread = database
+            .create_thread("Thread")
+            .await
+            .expect("Creating thread should work");
+        assert_eq!(thread.backend_id, None);
+
+        let backend = database
+            .create_backend(&new_backend("llama.cpp", "openai"))
+            .await
+            .expect("Creating backend should work");
+        database
+            .set_thread_backend(thread.id, Some(backend.id))
+            .await
+            .expect("Setting thread backend should work");
+        let thread = database
+            .get_thread(thread.id)
+            .await
+            .expect("Getting thread should work");
+        assert_eq!(thread.backend_id, Some(backend.id));
+
+        database
+            .delete_backend(backend.id)
+            .await
+            .expect("Deleting backend should work");
+        let thread = database
+            .get_thread(thread.id)
+            .await
+            .expect("Getting thread should work");
+        assert_eq!(thread.backend_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_structured_output() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        let thread = database
+            .create_thread("Thread")
+            .await
+            .expect("Creating thread should work");
+        assert_eq!(thread.response_format, None);
+        database
+            .set_thread_response_format(thread.id, Some("json"))
+            .await
+            .expect("Setting thread response format should work");
+        let thread = database
+            .get_thread(thread.id)
+            .await
+            .expect("Getting thread should work");
+        assert_eq!(thread.response_format.as_deref(), Some("json"));
+
+        let message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+        assert_eq!(message.format_error, None);
+        database
+            .set_message_format_error(message.id, "The answer is not valid JSON")
+            .await
+            .expect("Flagging message should work");
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        let message = messages
+            .iter()
+            .find(|other_message| other_message.id == message.id)
+            .expect("Message should exist");
+        assert_eq!(
+            message.format_error.as_deref(),
+            Some("The answer is not valid JSON")
+        );
+
+        // Edited answers are not flagged anymore
+        database
+            .edit_message(message.id, String::from("{}"))
+            .await
+            .expect("Editing message should work");
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert!(messages
+            .iter()
+            .all(|message| message.format_error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_recovering_partial_messages() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let empty_message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+        let partial_message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+        database
+            .append_to_message(partial_message.id, "Interrupted answer")
+            .await
+            .expect("Updating message should work");
+
+        let n_recovered = database
+            .recover_partial_messages()
+            .await
+            .expect("Recovering partial messages should work");
+
+        assert_eq!(n_recovered, 1);
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert_eq!(messages.len(), 2);
+        assert!(messages
+            .iter()
+            .all(|message| message.id != empty_message.id));
+        assert_eq!(messages[1].id, partial_message.id);
+        assert_eq!(messages[1].content, "Interrupted answer");
+        assert!(messages[1].complete);
+    }
+
+    #[tokio::test]
+    async fn test_loading_threads_during_write_transaction() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        database
+            .create_thread("Existing Thread")
+            .await
+            .expect("Creating thread should work");
+
+        // Keep a write transaction open on one of the connections
+        let mut writing_connection = database
+            .pool
+            .get()
+            .await
+            .expect("Getting a connection should work");
+        for query in [
+            "BEGIN IMMEDIATE",
+            "INSERT INTO threads (title) VALUES ('Uncommitted Thread')",
+        ] {
+            diesel::sql_query(query)
+                .execute(&mut *writing_connection)
+                .await
+                .expect("Executing query should work");
+        }
+
+        // Reading does not wait for the transaction to end
+        let threads = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            database.get_threads(ThreadFilterOptions::default()),
+        )
+        .await
+        .expect("Getting threads should not wait for the write transaction")
+        .expect("Getting threads should work");
+        assert_eq!(threads.len(), 1);
+        assert_eq!(threads[0].title, "Existing Thread");
+
+        diesel::sql_query("COMMIT")
+            .execute(&mut *writing_connection)
+            .await
+            .expect("Committing transaction should work");
+        drop(writing_connection);
+        let threads = database
+            .get_threads(ThreadFilterOptions::default())
+            .await
+            .expect("Getting threads should work");
+        assert_eq!(threads.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_loading_threads_while_streaming_answer() {
+        let database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &database_wrapper.database;
+        for i in 0..50 {
+            database
+                .create_thread(&format!("Thread {i}"))
+                .await
+                .expect("Creating thread should work");
+        }
+        let thread = database
+            .create_thread("Streaming Thread")
+            .await
+            .expect("Creating thread should work");
+        let message = database
+            .create_partial_message(thread.id, Role::Assistant)
+            .await
+            .expect("Creating message should work");
+
+        let streaming_done = std::cell::Cell::new(false);
+        let streaming = async {
+            let mut writer = message_writer::MessageWriter::new(thread.id, message.id);
+            // Every chunk is large enough to be written to the database right away
+            for _ in 0..200 {
+                writer
+                    .push(database, "a".repeat(2048))
+                    .await
+                    .expect("Pushing content should work");
+            }
+            writer
+                .finish(database)
+                .await
+                .expect("Finishing message should work");
+            streaming_done.set(true);
+        };
+        let loading = async {
+            let mut loads_while_streaming = 0;
+            for _ in 0..50 {
+                let threads = database
+                    .get_threads(ThreadFilterOptions::default())
+                    .await
+                    .expect("Getting threads should work");
+                assert_eq!(threads.len(), 51);
+                if !streaming_done.get() {
+                    loads_while_streaming += 1;
+                }
+                tokio::task::yield_now().await;
+            }
+            loads_while_streaming
+        };
+        let ((), loads_while_streaming) = tokio::join!(streaming, loading);
+        // Reading is not blocked until the answer is written completely
+        assert!(loads_while_streaming > 0);
+
+        let messages = database
+            .get_messages(thread.id)
+            .await
+            .expect("Getting messages should work");
+        assert!(messages[1].complete);
+        assert_eq!(messages[1].content.len(), 200 * 2048);
+    }
+
+    #[tokio::test]
+    async fn test_getting_messages_page() {
+        let mut database_wrapper = TestDatabaseWrapper::setup().await;
+        let database = &mut database_wrapper.database;
+        let thread = database
+            .create_thread("Test Thread Title")
+            .await
+            .expect("Creating thread should work");
+        let other_thread = database
+            .create_thread("Other Thread Title")
+            .await
+            .expect("Creating thread should work");
+        for i in 0..10 {
+            database
+                .create_message(thread.id, format!("Message {i}"), Role::User)
+                .await
+                .expect("Creating message should work");
+            database
+                .create_message(other_thread.id, format!("Other message {i}"), Role::User)
+                .await
+                .expect("Creating message should work");
+        }
+
+        let latest_messages = database
+            .get_messages_page(thread.id, None, 4)
+            .await
+            .expect("Getting messages page should work");
+        let contents: Vec<&str> = latest_messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            vec!["Message 6", "Message 7", "Message 8", "Message 9"]
+        );
+
+        let older_messages = database
+            .get_messages_page(thread.id, Some(latest_messages[0].id), 4)
+            .await
+            .expect("Getting messages page should work");
+        let contents: Vec<&str> = older_messages
+            .iter()
+            .map(|message| message.content.as_str())
+            .collect();
+        assert_eq!(
+            contents,
+            vec!["Message 2", "Message 3", "Message 4", "Message 5"]
+        );
+
+        // The first page also contains the system message
+        let first_messages = database
+            .get_messages_page(thread.id, Some(older_messages[0].id), 4)
+            .await
+            .expect("Getting messages page should work");
+        assert_eq!(first_messages.len(), 3);
+        assert_eq!(first_messages[0].role, "system");
+        assert_eq!(first_messages[2].content, "Message 1");
+    }
 }
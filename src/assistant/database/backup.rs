@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DurationRound, Local, NaiveDateTime};
+use diesel::migration::MigrationSource;
+use diesel::sqlite::Sqlite;
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags};
+
+use super::encryption::{DatabaseKey, EncryptionSettings};
+use super::MIGRATIONS;
+
+/// Number of backups kept for a database, older ones being deleted.
+pub const MAX_BACKUPS: usize = 7;
+/// Time after which a new backup is made automatically.
+pub const BACKUP_INTERVAL: chrono::Duration = chrono::Duration::days(1);
+
+const BACKUPS_DIR_NAME: &str = "backups";
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S%3f";
+/// Number of pages copied at once, letting other connections use the database in between.
+const PAGES_PER_STEP: i32 = 128;
+const PAUSE_BETWEEN_STEPS: Duration = Duration::from_millis(10);
+
+/// Backup file of a database.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupFile {
+    pub path: PathBuf,
+    pub created_at: NaiveDateTime,
+}
+
+/// Whether a backup was made from an encrypted database, its passphrase being needed to restore it.
+pub fn is_backup_encrypted(backup_path: &Path) -> Result<bool> {
+    Ok(EncryptionSettings::read(&path_to_url(backup_path)?)?.is_some())
+}
+
+/// Returns the directory containing the backups of a database, next to it.
+fn backups_dir(database_url: &str) -> PathBuf {
+    let database_path = Path::new(database_url);
+    database_path
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(BACKUPS_DIR_NAME)
+}
+
+/// Backups of different databases in the same directory are told apart by their prefix.
+fn backup_prefix(database_url: &str) -> String {
+    let stem = Path::new(database_url)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("database");
+    format!("{stem}-")
+}
+
+fn backup_path(backups_dir: &Path, database_url: &str, created_at: NaiveDateTime) -> PathBuf {
+    backups_dir.join(format!(
+        "{}{}.db",
+        backup_prefix(database_url),
+        created_at.format(TIMESTAMP_FORMAT)
+    ))
+}
+
+/// Returns the backups of a database, most recent first.
+pub fn list_backups(database_url: &str) -> Result<Vec<BackupFile>> {
+    let backups_dir = backups_dir(database_url);
+    if !backups_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let prefix = backup_prefix(database_url);
+    let mut backups: Vec<BackupFile> = std::fs::read_dir(backups_dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "db"))
+        .filter_map(|path| {
+            let timestamp = path.file_stem()?.to_str()?.strip_prefix(&prefix)?;
+            let created_at = NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+            Some(BackupFile { path, created_at })
+        })
+        .collect();
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+/// Copies a database with the online backup API of SQLite, which can be done while
+/// it is being used, then deletes the oldest backups.
+///
+/// The backup of an encrypted database is encrypted with the same key.
+pub fn create_backup(database_url: &str, key: Option<&DatabaseKey>) -> Result<BackupFile> {
+    let backups_dir = backups_dir(database_url);
+    std::fs::create_dir_all(&backups_dir)?;
+    // Truncated to the precision of the file name, so that it is the time listed afterwards
+    let mut created_at = Local::now()
+        .naive_local()
+        .duration_trunc(chrono::Duration::milliseconds(1))?;
+    let mut path = backup_path(&backups_dir, database_url, created_at);
+    // Backups made in the same millisecond must not overwrite each other
+    while path.exists() {
+        created_at += chrono::Duration::milliseconds(1);
+        path = backup_path(&backups_dir, database_url, created_at);
+    }
+    let backup_url = path_to_url(&path)?;
+
+    let source = open(database_url, key, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut destination = open(&backup_url, key, OpenFlags::default())?;
+    let result = Backup::new(&source, &mut destination)
+        .and_then(|backup| backup.run_to_completion(PAGES_PER_STEP, PAUSE_BETWEEN_STEPS, None))
+        // The copy is in write-ahead log mode like the database,
+        // which would leave extra files next to it when it is read
+        .and_then(|_| destination.pragma_update(None, "journal_mode", "DELETE"));
+    drop(destination);
+    if let Err(error) = result {
+        let _ = std::fs::remove_file(&path);
+        bail!("Backing up database failed because of: {error}");
+    }
+    if let Some(settings) = EncryptionSettings::read(database_url)? {
+        settings.write(&backup_url)?;
+    }
+
+    for backup in list_backups(database_url)?.into_iter().skip(MAX_BACKUPS) {
+        tracing::info!("Deleting old backup {}", backup.path.display());
+        std::fs::remove_file(&backup.path)?;
+        EncryptionSettings::remove(&path_to_url(&backup.path)?)?;
+    }
+    Ok(BackupFile { path, created_at })
+}
+
+/// Checks that a backup is a valid chat database that this version of the application can use,
+/// meaning that it does not contain migrations unknown to it.
+pub fn validate_backup(backup_path: &Path, key: Option<&DatabaseKey>) -> Result<()> {
+    let connection = open(
+        &path_to_url(backup_path)?,
+        key,
+        OpenFlags::SQLITE_OPEN_READ_ONLY,
+    )?;
+    let integrity: String = connection
+        .query_row("PRAGMA integrity_check", [], |row| row.get(0))
+        .map_err(|error| anyhow!("The backup cannot be read: {error}"))?;
+    if integrity != "ok" {
+        bail!("The backup is corrupted: {integrity}");
+    }
+
+    let known_versions: HashSet<String> = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+        .map_err(|error| anyhow!("Reading migrations failed because of: {error}"))?
+        .iter()
+        .map(|migration| migration.name().version().to_string())
+        .collect();
+    let applied_versions: Vec<String> = connection
+        .prepare("SELECT version FROM __diesel_schema_migrations")
+        .and_then(|mut statement| {
+            statement
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()
+        })
+        .map_err(|_| anyhow!("The backup is not a chat database"))?;
+    if let Some(version) = applied_versions
+        .iter()
+        .find(|version| !known_versions.contains(*version))
+    {
+        bail!("The backup was made by a newer version of the application (migration {version})");
+    }
+    Ok(())
+}
+
+fn open(database_url: &str, key: Option<&DatabaseKey>, flags: OpenFlags) -> Result<Connection> {
+    let connection = Connection::open_with_flags(database_url, flags)?;
+    if let Some(key) = key {
+        connection.execute_batch(&format!("PRAGMA key = {}", key.sql_literal()))?;
+    }
+    connection.busy_timeout(Duration::from_secs(5))?;
+    Ok(connection)
+}
+
+fn path_to_url(path: &Path) -> Result<String> {
+    path.to_str()
+        .map(String::from)
+        .ok_or_else(|| anyhow!("Path {path:?} is not valid UTF-8"))
+}
@@ -0,0 +1,269 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+const SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+
+/// Argon2id parameters recommended by OWASP.
+const DEFAULT_MEMORY_COST_KIB: u32 = 19 * 1024;
+const DEFAULT_ITERATIONS: u32 = 2;
+const DEFAULT_PARALLELISM: u32 = 1;
+
+/// Key derivation settings of an encrypted database, stored next to it.
+///
+/// The existence of this file is what marks a database as encrypted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionSettings {
+    /// Hexadecimal encoded salt.
+    pub salt: String,
+    pub memory_cost_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl EncryptionSettings {
+    /// Returns settings with a new random salt.
+    pub fn generate() -> Self {
+        let mut salt = [0u8; SALT_LENGTH];
+        rand::rng().fill_bytes(&mut salt);
+        Self {
+            salt: encode_hex(&salt),
+            memory_cost_kib: DEFAULT_MEMORY_COST_KIB,
+            iterations: DEFAULT_ITERATIONS,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+
+    fn path(database_url: &str) -> PathBuf {
+        PathBuf::from(format!("{database_url}.encryption.json"))
+    }
+
+    /// Reads the settings of a database, `None` meaning that it is not encrypted.
+    pub fn read(database_url: &str) -> Result<Option<Self>> {
+        let path = Self::path(database_url);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(path)?;
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    pub fn write(&self, database_url: &str) -> Result<()> {
+        let path = Self::path(database_url);
+        // Write to a temporary file first so that the settings are never partially written
+        let temporary_path = path.with_extension("json.tmp");
+        std::fs::write(&temporary_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(temporary_path, path)?;
+        Ok(())
+    }
+
+    pub fn remove(database_url: &str) -> Result<()> {
+        let path = Self::path(database_url);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn staged_path(database_url: &str) -> PathBuf {
+        PathBuf::from(format!("{database_url}.encryption.json.pending"))
+    }
+
+    /// Stages the settings of the file about to replace the database, `None` meaning
+    /// that it is not encrypted, so that they can be committed once it replaced it.
+    pub fn stage(settings: Option<&Self>, database_url: &str) -> Result<()> {
+        let path = Self::staged_path(database_url);
+        let temporary_path = path.with_extension("pending.tmp");
+        std::fs::write(&temporary_path, serde_json::to_string_pretty(&settings)?)?;
+        std::fs::rename(temporary_path, path)?;
+        Ok(())
+    }
+
+    pub fn is_staged(database_url: &str) -> bool {
+        Self::staged_path(database_url).exists()
+    }
+
+    /// Replaces the settings of the database with the staged ones.
+    pub fn commit_staged(database_url: &str) -> Result<()> {
+        let path = Self::staged_path(database_url);
+        let settings: Option<Self> = serde_json::from_slice(&std::fs::read(&path)?)?;
+        match settings {
+            Some(settings) => settings.write(database_url)?,
+            None => Self::remove(database_url)?,
+        }
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    pub fn discard_staged(database_url: &str) -> Result<()> {
+        let path = Self::staged_path(database_url);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Derives the key of the database from a passphrase with Argon2id.
+    pub fn derive_key(&self, passphrase: &str) -> Result<DatabaseKey> {
+        let salt = decode_hex(&self.salt)?;
+        let params = Params::new(
+            self.memory_cost_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LENGTH),
+        )
+        .map_err(|error| anyhow!("Invalid key derivation parameters: {error}"))?;
+        let mut key = DatabaseKey([0u8; KEY_LENGTH]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key.0)
+            .map_err(|error| anyhow!("Deriving the database key failed: {error}"))?;
+        Ok(key)
+    }
+}
+
+/// Raw SQLCipher key, erased from memory when dropped.
+#[derive(Clone, PartialEq, Zeroize, ZeroizeOnDrop)]
+pub struct DatabaseKey([u8; KEY_LENGTH]);
+
+impl DatabaseKey {
+    /// Returns the key as a SQL literal, used by `PRAGMA key` and `ATTACH ... KEY`.
+    ///
+    /// Passing a raw key skips the key derivation of SQLCipher, as it was already done.
+    pub fn sql_literal(&self) -> String {
+        format!("\"x'{}'\"", encode_hex(&self.0))
+    }
+}
+
+impl fmt::Debug for DatabaseKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "DatabaseKey(..)")
+    }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_hex(text: &str) -> Result<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return Err(anyhow!("Invalid hexadecimal string"));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| {
+            text.get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(|| anyhow!("Invalid hexadecimal string"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_encoding() {
+        let bytes = [0u8, 1, 171, 255];
+        assert_eq!(encode_hex(&bytes), "0001abff");
+        assert_eq!(
+            decode_hex("0001abff").expect("Decoding should work"),
+            bytes.to_vec()
+        );
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_deriving_key() {
+        let settings = EncryptionSettings::generate();
+        let key = settings
+            .derive_key("correct horse battery staple")
+            .expect("Deriving key should work");
+        let same_key = settings
+            .derive_key("correct horse battery staple")
+            .expect("Deriving key should work");
+        let other_key = settings
+            .derive_key("another passphrase")
+            .expect("Deriving key should work");
+        let other_salt_key = EncryptionSettings::generate()
+            .derive_key("correct horse battery staple")
+            .expect("Deriving key should work");
+
+        assert!(key == same_key);
+        assert!(key != other_key);
+        assert!(key != other_salt_key);
+        assert_eq!(key.sql_literal().len(), 2 * KEY_LENGTH + 5);
+        assert_eq!(format!("{key:?}"), "DatabaseKey(..)");
+    }
+
+    #[test]
+    fn test_writing_and_reading_settings() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("pincer_chat_encryption_{}.db", std::process::id()));
+        let database_url = path.to_str().expect("Path should be valid UTF-8");
+
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            None
+        );
+        let settings = EncryptionSettings::generate();
+        settings
+            .write(database_url)
+            .expect("Writing settings should work");
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            Some(settings)
+        );
+        EncryptionSettings::remove(database_url).expect("Removing settings should work");
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_committing_staged_settings() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("pincer_chat_staged_{}.db", std::process::id()));
+        let database_url = path.to_str().expect("Path should be valid UTF-8");
+
+        let settings = EncryptionSettings::generate();
+        EncryptionSettings::stage(Some(&settings), database_url)
+            .expect("Staging settings should work");
+        assert!(EncryptionSettings::is_staged(database_url));
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            None
+        );
+        EncryptionSettings::commit_staged(database_url).expect("Committing settings should work");
+        assert!(!EncryptionSettings::is_staged(database_url));
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            Some(settings.clone())
+        );
+
+        // Discarded settings leave the current ones untouched
+        EncryptionSettings::stage(None, database_url).expect("Staging settings should work");
+        EncryptionSettings::discard_staged(database_url).expect("Discarding settings should work");
+        assert!(!EncryptionSettings::is_staged(database_url));
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            Some(settings)
+        );
+
+        // Committing plaintext settings removes the current ones
+        EncryptionSettings::stage(None, database_url).expect("Staging settings should work");
+        EncryptionSettings::commit_staged(database_url).expect("Committing settings should work");
+        assert_eq!(
+            EncryptionSettings::read(database_url).expect("Reading settings should work"),
+            None
+        );
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use futures::{Stream, StreamExt};
+
+use super::Database;
+use crate::assistant::notification::DatabaseNotifierMessage;
+
+/// Maximum time streamed content is kept in memory before being written to the database.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+/// Size in bytes of the buffered content above which it is written to the database.
+const FLUSH_SIZE: usize = 1024;
+
+/// Writes the content of a message being streamed.
+///
+/// Every chunk is sent to the subscribers of the database notifier right away,
+/// but is only written to the database periodically to avoid an update per chunk.
+/// The message stays incomplete until [`MessageWriter::finish`] is called, so that
+/// content written before a crash can be recovered with
+/// [`Database::recover_partial_messages`].
+#[derive(Debug)]
+pub struct MessageWriter {
+    thread_id: i64,
+    message_id: i64,
+    buffer: String,
+    last_flush: Instant,
+}
+
+impl MessageWriter {
+    pub fn new(thread_id: i64, message_id: i64) -> Self {
+        Self {
+            thread_id,
+            message_id,
+            buffer: String::new(),
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub async fn push(&mut self, database: &Database, content: String) -> Result<()> {
+        self.buffer.push_str(&content);
+        database
+            .notifier
+            .notify(DatabaseNotifierMessage::UpdateMessage(
+                self.thread_id,
+                self.message_id,
+                content,
+            ));
+        if self.buffer.len() >= FLUSH_SIZE || self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush(database).await?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the next chunk of a stream, writing the buffered content to the database
+    /// whenever the stream stalls for longer than the flush interval.
+    pub async fn next_chunk<S>(
+        &mut self,
+        database: &Database,
+        stream: &mut S,
+    ) -> Result<Option<S::Item>>
+    where
+        S: Stream + Unpin,
+    {
+        loop {
+            let time_until_flush = FLUSH_INTERVAL.saturating_sub(self.last_flush.elapsed());
+            match tokio::time::timeout(time_until_flush, stream.next()).await {
+                Ok(chunk) => return Ok(chunk),
+                Err(_) => self.flush(database).await?,
+            }
+        }
+    }
+
+    /// Writes the buffered content to the database.
+    pub async fn flush(&mut self, database: &Database) -> Result<()> {
+        if !self.buffer.is_empty() {
+            database
+                .append_to_message(self.message_id, &self.buffer)
+                .await?;
+            self.buffer.clear();
+        }
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    /// Discards the content written so far.
+    pub async fn discard(&mut self, database: &Database) -> Result<()> {
+        self.buffer.clear();
+        self.last_flush = Instant::now();
+        database.edit_message(self.message_id, String::new()).await
+    }
+
+    /// Writes the remaining content and marks the message as complete.
+    pub async fn finish(mut self, database: &Database) -> Result<()> {
+        self.flush(database).await?;
+        database.complete_message(self.message_id).await
+    }
+}
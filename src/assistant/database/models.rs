@@ -1,4 +1,6 @@
-use super::schema::{messages, threads};
+use super::schema::{
+    backends, drafts, folders, messages, prompt_templates, tags, thread_tags, threads,
+};
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 
@@ -18,6 +20,17 @@ pub struct Thread {
     #[allow(dead_code)]
     pub created_at: NaiveDateTime,
     pub last_updated_at: NaiveDateTime,
+    pub pinned: bool,
+    pub archived: bool,
+    pub deleted_at: Option<NaiveDateTime>,
+    /// Backend used to generate answers, the active one being used if `None`.
+    pub backend_id: Option<i64>,
+    /// Format of the answers, read with [`ResponseFormat::parse`], free text if `None`.
+    ///
+    /// [`ResponseFormat::parse`]: crate::assistant::structured_output::ResponseFormat::parse
+    pub response_format: Option<String>,
+    /// Folder containing the thread, if any.
+    pub folder_id: Option<i64>,
 }
 
 #[derive(Insertable)]
@@ -27,6 +40,7 @@ pub struct NewMessage<'a> {
     pub thread_id: i64,
     pub content: &'a str,
     pub role: &'a str,
+    pub complete: bool,
 }
 
 #[derive(Debug, Clone, Queryable, Selectable)]
@@ -39,4 +53,129 @@ pub struct Message {
     pub created_at: NaiveDateTime,
     pub content: String,
     pub role: String,
+    #[allow(dead_code)]
+    pub complete: bool,
+    /// Why the answer does not follow the format of its thread.
+    pub format_error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = tags)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewTag<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = tags)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    #[allow(dead_code)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = folders)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewFolder<'a> {
+    pub name: &'a str,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = folders)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Folder {
+    pub id: i64,
+    pub name: String,
+    #[allow(dead_code)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable, Queryable, Selectable)]
+#[diesel(table_name = thread_tags)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ThreadTag {
+    pub thread_id: i64,
+    pub tag_id: i64,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = drafts)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewDraft<'a> {
+    pub thread_id: i64,
+    pub content: &'a str,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = prompt_templates)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct NewPromptTemplate<'a> {
+    pub name: &'a str,
+    pub content: &'a str,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable)]
+#[diesel(table_name = prompt_templates)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PromptTemplate {
+    pub id: i64,
+    pub name: String,
+    pub content: String,
+    #[allow(dead_code)]
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Insertable, AsChangeset)]
+#[diesel(table_name = backends)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+#[diesel(treat_none_as_null = true)]
+pub struct NewBackend {
+    pub name: String,
+    /// Kind of server, such as `ollama` or `openai`.
+    pub kind: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub connect_timeout_seconds: i64,
+    /// Maximum time waited for an answer to start, which includes loading the model.
+    pub first_byte_timeout_seconds: i64,
+    /// Maximum time waited between two chunks of an answer.
+    pub idle_timeout_seconds: i64,
+    /// Model selected when switching to the backend.
+    pub default_model: Option<String>,
+}
+
+/// Server configured to generate answers.
+#[derive(Clone, PartialEq, Queryable, Selectable)]
+#[diesel(table_name = backends)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Backend {
+    pub id: i64,
+    pub name: String,
+    pub kind: String,
+    pub base_url: String,
+    pub api_key: Option<String>,
+    pub connect_timeout_seconds: i64,
+    pub first_byte_timeout_seconds: i64,
+    pub idle_timeout_seconds: i64,
+    pub default_model: Option<String>,
+    /// Whether the backend is used at startup and for new threads.
+    pub active: bool,
+    #[allow(dead_code)]
+    pub created_at: NaiveDateTime,
+}
+
+impl std::fmt::Debug for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The API key must not end up in logs
+        f.debug_struct("Backend")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("base_url", &self.base_url)
+            .field("active", &self.active)
+            .finish_non_exhaustive()
+    }
 }
@@ -0,0 +1,164 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use diesel::sqlite::SqliteConnection;
+use diesel_async::sync_connection_wrapper::SyncConnectionWrapper;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use super::encryption::DatabaseKey;
+
+pub type Connection = SyncConnectionWrapper<SqliteConnection>;
+
+/// Time a connection waits for a lock held by another one before failing.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Small pool of SQLite connections.
+///
+/// Connections are opened lazily, up to `max_size`, and are returned to the pool when
+/// the [`PooledConnection`] is dropped.
+pub struct ConnectionPool {
+    database_url: String,
+    /// Key of an encrypted database, applied to every new connection.
+    key: RwLock<Option<DatabaseKey>>,
+    idle_connections: Mutex<Vec<Connection>>,
+    semaphore: Semaphore,
+    max_size: usize,
+}
+
+impl ConnectionPool {
+    pub fn new(database_url: String, max_size: usize) -> Self {
+        Self {
+            database_url,
+            key: RwLock::new(None),
+            idle_connections: Mutex::new(Vec::with_capacity(max_size)),
+            semaphore: Semaphore::new(max_size),
+            max_size,
+        }
+    }
+
+    pub fn key(&self) -> Option<DatabaseKey> {
+        self.key
+            .read()
+            .expect("Reading database key should work")
+            .clone()
+    }
+
+    /// Sets the key used by the connections opened from now on.
+    ///
+    /// Idle connections are closed because they were opened with the previous key.
+    pub fn set_key(&self, key: Option<DatabaseKey>) {
+        *self.key.write().expect("Writing database key should work") = key;
+        self.idle_connections
+            .lock()
+            .expect("Locking idle connections should work")
+            .clear();
+    }
+
+    /// Opens a connection outside of the pool, with the current key.
+    pub async fn connect(&self) -> Result<Connection> {
+        connect(&self.database_url, self.key().as_ref()).await
+    }
+
+    /// Waits for all connections to be returned and closes them,
+    /// preventing new ones from being used until the returned permit is dropped.
+    ///
+    /// This is needed before replacing the database file.
+    pub async fn exclusive(&self) -> Result<SemaphorePermit<'_>> {
+        let permit = self.semaphore.acquire_many(self.max_size as u32).await?;
+        self.idle_connections
+            .lock()
+            .expect("Locking idle connections should work")
+            .clear();
+        Ok(permit)
+    }
+
+    /// Waits for a connection to be available, opening a new one if needed.
+    pub async fn get(&self) -> Result<PooledConnection<'_>> {
+        let permit = self.semaphore.acquire().await?;
+        let idle_connection = self
+            .idle_connections
+            .lock()
+            .expect("Locking idle connections should work")
+            .pop();
+        let connection = match idle_connection {
+            Some(connection) => connection,
+            None => self.connect().await?,
+        };
+        Ok(PooledConnection {
+            pool: self,
+            connection: Some(connection),
+            _permit: permit,
+        })
+    }
+}
+
+/// Opens a connection configured for concurrent use by the pool.
+///
+/// The key of an encrypted database must be given, otherwise opening it fails.
+pub async fn connect(database_url: &str, key: Option<&DatabaseKey>) -> Result<Connection> {
+    let mut connection = Connection::establish(database_url).await?;
+    // The key has to be set before anything else is done with the connection
+    if let Some(key) = key {
+        diesel::sql_query(format!("PRAGMA key = {}", key.sql_literal()))
+            .execute(&mut connection)
+            .await?;
+    }
+    // SQLite does not enforce foreign key constraints unless explicitly told to,
+    // and this has to be done for every new connection.
+    diesel::sql_query("PRAGMA foreign_keys = ON")
+        .execute(&mut connection)
+        .await?;
+    diesel::sql_query(format!(
+        "PRAGMA busy_timeout = {}",
+        BUSY_TIMEOUT.as_millis()
+    ))
+    .execute(&mut connection)
+    .await?;
+    // With write-ahead logging, reads are not blocked by a write in progress
+    diesel::sql_query("PRAGMA journal_mode = WAL")
+        .execute(&mut connection)
+        .await?;
+    diesel::sql_query("PRAGMA synchronous = NORMAL")
+        .execute(&mut connection)
+        .await?;
+    Ok(connection)
+}
+
+pub struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    connection: Option<Connection>,
+    _permit: SemaphorePermit<'a>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        self.connection
+            .as_ref()
+            .expect("Pooled connection should be set until dropped")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.connection
+            .as_mut()
+            .expect("Pooled connection should be set until dropped")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool
+                .idle_connections
+                .lock()
+                .expect("Locking idle connections should work")
+                .push(connection);
+        }
+    }
+}
@@ -1,5 +1,131 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    /// Representation of the `backends` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    backends (id) {
+        /// The `id` column of the `backends` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> BigInt,
+        /// The `name` column of the `backends` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Text,
+        /// The `kind` column of the `backends` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        kind -> Text,
+        /// The `base_url` column of the `backends` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        base_url -> Text,
+        /// The `api_key` column of the `backends` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        api_key -> Nullable<Text>,
+        /// The `idle_timeout_seconds` column of the `backends` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        idle_timeout_seconds -> BigInt,
+        /// The `default_model` column of the `backends` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        default_model -> Nullable<Text>,
+        /// The `active` column of the `backends` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        active -> Bool,
+        /// The `created_at` column of the `backends` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+        /// The `connect_timeout_seconds` column of the `backends` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        connect_timeout_seconds -> BigInt,
+        /// The `first_byte_timeout_seconds` column of the `backends` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        first_byte_timeout_seconds -> BigInt,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `drafts` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    drafts (thread_id) {
+        /// The `thread_id` column of the `drafts` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        thread_id -> BigInt,
+        /// The `content` column of the `drafts` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        content -> Text,
+        /// The `updated_at` column of the `drafts` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `folders` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    folders (id) {
+        /// The `id` column of the `folders` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> BigInt,
+        /// The `name` column of the `folders` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Text,
+        /// The `created_at` column of the `folders` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     /// Representation of the `messages` table.
     ///
@@ -35,6 +161,96 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         created_at -> Timestamp,
+        /// The `complete` column of the `messages` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        complete -> Bool,
+        /// The `format_error` column of the `messages` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        format_error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `prompt_templates` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    prompt_templates (id) {
+        /// The `id` column of the `prompt_templates` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> BigInt,
+        /// The `name` column of the `prompt_templates` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Text,
+        /// The `content` column of the `prompt_templates` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        content -> Text,
+        /// The `created_at` column of the `prompt_templates` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `tags` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    tags (id) {
+        /// The `id` column of the `tags` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        id -> BigInt,
+        /// The `name` column of the `tags` table.
+        ///
+        /// Its SQL type is `Text`.
+        ///
+        /// (Automatically generated by Diesel.)
+        name -> Text,
+        /// The `created_at` column of the `tags` table.
+        ///
+        /// Its SQL type is `Timestamp`.
+        ///
+        /// (Automatically generated by Diesel.)
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    /// Representation of the `thread_tags` table.
+    ///
+    /// (Automatically generated by Diesel.)
+    thread_tags (thread_id, tag_id) {
+        /// The `thread_id` column of the `thread_tags` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        thread_id -> BigInt,
+        /// The `tag_id` column of the `thread_tags` table.
+        ///
+        /// Its SQL type is `BigInt`.
+        ///
+        /// (Automatically generated by Diesel.)
+        tag_id -> BigInt,
     }
 }
 
@@ -67,9 +283,57 @@ diesel::table! {
         ///
         /// (Automatically generated by Diesel.)
         last_updated_at -> Timestamp,
+        /// The `pinned` column of the `threads` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        pinned -> Bool,
+        /// The `archived` column of the `threads` table.
+        ///
+        /// Its SQL type is `Bool`.
+        ///
+        /// (Automatically generated by Diesel.)
+        archived -> Bool,
+        /// The `deleted_at` column of the `threads` table.
+        ///
+        /// Its SQL type is `Nullable<Timestamp>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        deleted_at -> Nullable<Timestamp>,
+        /// The `backend_id` column of the `threads` table.
+        ///
+        /// Its SQL type is `Nullable<BigInt>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        backend_id -> Nullable<BigInt>,
+        /// The `response_format` column of the `threads` table.
+        ///
+        /// Its SQL type is `Nullable<Text>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        response_format -> Nullable<Text>,
+        /// The `folder_id` column of the `threads` table.
+        ///
+        /// Its SQL type is `Nullable<BigInt>`.
+        ///
+        /// (Automatically generated by Diesel.)
+        folder_id -> Nullable<BigInt>,
     }
 }
 
+diesel::joinable!(drafts -> threads (thread_id));
 diesel::joinable!(messages -> threads (thread_id));
+diesel::joinable!(thread_tags -> tags (tag_id));
+diesel::joinable!(thread_tags -> threads (thread_id));
 
-diesel::allow_tables_to_appear_in_same_query!(messages, threads,);
+diesel::allow_tables_to_appear_in_same_query!(
+    backends,
+    drafts,
+    folders,
+    messages,
+    prompt_templates,
+    tags,
+    thread_tags,
+    threads,
+);
@@ -2,7 +2,7 @@ use relm4::spawn;
 use tokio::sync::broadcast;
 use tracing;
 
-use super::database::models::{Message, Thread};
+use super::database::models::{Backend, Folder, Message, PromptTemplate, Tag, Thread};
 
 pub struct DatabaseNotifier {
     broadcast_sender: broadcast::Sender<DatabaseNotifierMessage>,
@@ -11,10 +11,25 @@ pub struct DatabaseNotifier {
 #[derive(Debug, Clone)]
 pub enum DatabaseNotifierMessage {
     NewMessage(Message),
-    UpdateMessage(String),
+    /// Content appended to a message, with the thread id and the message id.
+    UpdateMessage(i64, i64, String),
+    EditMessage(Message),
+    /// Thread id and message id of a deleted message.
+    DeleteMessage(i64, i64),
     NewThread(Thread),
     UpdateThread(Thread),
-    GetThreadMessages(Vec<Message>),
+    UpdateThreadTitle(i64, String),
+    DeleteThread(i64),
+    GetThreadMessages(i64, Vec<Message>),
+    /// Page of messages sent before the ones already displayed for a thread.
+    GetOlderThreadMessages(i64, Vec<Message>),
+    NewTag(Tag),
+    DeleteTag(i64),
+    UpdateThreadTags(i64, Vec<Tag>),
+    NewFolder(Folder),
+    DeleteFolder(i64),
+    UpdatePromptTemplates(Vec<PromptTemplate>),
+    UpdateBackends(Vec<Backend>),
 }
 
 impl DatabaseNotifier {
@@ -44,7 +59,16 @@ impl DatabaseNotifier {
         let mut receiver = self.broadcast_sender.subscribe();
 
         spawn(async move {
-            while let Ok(input) = receiver.recv().await {
+            loop {
+                let input = match receiver.recv().await {
+                    Ok(input) => input,
+                    // Missing some updates is better than missing all the following ones
+                    Err(broadcast::error::RecvError::Lagged(n_skipped)) => {
+                        tracing::warn!("Subscriber skipped {n_skipped} notifications");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
                 let message = f(input);
                 if let Some(message) = message {
                     sender.emit(message);
@@ -1,2 +1,259 @@
 pub mod api;
+#[cfg(test)]
+pub mod mock_server;
+pub mod ndjson;
 pub mod types;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::{FutureExt, StreamExt};
+
+use super::backend::{
+    BackendKind, ChatBackend, ChatChunk, ChatOptions, ChatStream, CompletionChunk,
+    CompletionPrompt, CompletionStream, Endpoint, KeepAlive, LoadedModel, PullStream,
+    SamplingOptions, Timeouts,
+};
+use super::GenerationStats;
+use types::{ChatRequest, GenerateRequest, Message, ModelOptions};
+
+/// Model suggested to users who have not downloaded any yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecommendedModel {
+    pub name: &'static str,
+    /// Approximate download size, to be shown to users.
+    pub size: &'static str,
+    pub description: &'static str,
+}
+
+/// Models suggested on first run, from the smallest to the largest.
+pub const RECOMMENDED_MODELS: [RecommendedModel; 6] = [
+    RecommendedModel {
+        name: "llama3.2:1b",
+        size: "1.3 GB",
+        description: "Small and fast, runs on most computers",
+    },
+    RecommendedModel {
+        name: "llama3.2:3b",
+        size: "2.0 GB",
+        description: "Good balance between speed and quality",
+    },
+    RecommendedModel {
+        name: "qwen3:4b",
+        size: "2.5 GB",
+        description: "Thinks before answering, good at reasoning",
+    },
+    RecommendedModel {
+        name: "gemma3:4b",
+        size: "3.3 GB",
+        description: "Good at writing, understands many languages",
+    },
+    RecommendedModel {
+        name: "mistral:7b",
+        size: "4.1 GB",
+        description: "Larger general purpose model, needs 8 GB of memory",
+    },
+    RecommendedModel {
+        name: "qwen2.5-coder:7b",
+        size: "4.7 GB",
+        description: "Specialized in writing and explaining code",
+    },
+];
+
+/// Backend generating answers with an Ollama server.
+#[derive(Debug, Clone)]
+pub struct OllamaBackend {
+    endpoint: Endpoint,
+}
+
+impl OllamaBackend {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self { endpoint }
+    }
+}
+
+impl Default for OllamaBackend {
+    fn default() -> Self {
+        Self::new(Endpoint::new(
+            api::DEFAULT_BASE_URL,
+            None,
+            Timeouts::default(),
+        ))
+    }
+}
+
+impl ChatBackend for OllamaBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::Ollama
+    }
+
+    fn chat(
+        &self,
+        model: String,
+        messages: Vec<Message>,
+        options: ChatOptions,
+    ) -> BoxFuture<'static, Result<ChatStream>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            let request = ChatRequest {
+                model,
+                messages,
+                stream: true,
+                keep_alive: options.keep_alive.map(|keep_alive| keep_alive.seconds()),
+                format: options
+                    .response_format
+                    .map(|response_format| response_format.to_value()),
+            };
+            let response_stream = api::chat(&endpoint, request).await?;
+            let chunk_stream = response_stream.map(|chat_response| {
+                chat_response.map(|chat_response| ChatChunk {
+                    stats: chat_response
+                        .done
+                        .then(|| GenerationStats::from(&chat_response)),
+                    done: chat_response.done,
+                    message: chat_response.message,
+                })
+            });
+            Ok(chunk_stream.boxed())
+        }
+        .boxed()
+    }
+
+    fn complete(
+        &self,
+        model: String,
+        prompt: CompletionPrompt,
+        sampling: SamplingOptions,
+        keep_alive: Option<KeepAlive>,
+    ) -> BoxFuture<'static, Result<CompletionStream>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            let request = GenerateRequest {
+                model,
+                prompt: prompt.prompt,
+                suffix: prompt.suffix,
+                template: prompt.template,
+                raw: prompt.raw,
+                stream: true,
+                keep_alive: keep_alive.map(|keep_alive| keep_alive.seconds()),
+                options: Some(ModelOptions {
+                    temperature: sampling.temperature,
+                    top_k: sampling.top_k,
+                    top_p: sampling.top_p,
+                    seed: sampling.seed,
+                }),
+            };
+            let response_stream = api::generate(&endpoint, request).await?;
+            let chunk_stream = response_stream.map(|generate_response| {
+                generate_response.map(|generate_response| CompletionChunk {
+                    stats: generate_response
+                        .done
+                        .then(|| GenerationStats::from(&generate_response)),
+                    done: generate_response.done,
+                    text: generate_response.response,
+                })
+            });
+            Ok(chunk_stream.boxed())
+        }
+        .boxed()
+    }
+
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            let response = api::list_models(&endpoint).await?;
+            Ok(response
+                .models
+                .into_iter()
+                .map(|model| model.name)
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn health_check(&self) -> BoxFuture<'static, Result<()>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            api::version(&endpoint).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn is_model_loaded(&self, model: String) -> BoxFuture<'static, Option<bool>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            match api::running_models(&endpoint).await {
+                Ok(response) => {
+                    // Models without tag are the ones tagged `latest`
+                    let model = match model.contains(':') {
+                        true => model,
+                        false => format!("{model}:latest"),
+                    };
+                    Some(
+                        response
+                            .models
+                            .iter()
+                            .any(|running_model| running_model.name == model),
+                    )
+                }
+                Err(error) => {
+                    tracing::warn!("Listing loaded models failed because of: {error}");
+                    None
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn can_manage_loaded_models(&self) -> bool {
+        true
+    }
+
+    fn loaded_models(&self) -> BoxFuture<'static, Result<Vec<LoadedModel>>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            let response = api::running_models(&endpoint).await?;
+            Ok(response
+                .models
+                .into_iter()
+                .map(|model| LoadedModel {
+                    name: model.name,
+                    size: model.size,
+                    size_vram: model.size_vram.unwrap_or_default(),
+                })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn load_model(
+        &self,
+        model: String,
+        keep_alive: Option<KeepAlive>,
+    ) -> BoxFuture<'static, Result<()>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            api::load_model(
+                &endpoint,
+                model,
+                keep_alive.map(|keep_alive| keep_alive.seconds()),
+            )
+            .await
+        }
+        .boxed()
+    }
+
+    fn unload_model(&self, model: String) -> BoxFuture<'static, Result<()>> {
+        let endpoint = self.endpoint.clone();
+        async move { api::load_model(&endpoint, model, Some(0)).await }.boxed()
+    }
+
+    fn can_pull_models(&self) -> bool {
+        true
+    }
+
+    fn pull_model(&self, model: String) -> BoxFuture<'static, Result<PullStream>> {
+        let endpoint = self.endpoint.clone();
+        async move { Ok(api::pull_model(&endpoint, model).await?.boxed()) }.boxed()
+    }
+}
@@ -1,28 +1,32 @@
 use std::time::Duration;
 
 use anyhow::{Error, Result};
-use futures::stream::StreamExt;
+use futures::stream::{self, StreamExt};
 use futures::Stream;
-use reqwest;
+use serde::de::DeserializeOwned;
 use serde_json;
 use tracing;
 
+use super::super::backend::{status_error, BytesStream, Endpoint};
+use super::ndjson::NdjsonParser;
 use super::types::{
-    ChatRequest, ChatResponse, ListModelResponse, Message, PullModelRequest, PullModelResponse,
-    VersionResponse,
+    ChatRequest, ChatResponse, ErrorResponse, GenerateRequest, GenerateResponse, ListModelResponse,
+    PullModelRequest, PullModelResponse, RunningModelsResponse, VersionResponse,
 };
 
-pub async fn list_models() -> Result<ListModelResponse> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("http://localhost:11434/api/tags")
+/// Address of a server started with `ollama serve`.
+pub const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+
+pub async fn list_models(endpoint: &Endpoint) -> Result<ListModelResponse> {
+    let response = endpoint
+        .get("/api/tags")
         .timeout(Duration::from_secs(5))
         .send()
         .await?;
 
     if !response.status().is_success() {
         tracing::error!("Request to tags endpoint failed");
-        return Err(Error::msg(response.text().await?));
+        return Err(response_error(response).await);
     }
 
     let bytes = response.bytes().await?;
@@ -33,86 +37,113 @@ pub async fn list_models() -> Result<ListModelResponse> {
     }
 }
 
-pub async fn pull_model(model: String) -> Result<impl Stream<Item = Result<PullModelResponse>>> {
+pub async fn pull_model(
+    endpoint: &Endpoint,
+    model: String,
+) -> Result<impl Stream<Item = Result<PullModelResponse>>> {
     let body = PullModelRequest {
         model: model.clone(),
         insecure: false,
         stream: true,
     };
     let serialized_body = serde_json::to_string(&body)?;
-    let client = reqwest::Client::new();
-    let response = client
-        .post("http://localhost:11434/api/pull")
-        .timeout(Duration::from_secs(60))
-        .body(serialized_body)
-        .send()
-        .await?;
+    // Downloads take as long as they need, as long as the progress keeps being reported
+    let request = endpoint.post("/api/pull").body(serialized_body);
+    let response = endpoint.send_streaming(request).await?;
 
     if !response.status().is_success() {
-        return Err(Error::msg(response.text().await?));
+        return Err(response_error(response).await);
     }
 
-    let stream = response.bytes_stream().map(|response| match response {
-        Ok(bytes) => {
-            let result = serde_json::from_slice::<PullModelResponse>(&bytes);
-            match result {
-                Ok(result) => Ok(result),
-                Err(_) => Err(Error::msg("Failed parsing response")),
-            }
-        }
-        Err(e) => Err(e.into()),
-    });
-    Ok(stream)
+    Ok(parse_lines::<PullModelResponse>(
+        endpoint.body_stream(response),
+    ))
 }
 
 pub async fn chat(
-    model: String,
-    messages: Vec<Message>,
-    stream: bool,
+    endpoint: &Endpoint,
+    body: ChatRequest,
 ) -> Result<impl Stream<Item = Result<ChatResponse>>> {
+    let serialized_body = serde_json::to_string(&body)?;
+
+    let request = endpoint.post("/api/chat").body(serialized_body);
+    let response = endpoint.send_streaming(request).await?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response).await);
+    }
+
+    Ok(parse_lines::<ChatResponse>(endpoint.body_stream(response)))
+}
+
+/// Completes a prompt, without turning it into chat messages.
+pub async fn generate(
+    endpoint: &Endpoint,
+    body: GenerateRequest,
+) -> Result<impl Stream<Item = Result<GenerateResponse>>> {
+    let serialized_body = serde_json::to_string(&body)?;
+
+    let request = endpoint.post("/api/generate").body(serialized_body);
+    let response = endpoint.send_streaming(request).await?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response).await);
+    }
+
+    Ok(parse_lines::<GenerateResponse>(
+        endpoint.body_stream(response),
+    ))
+}
+
+/// Loads a model in memory, or unloads it with a keep-alive of 0 seconds.
+///
+/// Ollama does so when it receives a chat request without messages.
+pub async fn load_model(endpoint: &Endpoint, model: String, keep_alive: Option<i64>) -> Result<()> {
     let body = ChatRequest {
-        model: model.clone(),
-        messages,
-        stream,
+        model,
+        messages: Vec::new(),
+        stream: false,
+        keep_alive,
+        format: None,
     };
-    let serialized_body = serde_json::to_string(&body)?;
+    let mut response_stream = chat(endpoint, body).await?.boxed();
+    while let Some(response) = response_stream.next().await {
+        response?;
+    }
+    Ok(())
+}
 
-    let client = reqwest::Client::new();
-    let response = client
-        .post("http://localhost:11434/api/chat")
-        .timeout(Duration::from_secs(60))
-        .body(serialized_body)
+/// Lists the models loaded in memory.
+pub async fn running_models(endpoint: &Endpoint) -> Result<RunningModelsResponse> {
+    let response = endpoint
+        .get("/api/ps")
+        .timeout(Duration::from_secs(5))
         .send()
         .await?;
 
     if !response.status().is_success() {
-        return Err(Error::msg(response.text().await?));
+        tracing::error!("Request to ps endpoint failed");
+        return Err(response_error(response).await);
     }
 
-    let stream = response.bytes_stream().map(|response| match response {
-        Ok(bytes) => {
-            let result = serde_json::from_slice::<ChatResponse>(&bytes);
-            match result {
-                Ok(result) => Ok(result),
-                Err(e) => Err(Error::msg(format!("Failed parsing response {e}"))),
-            }
-        }
-        Err(e) => Err(e.into()),
-    });
-    Ok(stream)
+    let bytes = response.bytes().await?;
+    let result = serde_json::from_slice::<RunningModelsResponse>(&bytes);
+    match result {
+        Ok(result) => Ok(result),
+        Err(e) => Err(Error::msg(format!("Failed parsing response {e}"))),
+    }
 }
 
-pub async fn version() -> Result<VersionResponse> {
-    let client = reqwest::Client::new();
-    let response = client
-        .get("http://localhost:11434/api/version")
+pub async fn version(endpoint: &Endpoint) -> Result<VersionResponse> {
+    let response = endpoint
+        .get("/api/version")
         .timeout(Duration::from_secs(10))
         .send()
         .await?;
 
     if !response.status().is_success() {
         tracing::error!("Request to version endpoint failed");
-        return Err(Error::msg(response.text().await?));
+        return Err(response_error(response).await);
     }
     let bytes = response.bytes().await?;
     let result = serde_json::from_slice::<VersionResponse>(&bytes);
@@ -121,3 +152,53 @@ pub async fn version() -> Result<VersionResponse> {
         Err(e) => Err(Error::msg(format!("Failed parsing response {e}"))),
     }
 }
+
+/// Parses a streamed response, made of one JSON object per line.
+fn parse_lines<T: DeserializeOwned>(body: BytesStream) -> impl Stream<Item = Result<T>> {
+    let mut parser = NdjsonParser::new();
+    body.map(Some)
+        // Marks the end of the body, to handle a last line without trailing newline
+        .chain(stream::once(async { None }))
+        .flat_map(move |response| {
+            let results: Vec<Result<T>> = match response {
+                Some(Ok(bytes)) => parser
+                    .push(&bytes)
+                    .iter()
+                    .map(|line| parse_line(line))
+                    .collect(),
+                Some(Err(e)) => {
+                    // The rest of the incomplete line will never be received
+                    parser = NdjsonParser::new();
+                    vec![Err(e)]
+                }
+                None => parser
+                    .finish()
+                    .iter()
+                    .map(|line| parse_line(line))
+                    .collect(),
+            };
+            stream::iter(results)
+        })
+}
+
+fn parse_line<T: DeserializeOwned>(line: &str) -> Result<T> {
+    // Errors happening once the response started, e.g. while pulling a model, are streamed
+    if let Ok(response) = serde_json::from_str::<ErrorResponse>(line) {
+        return Err(Error::msg(response.error));
+    }
+    match serde_json::from_str::<T>(line) {
+        Ok(result) => Ok(result),
+        Err(e) => Err(Error::msg(format!("Failed parsing response {e}: {line}"))),
+    }
+}
+
+/// Returns the error of a failed request, whose message is the one sent by the server
+/// without the JSON around it.
+async fn response_error(response: reqwest::Response) -> Error {
+    status_error(response, |text| {
+        serde_json::from_str::<ErrorResponse>(&text)
+            .map(|response| response.error)
+            .unwrap_or(text)
+    })
+    .await
+}
@@ -0,0 +1,277 @@
+//! In-process fake Ollama server replaying scripted responses, used by tests.
+
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{StatusCode, Uri};
+use axum::response::Response;
+use axum::Router;
+use futures::stream::{self, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+use super::super::backend::{Endpoint, Timeouts};
+use super::OllamaBackend;
+
+/// Response replayed by the server.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    status: StatusCode,
+    chunks: Vec<Vec<u8>>,
+    /// Waited before sending each chunk.
+    delay: Duration,
+    /// Index of a chunk and additional time waited before sending it.
+    pause: Option<(usize, Duration)>,
+}
+
+impl Reply {
+    pub fn json(value: Value) -> Self {
+        Self {
+            status: StatusCode::OK,
+            chunks: vec![value.to_string().into_bytes()],
+            delay: Duration::ZERO,
+            pause: None,
+        }
+    }
+
+    /// Streams the given objects, one per line and one line per chunk.
+    pub fn json_lines(values: &[Value]) -> Self {
+        Self {
+            status: StatusCode::OK,
+            chunks: values
+                .iter()
+                .map(|value| format!("{value}\n").into_bytes())
+                .collect(),
+            delay: Duration::ZERO,
+            pause: None,
+        }
+    }
+
+    /// Fails with the given status, the error being formatted as Ollama does.
+    pub fn error(status: StatusCode, message: &str) -> Self {
+        Self {
+            status,
+            ..Self::json(json!({ "error": message }))
+        }
+    }
+
+    /// Streams an answer made of the given parts, followed by the statistics of the generation.
+    pub fn chat(parts: &[&str]) -> Self {
+        let mut lines: Vec<Value> = parts
+            .iter()
+            .map(|part| {
+                json!({
+                    "model": "mock",
+                    "created_at": "2025-01-01T00:00:00Z",
+                    "message": { "role": "assistant", "content": part },
+                    "done": false,
+                })
+            })
+            .collect();
+        lines.push(json!({
+            "model": "mock",
+            "created_at": "2025-01-01T00:00:00Z",
+            "message": { "role": "assistant", "content": "" },
+            "done": true,
+            "total_duration": 2_000_000_000u64,
+            "load_duration": 500_000_000u64,
+            "prompt_eval_count": 12,
+            "eval_count": parts.len(),
+            "eval_duration": 1_000_000_000u64,
+        }));
+        Self::json_lines(&lines)
+    }
+
+    /// Streams a completion made of the given parts, as returned by `/api/generate`.
+    pub fn generate(parts: &[&str]) -> Self {
+        let mut lines: Vec<Value> = parts
+            .iter()
+            .map(|part| {
+                json!({
+                    "model": "mock",
+                    "created_at": "2025-01-01T00:00:00Z",
+                    "response": part,
+                    "done": false,
+                })
+            })
+            .collect();
+        lines.push(json!({
+            "model": "mock",
+            "created_at": "2025-01-01T00:00:00Z",
+            "response": "",
+            "done": true,
+            "total_duration": 1_000_000_000u64,
+            "prompt_eval_count": 5,
+            "eval_count": parts.len(),
+            "eval_duration": 500_000_000u64,
+        }));
+        Self::json_lines(&lines)
+    }
+
+    /// Lists the given models, as returned by `/api/tags`.
+    pub fn tags(models: &[&str]) -> Self {
+        let models: Vec<Value> = models
+            .iter()
+            .map(|model| {
+                json!({
+                    "name": model,
+                    "modified_at": "2025-01-01T00:00:00Z",
+                    "size": 1_000_000_000u64,
+                    "digest": "0123456789abcdef",
+                    "details": {
+                        "format": "gguf",
+                        "family": "llama",
+                        "families": ["llama"],
+                        "parameter_size": "1.2B",
+                        "quantization_level": "Q8_0",
+                    },
+                })
+            })
+            .collect();
+        Self::json(json!({ "models": models }))
+    }
+
+    /// Sends the whole body again in chunks of the given size, ending anywhere in a line.
+    pub fn split_every(mut self, size: usize) -> Self {
+        self.chunks = self
+            .chunks
+            .concat()
+            .chunks(size)
+            .map(<[u8]>::to_vec)
+            .collect();
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Stops sending anything before the chunk at the given index, e.g. while a model is loaded.
+    pub fn with_pause(mut self, before_chunk: usize, pause: Duration) -> Self {
+        self.pause = Some((before_chunk, pause));
+        self
+    }
+}
+
+/// Request received by the server.
+#[derive(Debug, Clone)]
+pub struct ReceivedRequest {
+    pub path: String,
+    /// Body of the request, `Value::Null` if it is not JSON.
+    pub body: Value,
+}
+
+#[derive(Debug, Default)]
+struct ServerState {
+    /// Replies to the next requests, by path.
+    replies: HashMap<String, VecDeque<Reply>>,
+    requests: Vec<ReceivedRequest>,
+}
+
+/// Fake Ollama server listening on a random local port until it is dropped.
+///
+/// Each request is answered with the next reply scripted for its path,
+/// and with a 404 error once there are none left.
+pub struct MockOllama {
+    base_url: String,
+    state: Arc<Mutex<ServerState>>,
+    server: JoinHandle<()>,
+}
+
+impl MockOllama {
+    pub async fn start() -> Self {
+        let state = Arc::new(Mutex::new(ServerState::default()));
+        let app = Router::new().fallback(reply).with_state(state.clone());
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Binding mock server should work");
+        let address = listener
+            .local_addr()
+            .expect("Mock server address should be known");
+        let server = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .await
+                .expect("Mock server should run");
+        });
+        Self {
+            base_url: format!("http://{address}"),
+            state,
+            server,
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Returns a backend sending its requests to this server.
+    pub fn backend(&self, timeouts: Timeouts) -> OllamaBackend {
+        OllamaBackend::new(Endpoint::new(&self.base_url, None, timeouts))
+    }
+
+    /// Scripts the reply to the next request not answered yet on the given path.
+    pub fn reply(&self, path: &str, reply: Reply) -> &Self {
+        self.state
+            .lock()
+            .expect("Mock server state should be available")
+            .replies
+            .entry(path.to_string())
+            .or_default()
+            .push_back(reply);
+        self
+    }
+
+    /// Returns the requests received so far, in order.
+    pub fn requests(&self) -> Vec<ReceivedRequest> {
+        self.state
+            .lock()
+            .expect("Mock server state should be available")
+            .requests
+            .clone()
+    }
+}
+
+impl Drop for MockOllama {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn reply(State(state): State<Arc<Mutex<ServerState>>>, uri: Uri, body: Bytes) -> Response {
+    let path = uri.path().to_string();
+    let reply = {
+        let mut state = state.lock().expect("Mock server state should be available");
+        state.requests.push(ReceivedRequest {
+            path: path.clone(),
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+        });
+        state.replies.get_mut(&path).and_then(VecDeque::pop_front)
+    };
+    let reply = reply.unwrap_or_else(|| {
+        Reply::error(
+            StatusCode::NOT_FOUND,
+            &format!("no reply scripted for {path}"),
+        )
+    });
+
+    let (delay, pause) = (reply.delay, reply.pause);
+    let chunks =
+        stream::iter(reply.chunks.into_iter().enumerate()).then(move |(index, chunk)| async move {
+            tokio::time::sleep(delay).await;
+            if let Some((_, pause)) = pause.filter(|(before_chunk, _)| *before_chunk == index) {
+                tokio::time::sleep(pause).await;
+            }
+            Ok::<_, Infallible>(Bytes::from(chunk))
+        });
+    Response::builder()
+        .status(reply.status)
+        .header("content-type", "application/x-ndjson")
+        .body(Body::from_stream(chunks))
+        .expect("Mock response should be valid")
+}
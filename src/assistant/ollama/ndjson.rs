@@ -0,0 +1,62 @@
+/// Incremental parser of newline-delimited JSON, returning each complete line.
+///
+/// Ollama streams one JSON object per line, but chunks of the response body can end
+/// anywhere or contain several lines, so incomplete lines are kept until the rest is received.
+#[derive(Debug, Default)]
+pub struct NdjsonParser {
+    buffer: Vec<u8>,
+}
+
+impl NdjsonParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if !line.is_empty() {
+                lines.push(line.to_string());
+            }
+        }
+        lines
+    }
+
+    /// Returns the last line if the stream ended without a trailing newline.
+    pub fn finish(&mut self) -> Option<String> {
+        let line = String::from_utf8_lossy(&self.buffer).trim().to_string();
+        self.buffer.clear();
+        (!line.is_empty()).then_some(line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_lines() {
+        let mut parser = NdjsonParser::new();
+        let lines = parser.push(b"{\"a\": 1}\n\n{\"b\": 2}\r\n");
+        assert_eq!(lines, vec!["{\"a\": 1}", "{\"b\": 2}"]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn test_parsing_lines_split_across_chunks() {
+        let mut parser = NdjsonParser::new();
+        assert!(parser.push(b"{\"content\": \"caf").is_empty());
+        // The two bytes of "é" are received separately
+        assert!(parser.push(&[0xc3]).is_empty());
+        assert_eq!(
+            parser.push(&[0xa9, b'"', b'}', b'\n', b'{']),
+            vec!["{\"content\": \"café\"}"]
+        );
+        assert!(parser.push(b"}").is_empty());
+        assert_eq!(parser.finish().as_deref(), Some("{}"));
+    }
+}
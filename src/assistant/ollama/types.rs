@@ -72,6 +72,18 @@ pub struct PullModelResponse {
     pub completed: Option<u64>,
 }
 
+impl PullModelResponse {
+    /// Returns the downloaded fraction of the file being pulled, if the download has started.
+    pub fn progress(&self) -> Option<f64> {
+        match (self.completed, self.total) {
+            (Some(completed), Some(total)) if total > 0 => {
+                Some((completed as f64 / total as f64).min(1.0))
+            }
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ListModelResponse {
     pub models: Vec<ListModelSingleModelResponse>,
@@ -97,11 +109,33 @@ pub struct ModelDetails {
     pub quantization_level: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct RunningModelsResponse {
+    pub models: Vec<RunningModel>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+pub struct RunningModel {
+    pub name: String,
+    pub model: String,
+    pub size: u64,
+    pub size_vram: Option<u64>,
+    /// Time at which the model will be unloaded if it is not used.
+    pub expires_at: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<Message>,
     pub stream: bool,
+    /// Seconds during which the model stays loaded, negative meaning forever.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<i64>,
+    /// `"json"` for any JSON value, or a JSON schema the answer must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<serde_json::Value>,
 }
 
 #[allow(dead_code)]
@@ -119,6 +153,56 @@ pub struct ChatResponse {
     pub eval_duration: Option<u64>,
 }
 
+/// Sampling settings, named as in Ollama model files.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelOptions {
+    pub temperature: f64,
+    pub top_k: u64,
+    pub top_p: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenerateRequest {
+    pub model: String,
+    pub prompt: String,
+    /// Text after the completion, for models that fill in the middle.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+    /// Prompt template replacing the one of the model.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Whether the prompt is sent to the model as is, without any template.
+    pub raw: bool,
+    pub stream: bool,
+    /// Seconds during which the model stays loaded, negative meaning forever.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<ModelOptions>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize, Default)]
+pub struct GenerateResponse {
+    pub model: String,
+    pub created_at: String,
+    pub response: String,
+    pub done: bool,
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_count: Option<u64>,
+    pub prompt_eval_duration: Option<u64>,
+    pub eval_count: Option<u64>,
+    pub eval_duration: Option<u64>,
+}
+
+/// Body of failed requests, also streamed as a line of its own when a streamed request fails.
+#[derive(Debug, Deserialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Deserialize, Default)]
 pub struct VersionResponse {
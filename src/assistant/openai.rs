@@ -0,0 +1,233 @@
+pub mod api;
+pub mod sse;
+pub mod types;
+
+use std::time::Instant;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::{self, StreamExt};
+use futures::FutureExt;
+use serde_json::json;
+
+use super::backend::{BackendKind, ChatBackend, ChatChunk, ChatOptions, ChatStream, Endpoint};
+use super::ollama::types::{Message, Role};
+use super::structured_output::ResponseFormat;
+use super::GenerationStats;
+use api::ChatCompletionEvent;
+use types::{ChatCompletionRequest, StreamOptions, Usage};
+
+/// Backend generating answers with a server implementing the chat completions API of OpenAI.
+#[derive(Debug, Clone)]
+pub struct OpenAiBackend {
+    endpoint: Endpoint,
+}
+
+impl OpenAiBackend {
+    pub fn new(mut endpoint: Endpoint) -> Self {
+        // The base URL is the one of the server, `/v1` being added to every endpoint
+        endpoint.base_url = endpoint.base_url.trim_end_matches("/v1").to_string();
+        Self { endpoint }
+    }
+}
+
+impl ChatBackend for OpenAiBackend {
+    fn kind(&self) -> BackendKind {
+        BackendKind::OpenAi
+    }
+
+    fn chat(
+        &self,
+        model: String,
+        messages: Vec<Message>,
+        options: ChatOptions,
+    ) -> BoxFuture<'static, Result<ChatStream>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            let request = ChatCompletionRequest {
+                model,
+                messages: messages.into_iter().map(Into::into).collect(),
+                stream: true,
+                stream_options: StreamOptions {
+                    include_usage: true,
+                },
+                response_format: options.response_format.map(response_format),
+            };
+            let mut state = CompletionState::new();
+            let event_stream = api::chat_completions(&endpoint, request).await?;
+            let chunk_stream = event_stream
+                .map(Some)
+                .chain(stream::once(async { None }))
+                .filter_map(move |event| {
+                    let chunk = state.handle(event);
+                    async move { chunk }
+                });
+            Ok(chunk_stream.boxed())
+        }
+        .boxed()
+    }
+
+    fn list_models(&self) -> BoxFuture<'static, Result<Vec<String>>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            let response = api::list_models(&endpoint).await?;
+            Ok(response.data.into_iter().map(|model| model.id).collect())
+        }
+        .boxed()
+    }
+
+    fn health_check(&self) -> BoxFuture<'static, Result<()>> {
+        let endpoint = self.endpoint.clone();
+        async move {
+            api::list_models(&endpoint).await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Returns the `response_format` field of chat completion requests.
+fn response_format(response_format: ResponseFormat) -> serde_json::Value {
+    match response_format {
+        ResponseFormat::Json => json!({ "type": "json_object" }),
+        ResponseFormat::Schema(schema) => json!({
+            "type": "json_schema",
+            "json_schema": { "name": "answer", "schema": schema },
+        }),
+    }
+}
+
+/// Turns completion events into chunks, measuring the generation as the server does not.
+struct CompletionState {
+    started_at: Instant,
+    first_token_at: Option<Instant>,
+    usage: Option<Usage>,
+    done: bool,
+}
+
+impl CompletionState {
+    fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            first_token_at: None,
+            usage: None,
+            done: false,
+        }
+    }
+
+    /// Handles an event, `None` meaning that the stream ended.
+    fn handle(&mut self, event: Option<Result<ChatCompletionEvent>>) -> Option<Result<ChatChunk>> {
+        match event {
+            Some(Ok(ChatCompletionEvent::Chunk(chunk))) => {
+                if chunk.usage.is_some() {
+                    self.usage = chunk.usage;
+                }
+                let content: String = chunk
+                    .choices
+                    .into_iter()
+                    .filter_map(|choice| choice.delta.content)
+                    .collect();
+                if content.is_empty() {
+                    return None;
+                }
+                self.first_token_at.get_or_insert_with(Instant::now);
+                Some(Ok(ChatChunk {
+                    message: Message {
+                        role: Role::Assistant,
+                        content,
+                    },
+                    done: false,
+                    stats: None,
+                }))
+            }
+            Some(Err(error)) => Some(Err(error)),
+            // Not every server sends `[DONE]`, so the end of the stream also ends the answer
+            Some(Ok(ChatCompletionEvent::Done)) | None if !self.done => {
+                self.done = true;
+                Some(Ok(ChatChunk {
+                    message: Message {
+                        role: Role::Assistant,
+                        content: String::new(),
+                    },
+                    done: true,
+                    stats: Some(self.stats()),
+                }))
+            }
+            Some(Ok(ChatCompletionEvent::Done)) | None => None,
+        }
+    }
+
+    fn stats(&self) -> GenerationStats {
+        let now = Instant::now();
+        let first_token_at = self.first_token_at.unwrap_or(now);
+        let usage = self.usage.unwrap_or_default();
+        GenerationStats {
+            total_duration: now - self.started_at,
+            load_duration: first_token_at - self.started_at,
+            prompt_eval_count: usage.prompt_tokens,
+            eval_count: usage.completion_tokens,
+            eval_duration: now - first_token_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::types::{ChatCompletionChoice, ChatCompletionChunk, ChatCompletionDelta};
+    use super::*;
+
+    fn content_event(content: &str) -> Option<Result<ChatCompletionEvent>> {
+        Some(Ok(ChatCompletionEvent::Chunk(ChatCompletionChunk {
+            choices: vec![ChatCompletionChoice {
+                delta: ChatCompletionDelta {
+                    content: Some(content.to_string()),
+                },
+                finish_reason: None,
+            }],
+            usage: None,
+        })))
+    }
+
+    #[test]
+    fn test_completion_events_to_chunks() {
+        let mut state = CompletionState::new();
+        let chunk = state
+            .handle(content_event("Hello"))
+            .expect("Content should give a chunk")
+            .expect("Chunk should be valid");
+        assert_eq!(chunk.message.content, "Hello");
+        assert!(!chunk.done);
+        assert!(state.handle(content_event("")).is_none());
+
+        let usage_event = Some(Ok(ChatCompletionEvent::Chunk(ChatCompletionChunk {
+            choices: Vec::new(),
+            usage: Some(Usage {
+                prompt_tokens: 12,
+                completion_tokens: 3,
+            }),
+        })));
+        assert!(state.handle(usage_event).is_none());
+
+        let last_chunk = state
+            .handle(Some(Ok(ChatCompletionEvent::Done)))
+            .expect("Done should give a chunk")
+            .expect("Chunk should be valid");
+        assert!(last_chunk.done);
+        let stats = last_chunk.stats.expect("Last chunk should have statistics");
+        assert_eq!(stats.prompt_eval_count, 12);
+        assert_eq!(stats.eval_count, 3);
+        // The end of the stream does not end the answer twice
+        assert!(state.handle(None).is_none());
+    }
+
+    #[test]
+    fn test_end_of_stream_ends_answer() {
+        let mut state = CompletionState::new();
+        state.handle(content_event("Hello"));
+        let last_chunk = state
+            .handle(None)
+            .expect("End of stream should give a chunk")
+            .expect("Chunk should be valid");
+        assert!(last_chunk.done);
+    }
+}
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use anyhow::{Error, Result};
+use futures::stream::{self, StreamExt};
+use futures::Stream;
+use reqwest;
+use serde_json;
+use tracing;
+
+use super::super::backend::{status_error, Endpoint};
+use super::sse::SseParser;
+use super::types::{ChatCompletionChunk, ChatCompletionRequest, ListModelsResponse};
+
+/// Event received while streaming a chat completion.
+#[derive(Debug)]
+pub enum ChatCompletionEvent {
+    Chunk(ChatCompletionChunk),
+    /// Sent by the server once the completion is over.
+    Done,
+}
+
+pub async fn list_models(endpoint: &Endpoint) -> Result<ListModelsResponse> {
+    let response = endpoint
+        .get("/v1/models")
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        tracing::error!("Request to models endpoint failed");
+        return Err(status_error(response, |text| text).await);
+    }
+
+    let bytes = response.bytes().await?;
+    let result = serde_json::from_slice::<ListModelsResponse>(&bytes);
+    match result {
+        Ok(result) => Ok(result),
+        Err(e) => Err(Error::msg(format!("Failed parsing response {e}"))),
+    }
+}
+
+pub async fn chat_completions(
+    endpoint: &Endpoint,
+    request: ChatCompletionRequest,
+) -> Result<impl Stream<Item = Result<ChatCompletionEvent>>> {
+    let serialized_body = serde_json::to_string(&request)?;
+
+    let request = endpoint
+        .post("/v1/chat/completions")
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serialized_body);
+    let response = endpoint.send_streaming(request).await?;
+
+    if !response.status().is_success() {
+        return Err(status_error(response, |text| text).await);
+    }
+
+    let mut parser = SseParser::new();
+    let stream = endpoint
+        .body_stream(response)
+        .map(Some)
+        // Marks the end of the body, to handle a last event without trailing empty line
+        .chain(stream::once(async { None }))
+        .flat_map(move |response| {
+            let events: Vec<Result<ChatCompletionEvent>> = match response {
+                Some(Ok(bytes)) => parser.push(&bytes).into_iter().map(parse_event).collect(),
+                Some(Err(e)) => {
+                    // The rest of the incomplete event will never be received
+                    parser = SseParser::new();
+                    vec![Err(e)]
+                }
+                None => parser.finish().into_iter().map(parse_event).collect(),
+            };
+            stream::iter(events)
+        });
+    Ok(stream)
+}
+
+fn parse_event(data: String) -> Result<ChatCompletionEvent> {
+    if data.trim() == "[DONE]" {
+        return Ok(ChatCompletionEvent::Done);
+    }
+    match serde_json::from_str::<ChatCompletionChunk>(&data) {
+        Ok(chunk) => Ok(ChatCompletionEvent::Chunk(chunk)),
+        Err(e) => Err(Error::msg(format!("Failed parsing response {e}: {data}"))),
+    }
+}
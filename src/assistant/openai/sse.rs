@@ -0,0 +1,85 @@
+/// Incremental parser of server-sent events, returning the data of each complete event.
+///
+/// Chunks of the response body can end anywhere, including in the middle of a line,
+/// so incomplete lines are kept until the rest is received.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data_lines: Vec<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(position) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=position).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if line.is_empty() {
+                // An empty line ends the current event
+                if !self.data_lines.is_empty() {
+                    events.push(self.data_lines.join("\n"));
+                    self.data_lines.clear();
+                }
+            } else if let Some(data) = line.strip_prefix("data:") {
+                self.data_lines
+                    .push(data.strip_prefix(' ').unwrap_or(data).to_string());
+            }
+            // Comments, starting with ':', and other fields are ignored
+        }
+        events
+    }
+
+    /// Returns the data of the last event if the stream ended without an empty line.
+    pub fn finish(&mut self) -> Option<String> {
+        if !self.buffer.is_empty() {
+            self.push(b"\n");
+        }
+        if self.data_lines.is_empty() {
+            return None;
+        }
+        let event = self.data_lines.join("\n");
+        self.data_lines.clear();
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parsing_events() {
+        let mut parser = SseParser::new();
+        let events = parser.push(b"data: {\"a\": 1}\n\n: keep-alive comment\n\ndata: [DONE]\n\n");
+        assert_eq!(events, vec!["{\"a\": 1}", "[DONE]"]);
+        assert_eq!(parser.finish(), None);
+    }
+
+    #[test]
+    fn test_parsing_events_split_across_chunks() {
+        let mut parser = SseParser::new();
+        assert!(parser.push(b"data: {\"content\": \"caf").is_empty());
+        // The two bytes of "é" are received separately
+        assert!(parser.push(&[0xc3]).is_empty());
+        assert!(parser.push(&[0xa9]).is_empty());
+        assert!(parser.push(b"\"}\r\n").is_empty());
+        assert_eq!(parser.push(b"\r\n"), vec!["{\"content\": \"café\"}"]);
+    }
+
+    #[test]
+    fn test_parsing_multiline_event() {
+        let mut parser = SseParser::new();
+        assert_eq!(
+            parser.push(b"event: message\ndata: first\ndata:second\n\n"),
+            vec!["first\nsecond"]
+        );
+        assert!(parser.push(b"data: unterminated").is_empty());
+        assert_eq!(parser.finish().as_deref(), Some("unterminated"));
+    }
+}
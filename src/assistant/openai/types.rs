@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::assistant::ollama::types::Message;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatCompletionMessage {
+    pub role: String,
+    pub content: String,
+}
+
+impl From<Message> for ChatCompletionMessage {
+    fn from(value: Message) -> Self {
+        Self {
+            role: <&str>::from(value.role).to_string(),
+            content: value.content,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatCompletionMessage>,
+    pub stream: bool,
+    pub stream_options: StreamOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamOptions {
+    /// Ask for the number of tokens in a last chunk, which not all servers send.
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ChatCompletionChunk {
+    #[serde(default)]
+    pub choices: Vec<ChatCompletionChoice>,
+    pub usage: Option<Usage>,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Default, Deserialize)]
+pub struct ChatCompletionChoice {
+    #[serde(default)]
+    pub delta: ChatCompletionDelta,
+    pub finish_reason: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ChatCompletionDelta {
+    pub content: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListModelsResponse {
+    pub data: Vec<ModelObject>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelObject {
+    pub id: String,
+}
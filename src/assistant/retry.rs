@@ -0,0 +1,183 @@
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use rand::Rng;
+use reqwest::StatusCode;
+
+/// How requests failing before the server answered are sent again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of times a request is sent, including the first one.
+    pub max_attempts: u32,
+    /// Waited before the first retry, doubled before each of the following ones.
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(8),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the time waited before the given retry, starting at 1.
+    ///
+    /// A random part of up to half the delay is removed so that clients
+    /// do not all come back at the same time after a server restart.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let delay = self
+            .initial_delay
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max_delay);
+        let jitter = rand::rng().random_range(0.0..=0.5);
+        delay.mul_f64(1.0 - jitter)
+    }
+}
+
+/// Retry about to be made after a request failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryAttempt {
+    /// Number of the next attempt, starting at 2.
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Duration,
+    /// Why the previous attempt failed.
+    pub error: String,
+}
+
+impl fmt::Display for RetryAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Reconnecting (attempt {} of {})...",
+            self.attempt, self.max_attempts
+        )
+    }
+}
+
+/// Whether a request failed without reaching the server, or because it is restarting,
+/// in which case sending it again may work.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .any(|error| {
+            error.is_connect()
+                || error.status().is_some_and(|status| {
+                    matches!(
+                        status,
+                        StatusCode::BAD_GATEWAY
+                            | StatusCode::SERVICE_UNAVAILABLE
+                            | StatusCode::GATEWAY_TIMEOUT
+                    )
+                })
+        })
+}
+
+/// Runs an operation until it succeeds, fails with an error that is not transient,
+/// or the attempts are exhausted.
+///
+/// The operation must not have any effect when it fails, such as a streamed request
+/// that failed before anything was received.
+pub async fn retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    mut on_retry: impl FnMut(&RetryAttempt),
+    mut operation: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Ok(result) => return Ok(result),
+            Err(error) if attempt < policy.max_attempts && is_transient(&error) => {
+                attempt += 1;
+                let retry_attempt = RetryAttempt {
+                    attempt,
+                    max_attempts: policy.max_attempts,
+                    delay: policy.delay(attempt - 1),
+                    error: error.to_string(),
+                };
+                tracing::warn!(
+                    "Request failed because of: {error}, retrying in {:?} (attempt {attempt} of {})",
+                    retry_attempt.delay,
+                    policy.max_attempts
+                );
+                on_retry(&retry_attempt);
+                tokio::time::sleep(retry_attempt.delay).await;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn test_retry_delays() {
+        let policy = RetryPolicy::default();
+        for (retry, max_delay) in [(1, 500), (2, 1000), (3, 2000), (5, 8000), (10, 8000)] {
+            let delay = policy.delay(retry);
+            assert!(delay <= Duration::from_millis(max_delay));
+            assert!(delay >= Duration::from_millis(max_delay / 2));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retrying_only_transient_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+        };
+        // Nothing listens on port 1 of the local machine
+        let connection_error = || async {
+            reqwest::get("http://127.0.0.1:1")
+                .await
+                .map_err(anyhow::Error::from)
+        };
+
+        let attempts = Cell::new(0);
+        let mut retries = Vec::new();
+        let result = retry(
+            &policy,
+            |retry_attempt| retries.push(retry_attempt.attempt),
+            || {
+                attempts.set(attempts.get() + 1);
+                connection_error()
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(retries, vec![2, 3]);
+
+        attempts.set(0);
+        let result: Result<()> = retry(
+            &policy,
+            |_| {},
+            || {
+                attempts.set(attempts.get() + 1);
+                async { Err(anyhow!("model not found")) }
+            },
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+}
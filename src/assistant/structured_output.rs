@@ -0,0 +1,181 @@
+use std::fmt;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// Format that the answers of a thread must follow.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseFormat {
+    /// Any JSON value.
+    Json,
+    /// JSON value matching a JSON schema.
+    Schema(Value),
+}
+
+impl fmt::Display for ResponseFormat {
+    /// Formats it as stored for a thread, which [`ResponseFormat::parse`] reads back.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResponseFormat::Json => write!(f, "json"),
+            ResponseFormat::Schema(schema) => write!(f, "{schema}"),
+        }
+    }
+}
+
+impl ResponseFormat {
+    /// Parses `json` for any JSON value, and a JSON schema otherwise.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if text == "json" {
+            return Ok(ResponseFormat::Json);
+        }
+        let schema: Value = serde_json::from_str(text)
+            .map_err(|error| anyhow!("The schema is not valid JSON: {error}"))?;
+        if !schema.is_object() {
+            return Err(anyhow!("The schema must be a JSON object"));
+        }
+        validator(&schema)?;
+        Ok(ResponseFormat::Schema(schema))
+    }
+
+    /// Returns the value of the `format` field of Ollama chat requests.
+    pub fn to_value(&self) -> Value {
+        match self {
+            ResponseFormat::Json => Value::String(String::from("json")),
+            ResponseFormat::Schema(schema) => schema.clone(),
+        }
+    }
+
+    /// Checks that an answer follows the format, returning it pretty-printed,
+    /// or a description of what is wrong with it.
+    pub fn check_answer(&self, answer: &str) -> Result<String, String> {
+        let value: Value = serde_json::from_str(answer.trim())
+            .map_err(|error| format!("The answer is not valid JSON: {error}"))?;
+        if let ResponseFormat::Schema(schema) = self {
+            let validator = validator(schema).map_err(|error| error.to_string())?;
+            let errors: Vec<String> = validator
+                .iter_errors(&value)
+                .map(|error| {
+                    let path = error.instance_path.as_str();
+                    format!("{}: {error}", if path.is_empty() { "/" } else { path })
+                })
+                .collect();
+            if !errors.is_empty() {
+                return Err(format!(
+                    "The answer does not match the schema: {}",
+                    errors.join("; ")
+                ));
+            }
+        }
+        Ok(serde_json::to_string_pretty(&value).expect("Serializing JSON value should work"))
+    }
+}
+
+/// Compiles a schema, also checking the `format` of strings, which is otherwise only an annotation.
+fn validator(schema: &Value) -> Result<jsonschema::Validator> {
+    jsonschema::options()
+        .should_validate_formats(true)
+        .build(schema)
+        .map_err(|error| anyhow!("The schema is not valid: {error}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_parsing_response_format() {
+        assert_eq!(
+            ResponseFormat::parse(" json ").expect("Parsing json should work"),
+            ResponseFormat::Json
+        );
+        let format =
+            ResponseFormat::parse(r#"{"type": "object"}"#).expect("Parsing schema should work");
+        assert_eq!(format, ResponseFormat::Schema(json!({ "type": "object" })));
+        assert_eq!(
+            ResponseFormat::parse(&format.to_string()).expect("Parsing stored format should work"),
+            format
+        );
+        ResponseFormat::parse("{\"type\":").expect_err("Parsing invalid JSON should fail");
+        ResponseFormat::parse("[]").expect_err("Parsing non-object schema should fail");
+        ResponseFormat::parse(r#"{"type": "text"}"#)
+            .expect_err("Parsing invalid schema should fail");
+    }
+
+    #[test]
+    fn test_checking_answers() {
+        let format = ResponseFormat::Schema(json!({
+            "type": "object",
+            "properties": {
+                "name": { "type": "string", "minLength": 1 },
+                "age": { "type": "integer", "minimum": 0 },
+                "tags": { "type": "array", "items": { "enum": ["a", "b"] }, "maxItems": 2 },
+            },
+            "required": ["name", "age"],
+            "additionalProperties": false,
+        }));
+        assert_eq!(
+            format.check_answer(r#" {"name": "Ada", "age": 36, "tags": ["a"]} "#),
+            Ok(String::from(
+                "{\n  \"name\": \"Ada\",\n  \"age\": 36,\n  \"tags\": [\n    \"a\"\n  ]\n}"
+            ))
+        );
+        assert_eq!(
+            format.check_answer(r#"{"name": "", "age": 1.5, "tags": ["c", "a", "b"], "x": 1}"#),
+            Err(String::from(
+                "The answer does not match the schema: \
+                 /name: \"\" is shorter than 1 character; \
+                 /age: 1.5 is not of type \"integer\"; \
+                 /tags/0: \"c\" is not one of [\"a\",\"b\"]; \
+                 /tags: [\"c\",\"a\",\"b\"] has more than 2 items; \
+                 /: Additional properties are not allowed ('x' was unexpected)"
+            ))
+        );
+        assert_eq!(
+            format.check_answer("{}"),
+            Err(String::from(
+                "The answer does not match the schema: \
+                 /: \"name\" is a required property; /: \"age\" is a required property"
+            ))
+        );
+        assert!(format
+            .check_answer("Sure! Here is the JSON")
+            .is_err_and(|error| error.starts_with("The answer is not valid JSON")));
+        assert_eq!(
+            ResponseFormat::Json.check_answer("[1,2]"),
+            Ok(String::from("[\n  1,\n  2\n]"))
+        );
+    }
+
+    #[test]
+    fn test_checking_answers_with_composed_schema() {
+        let format = ResponseFormat::Schema(json!({
+            "$defs": {
+                "email": { "type": "string", "format": "email" },
+            },
+            "type": "object",
+            "properties": {
+                "contact": {
+                    "anyOf": [
+                        { "$ref": "#/$defs/email" },
+                        { "type": "string", "pattern": "^\\+[0-9]+$" },
+                    ],
+                },
+                "scores": {
+                    "type": "array",
+                    "items": { "type": "number", "exclusiveMinimum": 0 },
+                    "uniqueItems": true,
+                },
+            },
+        }));
+        assert!(format
+            .check_answer(r#"{"contact": "ada@example.com", "scores": [1, 2]}"#)
+            .is_ok());
+        assert!(format.check_answer(r#"{"contact": "+123"}"#).is_ok());
+        assert!(format.check_answer(r#"{"contact": "Ada"}"#).is_err());
+        assert!(format.check_answer(r#"{"scores": [0]}"#).is_err());
+        assert!(format.check_answer(r#"{"scores": [1, 1]}"#).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::database::models::PromptTemplate;
+
+const VARIABLE_START: &str = "{{";
+const VARIABLE_END: &str = "}}";
+
+/// Prompt template as stored in an exported template library file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplateEntry {
+    pub name: String,
+    pub content: String,
+}
+
+impl From<PromptTemplate> for PromptTemplateEntry {
+    fn from(value: PromptTemplate) -> Self {
+        Self {
+            name: value.name,
+            content: value.content,
+        }
+    }
+}
+
+/// Returns the names of the `{{variable}}` placeholders in a template,
+/// in order of first appearance and without duplicates.
+pub fn template_variables(content: &str) -> Vec<String> {
+    let mut variables: Vec<String> = Vec::new();
+    let mut rest = content;
+    while let Some(start) = rest.find(VARIABLE_START) {
+        let after_start = &rest[start + VARIABLE_START.len()..];
+        let Some(end) = after_start.find(VARIABLE_END) else {
+            break;
+        };
+        let name = after_start[..end].trim();
+        if is_valid_variable_name(name) && !variables.iter().any(|variable| variable == name) {
+            variables.push(name.to_string());
+        }
+        rest = &after_start[end + VARIABLE_END.len()..];
+    }
+    variables
+}
+
+/// Returns the name under which a template is saved, with its whitespace replaced by dashes
+/// since spaces would end the `/` command used to search templates.
+pub fn normalize_template_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<&str>>().join("-")
+}
+
+/// Replaces the `{{variable}}` placeholders of a template with the given values.
+///
+/// Placeholders without a value are left untouched.
+pub fn fill_template(content: &str, values: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(VARIABLE_START) {
+        let after_start = &rest[start + VARIABLE_START.len()..];
+        let Some(end) = after_start.find(VARIABLE_END) else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + VARIABLE_START.len() + end + VARIABLE_END.len()];
+        match values.get(after_start[..end].trim()) {
+            Some(value) => result.push_str(value),
+            None => result.push_str(placeholder),
+        }
+        rest = &after_start[end + VARIABLE_END.len()..];
+    }
+    result.push_str(rest);
+    result
+}
+
+fn is_valid_variable_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == ' ')
+}
+
+pub fn read_templates_file(path: &Path) -> Result<Vec<PromptTemplateEntry>> {
+    let bytes = std::fs::read(path)?;
+    let templates = serde_json::from_slice::<Vec<PromptTemplateEntry>>(&bytes)?;
+    Ok(templates)
+}
+
+pub fn write_templates_file(path: &Path, templates: Vec<PromptTemplate>) -> Result<()> {
+    let templates: Vec<PromptTemplateEntry> = templates.into_iter().map(Into::into).collect();
+    let serialized_templates = serde_json::to_string_pretty(&templates)?;
+    std::fs::write(path, serialized_templates)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template_variables() {
+        assert!(template_variables("No variables here").is_empty());
+        assert_eq!(
+            template_variables("Review this {{language}} diff:\n{{ diff }}"),
+            vec!["language", "diff"]
+        );
+        assert_eq!(
+            template_variables("{{name}} and {{name}} again"),
+            vec!["name"]
+        );
+    }
+
+    #[test]
+    fn test_template_variables_ignores_invalid_placeholders() {
+        assert!(template_variables("Unterminated {{variable").is_empty());
+        assert!(template_variables("Empty {{}} placeholder").is_empty());
+        assert!(template_variables("let x = {{\"a\": 1}};").is_empty());
+    }
+
+    #[test]
+    fn test_normalize_template_name() {
+        assert_eq!(normalize_template_name("review"), "review");
+        assert_eq!(
+            normalize_template_name("  code   review\tdiff "),
+            "code-review-diff"
+        );
+        assert_eq!(normalize_template_name(" \n "), "");
+    }
+
+    #[test]
+    fn test_fill_template() {
+        let values = HashMap::from([
+            (String::from("language"), String::from("Rust")),
+            (String::from("code"), String::from("fn main() {}")),
+        ]);
+        assert_eq!(
+            fill_template(
+                "Write tests for this {{ language }} code:\n{{code}}",
+                &values
+            ),
+            "Write tests for this Rust code:\nfn main() {}"
+        );
+        assert_eq!(
+            fill_template("{{language}} {{unknown}} {{language", &values),
+            "Rust {{unknown}} {{language"
+        );
+    }
+
+    #[test]
+    fn test_writing_and_reading_templates_file() {
+        let mut path = std::env::temp_dir();
+        path.push(format!("pincer_chat_templates_{}.json", std::process::id()));
+        let templates = vec![PromptTemplate {
+            id: 1,
+            name: String::from("review"),
+            content: String::from("Review this diff:\n{{diff}}"),
+            created_at: chrono::NaiveDateTime::default(),
+        }];
+
+        write_templates_file(&path, templates).expect("Writing templates file should work");
+        let entries = read_templates_file(&path).expect("Reading templates file should work");
+        std::fs::remove_file(&path).expect("Deleting templates file should work");
+
+        assert_eq!(
+            entries,
+            vec![PromptTemplateEntry {
+                name: String::from("review"),
+                content: String::from("Review this diff:\n{{diff}}"),
+            }]
+        );
+    }
+}
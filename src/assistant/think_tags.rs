@@ -0,0 +1,287 @@
+const THINK_START_TAG: &str = "<think>";
+const THINK_END_TAG: &str = "</think>";
+
+/// Streaming parser that strips `<think></think>` blocks from model output.
+///
+/// Chunks are fed one at a time with [`ThinkTagParser::push`], which returns
+/// the visible text that can safely be shown. Tags split across chunks are
+/// held back until they can be resolved, and [`ThinkTagParser::finish`] must
+/// be called once the stream ends to flush any held back text.
+///
+/// The parser handles:
+/// - multiple think blocks in the same response,
+/// - unterminated think blocks, whose content is never emitted,
+/// - a closing tag without a matching opening tag before any other tag, in which case
+///   the text before it is reasoning, since some chat templates open the think block
+///   themselves. Text that was already emitted must then be discarded, which
+///   [`ThinkTagParser::take_discard`] tells,
+/// - other stray closing tags, which are dropped.
+#[derive(Debug, Default)]
+pub struct ThinkTagParser {
+    in_think_block: bool,
+    pending: String,
+    /// Whether a tag was found, after which the text cannot be reasoning anymore.
+    seen_tag: bool,
+    /// Whether text was emitted before any tag was found.
+    emitted_leading_text: bool,
+    discard: bool,
+}
+
+impl ThinkTagParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of text into the parser and returns its visible part.
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut buffer = std::mem::take(&mut self.pending);
+        buffer.push_str(chunk);
+
+        let mut visible = String::new();
+        let mut rest = buffer.as_str();
+
+        loop {
+            if self.in_think_block {
+                match rest.find(THINK_END_TAG) {
+                    Some(index) => {
+                        rest = &rest[index + THINK_END_TAG.len()..];
+                        self.in_think_block = false;
+                    }
+                    None => {
+                        // Only a possible partial closing tag needs to be kept,
+                        // the rest is thinking content and can be discarded.
+                        let split = partial_tag_start(rest, &[THINK_END_TAG]);
+                        self.pending = rest[split..].to_string();
+                        return visible;
+                    }
+                }
+            } else {
+                let start = rest.find(THINK_START_TAG);
+                let end = rest.find(THINK_END_TAG);
+                match (start, end) {
+                    (Some(start), None) => {
+                        visible.push_str(&rest[..start]);
+                        rest = &rest[start + THINK_START_TAG.len()..];
+                        self.in_think_block = true;
+                    }
+                    (Some(start), Some(end)) if start < end => {
+                        visible.push_str(&rest[..start]);
+                        rest = &rest[start + THINK_START_TAG.len()..];
+                        self.in_think_block = true;
+                    }
+                    (_, Some(end)) if !self.seen_tag => {
+                        // The think block was opened by the chat template,
+                        // so everything before this closing tag is reasoning
+                        visible.clear();
+                        self.discard = self.emitted_leading_text;
+                        self.emitted_leading_text = false;
+                        rest = &rest[end + THINK_END_TAG.len()..];
+                    }
+                    (_, Some(end)) => {
+                        // Stray closing tag, drop it
+                        visible.push_str(&rest[..end]);
+                        rest = &rest[end + THINK_END_TAG.len()..];
+                    }
+                    (None, None) => {
+                        let split = partial_tag_start(rest, &[THINK_START_TAG, THINK_END_TAG]);
+                        visible.push_str(&rest[..split]);
+                        self.pending = rest[split..].to_string();
+                        if !self.seen_tag && !visible.is_empty() {
+                            self.emitted_leading_text = true;
+                        }
+                        return visible;
+                    }
+                }
+                self.seen_tag = true;
+            }
+        }
+    }
+
+    /// Returns true if the text emitted so far was reasoning and must be discarded,
+    /// which is only reported once.
+    pub fn take_discard(&mut self) -> bool {
+        std::mem::replace(&mut self.discard, false)
+    }
+
+    /// Flushes the text held back by the parser and resets it.
+    pub fn finish(&mut self) -> String {
+        let parser = std::mem::take(self);
+        if parser.in_think_block {
+            String::new()
+        } else {
+            parser.pending
+        }
+    }
+}
+
+/// Removes all `<think></think>` blocks from a complete text.
+#[cfg(test)]
+pub fn remove_think_tags(text: &str) -> String {
+    let mut parser = ThinkTagParser::new();
+    let mut result = parser.push(text);
+    result.push_str(&parser.finish());
+    result
+}
+
+/// Returns the byte index at which a suffix of `text` that is a proper prefix
+/// of one of the given tags starts, or the length of `text` if there is none.
+fn partial_tag_start(text: &str, tags: &[&str]) -> usize {
+    let mut split = text.len();
+    for tag in tags {
+        for length in (1..tag.len()).rev() {
+            if length > text.len() {
+                continue;
+            }
+            let index = text.len() - length;
+            if text.is_char_boundary(index) && tag.starts_with(&text[index..]) {
+                split = split.min(index);
+                break;
+            }
+        }
+    }
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn parse_in_chunks(text: &str, chunk_sizes: &[usize]) -> String {
+        let mut parser = ThinkTagParser::new();
+        let mut result = String::new();
+        let mut rest = text;
+        for size in chunk_sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let mut index = (*size).max(1).min(rest.len());
+            while !rest.is_char_boundary(index) {
+                index += 1;
+            }
+            let visible = parser.push(&rest[..index]);
+            if parser.take_discard() {
+                result.clear();
+            }
+            result.push_str(&visible);
+            rest = &rest[index..];
+        }
+        result.push_str(&parser.finish());
+        result
+    }
+
+    #[test]
+    fn test_text_without_tags_is_unchanged() {
+        assert_eq!(remove_think_tags(""), "");
+        assert_eq!(remove_think_tags("Hello world"), "Hello world");
+        assert_eq!(remove_think_tags("a < b and c > d"), "a < b and c > d");
+    }
+
+    #[test]
+    fn test_single_think_block() {
+        assert_eq!(
+            remove_think_tags("<think>Some reasoning</think>The answer"),
+            "The answer"
+        );
+        assert_eq!(
+            remove_think_tags("Before<think>reasoning</think>After"),
+            "BeforeAfter"
+        );
+    }
+
+    #[test]
+    fn test_multiple_think_blocks() {
+        assert_eq!(
+            remove_think_tags("<think>one</think>A<think>two</think>B<think>three</think>"),
+            "AB"
+        );
+    }
+
+    #[test]
+    fn test_unterminated_think_block() {
+        assert_eq!(remove_think_tags("Answer<think>still thinking"), "Answer");
+        assert_eq!(remove_think_tags("<think>"), "");
+    }
+
+    #[test]
+    fn test_text_before_leading_closing_tag_is_reasoning() {
+        assert_eq!(remove_think_tags("reasoning</think>Answer"), "Answer");
+        assert_eq!(remove_think_tags("</think><think>x</think>y"), "y");
+        assert_eq!(remove_think_tags("a</think>b</think>c"), "bc");
+    }
+
+    #[test]
+    fn test_stray_closing_tag_is_dropped() {
+        assert_eq!(remove_think_tags("<think>a</think>b</think>c"), "bc");
+    }
+
+    #[test]
+    fn test_emitted_reasoning_is_discarded() {
+        let mut parser = ThinkTagParser::new();
+        assert_eq!(parser.push("Some reas"), "Some reas");
+        assert!(!parser.take_discard());
+        assert_eq!(parser.push("oning</think>The"), "The");
+        assert!(parser.take_discard());
+        assert!(!parser.take_discard());
+        assert_eq!(parser.push(" answer</think>"), " answer");
+        assert!(!parser.take_discard());
+        assert_eq!(parser.finish(), "");
+    }
+
+    #[test]
+    fn test_nested_opening_tag_inside_think_block() {
+        assert_eq!(
+            remove_think_tags("<think>a<think>b</think>Answer"),
+            "Answer"
+        );
+    }
+
+    #[test]
+    fn test_partial_tag_at_end_of_text_is_kept() {
+        assert_eq!(remove_think_tags("Answer <thi"), "Answer <thi");
+        assert_eq!(remove_think_tags("Answer </"), "Answer </");
+    }
+
+    #[test]
+    fn test_tags_split_across_chunks() {
+        let mut parser = ThinkTagParser::new();
+        assert_eq!(parser.push("Hello <th"), "Hello ");
+        assert_eq!(parser.push("ink>reason"), "");
+        assert_eq!(parser.push("ing</thi"), "");
+        assert_eq!(parser.push("nk> world"), " world");
+        assert_eq!(parser.finish(), "");
+    }
+
+    #[test]
+    fn test_finish_resets_parser() {
+        let mut parser = ThinkTagParser::new();
+        assert_eq!(parser.push("<think>unterminated"), "");
+        assert_eq!(parser.finish(), "");
+        assert_eq!(parser.push("visible"), "visible");
+        assert_eq!(parser.finish(), "");
+    }
+
+    #[test]
+    fn test_random_chunk_splits_match_whole_text() {
+        let fragments = [
+            "<think>", "</think>", "<", "</", "<thi", "think>", "Hello", " world", "é", "🦀", "\n",
+            "a < b",
+        ];
+        let mut rng = rand::rng();
+        for _ in 0..500 {
+            let n_fragments = rng.random_range(0..20);
+            let text: String = (0..n_fragments)
+                .map(|_| fragments[rng.random_range(0..fragments.len())])
+                .collect();
+            let chunk_sizes: Vec<usize> = (0..5).map(|_| rng.random_range(1..10)).collect();
+
+            let expected = remove_think_tags(&text);
+            let actual = parse_in_chunks(&text, &chunk_sizes);
+            assert_eq!(
+                expected, actual,
+                "Text: {text:?}, chunk sizes: {chunk_sizes:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+
+use crate::assistant::database::{backup, Database};
+use crate::config::CliOptions;
+
+/// Runs the database commands given on the command line.
+pub fn run_database_commands(options: &CliOptions, database_url: String) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let database = Database::new(database_url).await?;
+
+        if options.list_backups {
+            for backup in database.list_backups()? {
+                println!(
+                    "{}\t{}",
+                    backup.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    backup.path.display()
+                );
+            }
+        }
+
+        if let Some(backup_path) = &options.restore_backup {
+            let passphrase = if backup::is_backup_encrypted(backup_path)? {
+                Some(read_passphrase("Passphrase of the backup: ")?)
+            } else {
+                None
+            };
+            database
+                .restore_backup(backup_path.clone(), passphrase.as_deref())
+                .await?;
+            println!("Restored backup {}", backup_path.display());
+        }
+
+        if options.backup {
+            if database.is_encrypted()? {
+                database
+                    .unlock(&read_passphrase("Passphrase of the database: ")?)
+                    .await?;
+            }
+            let backup = database.backup().await?;
+            println!("Created backup {}", backup.path.display());
+        }
+        Ok(())
+    })
+}
+
+fn read_passphrase(prompt: &str) -> Result<String> {
+    eprint!("{prompt}");
+    std::io::stderr().flush()?;
+    let mut passphrase = String::new();
+    std::io::stdin().lock().read_line(&mut passphrase)?;
+    Ok(passphrase.trim_end_matches(['\r', '\n']).to_string())
+}
@@ -1,4 +1,7 @@
 pub mod assistant_parameters;
 pub mod chat_input;
+pub mod encryption_settings;
 pub mod message_bubble;
+pub mod model_comparison;
+pub mod response_format;
 pub mod thread_list;
@@ -1,10 +1,20 @@
 use gtk::prelude::*;
 use relm4::prelude::*;
+use relm4::RelmRemoveAllExt;
+
+use crate::assistant::backend::{KeepAlive, LoadedModel};
+use crate::assistant::{model_labels, ModelChoice};
 
 #[derive(Debug)]
 pub struct AssistantParametersComponent {
-    model: String,
+    /// Models of every backend, grouped by backend.
+    models: Vec<ModelChoice>,
+    /// Model of the model selection, updated when the models change.
+    model_list: gtk::StringList,
+    /// Backend id and name of the selected model.
+    selected_model: Option<(i64, String)>,
     generation_parameters: GenerationParameters,
+    keep_alive: Option<KeepAlive>,
 }
 
 #[derive(Debug)]
@@ -26,20 +36,37 @@ impl Default for GenerationParameters {
 
 #[derive(Debug)]
 pub enum AssistantParametersInputMsg {
-    SelectModel(String),
+    /// Replaces the models that can be selected, when backends change.
+    SetModels(Vec<ModelChoice>),
+    /// Index of the model selected by the user.
+    SelectModel(usize),
+    /// Shows the model of the given backend in the model selection, without selecting it.
+    ShowModel(i64, String),
     Temperature(f64),
     TopK(u64),
     TopP(f64),
     ResetParameters,
+    /// Index of the keep-alive duration in [`KeepAlive::CHOICES`].
+    KeepAlive(usize),
+    RefreshLoadedModels,
+    /// Models in the memory of the selected backend, or why they could not be listed.
+    SetLoadedModels(Result<Vec<LoadedModel>, String>),
+    PreloadModel,
+    UnloadModel(String),
 }
 
 #[derive(Debug)]
 pub enum AssistantParametersOutputMsg {
-    SelectModel(String),
+    /// Backend id and name of the selected model.
+    SelectModel(i64, String),
     Temperature(f64),
     TopK(u64),
     TopP(f64),
     ResetParameters,
+    KeepAlive(Option<KeepAlive>),
+    ListLoadedModels,
+    PreloadModel,
+    UnloadModel(String),
 }
 
 #[relm4::widget_template(pub)]
@@ -57,7 +84,8 @@ impl WidgetTemplate for ParameterSpinButton {
 
 #[relm4::component(pub)]
 impl Component for AssistantParametersComponent {
-    type Init = Vec<String>;
+    /// Models of every backend, and backend id and name of the selected model.
+    type Init = (Vec<ModelChoice>, Option<(i64, String)>);
     type Input = AssistantParametersInputMsg;
     type Output = AssistantParametersOutputMsg;
     type CommandOutput = ();
@@ -84,15 +112,53 @@ impl Component for AssistantParametersComponent {
                     set_hexpand: true,
                     set_halign: gtk::Align::Fill,
                     set_css_classes: &["dropdown", "model_dropdown"],
+                    set_model: Some(&model.model_list),
 
                     connect_selected_notify[sender] => move |model_drop_down| {
                         sender.input(AssistantParametersInputMsg::SelectModel(
-                            model_drop_down
-                            .selected_item()
-                            .expect("Getting selected item from dropdown should work")
-                            .downcast::<gtk::StringObject>()
-                            .expect("Conversion of gtk StringObject to String should work")
-                            .into()))
+                            model_drop_down.selected() as usize,
+                        ));
+                    } @model_selected_handler,
+                },
+
+                gtk::MenuButton {
+                    set_icon_name: "media-flash-symbolic",
+                    set_tooltip_text: Some("Models in memory"),
+                    set_direction: gtk::ArrowType::Down,
+                    set_css_classes: &["button", "loaded_models_button"],
+
+                    #[wrap(Some)]
+                    set_popover: loaded_models_popover = &gtk::Popover {
+                        set_position: gtk::PositionType::Bottom,
+                        connect_show => AssistantParametersInputMsg::RefreshLoadedModels,
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_margin_all: 5,
+                            set_spacing: 5,
+
+                            gtk::Label {
+                                set_label: "Models in memory",
+                                set_css_classes: &["heading"],
+                            },
+                            #[name = "loaded_models_box"]
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 5,
+                            },
+                            #[name = "loaded_models_status_label"]
+                            gtk::Label {
+                                set_wrap: true,
+                                set_max_width_chars: 40,
+                                set_css_classes: &["dim-label"],
+                            },
+                            gtk::Button {
+                                set_label: "Load selected model",
+                                set_tooltip_text: Some("Load the model now, so that the next answer starts quickly"),
+                                set_css_classes: &["button", "preload_model_button"],
+                                connect_clicked => AssistantParametersInputMsg::PreloadModel,
+                            },
+                        },
                     },
                 },
 
@@ -174,6 +240,26 @@ impl Component for AssistantParametersComponent {
                                 },
                             },
 
+                            // Keep-alive
+                            #[template]
+                            ParameterSpinButton {
+                                gtk::Label {
+                                    set_label: "Keep model in memory",
+                                    set_halign: gtk::Align::Fill,
+                                    set_justify: gtk::Justification::Left,
+                                },
+                                gtk::DropDown::from_strings(&keep_alive_labels) {
+                                    set_halign: gtk::Align::Fill,
+                                    set_css_classes: &["dropdown"],
+
+                                    connect_selected_notify[sender] => move |drop_down| {
+                                        sender.input(AssistantParametersInputMsg::KeepAlive(
+                                            drop_down.selected() as usize,
+                                        ));
+                                    },
+                                },
+                            },
+
                             gtk::Button {
                                 set_hexpand: true,
                                 set_halign: gtk::Align::Fill,
@@ -190,29 +276,40 @@ impl Component for AssistantParametersComponent {
     }
 
     fn init(
-        models: Self::Init,
+        (models, selected_model): Self::Init,
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
+        let model_list = gtk::StringList::default();
+        for label in model_labels(&models) {
+            model_list.append(&label);
+        }
         let model = AssistantParametersComponent {
-            model: models[0].clone(),
+            models,
+            model_list,
+            selected_model,
             generation_parameters: GenerationParameters::default(),
+            keep_alive: None,
         };
 
+        let keep_alive_labels: Vec<String> = KeepAlive::CHOICES
+            .into_iter()
+            .map(KeepAlive::label)
+            .collect();
+        let keep_alive_labels: Vec<&str> = keep_alive_labels.iter().map(String::as_str).collect();
         let widgets = view_output!();
-
-        let model_list = gtk::StringList::default();
-        for model_name in models {
-            model_list.append(&model_name);
-        }
-        widgets
-            .model_selection_drop_down
-            .set_model(Some(&model_list));
+        model.show_selected_model(&widgets);
 
         ComponentParts { model, widgets }
     }
 
-    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _: &Self::Root) {
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        _: &Self::Root,
+    ) {
         match message {
             AssistantParametersInputMsg::Temperature(value) => {
                 self.generation_parameters.temperature = value;
@@ -238,12 +335,151 @@ impl Component for AssistantParametersComponent {
                     .output_sender()
                     .emit(AssistantParametersOutputMsg::ResetParameters);
             }
-            AssistantParametersInputMsg::SelectModel(model) => {
-                self.model = model.clone();
+            AssistantParametersInputMsg::KeepAlive(index) => {
+                if let Some(keep_alive) = KeepAlive::CHOICES.get(index) {
+                    self.keep_alive = *keep_alive;
+                    sender
+                        .output_sender()
+                        .emit(AssistantParametersOutputMsg::KeepAlive(self.keep_alive));
+                }
+            }
+            AssistantParametersInputMsg::RefreshLoadedModels => {
+                widgets
+                    .loaded_models_status_label
+                    .set_label("Listing models...");
+                sender
+                    .output_sender()
+                    .emit(AssistantParametersOutputMsg::ListLoadedModels);
+            }
+            AssistantParametersInputMsg::SetLoadedModels(loaded_models) => {
+                show_loaded_models(widgets, loaded_models, sender.input_sender());
+            }
+            AssistantParametersInputMsg::PreloadModel => {
+                if let Some((_, model)) = &self.selected_model {
+                    widgets
+                        .loaded_models_status_label
+                        .set_label(&format!("Loading {model}..."));
+                    sender
+                        .output_sender()
+                        .emit(AssistantParametersOutputMsg::PreloadModel);
+                }
+            }
+            AssistantParametersInputMsg::UnloadModel(model) => {
+                widgets
+                    .loaded_models_status_label
+                    .set_label(&format!("Unloading {model}..."));
                 sender
                     .output_sender()
-                    .emit(AssistantParametersOutputMsg::SelectModel(model));
+                    .emit(AssistantParametersOutputMsg::UnloadModel(model));
+            }
+            AssistantParametersInputMsg::SetModels(models) => {
+                let labels = model_labels(&models);
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                // Replacing the items changes the selection, which the user did not do
+                widgets
+                    .model_selection_drop_down
+                    .block_signal(&widgets.model_selected_handler);
+                self.model_list
+                    .splice(0, self.model_list.n_items(), &labels);
+                widgets
+                    .model_selection_drop_down
+                    .unblock_signal(&widgets.model_selected_handler);
+                self.models = models;
+                self.show_selected_model(widgets);
+            }
+            AssistantParametersInputMsg::ShowModel(backend_id, model) => {
+                self.selected_model = Some((backend_id, model));
+                self.show_selected_model(widgets);
+            }
+            AssistantParametersInputMsg::SelectModel(index) => {
+                if let Some(choice) = self.models.get(index) {
+                    let selected_model = (choice.backend_id, choice.model.clone());
+                    if self.selected_model.as_ref() != Some(&selected_model) {
+                        self.selected_model = Some(selected_model.clone());
+                        sender
+                            .output_sender()
+                            .emit(AssistantParametersOutputMsg::SelectModel(
+                                selected_model.0,
+                                selected_model.1,
+                            ));
+                    }
+                }
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+}
+
+impl AssistantParametersComponent {
+    fn show_selected_model(&self, widgets: &AssistantParametersComponentWidgets) {
+        let Some((backend_id, model)) = &self.selected_model else {
+            return;
+        };
+        if let Some(position) = self
+            .models
+            .iter()
+            .position(|choice| choice.is(*backend_id, model))
+        {
+            widgets
+                .model_selection_drop_down
+                .block_signal(&widgets.model_selected_handler);
+            widgets
+                .model_selection_drop_down
+                .set_selected(position as u32);
+            widgets
+                .model_selection_drop_down
+                .unblock_signal(&widgets.model_selected_handler);
+        }
+    }
+}
+
+/// Lists the models in memory, each with the memory it uses and a button to unload it.
+fn show_loaded_models(
+    widgets: &AssistantParametersComponentWidgets,
+    loaded_models: Result<Vec<LoadedModel>, String>,
+    sender: &relm4::Sender<AssistantParametersInputMsg>,
+) {
+    widgets.loaded_models_box.remove_all();
+    let loaded_models = match loaded_models {
+        Ok(loaded_models) => loaded_models,
+        Err(error) => {
+            widgets.loaded_models_status_label.set_label(&error);
+            return;
+        }
+    };
+    widgets
+        .loaded_models_status_label
+        .set_label(match loaded_models.is_empty() {
+            true => "No model is loaded",
+            false => "",
+        });
+    for loaded_model in loaded_models {
+        let name = loaded_model.name.clone();
+        relm4::view! {
+            loaded_model_row = gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 10,
+
+                gtk::Label {
+                    set_hexpand: true,
+                    set_halign: gtk::Align::Start,
+                    set_label: &loaded_model.name,
+                },
+                gtk::Label {
+                    set_label: &loaded_model.memory_usage(),
+                    set_css_classes: &["dim-label"],
+                },
+                gtk::Button {
+                    set_icon_name: "media-eject-symbolic",
+                    set_tooltip_text: Some("Unload model"),
+                    set_css_classes: &["button", "unload_model_button"],
+
+                    connect_clicked[sender] => move |_| {
+                        sender.emit(AssistantParametersInputMsg::UnloadModel(name.clone()));
+                    },
+                },
             }
         }
+        widgets.loaded_models_box.append(&loaded_model_row);
     }
 }
@@ -1,103 +1,680 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use gtk::gdk;
+use gtk::glib;
 use gtk::prelude::*;
 use relm4::prelude::*;
+use relm4::RelmRemoveAllExt;
+use relm4_components::open_dialog::{
+    OpenDialog, OpenDialogMsg, OpenDialogResponse, OpenDialogSettings,
+};
+use relm4_components::save_dialog::{
+    SaveDialog, SaveDialogMsg, SaveDialogResponse, SaveDialogSettings,
+};
+
+use crate::assistant::database::models::PromptTemplate;
+use crate::assistant::templates::{fill_template, normalize_template_name, template_variables};
+
+/// How long to wait after the last edit before saving the draft.
+const DRAFT_SAVE_DELAY: Duration = Duration::from_secs(1);
+/// Prefix used to search prompt templates from the chat input.
+const TEMPLATE_COMMAND_PREFIX: char = '/';
+const TEMPLATES_EXPORT_FILENAME: &str = "prompt_templates.json";
 
 #[derive(Debug)]
 pub struct ChatInputComponent {
     enabled: bool,
-    user_input: gtk::EntryBuffer,
+    user_input: gtk::TextBuffer,
+    // Prompt history
+    history: Vec<String>,
+    history_index: Option<usize>,
+    input_before_history: String,
+    // Drafts
+    thread_id: Option<i64>,
+    saved_draft: String,
+    draft_counter: u64,
+    // Prompt templates
+    templates: Vec<PromptTemplate>,
+    template_matches: Vec<PromptTemplate>,
+    show_template_picker: bool,
+    template_picker: gtk::ListBox,
+    templates_box: gtk::Box,
+    new_template_name: gtk::EntryBuffer,
+    selected_template: Option<PromptTemplate>,
+    variables_box: gtk::Box,
+    variable_entries: Vec<(String, gtk::Entry)>,
+    open_dialog: Controller<OpenDialog>,
+    save_dialog: Controller<SaveDialog>,
 }
 
 #[derive(Debug)]
 pub enum ChatInputInputMsg {
-    Disable,
+    SetEnabled(bool),
     Submit,
+    HistoryPrevious,
+    HistoryNext,
+    InputChanged,
+    LoadDraft(i64, String),
+    // Prompt templates
+    UpdateTemplates(Vec<PromptTemplate>),
+    SelectTemplate(i64),
+    SelectTemplateMatch(usize),
+    CloseTemplatePicker,
+    InsertTemplate,
+    CancelTemplate,
+    SaveTemplate,
+    DeleteTemplate(i64),
+    ImportTemplates,
+    ExportTemplates,
+    ImportTemplatesFile(PathBuf),
+    ExportTemplatesFile(PathBuf),
+    CancelDialog,
 }
 
 #[derive(Debug)]
 pub enum ChatInputOutputMsg {
     SubmitUserInput(String),
+    SaveDraft(i64, String),
+    SaveTemplate(String, String),
+    DeleteTemplate(i64),
+    ImportTemplates(PathBuf),
+    ExportTemplates(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum ChatInputCmdMsg {
+    SaveDraft(u64),
 }
 
 impl ChatInputComponent {
     fn disable(&mut self) {
         self.enabled = false;
     }
+
+    fn text(&self) -> String {
+        let (start, end) = self.user_input.bounds();
+        self.user_input.text(&start, &end, false).to_string()
+    }
+
+    fn save_draft(&mut self, sender: &ComponentSender<Self>) {
+        let Some(thread_id) = self.thread_id else {
+            return;
+        };
+        let text = self.text();
+        if text == self.saved_draft {
+            return;
+        }
+        self.saved_draft = text.clone();
+        sender
+            .output_sender()
+            .emit(ChatInputOutputMsg::SaveDraft(thread_id, text));
+    }
+
+    /// Shows the templates matching the `/` command being typed, if any.
+    fn search_templates(&mut self) {
+        let text = self.text();
+        let query = match text.strip_prefix(TEMPLATE_COMMAND_PREFIX) {
+            Some(query) if !query.contains(char::is_whitespace) => query.to_lowercase(),
+            _ => {
+                self.show_template_picker = false;
+                return;
+            }
+        };
+        self.template_matches = self
+            .templates
+            .iter()
+            .filter(|template| template.name.to_lowercase().contains(&query))
+            .cloned()
+            .collect();
+        self.show_template_picker = !self.template_matches.is_empty();
+
+        self.template_picker.remove_all();
+        for template in self.template_matches.iter() {
+            relm4::view! {
+                template_row = gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 10,
+                    set_margin_all: 5,
+
+                    gtk::Label {
+                        set_label: &format!("{TEMPLATE_COMMAND_PREFIX}{}", template.name),
+                        set_css_classes: &["template_name"],
+                    },
+
+                    gtk::Label {
+                        set_label: &template.content.replace('\n', " "),
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                        set_ellipsize: gtk::pango::EllipsizeMode::End,
+                        set_css_classes: &["template_preview"],
+                    },
+                }
+            }
+            self.template_picker.append(&template_row);
+        }
+    }
+
+    fn refresh_templates(&self, sender: &relm4::Sender<ChatInputInputMsg>) {
+        self.templates_box.remove_all();
+
+        for template in self.templates.iter() {
+            let template_id = template.id;
+            relm4::view! {
+                template_row = gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+
+                    gtk::Button {
+                        set_label: &template.name,
+                        set_hexpand: true,
+                        set_tooltip_text: Some(template.content.as_str()),
+                        set_css_classes: &["button", "template_button"],
+
+                        connect_clicked[sender] => move |_| {
+                            sender.emit(ChatInputInputMsg::SelectTemplate(template_id));
+                        },
+                    },
+
+                    gtk::Button {
+                        set_icon_name: "edit-delete-symbolic",
+                        set_tooltip_text: Some("Delete template"),
+                        set_css_classes: &["button", "delete_template_button"],
+
+                        connect_clicked[sender] => move |_| {
+                            sender.emit(ChatInputInputMsg::DeleteTemplate(template_id));
+                        },
+                    },
+                }
+            }
+            self.templates_box.append(&template_row);
+        }
+    }
+
+    /// Inserts a template in the input, first asking for the values of its variables if it has any.
+    fn use_template(
+        &mut self,
+        template: PromptTemplate,
+        sender: &relm4::Sender<ChatInputInputMsg>,
+    ) {
+        tracing::info!("Using prompt template {}", template.name);
+        self.show_template_picker = false;
+        let variables = template_variables(&template.content);
+        if variables.is_empty() {
+            self.user_input.set_text(&template.content);
+            return;
+        }
+
+        self.variables_box.remove_all();
+        self.variable_entries.clear();
+        for variable in variables {
+            relm4::view! {
+                variable_entry = gtk::Entry {
+                    set_hexpand: true,
+                    set_placeholder_text: Some(variable.as_str()),
+                    set_tooltip_text: Some(variable.as_str()),
+
+                    connect_activate[sender] => move |_| {
+                        sender.emit(ChatInputInputMsg::InsertTemplate);
+                    },
+                }
+            }
+            self.variables_box.append(&variable_entry);
+            self.variable_entries.push((variable, variable_entry));
+        }
+        if let Some((_, entry)) = self.variable_entries.first() {
+            entry.grab_focus();
+        }
+        self.selected_template = Some(template);
+    }
+
+    fn clear_selected_template(&mut self) {
+        self.selected_template = None;
+        self.variables_box.remove_all();
+        self.variable_entries.clear();
+    }
 }
 
 #[relm4::component(pub)]
 impl Component for ChatInputComponent {
-    type Init = ();
+    type Init = (Vec<String>, Vec<PromptTemplate>);
     type Input = ChatInputInputMsg;
     type Output = ChatInputOutputMsg;
-    type CommandOutput = ();
+    type CommandOutput = ChatInputCmdMsg;
 
     view! {
         #[root]
-        chat_input_container = gtk::Box {
-            set_orientation: gtk::Orientation::Horizontal,
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
             set_margin_all: 5,
             set_spacing: 5,
             #[watch]
             set_sensitive: model.enabled,
 
-            #[name = "text_input"]
-            gtk::Entry {
-                set_buffer: &model.user_input,
+            // Templates matching the `/` command being typed
+            gtk::Revealer {
+                set_transition_type: gtk::RevealerTransitionType::SlideUp,
                 #[watch]
-                set_tooltip_text: Some("Write a message"),
+                set_reveal_child: model.show_template_picker,
+
+                gtk::ScrolledWindow {
+                    set_hscrollbar_policy: gtk::PolicyType::Never,
+                    set_propagate_natural_height: true,
+                    set_max_content_height: 200,
+
+                    #[local_ref]
+                    template_picker -> gtk::ListBox {
+                        set_selection_mode: gtk::SelectionMode::None,
+                        set_css_classes: &["template_picker"],
+                    },
+                },
+            },
+
+            // Values of the variables of the selected template
+            gtk::Revealer {
+                set_transition_type: gtk::RevealerTransitionType::SlideUp,
                 #[watch]
-                set_placeholder_text: if model.enabled { Some("Write a message") } else { Some("Loading ...") },
-                set_hexpand: true,
-                set_halign: gtk::Align::Fill,
-                set_css_classes: &["user_input"],
+                set_reveal_child: model.selected_template.is_some(),
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                    set_css_classes: &["template_variables"],
+
+                    gtk::Label {
+                        #[watch]
+                        set_label: &model
+                            .selected_template
+                            .as_ref()
+                            .map(|template| format!("{TEMPLATE_COMMAND_PREFIX}{}", template.name))
+                            .unwrap_or_default(),
+                    },
+
+                    #[local_ref]
+                    variables_box -> gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_hexpand: true,
+                        set_spacing: 5,
+                    },
 
-                connect_activate => ChatInputInputMsg::Submit,
+                    gtk::Button {
+                        set_label: "Insert",
+                        set_tooltip_text: Some("Insert template with these values"),
+                        set_css_classes: &["button"],
+                        connect_clicked => ChatInputInputMsg::InsertTemplate,
+                    },
+
+                    gtk::Button {
+                        set_icon_name: "window-close-symbolic",
+                        set_tooltip_text: Some("Cancel"),
+                        set_css_classes: &["button"],
+                        connect_clicked => ChatInputInputMsg::CancelTemplate,
+                    },
+                },
             },
 
-            #[name = "submit_button"]
-            gtk::Button {
-                set_tooltip_text: Some("Submit message"),
-                set_icon_name: "document-send-symbolic",
-                set_css_classes: &["button", "submit_message_button"],
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+
+                gtk::MenuButton {
+                    set_icon_name: "accessories-text-editor-symbolic",
+                    set_tooltip_text: Some("Prompt templates"),
+                    set_direction: gtk::ArrowType::Up,
+                    set_valign: gtk::Align::End,
+                    set_css_classes: &["button", "templates_menu_button"],
+
+                    #[wrap(Some)]
+                    set_popover = &gtk::Popover {
+                        set_position: gtk::PositionType::Top,
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 5,
+
+                            #[local_ref]
+                            templates_box -> gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 5,
+                            },
+
+                            gtk::Entry {
+                                set_buffer: &model.new_template_name,
+                                set_placeholder_text: Some("Save input as template"),
+                                set_tooltip_text: Some("Use {{variable}} in the input for values to fill in"),
+                                connect_activate => ChatInputInputMsg::SaveTemplate,
+                            },
+
+                            gtk::Box {
+                                set_orientation: gtk::Orientation::Horizontal,
+                                set_spacing: 5,
+                                set_homogeneous: true,
+
+                                gtk::Button {
+                                    set_label: "Import",
+                                    set_tooltip_text: Some("Import templates from a file"),
+                                    set_css_classes: &["button"],
+                                    connect_clicked => ChatInputInputMsg::ImportTemplates,
+                                },
+
+                                gtk::Button {
+                                    set_label: "Export",
+                                    set_tooltip_text: Some("Export templates to a file"),
+                                    set_css_classes: &["button"],
+                                    connect_clicked => ChatInputInputMsg::ExportTemplates,
+                                },
+                            },
+                        },
+                    },
+                },
+
+                // The input grows with its content up to a maximum height
+                gtk::ScrolledWindow {
+                    set_hexpand: true,
+                    set_halign: gtk::Align::Fill,
+                    set_hscrollbar_policy: gtk::PolicyType::Never,
+                    set_propagate_natural_height: true,
+                    set_max_content_height: 200,
+
+                    #[name = "text_input"]
+                    gtk::TextView {
+                        set_buffer: Some(&model.user_input),
+                        set_tooltip_text: Some("Write a message. Press Shift+Enter to add a new line, or type / to use a template"),
+                        set_wrap_mode: gtk::WrapMode::WordChar,
+                        set_accepts_tab: false,
+                        set_css_classes: &["user_input"],
+                    },
+                },
+
+                #[name = "submit_button"]
+                gtk::Button {
+                    set_tooltip_text: Some("Submit message"),
+                    set_icon_name: "document-send-symbolic",
+                    set_valign: gtk::Align::End,
+                    set_css_classes: &["button", "submit_message_button"],
 
-                connect_clicked => ChatInputInputMsg::Disable,
+                    connect_clicked => ChatInputInputMsg::Submit,
+                },
             },
         },
     }
 
     fn init(
-        _: Self::Init,
+        init: Self::Init,
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
+        let (history, templates) = init;
+
+        let json_filter = gtk::FileFilter::new();
+        json_filter.set_name(Some("JSON files"));
+        json_filter.add_pattern("*.json");
+
+        let open_dialog = OpenDialog::builder()
+            .transient_for_native(&root)
+            .launch(OpenDialogSettings {
+                filters: vec![json_filter.clone()],
+                ..OpenDialogSettings::default()
+            })
+            .forward(sender.input_sender(), |response| match response {
+                OpenDialogResponse::Accept(path) => ChatInputInputMsg::ImportTemplatesFile(path),
+                OpenDialogResponse::Cancel => ChatInputInputMsg::CancelDialog,
+            });
+        let save_dialog = SaveDialog::builder()
+            .transient_for_native(&root)
+            .launch(SaveDialogSettings {
+                filters: vec![json_filter],
+                ..SaveDialogSettings::default()
+            })
+            .forward(sender.input_sender(), |response| match response {
+                SaveDialogResponse::Accept(path) => ChatInputInputMsg::ExportTemplatesFile(path),
+                SaveDialogResponse::Cancel => ChatInputInputMsg::CancelDialog,
+            });
+
         let model = ChatInputComponent {
             enabled: true,
-            user_input: gtk::EntryBuffer::default(),
+            user_input: gtk::TextBuffer::default(),
+            history,
+            history_index: None,
+            input_before_history: String::new(),
+            thread_id: None,
+            saved_draft: String::new(),
+            draft_counter: 0,
+            templates,
+            template_matches: Vec::new(),
+            show_template_picker: false,
+            template_picker: gtk::ListBox::default(),
+            templates_box: gtk::Box::default(),
+            new_template_name: gtk::EntryBuffer::default(),
+            selected_template: None,
+            variables_box: gtk::Box::default(),
+            variable_entries: Vec::new(),
+            open_dialog,
+            save_dialog,
         };
+        model.refresh_templates(sender.input_sender());
+
+        // References used in the view macro
+        let template_picker = &model.template_picker;
+        let templates_box = &model.templates_box;
+        let variables_box = &model.variables_box;
 
         let widgets = view_output!();
 
+        {
+            let sender = sender.clone();
+            model.template_picker.connect_row_activated(move |_, row| {
+                sender.input(ChatInputInputMsg::SelectTemplateMatch(row.index() as usize));
+            });
+        }
+
+        // Enter submits the message, Shift+Enter inserts a new line
+        // and the arrow keys recall previous prompts when on the first or last line.
+        let key_controller = gtk::EventControllerKey::new();
+        {
+            let sender = sender.clone();
+            let buffer = model.user_input.clone();
+            key_controller.connect_key_pressed(move |_, key, _, modifiers| {
+                let cursor = buffer.iter_at_mark(&buffer.get_insert());
+                match key {
+                    gdk::Key::Return | gdk::Key::KP_Enter
+                        if !modifiers.contains(gdk::ModifierType::SHIFT_MASK) =>
+                    {
+                        sender.input(ChatInputInputMsg::Submit);
+                        glib::Propagation::Stop
+                    }
+                    gdk::Key::Up if cursor.line() == 0 => {
+                        sender.input(ChatInputInputMsg::HistoryPrevious);
+                        glib::Propagation::Stop
+                    }
+                    gdk::Key::Down if cursor.line() == buffer.line_count() - 1 => {
+                        sender.input(ChatInputInputMsg::HistoryNext);
+                        glib::Propagation::Stop
+                    }
+                    gdk::Key::Escape => {
+                        sender.input(ChatInputInputMsg::CloseTemplatePicker);
+                        glib::Propagation::Proceed
+                    }
+                    _ => glib::Propagation::Proceed,
+                }
+            });
+        }
+        widgets.text_input.add_controller(key_controller);
+
+        {
+            let sender = sender.clone();
+            model
+                .user_input
+                .connect_changed(move |_| sender.input(ChatInputInputMsg::InputChanged));
+        }
+
         ComponentParts { model, widgets }
     }
 
     fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _: &Self::Root) {
         match message {
-            ChatInputInputMsg::Disable => {
-                self.disable();
+            ChatInputInputMsg::SetEnabled(enabled) => {
+                self.enabled = enabled;
             }
             ChatInputInputMsg::Submit => {
-                let text = self.user_input.text();
-                if !text.is_empty() {
-                    tracing::info!("Submitting user input {}", text.to_string());
+                // Enter picks the first matching template while searching templates
+                if self.show_template_picker {
+                    if let Some(template) = self.template_matches.first().cloned() {
+                        self.use_template(template, sender.input_sender());
+                    }
+                    return;
+                }
+                let text = self.text();
+                if !text.trim().is_empty() {
+                    tracing::info!("Submitting user input {}", text);
                     sender
-                        .output(ChatInputOutputMsg::SubmitUserInput(text.to_string()))
+                        .output(ChatInputOutputMsg::SubmitUserInput(text.clone()))
                         .expect("Sending component message should work");
+                    if self.history.last() != Some(&text) {
+                        self.history.push(text);
+                    }
+                    self.history_index = None;
                     tracing::info!("Clearing user input field");
                     self.user_input.set_text("");
+                    // The draft is removed along with the submitted input
+                    self.saved_draft = String::new();
                     tracing::info!("Disabling user input temporarily");
                     self.disable();
                 };
             }
+            ChatInputInputMsg::HistoryPrevious => {
+                let index = match self.history_index {
+                    None if self.history.is_empty() => return,
+                    None => {
+                        self.input_before_history = self.text();
+                        self.history.len() - 1
+                    }
+                    Some(index) => index.saturating_sub(1),
+                };
+                self.history_index = Some(index);
+                self.user_input.set_text(&self.history[index]);
+            }
+            ChatInputInputMsg::HistoryNext => {
+                let Some(index) = self.history_index else {
+                    return;
+                };
+                if index + 1 < self.history.len() {
+                    self.history_index = Some(index + 1);
+                    self.user_input.set_text(&self.history[index + 1]);
+                } else {
+                    self.history_index = None;
+                    self.user_input.set_text(&self.input_before_history);
+                }
+            }
+            ChatInputInputMsg::InputChanged => {
+                self.search_templates();
+                // Debounce draft saving to avoid writing to the database on every key press
+                self.draft_counter += 1;
+                let draft_counter = self.draft_counter;
+                sender.oneshot_command(async move {
+                    tokio::time::sleep(DRAFT_SAVE_DELAY).await;
+                    ChatInputCmdMsg::SaveDraft(draft_counter)
+                });
+            }
+            ChatInputInputMsg::LoadDraft(thread_id, draft) => {
+                // Save the draft of the previous thread before switching
+                self.save_draft(&sender);
+                self.thread_id = Some(thread_id);
+                self.saved_draft = draft.clone();
+                self.history_index = None;
+                self.clear_selected_template();
+                self.user_input.set_text(&draft);
+            }
+            ChatInputInputMsg::UpdateTemplates(templates) => {
+                self.templates = templates;
+                self.refresh_templates(sender.input_sender());
+                self.search_templates();
+            }
+            ChatInputInputMsg::SelectTemplate(template_id) => {
+                let template = self
+                    .templates
+                    .iter()
+                    .find(|template| template.id == template_id)
+                    .cloned();
+                if let Some(template) = template {
+                    self.use_template(template, sender.input_sender());
+                }
+            }
+            ChatInputInputMsg::SelectTemplateMatch(index) => {
+                if let Some(template) = self.template_matches.get(index).cloned() {
+                    self.use_template(template, sender.input_sender());
+                }
+            }
+            ChatInputInputMsg::CloseTemplatePicker => {
+                self.show_template_picker = false;
+            }
+            ChatInputInputMsg::InsertTemplate => {
+                let Some(template) = self.selected_template.take() else {
+                    return;
+                };
+                let values: HashMap<String, String> = self
+                    .variable_entries
+                    .iter()
+                    .map(|(variable, entry)| (variable.clone(), entry.text().to_string()))
+                    .collect();
+                self.clear_selected_template();
+                self.user_input
+                    .set_text(&fill_template(&template.content, &values));
+            }
+            ChatInputInputMsg::CancelTemplate => {
+                self.clear_selected_template();
+            }
+            ChatInputInputMsg::SaveTemplate => {
+                let name = normalize_template_name(&self.new_template_name.text());
+                let content = self.text();
+                if name.is_empty() || content.trim().is_empty() {
+                    return;
+                }
+                self.new_template_name.set_text("");
+                sender
+                    .output(ChatInputOutputMsg::SaveTemplate(name, content))
+                    .expect("Sending component message should work");
+            }
+            ChatInputInputMsg::DeleteTemplate(template_id) => {
+                sender
+                    .output(ChatInputOutputMsg::DeleteTemplate(template_id))
+                    .expect("Sending component message should work");
+            }
+            ChatInputInputMsg::ImportTemplates => {
+                self.open_dialog.emit(OpenDialogMsg::Open);
+            }
+            ChatInputInputMsg::ExportTemplates => {
+                self.save_dialog.emit(SaveDialogMsg::SaveAs(String::from(
+                    TEMPLATES_EXPORT_FILENAME,
+                )));
+            }
+            ChatInputInputMsg::ImportTemplatesFile(path) => {
+                sender
+                    .output(ChatInputOutputMsg::ImportTemplates(path))
+                    .expect("Sending component message should work");
+            }
+            ChatInputInputMsg::ExportTemplatesFile(path) => {
+                sender
+                    .output(ChatInputOutputMsg::ExportTemplates(path))
+                    .expect("Sending component message should work");
+            }
+            ChatInputInputMsg::CancelDialog => {}
+        }
+    }
+
+    fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            ChatInputCmdMsg::SaveDraft(draft_counter) => {
+                if draft_counter == self.draft_counter {
+                    self.save_draft(&sender);
+                }
+            }
         }
     }
 }
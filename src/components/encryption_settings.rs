@@ -0,0 +1,188 @@
+use gtk::prelude::*;
+use relm4::prelude::*;
+
+#[derive(Debug)]
+pub struct EncryptionSettingsComponent {
+    encrypted: bool,
+    /// Whether a change of the encryption is being applied.
+    busy: bool,
+    status: String,
+}
+
+#[derive(Debug)]
+pub enum EncryptionSettingsInputMsg {
+    Submit,
+    RemoveEncryption,
+    /// Result of the last requested change, with an error message if it failed.
+    ChangeApplied(Result<bool, String>),
+}
+
+#[derive(Debug)]
+pub enum EncryptionSettingsOutputMsg {
+    EnableEncryption(String),
+    ChangePassphrase(String, String),
+    DisableEncryption(String),
+}
+
+#[relm4::component(pub)]
+impl Component for EncryptionSettingsComponent {
+    /// Whether the database is encrypted.
+    type Init = bool;
+    type Input = EncryptionSettingsInputMsg;
+    type Output = EncryptionSettingsOutputMsg;
+    type CommandOutput = ();
+
+    view! {
+        gtk::MenuButton {
+            #[watch]
+            set_icon_name: if model.encrypted {
+                "channel-secure-symbolic"
+            } else {
+                "channel-insecure-symbolic"
+            },
+            set_direction: gtk::ArrowType::Down,
+            set_tooltip_text: Some("Database encryption"),
+            set_css_classes: &["button", "encryption_settings_button"],
+
+            #[wrap(Some)]
+            set_popover = &gtk::Popover {
+                set_position: gtk::PositionType::Bottom,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_margin_all: 5,
+                    set_spacing: 5,
+                    set_width_request: 250,
+                    #[watch]
+                    set_sensitive: !model.busy,
+
+                    gtk::Label {
+                        set_wrap: true,
+                        #[watch]
+                        set_label: if model.encrypted {
+                            "The chat history is encrypted"
+                        } else {
+                            "The chat history is not encrypted"
+                        },
+                    },
+
+                    #[name = "passphrase_entry"]
+                    gtk::PasswordEntry {
+                        set_show_peek_icon: true,
+                        set_placeholder_text: Some("Current passphrase"),
+                        #[watch]
+                        set_visible: model.encrypted,
+                    },
+                    #[name = "new_passphrase_entry"]
+                    gtk::PasswordEntry {
+                        set_show_peek_icon: true,
+                        set_placeholder_text: Some("New passphrase"),
+                    },
+                    #[name = "confirmation_entry"]
+                    gtk::PasswordEntry {
+                        set_show_peek_icon: true,
+                        set_placeholder_text: Some("Confirm new passphrase"),
+                        connect_activate => EncryptionSettingsInputMsg::Submit,
+                    },
+
+                    gtk::Button {
+                        #[watch]
+                        set_label: if model.encrypted {
+                            "Change passphrase"
+                        } else {
+                            "Encrypt"
+                        },
+                        set_css_classes: &["button"],
+                        connect_clicked => EncryptionSettingsInputMsg::Submit,
+                    },
+                    gtk::Button {
+                        set_label: "Remove encryption",
+                        set_css_classes: &["button", "destructive-action"],
+                        #[watch]
+                        set_visible: model.encrypted,
+                        connect_clicked => EncryptionSettingsInputMsg::RemoveEncryption,
+                    },
+
+                    gtk::Label {
+                        set_wrap: true,
+                        #[watch]
+                        set_visible: !model.status.is_empty(),
+                        #[watch]
+                        set_label: &model.status,
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        encrypted: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = EncryptionSettingsComponent {
+            encrypted,
+            busy: false,
+            status: String::new(),
+        };
+
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            EncryptionSettingsInputMsg::Submit => {
+                let passphrase = widgets.passphrase_entry.text().to_string();
+                let new_passphrase = widgets.new_passphrase_entry.text().to_string();
+                if new_passphrase.is_empty() {
+                    self.status = String::from("The new passphrase must not be empty");
+                } else if new_passphrase != widgets.confirmation_entry.text() {
+                    self.status = String::from("The new passphrases do not match");
+                } else {
+                    self.busy = true;
+                    self.status = String::from("Encrypting the chat history...");
+                    sender.output_sender().emit(if self.encrypted {
+                        EncryptionSettingsOutputMsg::ChangePassphrase(passphrase, new_passphrase)
+                    } else {
+                        EncryptionSettingsOutputMsg::EnableEncryption(new_passphrase)
+                    });
+                }
+            }
+            EncryptionSettingsInputMsg::RemoveEncryption => {
+                self.busy = true;
+                self.status = String::from("Decrypting the chat history...");
+                sender
+                    .output_sender()
+                    .emit(EncryptionSettingsOutputMsg::DisableEncryption(
+                        widgets.passphrase_entry.text().to_string(),
+                    ));
+            }
+            EncryptionSettingsInputMsg::ChangeApplied(result) => {
+                self.busy = false;
+                match result {
+                    Ok(encrypted) => {
+                        self.encrypted = encrypted;
+                        self.status = String::from("Done!");
+                        for entry in [
+                            &widgets.passphrase_entry,
+                            &widgets.new_passphrase_entry,
+                            &widgets.confirmation_entry,
+                        ] {
+                            entry.set_text("");
+                        }
+                    }
+                    Err(error) => self.status = error,
+                }
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+}
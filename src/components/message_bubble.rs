@@ -1,33 +1,100 @@
-use std::future::Future;
+use std::cell::Cell;
+use std::rc::Rc;
 
-use anyhow::Result;
+use chrono::NaiveDateTime;
+use gtk::glib;
 use gtk::prelude::*;
 use relm4::component::{AsyncComponent, AsyncComponentParts, AsyncComponentSender};
-use relm4::factory::{AsyncFactoryComponent, AsyncFactoryVecDeque};
-use relm4::loading_widgets::LoadingWidgets;
 use relm4::prelude::*;
-use relm4::view;
+use relm4::typed_view::list::{RelmListItem, TypedListView};
 
 use crate::assistant::database::models::Message;
 use crate::assistant::ollama::types::Role;
 
+/// Number of messages loaded at once, older ones being loaded when scrolling up.
+pub const MESSAGES_PAGE_SIZE: i64 = 50;
+
 #[derive(Debug)]
 pub struct MessageBubbleContainerComponent {
-    message_bubbles: AsyncFactoryVecDeque<MessageBubbleComponent>,
+    /// Only the rows of visible messages are created by the list view.
+    list_view_wrapper: TypedListView<MessageBubbleItem, gtk::NoSelection>,
+    has_older_messages: bool,
+    loading_older_messages: bool,
+    /// Distance between the bottom of the view and the end of the messages,
+    /// kept when messages are added or grow so that the view does not jump.
+    distance_to_bottom: Rc<Cell<f64>>,
 }
 
 #[derive(Debug)]
 pub enum MessageBubbleContainerInputMsg {
     RefreshMessages(Vec<Message>),
+    PrependMessages(Vec<Message>),
+    LoadOlderMessages,
     AddNewMessage(Message),
     AppendToLastMessage(String),
+    ClearLastMessage,
+    AppendToMessage(i64, String),
+    EditMessage(Message),
+    DeleteMessage(i64),
+    ToggleEditing(i64, bool),
+    RequestDeleteMessage(i64),
+    RequestRetryMessage(i64),
+}
+
+#[derive(Debug)]
+pub enum MessageBubbleContainerOutputMsg {
+    /// Requests the page of messages sent before the given message.
+    LoadOlderMessages(i64),
+    EditMessage(i64, String),
+    DeleteMessage(i64),
+    /// Replaces an answer that does not follow the format of its thread by a new one.
+    RetryMessage(i64),
+}
+
+impl MessageBubbleContainerComponent {
+    fn position(&self, message_id: i64) -> Option<u32> {
+        (0..self.list_view_wrapper.len()).find(|position| {
+            match self.list_view_wrapper.get(*position) {
+                Some(item) => item.borrow().message_id == message_id,
+                None => false,
+            }
+        })
+    }
+
+    fn with_message<R>(
+        &self,
+        message_id: i64,
+        f: impl FnOnce(&MessageBubbleItem) -> R,
+    ) -> Option<R> {
+        let item = self.list_view_wrapper.get(self.position(message_id)?)?;
+        let result = f(&item.borrow());
+        Some(result)
+    }
+
+    fn append_messages(
+        &mut self,
+        messages: Vec<Message>,
+        sender: &relm4::Sender<MessageBubbleContainerInputMsg>,
+    ) {
+        self.list_view_wrapper.extend_from_iter(
+            messages
+                .into_iter()
+                .map(|message| MessageBubbleItem::new(message, sender.clone())),
+        );
+    }
+
+    fn scroll_to_bottom(&self, widgets: &MessageBubbleContainerComponentWidgets) {
+        self.distance_to_bottom.set(0.0);
+        let adjustment = widgets.scrolled_window.vadjustment();
+        adjustment.set_value(adjustment.upper() - adjustment.page_size());
+    }
 }
 
 #[relm4::component(async, pub)]
 impl AsyncComponent for MessageBubbleContainerComponent {
     type Init = Vec<Message>;
     type Input = MessageBubbleContainerInputMsg;
-    type Output = ();
+    type Output = MessageBubbleContainerOutputMsg;
     type CommandOutput = ();
 
     view! {
@@ -40,11 +107,16 @@ impl AsyncComponent for MessageBubbleContainerComponent {
                 set_hscrollbar_policy: gtk::PolicyType::Never,
                 set_hexpand: true,
 
-                #[local]
-                factory_box -> gtk::Box {
-                    set_orientation: gtk::Orientation::Vertical,
+                connect_edge_reached[sender] => move |_, position| {
+                    if position == gtk::PositionType::Top {
+                        sender.input(MessageBubbleContainerInputMsg::LoadOlderMessages);
+                    }
+                },
+
+                #[local_ref]
+                message_list -> gtk::ListView {
                     set_margin_all: 10,
-                    set_spacing: 10,
+                    set_css_classes: &["message_list"],
                 },
             },
         },
@@ -55,20 +127,36 @@ impl AsyncComponent for MessageBubbleContainerComponent {
         root: Self::Root,
         sender: AsyncComponentSender<Self>,
     ) -> AsyncComponentParts<Self> {
-        let factory_box = gtk::Box::default();
+        let list_view_wrapper: TypedListView<MessageBubbleItem, gtk::NoSelection> =
+            TypedListView::new();
 
-        let message_bubbles = AsyncFactoryVecDeque::builder()
-            .launch(factory_box.clone())
-            .detach();
-
-        let model = MessageBubbleContainerComponent { message_bubbles };
+        let model = MessageBubbleContainerComponent {
+            list_view_wrapper,
+            has_older_messages: false,
+            loading_older_messages: false,
+            distance_to_bottom: Rc::new(Cell::new(0.0)),
+        };
 
         sender
             .input_sender()
             .emit(MessageBubbleContainerInputMsg::RefreshMessages(messages));
 
+        let message_list = &model.list_view_wrapper.view;
+
         let widgets = view_output!();
 
+        let adjustment = widgets.scrolled_window.vadjustment();
+        let distance_to_bottom = model.distance_to_bottom.clone();
+        adjustment.connect_value_changed(move |adjustment| {
+            distance_to_bottom
+                .set(adjustment.upper() - adjustment.page_size() - adjustment.value());
+        });
+        let distance_to_bottom = model.distance_to_bottom.clone();
+        adjustment.connect_upper_notify(move |adjustment| {
+            adjustment
+                .set_value(adjustment.upper() - adjustment.page_size() - distance_to_bottom.get());
+        });
+
         AsyncComponentParts { model, widgets }
     }
 
@@ -76,160 +164,358 @@ impl AsyncComponent for MessageBubbleContainerComponent {
         &mut self,
         widgets: &mut Self::Widgets,
         message: Self::Input,
-        _: AsyncComponentSender<Self>,
+        sender: AsyncComponentSender<Self>,
         _: &Self::Root,
     ) {
         match message {
             MessageBubbleContainerInputMsg::RefreshMessages(messages) => {
-                let mut guard = self.message_bubbles.guard();
-                guard.clear();
-                let _ = messages
-                    .into_iter()
-                    .map(|message| guard.push_back(message))
-                    .collect::<Vec<_>>();
-
-                let adjustment = widgets.scrolled_window.vadjustment();
-                adjustment.set_value(adjustment.upper() - adjustment.page_size());
-                widgets.scrolled_window.set_vadjustment(Some(&adjustment));
+                self.has_older_messages = messages.len() as i64 >= MESSAGES_PAGE_SIZE;
+                self.loading_older_messages = false;
+                self.list_view_wrapper.clear();
+                self.append_messages(messages, sender.input_sender());
+
+                self.scroll_to_bottom(widgets);
+            }
+            MessageBubbleContainerInputMsg::PrependMessages(messages) => {
+                self.has_older_messages = messages.len() as i64 >= MESSAGES_PAGE_SIZE;
+                self.loading_older_messages = false;
+                // The distance to the bottom is kept, so the view stays on the same messages
+                for (position, message) in messages.into_iter().enumerate() {
+                    self.list_view_wrapper.insert(
+                        position as u32,
+                        MessageBubbleItem::new(message, sender.input_sender().clone()),
+                    );
+                }
+            }
+            MessageBubbleContainerInputMsg::LoadOlderMessages => {
+                if !self.has_older_messages || self.loading_older_messages {
+                    return;
+                }
+                let Some(first_message) = self.list_view_wrapper.get(0) else {
+                    return;
+                };
+                let first_message_id = first_message.borrow().message_id;
+                self.loading_older_messages = true;
+                sender
+                    .output_sender()
+                    .emit(MessageBubbleContainerOutputMsg::LoadOlderMessages(
+                        first_message_id,
+                    ));
             }
             MessageBubbleContainerInputMsg::AddNewMessage(message) => {
-                let mut guard = self.message_bubbles.guard();
-                guard.push_back(message);
+                self.append_messages(vec![message], sender.input_sender());
 
-                let adjustment = widgets.scrolled_window.vadjustment();
-                adjustment.set_value(adjustment.upper() - adjustment.page_size());
-                widgets.scrolled_window.set_vadjustment(Some(&adjustment));
+                self.scroll_to_bottom(widgets);
+            }
+            MessageBubbleContainerInputMsg::AppendToLastMessage(content) => {
+                let last_position = self.list_view_wrapper.len().saturating_sub(1);
+                if let Some(item) = self.list_view_wrapper.get(last_position) {
+                    item.borrow().append(&content);
+                }
+            }
+            MessageBubbleContainerInputMsg::ClearLastMessage => {
+                let last_position = self.list_view_wrapper.len().saturating_sub(1);
+                if let Some(item) = self.list_view_wrapper.get(last_position) {
+                    item.borrow().buffer.set_text("");
+                }
+            }
+            MessageBubbleContainerInputMsg::AppendToMessage(message_id, content) => {
+                if self
+                    .with_message(message_id, |item| item.append(&content))
+                    .is_none()
+                {
+                    tracing::warn!("Message with id {message_id} is not displayed");
+                }
+            }
+            MessageBubbleContainerInputMsg::EditMessage(message) => {
+                let Some(position) = self.position(message.id) else {
+                    return;
+                };
+                let format_error_changed = self.with_message(message.id, |item| {
+                    if item.content() != message.content {
+                        item.buffer.set_text(&message.content);
+                    }
+                    item.format_error != message.format_error
+                });
+                // The row is only bound again when its item is replaced
+                if format_error_changed == Some(true) {
+                    self.list_view_wrapper.remove(position);
+                    self.list_view_wrapper.insert(
+                        position,
+                        MessageBubbleItem::new(message, sender.input_sender().clone()),
+                    );
+                }
+            }
+            MessageBubbleContainerInputMsg::DeleteMessage(message_id) => {
+                if let Some(position) = self.position(message_id) {
+                    self.list_view_wrapper.remove(position);
+                }
+            }
+            MessageBubbleContainerInputMsg::ToggleEditing(message_id, editing) => {
+                let Some(item) = self
+                    .position(message_id)
+                    .and_then(|position| self.list_view_wrapper.get(position))
+                else {
+                    return;
+                };
+                let mut item = item.borrow_mut();
+                if item.editing == editing {
+                    return;
+                }
+                item.editing = editing;
+                if !editing {
+                    sender
+                        .output_sender()
+                        .emit(MessageBubbleContainerOutputMsg::EditMessage(
+                            message_id,
+                            item.content(),
+                        ));
+                }
             }
-            MessageBubbleContainerInputMsg::AppendToLastMessage(message) => {
-                let mut guard = self.message_bubbles.guard();
-                guard
-                    .back_mut()
-                    .expect("There should be at least one previous message")
-                    .append_to_message(message)
-                    .await
-                    .expect("Appending to message should work");
-
-                let adjustment = widgets.scrolled_window.vadjustment();
-                adjustment.set_value(adjustment.upper() - adjustment.page_size());
-                widgets.scrolled_window.set_vadjustment(Some(&adjustment));
+            MessageBubbleContainerInputMsg::RequestDeleteMessage(message_id) => {
+                sender
+                    .output_sender()
+                    .emit(MessageBubbleContainerOutputMsg::DeleteMessage(message_id));
+            }
+            MessageBubbleContainerInputMsg::RequestRetryMessage(message_id) => {
+                sender
+                    .output_sender()
+                    .emit(MessageBubbleContainerOutputMsg::RetryMessage(message_id));
             }
         }
     }
 }
 
+fn format_timestamp(created_at: NaiveDateTime) -> String {
+    let current_time = chrono::offset::Local::now().naive_local();
+    let time_difference = current_time - created_at;
+    let absolute_timestamp = created_at.format("%d %B %Y at %R").to_string();
+    if time_difference.num_minutes() == 0 {
+        format!("{} second(s) ago", time_difference.num_seconds())
+    } else if time_difference.num_hours() == 0 {
+        format!("{} minute(s) ago", time_difference.num_minutes())
+    } else if time_difference.num_days() == 0 {
+        format!("{} hour(s) ago", time_difference.num_hours())
+    } else if time_difference.num_weeks() <= 1 {
+        format!(
+            "{} ( {} day(s) ago )",
+            absolute_timestamp,
+            time_difference.num_days()
+        )
+    } else {
+        format!(
+            "{} ({} week(s) ago )",
+            absolute_timestamp,
+            time_difference.num_weeks()
+        )
+    }
+}
+
 #[derive(Debug)]
-pub struct MessageBubbleComponent {
+struct MessageBubbleItem {
+    message_id: i64,
+    /// The content is kept in a buffer so that streamed content shows up in bound rows.
     buffer: gtk::TextBuffer,
     role: Role,
     timestamp: String,
+    editing: bool,
+    /// Why the answer does not follow the format of its thread.
+    format_error: Option<String>,
+    sender: relm4::Sender<MessageBubbleContainerInputMsg>,
 }
 
-impl MessageBubbleComponent {
-    pub async fn new(message: Message) -> Self {
+impl MessageBubbleItem {
+    fn new(message: Message, sender: relm4::Sender<MessageBubbleContainerInputMsg>) -> Self {
         let buffer = gtk::TextBuffer::builder().text(&*message.content).build();
-        let current_time = chrono::offset::Local::now().naive_local();
-        let creation_time = message.created_at;
-        let time_difference = current_time - creation_time;
-        let absolute_timestamp = message.created_at.format("%d %B %Y at %R").to_string();
-        let timestamp: String;
-        if time_difference.num_minutes() == 0 {
-            timestamp = format!("{} second(s) ago", time_difference.num_seconds());
-        } else if time_difference.num_hours() == 0 {
-            timestamp = format!("{} minute(s) ago", time_difference.num_minutes());
-        } else if time_difference.num_days() == 0 {
-            timestamp = format!("{} hour(s) ago", time_difference.num_hours());
-        } else if time_difference.num_weeks() <= 1 {
-            timestamp = format!(
-                "{} ( {} day(s) ago )",
-                absolute_timestamp,
-                time_difference.num_days()
-            );
-        } else {
-            timestamp = format!(
-                "{} ({} week(s) ago )",
-                absolute_timestamp,
-                time_difference.num_weeks()
-            );
-        }
+        let timestamp = format_timestamp(message.created_at);
         let role =
             Role::try_from(message.role).expect("Converting role from string to enum should work");
         Self {
+            message_id: message.id,
             buffer,
             role,
             timestamp,
+            editing: false,
+            format_error: message.format_error,
+            sender,
         }
     }
 
-    pub async fn append_to_message(&mut self, content: String) -> Result<()> {
-        self.buffer.insert_at_cursor(&content);
-        Ok(())
+    fn append(&self, content: &str) {
+        self.buffer.insert(&mut self.buffer.end_iter(), content);
+    }
+
+    fn content(&self) -> String {
+        let (start, end) = self.buffer.bounds();
+        self.buffer.text(&start, &end, false).to_string()
     }
 }
 
-#[relm4::factory(async, pub)]
-impl AsyncFactoryComponent for MessageBubbleComponent {
-    type Init = Message;
-    type Input = ();
-    type Output = ();
-    type CommandOutput = ();
-    type ParentWidget = gtk::Box;
+struct MessageBubbleItemWidgets {
+    timestamp: gtk::Label,
+    edit_button: gtk::ToggleButton,
+    delete_button: gtk::Button,
+    text_view: gtk::TextView,
+    format_error_box: gtk::Box,
+    format_error_label: gtk::Label,
+    retry_button: gtk::Button,
+    edit_handler: Option<glib::SignalHandlerId>,
+    delete_handler: Option<glib::SignalHandlerId>,
+    retry_handler: Option<glib::SignalHandlerId>,
+}
 
-    view! {
-        gtk::Box{
-            set_orientation: gtk::Orientation::Vertical,
-            set_margin_all: 5,
-            set_spacing: 5,
-            set_halign: gtk::Align::Fill,
-            set_valign: gtk::Align::Fill,
+impl RelmListItem for MessageBubbleItem {
+    type Root = gtk::Box;
+    type Widgets = MessageBubbleItemWidgets;
 
-            gtk::Label {
-                set_text: &*self.timestamp,
-            },
-            gtk::TextView {
-                #[watch]
-                set_buffer: Some(&self.buffer),
-                set_focusable: false,
-                set_editable: false,
-                set_overwrite: true,
-                set_justification: gtk::Justification::Left,
-                set_wrap_mode: gtk::WrapMode::WordChar,
-                set_css_classes: &["message_bubble"],
-                add_css_class: match self.role {
-                    Role::System => "system_message",
-                    Role::User => "user_message",
-                    Role::Assistant => "assistant_message",
-                    Role::Tool => "tool_message",
-                }
-            }
-        }
-    }
+    fn setup(_item: &gtk::ListItem) -> (Self::Root, Self::Widgets) {
+        relm4::view! {
+            root = gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 5,
+                set_spacing: 5,
+                set_halign: gtk::Align::Fill,
+                set_valign: gtk::Align::Fill,
 
-    fn init_loading_widgets(root: Self::Root) -> Option<LoadingWidgets> {
-        view! {
-            #[local]
-            root {
-                #[name = "placeholder"]
-                gtk::Box{
-                    set_orientation: gtk::Orientation::Vertical,
-                    set_margin_all: 5,
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
                     set_spacing: 5,
-                    set_halign: gtk::Align::Fill,
-                    set_valign: gtk::Align::Fill,
 
-                    gtk::Spinner {
-                        set_spinning: true,
-                    }
-                }
+                    #[name = "timestamp"]
+                    gtk::Label {
+                        set_hexpand: true,
+                    },
+
+                    #[name = "edit_button"]
+                    gtk::ToggleButton {
+                        set_icon_name: "document-edit-symbolic",
+                        set_tooltip_text: Some("Edit message"),
+                        set_css_classes: &["button", "edit_message_button"],
+                    },
+
+                    #[name = "delete_button"]
+                    gtk::Button {
+                        set_icon_name: "edit-delete-symbolic",
+                        set_tooltip_text: Some("Delete message"),
+                        set_css_classes: &["button", "delete_message_button"],
+                    },
+                },
+
+                #[name = "text_view"]
+                gtk::TextView {
+                    set_overwrite: true,
+                    set_justification: gtk::Justification::Left,
+                    set_wrap_mode: gtk::WrapMode::WordChar,
+                },
+
+                #[name = "format_error_box"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                    set_css_classes: &["format_error"],
+
+                    gtk::Image {
+                        set_icon_name: Some("dialog-warning-symbolic"),
+                    },
+                    #[name = "format_error_label"]
+                    gtk::Label {
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                        set_wrap: true,
+                    },
+                    #[name = "retry_button"]
+                    gtk::Button {
+                        set_label: "Retry",
+                        set_tooltip_text: Some("Replace this answer by a new one"),
+                        set_css_classes: &["button", "retry_message_button"],
+                    },
+                },
             }
         }
-        Some(LoadingWidgets::new(root, placeholder))
+
+        let widgets = Self::Widgets {
+            timestamp,
+            edit_button,
+            delete_button,
+            text_view,
+            format_error_box,
+            format_error_label,
+            retry_button,
+            edit_handler: None,
+            delete_handler: None,
+            retry_handler: None,
+        };
+
+        (root, widgets)
     }
 
-    fn init_model(
-        init: Self::Init,
-        _: &DynamicIndex,
-        _: AsyncFactorySender<Self>,
-    ) -> impl Future<Output = Self> {
-        Self::new(init)
+    fn bind(&mut self, widgets: &mut Self::Widgets, _: &mut Self::Root) {
+        let Self::Widgets {
+            timestamp,
+            edit_button,
+            delete_button,
+            text_view,
+            format_error_box,
+            format_error_label,
+            retry_button,
+            edit_handler,
+            delete_handler,
+            retry_handler,
+        } = widgets;
+
+        timestamp.set_text(&self.timestamp);
+        text_view.set_buffer(Some(&self.buffer));
+        text_view.set_css_classes(&[
+            "message_bubble",
+            match self.role {
+                Role::System => "system_message",
+                Role::User => "user_message",
+                Role::Assistant => "assistant_message",
+                Role::Tool => "tool_message",
+            },
+        ]);
+        format_error_box.set_visible(self.format_error.is_some());
+        format_error_label.set_label(self.format_error.as_deref().unwrap_or_default());
+        edit_button.set_active(self.editing);
+        text_view.set_editable(self.editing);
+        text_view.set_focusable(self.editing);
+
+        let message_id = self.message_id;
+        let sender = self.sender.clone();
+        let text_view_handle = text_view.clone();
+        *edit_handler = Some(edit_button.connect_toggled(move |button| {
+            text_view_handle.set_editable(button.is_active());
+            text_view_handle.set_focusable(button.is_active());
+            sender.emit(MessageBubbleContainerInputMsg::ToggleEditing(
+                message_id,
+                button.is_active(),
+            ));
+        }));
+
+        let sender = self.sender.clone();
+        *delete_handler = Some(delete_button.connect_clicked(move |_| {
+            sender.emit(MessageBubbleContainerInputMsg::RequestDeleteMessage(
+                message_id,
+            ));
+        }));
+
+        let sender = self.sender.clone();
+        *retry_handler = Some(retry_button.connect_clicked(move |_| {
+            sender.emit(MessageBubbleContainerInputMsg::RequestRetryMessage(
+                message_id,
+            ));
+        }));
+    }
+
+    fn unbind(&mut self, widgets: &mut Self::Widgets, _: &mut Self::Root) {
+        if let Some(handler) = widgets.edit_handler.take() {
+            widgets.edit_button.disconnect(handler);
+        }
+        if let Some(handler) = widgets.delete_handler.take() {
+            widgets.delete_button.disconnect(handler);
+        }
+        if let Some(handler) = widgets.retry_handler.take() {
+            widgets.retry_button.disconnect(handler);
+        }
     }
 }
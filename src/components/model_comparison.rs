@@ -0,0 +1,420 @@
+use futures::{FutureExt, StreamExt};
+use gtk::prelude::*;
+use relm4::prelude::*;
+use relm4::RelmRemoveAllExt;
+
+use crate::assistant::backend::ChatOptions;
+use crate::assistant::database::models::Message;
+use crate::assistant::ollama::types::{Message as OllamaMessage, Role};
+use crate::assistant::retry::{RetryAttempt, RetryPolicy};
+use crate::assistant::{model_labels, Assistant, GenerationStats, ModelChoice};
+use crate::components::message_bubble::{
+    MessageBubbleContainerComponent, MessageBubbleContainerInputMsg,
+};
+
+#[derive(Debug)]
+pub struct ModelComparisonComponent {
+    selected_models: Vec<ModelChoice>,
+    /// Check buttons selecting the models of every backend.
+    models_box: gtk::Box,
+    columns: Vec<ComparisonColumn>,
+    columns_box: gtk::Box,
+    /// Incremented for every comparison, to ignore answers of previous ones.
+    comparison_id: u64,
+    running_generations: usize,
+}
+
+/// Answer of a single model in the comparison view.
+#[derive(Debug)]
+struct ComparisonColumn {
+    choice: ModelChoice,
+    content: String,
+    message_bubbles: AsyncController<MessageBubbleContainerComponent>,
+    stats_label: gtk::Label,
+    continue_button: gtk::Button,
+}
+
+impl ComparisonColumn {
+    fn finish(&mut self, stats: Option<GenerationStats>) {
+        let stats_text = match stats {
+            Some(stats) => format!(
+                "{} tokens · {:.1} tokens/s · {:.2}s total · {:.2}s load · {} prompt tokens",
+                stats.eval_count,
+                stats.tokens_per_second(),
+                stats.total_duration.as_secs_f64(),
+                stats.load_duration.as_secs_f64(),
+                stats.prompt_eval_count,
+            ),
+            None => String::from("No statistics available"),
+        };
+        self.stats_label.set_label(&stats_text);
+        self.continue_button.set_sensitive(true);
+    }
+
+    fn fail(&mut self, error: String) {
+        self.stats_label
+            .set_label(&format!("Generation failed: {error}"));
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelComparisonInputMsg {
+    /// Replaces the models that can be compared, when backends change.
+    SetModels(Vec<ModelChoice>),
+    ToggleModel(ModelChoice, bool),
+    /// Sends the conversation, ending with the user prompt, to all selected models.
+    Compare(Vec<OllamaMessage>),
+    PickAnswer(usize),
+}
+
+#[derive(Debug)]
+pub enum ModelComparisonOutputMsg {
+    /// Backend id and name of the picked model, and its answer.
+    PickAnswer(i64, String, String),
+    Finished,
+}
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ModelComparisonCmdMsg {
+    AnswerChunk(u64, usize, String),
+    /// The answer received so far was reasoning.
+    DiscardAnswer(u64, usize),
+    AnswerEnd(u64, usize, Option<GenerationStats>),
+    AnswerFailed(u64, usize, String),
+    Reconnecting(u64, usize, RetryAttempt),
+}
+
+impl ModelComparisonComponent {
+    fn end_generation(&mut self, sender: &ComponentSender<Self>) {
+        self.running_generations = self.running_generations.saturating_sub(1);
+        if self.running_generations == 0 {
+            sender
+                .output_sender()
+                .emit(ModelComparisonOutputMsg::Finished);
+        }
+    }
+
+    /// Shows a check button for each model, the selected models being unselected.
+    fn show_models(&mut self, models: Vec<ModelChoice>, sender: &ComponentSender<Self>) {
+        self.selected_models.clear();
+        self.models_box.remove_all();
+        for (label, choice) in model_labels(&models).into_iter().zip(models) {
+            relm4::view! {
+                model_check_button = gtk::CheckButton {
+                    set_label: Some(label.as_str()),
+
+                    connect_toggled[sender] => move |button| {
+                        sender.input(ModelComparisonInputMsg::ToggleModel(choice.clone(), button.is_active()));
+                    },
+                }
+            }
+            self.models_box.append(&model_check_button);
+        }
+    }
+
+    fn column(&mut self, comparison_id: u64, index: usize) -> Option<&mut ComparisonColumn> {
+        if comparison_id != self.comparison_id {
+            return None;
+        }
+        self.columns.get_mut(index)
+    }
+}
+
+#[relm4::component(pub)]
+impl Component for ModelComparisonComponent {
+    /// Models of every backend.
+    type Init = Vec<ModelChoice>;
+    type Input = ModelComparisonInputMsg;
+    type Output = ModelComparisonOutputMsg;
+    type CommandOutput = ModelComparisonCmdMsg;
+
+    view! {
+        gtk::Box {
+            set_orientation: gtk::Orientation::Vertical,
+            set_vexpand: true,
+            set_valign: gtk::Align::Fill,
+            set_spacing: 5,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_spacing: 5,
+
+                gtk::Label {
+                    set_label: "Compare",
+                    set_tooltip_text: Some("Select two or more models to compare their answers"),
+                },
+                #[local_ref]
+                models_box -> gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                },
+            },
+
+            gtk::ScrolledWindow {
+                set_vexpand: true,
+                set_vscrollbar_policy: gtk::PolicyType::Never,
+
+                #[local_ref]
+                columns_box -> gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_homogeneous: true,
+                    set_spacing: 10,
+                },
+            },
+        },
+    }
+
+    fn init(
+        models: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let mut model = ModelComparisonComponent {
+            selected_models: Vec::new(),
+            models_box: gtk::Box::default(),
+            columns: Vec::new(),
+            columns_box: gtk::Box::default(),
+            comparison_id: 0,
+            running_generations: 0,
+        };
+
+        // References used in the view macro
+        let models_box = &model.models_box;
+        let columns_box = &model.columns_box;
+
+        let widgets = view_output!();
+
+        model.show_models(models, &sender);
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, message: Self::Input, sender: ComponentSender<Self>, _: &Self::Root) {
+        match message {
+            ModelComparisonInputMsg::SetModels(models) => {
+                self.show_models(models, &sender);
+            }
+            ModelComparisonInputMsg::ToggleModel(choice, selected) => {
+                if selected {
+                    self.selected_models.push(choice);
+                } else {
+                    self.selected_models.retain(|selected_choice| {
+                        !selected_choice.is(choice.backend_id, &choice.model)
+                    });
+                }
+            }
+            ModelComparisonInputMsg::Compare(messages) => {
+                self.comparison_id += 1;
+                self.columns.clear();
+                self.columns_box.remove_all();
+
+                if self.selected_models.is_empty() {
+                    tracing::warn!("No models were selected for the comparison");
+                    sender
+                        .output_sender()
+                        .emit(ModelComparisonOutputMsg::Finished);
+                    return;
+                }
+
+                let prompt = messages
+                    .last()
+                    .map(|message| message.content.clone())
+                    .unwrap_or_default();
+                let created_at = chrono::offset::Local::now().naive_local();
+                let bubble_messages = vec![
+                    Message {
+                        id: 0,
+                        thread_id: 0,
+                        created_at,
+                        content: prompt,
+                        role: String::from(<&str>::from(Role::User)),
+                        complete: true,
+                        format_error: None,
+                    },
+                    Message {
+                        id: 0,
+                        thread_id: 0,
+                        created_at,
+                        content: String::new(),
+                        role: String::from(<&str>::from(Role::Assistant)),
+                        complete: false,
+                        format_error: None,
+                    },
+                ];
+
+                let labels = model_labels(&self.selected_models);
+                for (index, (choice, label)) in self.selected_models.iter().zip(labels).enumerate()
+                {
+                    let message_bubbles = MessageBubbleContainerComponent::builder()
+                        .launch(bubble_messages.clone())
+                        .detach();
+                    let stats_label = gtk::Label::new(Some("Generating..."));
+                    stats_label.set_wrap(true);
+                    stats_label.add_css_class("comparison_stats");
+                    let continue_button = gtk::Button::with_label("Continue with this model");
+                    continue_button.set_sensitive(false);
+                    continue_button.set_css_classes(&["button", "continue_comparison_button"]);
+                    {
+                        let sender = sender.clone();
+                        continue_button.connect_clicked(move |_| {
+                            sender.input(ModelComparisonInputMsg::PickAnswer(index));
+                        });
+                    }
+
+                    let model_label = gtk::Label::new(Some(&label));
+                    model_label.add_css_class("comparison_model");
+                    let column = gtk::Box::new(gtk::Orientation::Vertical, 5);
+                    column.add_css_class("comparison_column");
+                    column.append(&model_label);
+                    column.append(message_bubbles.widget());
+                    column.append(&stats_label);
+                    column.append(&continue_button);
+                    self.columns_box.append(&column);
+
+                    self.columns.push(ComparisonColumn {
+                        choice: choice.clone(),
+                        content: String::new(),
+                        message_bubbles,
+                        stats_label,
+                        continue_button,
+                    });
+
+                    let comparison_id = self.comparison_id;
+                    let backend = choice.backend.clone();
+                    let model = choice.model.clone();
+                    let messages = messages.clone();
+                    sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
+                        shutdown
+                            .register(async move {
+                                let mut message_stream =
+                                    match Assistant::generate_answer_with_model(
+                                        backend,
+                                        model,
+                                        messages,
+                                        ChatOptions::default(),
+                                        &RetryPolicy::default(),
+                                        |retry_attempt| {
+                                            out.emit(ModelComparisonCmdMsg::Reconnecting(
+                                                comparison_id,
+                                                index,
+                                                retry_attempt.clone(),
+                                            ))
+                                        },
+                                    )
+                                    .await
+                                    {
+                                        Ok(stream) => stream,
+                                        Err(error) => {
+                                            out.emit(ModelComparisonCmdMsg::AnswerFailed(
+                                                comparison_id,
+                                                index,
+                                                error.to_string(),
+                                            ));
+                                            return;
+                                        }
+                                    };
+
+                                let mut stats = None;
+                                while let Some(result) = message_stream.next().await {
+                                    match result {
+                                        Ok(chunk) => {
+                                            if chunk.discard_previous {
+                                                out.emit(ModelComparisonCmdMsg::DiscardAnswer(
+                                                    comparison_id,
+                                                    index,
+                                                ));
+                                            }
+                                            out.emit(ModelComparisonCmdMsg::AnswerChunk(
+                                                comparison_id,
+                                                index,
+                                                chunk.content,
+                                            ));
+                                            stats = chunk.stats.or(stats);
+                                        }
+                                        Err(error) => {
+                                            out.emit(ModelComparisonCmdMsg::AnswerFailed(
+                                                comparison_id,
+                                                index,
+                                                error.to_string(),
+                                            ));
+                                            return;
+                                        }
+                                    }
+                                }
+                                out.emit(ModelComparisonCmdMsg::AnswerEnd(
+                                    comparison_id,
+                                    index,
+                                    stats,
+                                ));
+                            })
+                            // Perform task until a shutdown interrupts it
+                            .drop_on_shutdown()
+                            // Wrap into a `Pin<Box<Future>>` for return
+                            .boxed()
+                    });
+                }
+                self.running_generations = self.columns.len();
+            }
+            ModelComparisonInputMsg::PickAnswer(index) => {
+                if let Some(column) = self.columns.get(index) {
+                    tracing::info!(
+                        "Continuing thread with the answer of {}",
+                        column.choice.model
+                    );
+                    sender
+                        .output_sender()
+                        .emit(ModelComparisonOutputMsg::PickAnswer(
+                            column.choice.backend_id,
+                            column.choice.model.clone(),
+                            column.content.clone(),
+                        ));
+                }
+            }
+        }
+    }
+
+    fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        sender: ComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            ModelComparisonCmdMsg::AnswerChunk(comparison_id, index, content) => {
+                if let Some(column) = self.column(comparison_id, index) {
+                    column.content.push_str(&content);
+                    column
+                        .message_bubbles
+                        .emit(MessageBubbleContainerInputMsg::AppendToLastMessage(content));
+                }
+            }
+            ModelComparisonCmdMsg::DiscardAnswer(comparison_id, index) => {
+                if let Some(column) = self.column(comparison_id, index) {
+                    column.content.clear();
+                    column
+                        .message_bubbles
+                        .emit(MessageBubbleContainerInputMsg::ClearLastMessage);
+                }
+            }
+            ModelComparisonCmdMsg::AnswerEnd(comparison_id, index, stats) => {
+                if let Some(column) = self.column(comparison_id, index) {
+                    column.finish(stats);
+                    self.end_generation(&sender);
+                }
+            }
+            ModelComparisonCmdMsg::Reconnecting(comparison_id, index, retry_attempt) => {
+                if let Some(column) = self.column(comparison_id, index) {
+                    column.stats_label.set_label(&retry_attempt.to_string());
+                }
+            }
+            ModelComparisonCmdMsg::AnswerFailed(comparison_id, index, error) => {
+                tracing::error!("Error receiving comparison answer because of: {error}");
+                if let Some(column) = self.column(comparison_id, index) {
+                    column.fail(error);
+                    self.end_generation(&sender);
+                }
+            }
+        }
+    }
+}
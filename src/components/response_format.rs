@@ -0,0 +1,191 @@
+use gtk::prelude::*;
+use relm4::prelude::*;
+
+use crate::assistant::structured_output::ResponseFormat;
+
+/// Modes of the mode selection, in order.
+const MODES: [&str; 3] = ["Free text", "Any JSON", "JSON matching a schema"];
+const SCHEMA_MODE: u32 = 2;
+
+#[derive(Debug)]
+pub struct ResponseFormatComponent {
+    /// Format of the answers of the displayed thread.
+    response_format: Option<ResponseFormat>,
+    /// Index of the selected mode in [`MODES`].
+    mode: u32,
+    schema_buffer: gtk::TextBuffer,
+    status: String,
+}
+
+#[derive(Debug)]
+pub enum ResponseFormatInputMsg {
+    /// Shows the format of the answers of the displayed thread.
+    ShowFormat(Option<ResponseFormat>),
+    SelectMode(u32),
+    Apply,
+    /// Result of the last requested change, with an error message if it failed.
+    ChangeApplied(Result<(), String>),
+}
+
+#[derive(Debug)]
+pub enum ResponseFormatOutputMsg {
+    SetFormat(Option<ResponseFormat>),
+}
+
+#[relm4::component(pub)]
+impl Component for ResponseFormatComponent {
+    type Init = ();
+    type Input = ResponseFormatInputMsg;
+    type Output = ResponseFormatOutputMsg;
+    type CommandOutput = ();
+
+    view! {
+        gtk::MenuButton {
+            set_label: "JSON",
+            set_direction: gtk::ArrowType::Down,
+            set_tooltip_text: Some("Structured output"),
+            #[watch]
+            set_css_classes: if model.response_format.is_some() {
+                &["button", "response_format_button", "suggested-action"] as &[&str]
+            } else {
+                &["button", "response_format_button"]
+            },
+
+            #[wrap(Some)]
+            set_popover = &gtk::Popover {
+                set_position: gtk::PositionType::Bottom,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_margin_all: 5,
+                    set_spacing: 5,
+                    set_width_request: 350,
+
+                    gtk::Label {
+                        set_label: "Format of the answers of this thread",
+                        set_wrap: true,
+                    },
+
+                    #[name = "mode_drop_down"]
+                    gtk::DropDown::from_strings(&MODES) {
+                        set_css_classes: &["dropdown"],
+
+                        connect_selected_notify[sender] => move |drop_down| {
+                            sender.input(ResponseFormatInputMsg::SelectMode(drop_down.selected()));
+                        } @mode_selected_handler,
+                    },
+
+                    gtk::ScrolledWindow {
+                        set_min_content_height: 200,
+                        set_hscrollbar_policy: gtk::PolicyType::Never,
+                        #[watch]
+                        set_visible: model.mode == SCHEMA_MODE,
+
+                        gtk::TextView {
+                            set_buffer: Some(&model.schema_buffer),
+                            set_monospace: true,
+                            set_wrap_mode: gtk::WrapMode::WordChar,
+                            set_css_classes: &["schema_input"],
+                        },
+                    },
+
+                    gtk::Button {
+                        set_label: "Apply",
+                        set_css_classes: &["button"],
+                        connect_clicked => ResponseFormatInputMsg::Apply,
+                    },
+
+                    gtk::Label {
+                        set_wrap: true,
+                        set_max_width_chars: 50,
+                        #[watch]
+                        set_visible: !model.status.is_empty(),
+                        #[watch]
+                        set_label: &model.status,
+                    },
+                },
+            },
+        }
+    }
+
+    fn init(
+        _: Self::Init,
+        root: Self::Root,
+        sender: ComponentSender<Self>,
+    ) -> ComponentParts<Self> {
+        let model = ResponseFormatComponent {
+            response_format: None,
+            mode: 0,
+            schema_buffer: gtk::TextBuffer::default(),
+            status: String::new(),
+        };
+
+        let widgets = view_output!();
+
+        ComponentParts { model, widgets }
+    }
+
+    fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: ComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            ResponseFormatInputMsg::ShowFormat(response_format) => {
+                self.mode = match &response_format {
+                    None => 0,
+                    Some(ResponseFormat::Json) => 1,
+                    Some(ResponseFormat::Schema(schema)) => {
+                        self.schema_buffer.set_text(
+                            &serde_json::to_string_pretty(schema)
+                                .expect("Serializing JSON schema should work"),
+                        );
+                        SCHEMA_MODE
+                    }
+                };
+                self.response_format = response_format;
+                self.status = String::new();
+                widgets
+                    .mode_drop_down
+                    .block_signal(&widgets.mode_selected_handler);
+                widgets.mode_drop_down.set_selected(self.mode);
+                widgets
+                    .mode_drop_down
+                    .unblock_signal(&widgets.mode_selected_handler);
+            }
+            ResponseFormatInputMsg::SelectMode(mode) => {
+                self.mode = mode;
+            }
+            ResponseFormatInputMsg::Apply => {
+                let response_format = match self.mode {
+                    0 => Ok(None),
+                    1 => Ok(Some(ResponseFormat::Json)),
+                    _ => {
+                        let (start, end) = self.schema_buffer.bounds();
+                        let schema = self.schema_buffer.text(&start, &end, false);
+                        ResponseFormat::parse(&schema).map(Some)
+                    }
+                };
+                match response_format {
+                    Ok(response_format) => {
+                        self.status = String::from("Saving...");
+                        sender
+                            .output_sender()
+                            .emit(ResponseFormatOutputMsg::SetFormat(response_format.clone()));
+                        self.response_format = response_format;
+                    }
+                    Err(error) => self.status = error.to_string(),
+                }
+            }
+            ResponseFormatInputMsg::ChangeApplied(result) => {
+                self.status = match result {
+                    Ok(_) => String::from("Done! The next answers will follow this format"),
+                    Err(error) => error,
+                };
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+}
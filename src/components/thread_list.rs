@@ -1,15 +1,40 @@
 use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::time::Duration;
 
 use chrono::NaiveDateTime;
+use gtk::glib;
+use gtk::graphene::Point;
 use gtk::prelude::*;
 use relm4::prelude::*;
 use relm4::typed_view::list::{RelmListItem, TypedListView};
+use relm4::RelmRemoveAllExt;
 
-use crate::assistant::database::models::Thread;
+use crate::assistant::database::models::{Folder, Tag, Thread, ThreadTag};
 
 #[derive(Debug)]
 pub struct ThreadListContainerComponent {
-    current_position: u32,
+    current_thread_id: Option<i64>,
+    filter_text: String,
+    show_archived: bool,
+    show_trash: bool,
+    deleted_thread_id: Option<i64>,
+    undo_toast_counter: u64,
+    tag_filter: Option<i64>,
+    tags: Vec<Tag>,
+    folder_filter: Option<i64>,
+    folders: Vec<Folder>,
+    /// Threads for which an answer is being generated.
+    generating_threads: HashSet<i64>,
+    tag_filter_list: gtk::StringList,
+    thread_tags_box: gtk::Box,
+    new_tag_name: gtk::EntryBuffer,
+    folder_filter_list: gtk::StringList,
+    thread_folders_box: gtk::Box,
+    new_folder_name: gtk::EntryBuffer,
+    /// Menu opened by right-clicking a thread, to move it to a folder or tag it.
+    thread_menu: gtk::Popover,
+    thread_menu_box: gtk::Box,
     list_view_wrapper: TypedListView<ThreadListItem, gtk::SingleSelection>,
 }
 
@@ -21,6 +46,33 @@ pub enum ThreadListContainerInputMsg {
     FilterThreads(String),
     DeleteThread,
     UpdateThread(Thread),
+    RenameThread(i64, String),
+    TogglePinThread,
+    ToggleArchiveThread,
+    ShowArchived(bool),
+    ShowTrash(bool),
+    RestoreThread,
+    UndoDeleteThread,
+    RemoveThread(i64),
+    CreateTag,
+    DeleteTag(i64),
+    /// Thread id, tag id and whether the thread should have the tag.
+    TagThread(i64, i64, bool),
+    FilterByTag(u32),
+    AddTag(Tag),
+    RemoveTag(i64),
+    UpdateThreadTags(i64, Vec<Tag>),
+    CreateFolder,
+    DeleteFolder(i64),
+    /// Thread id and folder id, `None` to move the thread out of any folder.
+    MoveThreadToFolder(i64, Option<i64>),
+    FilterByFolder(u32),
+    AddFolder(Folder),
+    RemoveFolder(i64),
+    /// Opens the menu of a thread at the given position of the list.
+    ShowThreadMenu(i64, f64, f64),
+    SetThreadGenerating(i64, bool),
+    SetThreadTitle(i64, String),
 }
 
 #[derive(Debug)]
@@ -28,14 +80,256 @@ pub enum ThreadListContainerOutputMsg {
     CreateNewThread,
     GetThreadMessages(i64),
     DeleteThread(i64),
+    RenameThread(i64, String),
+    PinThread(i64, bool),
+    ArchiveThread(i64, bool),
+    RestoreThread(i64),
+    PurgeThread(i64),
+    CreateTag(String),
+    DeleteTag(i64),
+    AddTagToThread(i64, i64),
+    RemoveTagFromThread(i64, i64),
+    CreateFolder(String),
+    DeleteFolder(i64),
+    MoveThreadToFolder(i64, Option<i64>),
+}
+
+#[derive(Debug)]
+pub enum ThreadListContainerCmdMsg {
+    HideUndoToast(u64),
+}
+
+/// How long the toast allowing to undo a thread deletion is shown.
+const UNDO_TOAST_DURATION: Duration = Duration::from_secs(5);
+
+impl ThreadListContainerComponent {
+    /// Returns the position of the thread in the underlying, unfiltered, list.
+    fn position(&self, thread_id: i64) -> Option<u32> {
+        (0..self.list_view_wrapper.len()).find(|position| {
+            match self.list_view_wrapper.get(*position) {
+                Some(item) => item.borrow().thread_id == thread_id,
+                None => false,
+            }
+        })
+    }
+
+    /// Returns the position of the thread in the list of visible threads.
+    fn visible_position(&self, thread_id: i64) -> Option<u32> {
+        let mut position = 0;
+        while let Some(item) = self.list_view_wrapper.get_visible(position) {
+            if item.borrow().thread_id == thread_id {
+                return Some(position);
+            }
+            position += 1;
+        }
+        None
+    }
+
+    fn with_thread<R>(&self, thread_id: i64, f: impl FnOnce(&ThreadListItem) -> R) -> Option<R> {
+        let position = self.position(thread_id)?;
+        let item = self.list_view_wrapper.get(position)?;
+        let result = f(&item.borrow());
+        Some(result)
+    }
+
+    fn with_current_thread<R>(&self, f: impl FnOnce(&ThreadListItem) -> R) -> Option<R> {
+        self.with_thread(self.current_thread_id?, f)
+    }
+
+    fn apply_filters(&mut self) {
+        self.list_view_wrapper.clear_filters();
+
+        // Deleted threads are only shown in the trash, regardless of whether they are archived
+        let show_archived = self.show_archived;
+        let show_trash = self.show_trash;
+        self.list_view_wrapper.add_filter(move |thread_list_item| {
+            if show_trash {
+                thread_list_item.deleted
+            } else {
+                !thread_list_item.deleted && thread_list_item.archived == show_archived
+            }
+        });
+
+        let filter_text = self.filter_text.clone();
+        self.list_view_wrapper
+            .add_filter(move |thread_list_item| thread_list_item.title.contains(&*filter_text));
+
+        if let Some(tag_id) = self.tag_filter {
+            self.list_view_wrapper
+                .add_filter(move |thread_list_item| thread_list_item.tag_ids.contains(&tag_id));
+        }
+
+        if let Some(folder_id) = self.folder_filter {
+            self.list_view_wrapper
+                .add_filter(move |thread_list_item| thread_list_item.folder_id == Some(folder_id));
+        }
+    }
+
+    /// Modifies a thread of the list, re-inserting it so that its row is bound again.
+    fn modify_item(&mut self, thread_id: i64, f: impl FnOnce(&mut ThreadListItem)) {
+        let Some(position) = self.position(thread_id) else {
+            return;
+        };
+        let Some(item) = self.list_view_wrapper.get(position) else {
+            return;
+        };
+        let mut thread_list_item = item.borrow().clone();
+        f(&mut thread_list_item);
+        self.list_view_wrapper.remove(position);
+        self.list_view_wrapper
+            .insert_sorted(thread_list_item, ThreadListItem::reverse_cmp);
+        if let Some(position) = self
+            .current_thread_id
+            .and_then(|thread_id| self.visible_position(thread_id))
+        {
+            self.list_view_wrapper
+                .selection_model
+                .set_selected(position);
+        }
+    }
+
+    /// Rebuilds the list of tags shown in the tags popover,
+    /// with the tags of the current thread checked.
+    fn refresh_thread_tags(&self, sender: &relm4::Sender<ThreadListContainerInputMsg>) {
+        self.thread_tags_box.remove_all();
+        self.append_tag_buttons(&self.thread_tags_box, self.current_thread_id, true, sender);
+    }
+
+    /// Rebuilds the list of folders shown in the folders popover,
+    /// with the folder of the current thread selected.
+    fn refresh_thread_folders(&self, sender: &relm4::Sender<ThreadListContainerInputMsg>) {
+        self.thread_folders_box.remove_all();
+        self.append_folder_buttons(
+            &self.thread_folders_box,
+            self.current_thread_id,
+            true,
+            sender,
+        );
+    }
+
+    /// Appends a check button per tag to `container`, checked for the tags of the thread,
+    /// and a button to delete the tag if `deletable` is set.
+    fn append_tag_buttons(
+        &self,
+        container: &gtk::Box,
+        thread_id: Option<i64>,
+        deletable: bool,
+        sender: &relm4::Sender<ThreadListContainerInputMsg>,
+    ) {
+        let thread_tag_ids = thread_id
+            .and_then(|thread_id| self.with_thread(thread_id, |item| item.tag_ids.clone()))
+            .unwrap_or_default();
+
+        for tag in self.tags.iter() {
+            let tag_id = tag.id;
+            relm4::view! {
+                tag_row = gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+
+                    gtk::CheckButton {
+                        set_label: Some(tag.name.as_str()),
+                        set_hexpand: true,
+                        set_active: thread_tag_ids.contains(&tag_id),
+                        set_sensitive: thread_id.is_some(),
+
+                        connect_toggled[sender] => move |button| {
+                            if let Some(thread_id) = thread_id {
+                                sender.emit(ThreadListContainerInputMsg::TagThread(
+                                    thread_id,
+                                    tag_id,
+                                    button.is_active(),
+                                ));
+                            }
+                        },
+                    },
+
+                    gtk::Button {
+                        set_visible: deletable,
+                        set_icon_name: "edit-delete-symbolic",
+                        set_tooltip_text: Some("Delete tag"),
+                        set_css_classes: &["button", "delete_tag_button"],
+
+                        connect_clicked[sender] => move |_| {
+                            sender.emit(ThreadListContainerInputMsg::DeleteTag(tag_id));
+                        },
+                    },
+                }
+            }
+            container.append(&tag_row);
+        }
+    }
+
+    /// Appends a radio button per folder to `container`, plus one to move the thread
+    /// out of any folder, and a button to delete the folder if `deletable` is set.
+    fn append_folder_buttons(
+        &self,
+        container: &gtk::Box,
+        thread_id: Option<i64>,
+        deletable: bool,
+        sender: &relm4::Sender<ThreadListContainerInputMsg>,
+    ) {
+        let thread_folder_id = thread_id
+            .and_then(|thread_id| self.with_thread(thread_id, |item| item.folder_id))
+            .flatten();
+
+        // The first button creates the group of the others, so that only one is active
+        let mut group: Option<gtk::CheckButton> = None;
+        let choices = std::iter::once((None, "No folder")).chain(
+            self.folders
+                .iter()
+                .map(|folder| (Some(folder.id), folder.name.as_str())),
+        );
+        for (folder_id, folder_name) in choices {
+            relm4::view! {
+                folder_row = gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+
+                    #[name = "folder_button"]
+                    gtk::CheckButton {
+                        set_label: Some(folder_name),
+                        set_hexpand: true,
+                        set_group: group.as_ref(),
+                        set_active: thread_folder_id == folder_id,
+                        set_sensitive: thread_id.is_some(),
+
+                        connect_toggled[sender] => move |button| {
+                            if let (Some(thread_id), true) = (thread_id, button.is_active()) {
+                                sender.emit(ThreadListContainerInputMsg::MoveThreadToFolder(
+                                    thread_id,
+                                    folder_id,
+                                ));
+                            }
+                        },
+                    },
+
+                    gtk::Button {
+                        set_visible: deletable && folder_id.is_some(),
+                        set_icon_name: "edit-delete-symbolic",
+                        set_tooltip_text: Some("Delete folder"),
+                        set_css_classes: &["button", "delete_folder_button"],
+
+                        connect_clicked[sender] => move |_| {
+                            if let Some(folder_id) = folder_id {
+                                sender.emit(ThreadListContainerInputMsg::DeleteFolder(folder_id));
+                            }
+                        },
+                    },
+                }
+            }
+            container.append(&folder_row);
+            group.get_or_insert(folder_button);
+        }
+    }
 }
 
 #[relm4::component(async, pub)]
 impl AsyncComponent for ThreadListContainerComponent {
-    type Init = Vec<Thread>;
+    type Init = (Vec<Thread>, Vec<Tag>, Vec<ThreadTag>, Vec<Folder>);
     type Input = ThreadListContainerInputMsg;
     type Output = ThreadListContainerOutputMsg;
-    type CommandOutput = ();
+    type CommandOutput = ThreadListContainerCmdMsg;
 
     view! {
         gtk::Box {
@@ -66,9 +360,153 @@ impl AsyncComponent for ThreadListContainerComponent {
 
                 gtk::Button {
                     set_icon_name: "edit-delete-symbolic",
+                    #[watch]
+                    set_tooltip_text: if model.show_trash {
+                        Some("Delete thread permanently")
+                    } else {
+                        Some("Move thread to trash")
+                    },
                     set_css_classes: &["button", "delete_thread_button"],
                     connect_clicked => ThreadListContainerInputMsg::DeleteThread,
                 },
+
+                gtk::Button {
+                    set_icon_name: "edit-undo-symbolic",
+                    set_tooltip_text: Some("Restore thread"),
+                    #[watch]
+                    set_visible: model.show_trash,
+                    set_css_classes: &["button", "restore_thread_button"],
+                    connect_clicked => ThreadListContainerInputMsg::RestoreThread,
+                },
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_margin_start: 5,
+                set_margin_end: 5,
+                set_spacing: 5,
+
+                gtk::Button {
+                    set_icon_name: "view-pin-symbolic",
+                    set_tooltip_text: Some("Pin or unpin thread"),
+                    set_css_classes: &["button", "pin_thread_button"],
+                    connect_clicked => ThreadListContainerInputMsg::TogglePinThread,
+                },
+
+                gtk::Button {
+                    set_icon_name: "folder-symbolic",
+                    set_tooltip_text: Some("Archive or unarchive thread"),
+                    set_css_classes: &["button", "archive_thread_button"],
+                    connect_clicked => ThreadListContainerInputMsg::ToggleArchiveThread,
+                },
+
+                gtk::MenuButton {
+                    set_label: "Tags",
+                    set_tooltip_text: Some("Manage tags"),
+                    set_direction: gtk::ArrowType::Down,
+                    set_css_classes: &["button", "tags_menu_button"],
+
+                    #[wrap(Some)]
+                    set_popover = &gtk::Popover {
+                        set_position: gtk::PositionType::Bottom,
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 5,
+
+                            #[local_ref]
+                            thread_tags_box -> gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 5,
+                            },
+
+                            gtk::Entry {
+                                set_buffer: &model.new_tag_name,
+                                set_placeholder_text: Some("New tag"),
+                                connect_activate => ThreadListContainerInputMsg::CreateTag,
+                            },
+                        },
+                    },
+                },
+
+                gtk::MenuButton {
+                    set_label: "Folders",
+                    set_tooltip_text: Some("Manage folders"),
+                    set_direction: gtk::ArrowType::Down,
+                    set_css_classes: &["button", "folders_menu_button"],
+
+                    #[wrap(Some)]
+                    set_popover = &gtk::Popover {
+                        set_position: gtk::PositionType::Bottom,
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Vertical,
+                            set_spacing: 5,
+
+                            #[local_ref]
+                            thread_folders_box -> gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 5,
+                            },
+
+                            gtk::Entry {
+                                set_buffer: &model.new_folder_name,
+                                set_placeholder_text: Some("New folder"),
+                                connect_activate => ThreadListContainerInputMsg::CreateFolder,
+                            },
+                        },
+                    },
+                },
+
+                gtk::ToggleButton {
+                    set_hexpand: true,
+                    set_halign: gtk::Align::End,
+                    set_label: "Archived",
+                    set_tooltip_text: Some("Show archived threads"),
+                    set_css_classes: &["button", "show_archived_button"],
+
+                    connect_toggled[sender] => move |button| {
+                        sender.input(ThreadListContainerInputMsg::ShowArchived(button.is_active()));
+                    },
+                },
+
+                gtk::ToggleButton {
+                    set_icon_name: "user-trash-symbolic",
+                    set_tooltip_text: Some("Show deleted threads"),
+                    set_css_classes: &["button", "show_trash_button"],
+
+                    connect_toggled[sender] => move |button| {
+                        sender.input(ThreadListContainerInputMsg::ShowTrash(button.is_active()));
+                    },
+                },
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_margin_start: 5,
+                set_margin_end: 5,
+                set_spacing: 5,
+                set_homogeneous: true,
+
+                gtk::DropDown {
+                    set_model: Some(&model.folder_filter_list),
+                    set_tooltip_text: Some("Filter threads by folder"),
+                    set_css_classes: &["dropdown", "folder_filter_dropdown"],
+
+                    connect_selected_notify[sender] => move |drop_down| {
+                        sender.input(ThreadListContainerInputMsg::FilterByFolder(drop_down.selected()));
+                    },
+                },
+
+                gtk::DropDown {
+                    set_model: Some(&model.tag_filter_list),
+                    set_tooltip_text: Some("Filter threads by tag"),
+                    set_css_classes: &["dropdown", "tag_filter_dropdown"],
+
+                    connect_selected_notify[sender] => move |drop_down| {
+                        sender.input(ThreadListContainerInputMsg::FilterByTag(drop_down.selected()));
+                    },
+                },
             },
 
             #[name = "scrolled_window"]
@@ -89,31 +527,109 @@ impl AsyncComponent for ThreadListContainerComponent {
                     },
                 },
             },
+
+            gtk::Revealer {
+                set_transition_type: gtk::RevealerTransitionType::SlideUp,
+                #[watch]
+                set_reveal_child: model.deleted_thread_id.is_some(),
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_margin_all: 5,
+                    set_spacing: 5,
+                    set_css_classes: &["undo_toast"],
+
+                    gtk::Label {
+                        set_label: "Thread deleted",
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                    },
+
+                    gtk::Button {
+                        set_label: "Undo",
+                        set_css_classes: &["button", "undo_button"],
+                        connect_clicked => ThreadListContainerInputMsg::UndoDeleteThread,
+                    },
+                },
+            },
         },
     }
 
     async fn init(
-        threads: Self::Init,
+        init: Self::Init,
         _root: Self::Root,
         sender: AsyncComponentSender<Self>,
     ) -> AsyncComponentParts<Self> {
+        let (threads, tags, thread_tags, folders) = init;
+
         let list_view_wrapper: TypedListView<ThreadListItem, gtk::SingleSelection> =
             TypedListView::new();
 
         let _ = threads
             .into_iter()
             .map(|thread| {
-                list_view_wrapper
-                    .insert_sorted(ThreadListItem::new(thread), ThreadListItem::reverse_cmp)
+                let tag_ids = thread_tags
+                    .iter()
+                    .filter(|thread_tag| thread_tag.thread_id == thread.id)
+                    .map(|thread_tag| thread_tag.tag_id)
+                    .collect();
+                list_view_wrapper.insert_sorted(
+                    ThreadListItem::new(thread, tag_ids, sender.input_sender().clone()),
+                    ThreadListItem::reverse_cmp,
+                )
             })
             .collect::<Vec<_>>();
 
-        let model = ThreadListContainerComponent {
-            current_position: 0,
+        let tag_filter_list = gtk::StringList::default();
+        tag_filter_list.append("All tags");
+        for tag in tags.iter() {
+            tag_filter_list.append(&tag.name);
+        }
+
+        let folder_filter_list = gtk::StringList::default();
+        folder_filter_list.append("All folders");
+        for folder in folders.iter() {
+            folder_filter_list.append(&folder.name);
+        }
+
+        let thread_menu_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+        let thread_menu = gtk::Popover::builder()
+            .child(&thread_menu_box)
+            .has_arrow(false)
+            .position(gtk::PositionType::Bottom)
+            .build();
+        // Rows are recycled, so the menu is attached to the list instead
+        thread_menu.set_parent(&list_view_wrapper.view);
+
+        let mut model = ThreadListContainerComponent {
+            current_thread_id: None,
+            filter_text: String::new(),
+            show_archived: false,
+            show_trash: false,
+            deleted_thread_id: None,
+            undo_toast_counter: 0,
+            tag_filter: None,
+            tags,
+            folder_filter: None,
+            folders,
+            generating_threads: HashSet::new(),
+            tag_filter_list,
+            thread_tags_box: gtk::Box::default(),
+            new_tag_name: gtk::EntryBuffer::default(),
+            folder_filter_list,
+            thread_folders_box: gtk::Box::default(),
+            new_folder_name: gtk::EntryBuffer::default(),
+            thread_menu,
+            thread_menu_box,
             list_view_wrapper,
         };
+        model.apply_filters();
+        model.refresh_thread_tags(sender.input_sender());
+        model.refresh_thread_folders(sender.input_sender());
 
         let thread_list = &model.list_view_wrapper.view;
+        let thread_tags_box = &model.thread_tags_box;
+        let thread_folders_box = &model.thread_folders_box;
 
         sender
             .input_sender()
@@ -137,73 +653,382 @@ impl AsyncComponent for ThreadListContainerComponent {
                     .emit(ThreadListContainerOutputMsg::CreateNewThread);
             }
             ThreadListContainerInputMsg::DeleteThread => {
-                let thread_list_item = self
-                    .list_view_wrapper
-                    .get(self.current_position)
-                    .expect("Thread item at {self.current_position} should exist");
-                let thread_id = thread_list_item.borrow().thread_id;
+                let Some(thread_id) = self.current_thread_id else {
+                    return;
+                };
+                if self.show_trash {
+                    sender
+                        .output_sender()
+                        .emit(ThreadListContainerOutputMsg::PurgeThread(thread_id));
+                    return;
+                }
                 sender
                     .output_sender()
                     .emit(ThreadListContainerOutputMsg::DeleteThread(thread_id));
-                self.list_view_wrapper.remove(self.current_position);
+
+                self.deleted_thread_id = Some(thread_id);
+                self.undo_toast_counter += 1;
+                let undo_toast_counter = self.undo_toast_counter;
+                sender.oneshot_command(async move {
+                    tokio::time::sleep(UNDO_TOAST_DURATION).await;
+                    ThreadListContainerCmdMsg::HideUndoToast(undo_toast_counter)
+                });
+            }
+            ThreadListContainerInputMsg::UndoDeleteThread => {
+                if let Some(thread_id) = self.deleted_thread_id.take() {
+                    sender
+                        .output_sender()
+                        .emit(ThreadListContainerOutputMsg::RestoreThread(thread_id));
+                }
+            }
+            ThreadListContainerInputMsg::RestoreThread => {
+                if let Some(thread_id) = self.current_thread_id {
+                    sender
+                        .output_sender()
+                        .emit(ThreadListContainerOutputMsg::RestoreThread(thread_id));
+                }
+            }
+            ThreadListContainerInputMsg::RemoveThread(thread_id) => {
+                if let Some(position) = self.position(thread_id) {
+                    self.list_view_wrapper.remove(position);
+                }
+                if self.current_thread_id == Some(thread_id) {
+                    self.current_thread_id = None;
+                    sender
+                        .input_sender()
+                        .emit(ThreadListContainerInputMsg::SelectThread(0));
+                }
             }
             ThreadListContainerInputMsg::AddThread(thread) => {
-                let thread_list_item = ThreadListItem::new(thread);
-                let position = self
-                    .list_view_wrapper
+                let thread_id = thread.id;
+                let thread_list_item =
+                    ThreadListItem::new(thread, Vec::new(), sender.input_sender().clone());
+                self.list_view_wrapper
                     .insert_sorted(thread_list_item, ThreadListItem::reverse_cmp);
-                sender
-                    .input_sender()
-                    .emit(ThreadListContainerInputMsg::SelectThread(position))
+                if let Some(position) = self.visible_position(thread_id) {
+                    sender
+                        .input_sender()
+                        .emit(ThreadListContainerInputMsg::SelectThread(position))
+                }
             }
             ThreadListContainerInputMsg::FilterThreads(filter_text) => {
-                self.list_view_wrapper.pop_filter();
-                self.list_view_wrapper.add_filter(move |thread_list_item| {
-                    thread_list_item.title.contains(&*filter_text)
-                });
+                self.filter_text = filter_text;
+                self.apply_filters();
+            }
+            ThreadListContainerInputMsg::ShowArchived(show_archived) => {
+                self.show_archived = show_archived;
+                self.apply_filters();
+            }
+            ThreadListContainerInputMsg::ShowTrash(show_trash) => {
+                self.show_trash = show_trash;
+                self.apply_filters();
             }
             ThreadListContainerInputMsg::SelectThread(position) => {
-                self.current_position = position;
-                let thread_list_item = self
-                    .list_view_wrapper
-                    .get(position)
-                    .expect("Getting thread item at position should work");
+                let Some(thread_list_item) = self.list_view_wrapper.get_visible(position) else {
+                    return;
+                };
                 let thread_id = thread_list_item.borrow().thread_id;
+                self.current_thread_id = Some(thread_id);
+                self.list_view_wrapper
+                    .selection_model
+                    .set_selected(position);
+                self.refresh_thread_tags(sender.input_sender());
+                self.refresh_thread_folders(sender.input_sender());
                 sender
                     .output_sender()
                     .emit(ThreadListContainerOutputMsg::GetThreadMessages(thread_id));
             }
             ThreadListContainerInputMsg::UpdateThread(thread) => {
+                // Re-insert the thread because its position in the sorted list may have changed
                 let thread_id = thread.id;
+                let is_deleted = thread.deleted_at.is_some();
+                let mut tag_ids = Vec::new();
+                if let Some(position) = self.position(thread.id) {
+                    if let Some(item) = self.list_view_wrapper.get(position) {
+                        tag_ids = item.borrow().tag_ids.clone();
+                    }
+                    self.list_view_wrapper.remove(position);
+                }
+                let mut thread_list_item =
+                    ThreadListItem::new(thread, tag_ids, sender.input_sender().clone());
+                thread_list_item.generating = self.generating_threads.contains(&thread_id);
                 self.list_view_wrapper
-                    .add_filter(move |thread_list_item| thread_list_item.thread_id == thread_id);
-                let thread_list_item = self
-                    .list_view_wrapper
-                    .get_visible(0)
-                    .expect("First item should exist");
+                    .insert_sorted(thread_list_item, ThreadListItem::reverse_cmp);
+                if let Some(position) = self
+                    .current_thread_id
+                    .and_then(|thread_id| self.visible_position(thread_id))
                 {
-                    let mut thread_list_item = thread_list_item.borrow_mut();
-                    thread_list_item.title = thread.title;
+                    self.list_view_wrapper
+                        .selection_model
+                        .set_selected(position);
+                } else if self.current_thread_id == Some(thread_id)
+                    && is_deleted
+                    && !self.show_trash
+                {
+                    // Switch to another thread when the current one is moved to the trash
+                    sender
+                        .input_sender()
+                        .emit(ThreadListContainerInputMsg::SelectThread(0));
+                }
+                if self.current_thread_id == Some(thread_id) {
+                    self.refresh_thread_folders(sender.input_sender());
+                }
+            }
+            ThreadListContainerInputMsg::RenameThread(thread_id, title) => {
+                sender
+                    .output_sender()
+                    .emit(ThreadListContainerOutputMsg::RenameThread(thread_id, title));
+            }
+            ThreadListContainerInputMsg::TogglePinThread => {
+                if let Some((thread_id, pinned)) =
+                    self.with_current_thread(|item| (item.thread_id, item.pinned))
+                {
+                    sender
+                        .output_sender()
+                        .emit(ThreadListContainerOutputMsg::PinThread(thread_id, !pinned));
+                }
+            }
+            ThreadListContainerInputMsg::ToggleArchiveThread => {
+                if let Some((thread_id, archived)) =
+                    self.with_current_thread(|item| (item.thread_id, item.archived))
+                {
+                    sender
+                        .output_sender()
+                        .emit(ThreadListContainerOutputMsg::ArchiveThread(
+                            thread_id, !archived,
+                        ));
+                }
+            }
+            ThreadListContainerInputMsg::CreateTag => {
+                let name = self.new_tag_name.text().trim().to_string();
+                if name.is_empty() || self.tags.iter().any(|tag| tag.name == name) {
+                    return;
+                }
+                self.new_tag_name.set_text("");
+                sender
+                    .output_sender()
+                    .emit(ThreadListContainerOutputMsg::CreateTag(name));
+            }
+            ThreadListContainerInputMsg::DeleteTag(tag_id) => {
+                sender
+                    .output_sender()
+                    .emit(ThreadListContainerOutputMsg::DeleteTag(tag_id));
+            }
+            ThreadListContainerInputMsg::TagThread(thread_id, tag_id, active) => {
+                let message = if active {
+                    ThreadListContainerOutputMsg::AddTagToThread(thread_id, tag_id)
+                } else {
+                    ThreadListContainerOutputMsg::RemoveTagFromThread(thread_id, tag_id)
+                };
+                sender.output_sender().emit(message);
+            }
+            ThreadListContainerInputMsg::FilterByTag(index) => {
+                // The first entry of the drop down is used to show threads with any tag
+                self.tag_filter = match index {
+                    0 => None,
+                    index => self.tags.get(index as usize - 1).map(|tag| tag.id),
+                };
+                self.apply_filters();
+            }
+            ThreadListContainerInputMsg::AddTag(tag) => {
+                let index = self
+                    .tags
+                    .iter()
+                    .position(|other| other.name > tag.name)
+                    .unwrap_or(self.tags.len());
+                self.tag_filter_list
+                    .splice(index as u32 + 1, 0, &[tag.name.as_str()]);
+                self.tags.insert(index, tag);
+                self.refresh_thread_tags(sender.input_sender());
+            }
+            ThreadListContainerInputMsg::RemoveTag(tag_id) => {
+                if let Some(index) = self.tags.iter().position(|tag| tag.id == tag_id) {
+                    self.tags.remove(index);
+                    self.tag_filter_list.remove(index as u32 + 1);
+                }
+                for position in 0..self.list_view_wrapper.len() {
+                    if let Some(item) = self.list_view_wrapper.get(position) {
+                        item.borrow_mut().tag_ids.retain(|id| *id != tag_id);
+                    }
+                }
+                if self.tag_filter == Some(tag_id) {
+                    self.tag_filter = None;
+                }
+                self.apply_filters();
+                self.refresh_thread_tags(sender.input_sender());
+            }
+            ThreadListContainerInputMsg::UpdateThreadTags(thread_id, tags) => {
+                if let Some(item) = self
+                    .position(thread_id)
+                    .and_then(|position| self.list_view_wrapper.get(position))
+                {
+                    item.borrow_mut().tag_ids = tags.iter().map(|tag| tag.id).collect();
+                }
+                self.apply_filters();
+                if self.current_thread_id == Some(thread_id) {
+                    self.refresh_thread_tags(sender.input_sender());
+                }
+            }
+            ThreadListContainerInputMsg::CreateFolder => {
+                let name = self.new_folder_name.text().trim().to_string();
+                if name.is_empty() || self.folders.iter().any(|folder| folder.name == name) {
+                    return;
+                }
+                self.new_folder_name.set_text("");
+                sender
+                    .output_sender()
+                    .emit(ThreadListContainerOutputMsg::CreateFolder(name));
+            }
+            ThreadListContainerInputMsg::DeleteFolder(folder_id) => {
+                sender
+                    .output_sender()
+                    .emit(ThreadListContainerOutputMsg::DeleteFolder(folder_id));
+            }
+            ThreadListContainerInputMsg::MoveThreadToFolder(thread_id, folder_id) => {
+                if self.with_thread(thread_id, |item| item.folder_id) == Some(folder_id) {
+                    return;
+                }
+                sender
+                    .output_sender()
+                    .emit(ThreadListContainerOutputMsg::MoveThreadToFolder(
+                        thread_id, folder_id,
+                    ));
+            }
+            ThreadListContainerInputMsg::FilterByFolder(index) => {
+                // The first entry of the drop down is used to show threads in any folder
+                self.folder_filter = match index {
+                    0 => None,
+                    index => self.folders.get(index as usize - 1).map(|folder| folder.id),
+                };
+                self.apply_filters();
+            }
+            ThreadListContainerInputMsg::AddFolder(folder) => {
+                let index = self
+                    .folders
+                    .iter()
+                    .position(|other| other.name > folder.name)
+                    .unwrap_or(self.folders.len());
+                self.folder_filter_list
+                    .splice(index as u32 + 1, 0, &[folder.name.as_str()]);
+                self.folders.insert(index, folder);
+                self.refresh_thread_folders(sender.input_sender());
+            }
+            ThreadListContainerInputMsg::RemoveFolder(folder_id) => {
+                // The threads of the folder are updated separately
+                if let Some(index) = self
+                    .folders
+                    .iter()
+                    .position(|folder| folder.id == folder_id)
+                {
+                    self.folders.remove(index);
+                    self.folder_filter_list.remove(index as u32 + 1);
+                }
+                if self.folder_filter == Some(folder_id) {
+                    self.folder_filter = None;
+                }
+                self.apply_filters();
+                self.refresh_thread_folders(sender.input_sender());
+            }
+            ThreadListContainerInputMsg::ShowThreadMenu(thread_id, x, y) => {
+                let input_sender = sender.input_sender();
+                self.thread_menu_box.remove_all();
+                self.thread_menu_box
+                    .append(&gtk::Label::new(Some("Move to folder")));
+                self.append_folder_buttons(
+                    &self.thread_menu_box,
+                    Some(thread_id),
+                    false,
+                    input_sender,
+                );
+                self.thread_menu_box
+                    .append(&gtk::Separator::new(gtk::Orientation::Horizontal));
+                if self.tags.is_empty() {
+                    self.thread_menu_box
+                        .append(&gtk::Label::new(Some("No tags")));
+                } else {
+                    self.thread_menu_box.append(&gtk::Label::new(Some("Tags")));
+                    self.append_tag_buttons(
+                        &self.thread_menu_box,
+                        Some(thread_id),
+                        false,
+                        input_sender,
+                    );
+                }
+
+                self.thread_menu
+                    .set_pointing_to(Some(&gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+                self.thread_menu.popup();
+            }
+            ThreadListContainerInputMsg::SetThreadGenerating(thread_id, generating) => {
+                if generating {
+                    self.generating_threads.insert(thread_id);
+                } else {
+                    self.generating_threads.remove(&thread_id);
+                }
+                self.modify_item(thread_id, |thread_list_item| {
+                    thread_list_item.generating = generating;
+                });
+            }
+            ThreadListContainerInputMsg::SetThreadTitle(thread_id, title) => {
+                self.modify_item(thread_id, |thread_list_item| {
+                    thread_list_item.title = title;
+                });
+            }
+        }
+    }
+
+    fn shutdown(&mut self, _: &mut Self::Widgets, _: relm4::Sender<Self::Output>) {
+        self.thread_menu.unparent();
+    }
+
+    async fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        _: AsyncComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            ThreadListContainerCmdMsg::HideUndoToast(undo_toast_counter) => {
+                // Only hide the toast if no other thread was deleted in the meantime
+                if undo_toast_counter == self.undo_toast_counter {
+                    self.deleted_thread_id = None;
                 }
-                self.list_view_wrapper.pop_filter();
             }
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 struct ThreadListItem {
     thread_id: i64,
     title: String,
     last_updated_at: NaiveDateTime,
+    pinned: bool,
+    archived: bool,
+    deleted: bool,
+    generating: bool,
+    tag_ids: Vec<i64>,
+    folder_id: Option<i64>,
+    sender: relm4::Sender<ThreadListContainerInputMsg>,
 }
 
 impl ThreadListItem {
-    fn new(thread: Thread) -> Self {
+    fn new(
+        thread: Thread,
+        tag_ids: Vec<i64>,
+        sender: relm4::Sender<ThreadListContainerInputMsg>,
+    ) -> Self {
         Self {
             thread_id: thread.id,
             title: thread.title,
             last_updated_at: thread.last_updated_at,
+            pinned: thread.pinned,
+            archived: thread.archived,
+            deleted: thread.deleted_at.is_some(),
+            generating: false,
+            tag_ids,
+            folder_id: thread.folder_id,
+            sender,
         }
     }
 
@@ -212,21 +1037,35 @@ impl ThreadListItem {
     }
 }
 
+// Pinned threads are always placed before the others.
+impl PartialEq for ThreadListItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ThreadListItem {}
+
 impl PartialOrd for ThreadListItem {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.last_updated_at.cmp(&other.last_updated_at))
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for ThreadListItem {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.last_updated_at.cmp(&other.last_updated_at)
+        (self.pinned, self.last_updated_at).cmp(&(other.pinned, other.last_updated_at))
     }
 }
 
 struct ThreadListItemWidgets {
-    title: gtk::Label,
+    title: gtk::EditableLabel,
+    pin_icon: gtk::Image,
+    generating_spinner: gtk::Spinner,
     timestamp: gtk::Label,
+    menu_gesture: gtk::GestureClick,
+    rename_handler: Option<glib::SignalHandlerId>,
+    menu_handler: Option<glib::SignalHandlerId>,
 }
 
 impl RelmListItem for ThreadListItem {
@@ -245,8 +1084,8 @@ impl RelmListItem for ThreadListItem {
                     set_halign: gtk::Align::Fill,
 
                     #[name = "title"]
-                    gtk::Label {
-                        set_wrap: true,
+                    gtk::EditableLabel::new("") {
+                        set_tooltip_text: Some("Rename thread"),
                         set_max_width_chars: 30,
                         set_width_chars: 10,
                     },
@@ -254,18 +1093,98 @@ impl RelmListItem for ThreadListItem {
                     #[name = "timestamp"]
                     gtk::Label,
                 },
+
+                #[name = "generating_spinner"]
+                gtk::Spinner {
+                    set_tooltip_text: Some("Generating an answer"),
+                    set_valign: gtk::Align::Start,
+                },
+
+                #[name = "pin_icon"]
+                gtk::Image {
+                    set_icon_name: Some("view-pin-symbolic"),
+                    set_valign: gtk::Align::Start,
+                },
             }
         }
 
-        let widgets = Self::Widgets { title, timestamp };
+        let menu_gesture = gtk::GestureClick::builder()
+            .button(gtk::gdk::BUTTON_SECONDARY)
+            .build();
+        root.add_controller(menu_gesture.clone());
+
+        let widgets = Self::Widgets {
+            title,
+            pin_icon,
+            generating_spinner,
+            timestamp,
+            menu_gesture,
+            rename_handler: None,
+            menu_handler: None,
+        };
 
         (root, widgets)
     }
 
     fn bind(&mut self, widgets: &mut Self::Widgets, _: &mut Self::Root) {
-        let Self::Widgets { title, timestamp } = widgets;
+        let Self::Widgets {
+            title,
+            pin_icon,
+            generating_spinner,
+            timestamp,
+            menu_gesture,
+            rename_handler,
+            menu_handler,
+        } = widgets;
 
-        title.set_label(&self.title);
+        title.set_text(&self.title);
+        pin_icon.set_visible(self.pinned);
+        generating_spinner.set_visible(self.generating);
+        generating_spinner.set_spinning(self.generating);
         timestamp.set_label(&self.last_updated_at.format("%d %B %Y at %R").to_string());
+
+        let thread_id = self.thread_id;
+        let current_title = self.title.clone();
+        let sender = self.sender.clone();
+        *rename_handler = Some(title.connect_editing_notify(move |label| {
+            if label.is_editing() {
+                return;
+            }
+            let new_title = label.text().trim().to_string();
+            if new_title.is_empty() || new_title == current_title {
+                label.set_text(&current_title);
+                return;
+            }
+            sender.emit(ThreadListContainerInputMsg::RenameThread(
+                thread_id, new_title,
+            ));
+        }));
+
+        let sender = self.sender.clone();
+        *menu_handler = Some(menu_gesture.connect_pressed(move |gesture, _, x, y| {
+            let Some(row) = gesture.widget() else {
+                return;
+            };
+            let Some(point) = row
+                .ancestor(gtk::ListView::static_type())
+                .and_then(|list| row.compute_point(&list, &Point::new(x as f32, y as f32)))
+            else {
+                return;
+            };
+            sender.emit(ThreadListContainerInputMsg::ShowThreadMenu(
+                thread_id,
+                point.x() as f64,
+                point.y() as f64,
+            ));
+        }));
+    }
+
+    fn unbind(&mut self, widgets: &mut Self::Widgets, _: &mut Self::Root) {
+        if let Some(handler) = widgets.rename_handler.take() {
+            widgets.title.disconnect(handler);
+        }
+        if let Some(handler) = widgets.menu_handler.take() {
+            widgets.menu_gesture.disconnect(handler);
+        }
     }
 }
@@ -0,0 +1,339 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use home::home_dir;
+
+/// Environment variable overriding the path of the database file.
+pub const DATABASE_ENV_VAR: &str = "PINCER_CHAT_DATABASE";
+/// Environment variable selecting the profile used when none is given on the command line.
+pub const PROFILE_ENV_VAR: &str = "PINCER_CHAT_PROFILE";
+
+const APP_DIR_NAME: &str = "pincer_chat";
+const DATABASE_FILE_NAME: &str = "database.db";
+const PROFILES_DIR_NAME: &str = "profiles";
+
+/// Options given on the command line.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CliOptions {
+    pub database: Option<PathBuf>,
+    pub profile: Option<String>,
+    /// Print the names of the existing profiles instead of starting the application.
+    pub list_profiles: bool,
+    /// Back up the database instead of starting the application.
+    pub backup: bool,
+    /// Print the backups of the database instead of starting the application.
+    pub list_backups: bool,
+    /// Restore the database from the given backup instead of starting the application.
+    pub restore_backup: Option<PathBuf>,
+}
+
+impl CliOptions {
+    /// Parses the options of the application, returning the other arguments
+    /// so that they can be handled by GTK.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<(Self, Vec<String>)> {
+        let mut options = Self::default();
+        let mut other_args = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--list-profiles" => {
+                    options.list_profiles = true;
+                    continue;
+                }
+                "--backup" => {
+                    options.backup = true;
+                    continue;
+                }
+                "--list-backups" => {
+                    options.list_backups = true;
+                    continue;
+                }
+                _ => {}
+            }
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            if !["--database", "--profile", "--restore-backup"].contains(&name.as_str()) {
+                other_args.push(arg);
+                continue;
+            }
+            let value = match inline_value.or_else(|| args.next()) {
+                Some(value) if !value.is_empty() => value,
+                _ => bail!("Missing value for the {name} option"),
+            };
+            match name.as_str() {
+                "--database" => options.database = Some(PathBuf::from(value)),
+                "--profile" => options.profile = Some(value),
+                _ => options.restore_backup = Some(PathBuf::from(value)),
+            }
+        }
+        Ok((options, other_args))
+    }
+
+    /// Whether a database command was given, which is run instead of starting the application.
+    pub fn has_database_command(&self) -> bool {
+        self.backup || self.list_backups || self.restore_backup.is_some()
+    }
+}
+
+/// Location of the data of the application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Named profile whose database is used, `None` for the default one.
+    pub profile: Option<String>,
+    pub database_path: PathBuf,
+    /// Whether the profile can be chosen on startup, as neither it nor the database was given.
+    pub profile_choosable: bool,
+}
+
+impl Config {
+    /// Resolves the database to use, in order of precedence from the `--database` option,
+    /// the database environment variable, then the selected profile in the data directory.
+    pub fn load(options: CliOptions) -> Result<Self> {
+        Self::resolve(options, |name| std::env::var(name).ok())
+    }
+
+    fn resolve(options: CliOptions, env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let profile = match options.profile.or_else(|| env(PROFILE_ENV_VAR)) {
+            Some(profile) if !profile.is_empty() => Some(validate_profile_name(profile)?),
+            _ => None,
+        };
+
+        let database_path = options.database.or_else(|| {
+            env(DATABASE_ENV_VAR)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        });
+        let profile_choosable = profile.is_none() && database_path.is_none();
+        let database_path = match database_path {
+            Some(database_path) => database_path,
+            None => {
+                let data_dir = data_dir(&env)?;
+                match &profile {
+                    Some(profile) => profile_database_path(&data_dir, profile),
+                    None => default_database_path(&data_dir, &env),
+                }
+            }
+        };
+
+        Ok(Self {
+            profile,
+            database_path,
+            profile_choosable,
+        })
+    }
+
+    /// Creates the directory containing the database file and returns the database URL.
+    pub fn prepare_database(&self) -> Result<String> {
+        if let Some(parent) = self.database_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+        self.database_path
+            .to_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow!("Database path {:?} is not valid UTF-8", self.database_path))
+    }
+
+    /// Returns the names of the profiles that have a database in the data directory.
+    pub fn list_profiles() -> Result<Vec<String>> {
+        let profiles_dir =
+            data_dir(&|name: &str| std::env::var(name).ok())?.join(PROFILES_DIR_NAME);
+        if !profiles_dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut profiles: Vec<String> = std::fs::read_dir(profiles_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "db"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+            .collect();
+        profiles.sort();
+        Ok(profiles)
+    }
+}
+
+/// Returns the data directory of the application, following the XDG base directory specification.
+fn data_dir(env: &impl Fn(&str) -> Option<String>) -> Result<PathBuf> {
+    let base_dir = match env("XDG_DATA_HOME").map(PathBuf::from) {
+        // Relative paths must be ignored according to the specification
+        Some(path) if path.is_absolute() => path,
+        _ => match user_home_dir(env) {
+            Some(path) => path.join(".local").join("share"),
+            None => bail!("Unable to find the data directory, please use the --database option"),
+        },
+    };
+    Ok(base_dir.join(APP_DIR_NAME))
+}
+
+fn user_home_dir(env: &impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    env("HOME")
+        .map(PathBuf::from)
+        .or_else(home_dir)
+        .filter(|path| !path.as_os_str().is_empty())
+}
+
+fn default_database_path(data_dir: &Path, env: &impl Fn(&str) -> Option<String>) -> PathBuf {
+    let database_path = data_dir.join(DATABASE_FILE_NAME);
+    // Keep using the database created by previous versions
+    if !database_path.exists() {
+        if let Some(legacy_path) = user_home_dir(env)
+            .map(|home| home.join(".pincer_chat").join(DATABASE_FILE_NAME))
+            .filter(|legacy_path| legacy_path.exists())
+        {
+            return legacy_path;
+        }
+    }
+    database_path
+}
+
+fn profile_database_path(data_dir: &Path, profile: &str) -> PathBuf {
+    data_dir
+        .join(PROFILES_DIR_NAME)
+        .join(format!("{profile}.db"))
+}
+
+fn validate_profile_name(profile: String) -> Result<String> {
+    if profile
+        .chars()
+        .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    {
+        Ok(profile)
+    } else {
+        Err(anyhow!(
+            "Invalid profile name {profile:?}, only letters, digits, '-' and '_' are allowed"
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn env(variables: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let variables: HashMap<String, String> = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| variables.get(name).cloned()
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parsing_cli_options() {
+        let (options, other_args) = CliOptions::parse(args(&[
+            "pincer-chat",
+            "--profile",
+            "work",
+            "--database=/tmp/chat.db",
+            "--gtk-debug",
+            "--restore-backup",
+            "/tmp/backup.db",
+            "--list-backups",
+        ]))
+        .expect("Parsing arguments should work");
+        assert_eq!(
+            options,
+            CliOptions {
+                database: Some(PathBuf::from("/tmp/chat.db")),
+                profile: Some(String::from("work")),
+                list_profiles: false,
+                backup: false,
+                list_backups: true,
+                restore_backup: Some(PathBuf::from("/tmp/backup.db")),
+            }
+        );
+        assert_eq!(other_args, args(&["pincer-chat", "--gtk-debug"]));
+
+        assert!(CliOptions::parse(args(&["pincer-chat", "--profile"])).is_err());
+    }
+
+    #[test]
+    fn test_resolving_database_path() {
+        let xdg_env = env(&[("XDG_DATA_HOME", "/data"), ("HOME", "/home/test")]);
+        let config = Config::resolve(CliOptions::default(), &xdg_env).expect("Config should work");
+        assert_eq!(config.profile, None);
+        assert_eq!(
+            config.database_path,
+            PathBuf::from("/data/pincer_chat/database.db")
+        );
+        assert!(config.profile_choosable);
+
+        let options = CliOptions {
+            profile: Some(String::from("work")),
+            ..CliOptions::default()
+        };
+        let config = Config::resolve(options, &xdg_env).expect("Config should work");
+        assert_eq!(config.profile.as_deref(), Some("work"));
+        assert_eq!(
+            config.database_path,
+            PathBuf::from("/data/pincer_chat/profiles/work.db")
+        );
+        assert!(!config.profile_choosable);
+
+        let config = Config::resolve(
+            CliOptions::default(),
+            env(&[
+                ("XDG_DATA_HOME", "/data"),
+                (PROFILE_ENV_VAR, "personal"),
+                (DATABASE_ENV_VAR, "/tmp/env.db"),
+            ]),
+        )
+        .expect("Config should work");
+        assert_eq!(config.profile.as_deref(), Some("personal"));
+        assert_eq!(config.database_path, PathBuf::from("/tmp/env.db"));
+
+        // The command line takes precedence over the environment
+        let options = CliOptions {
+            database: Some(PathBuf::from("/tmp/cli.db")),
+            ..CliOptions::default()
+        };
+        let config = Config::resolve(options, env(&[(DATABASE_ENV_VAR, "/tmp/env.db")]))
+            .expect("Config should work");
+        assert_eq!(config.database_path, PathBuf::from("/tmp/cli.db"));
+        assert!(!config.profile_choosable);
+
+        // Without XDG data directory, the data is stored in the home directory
+        let config = Config::resolve(CliOptions::default(), env(&[("HOME", "/home/test")]))
+            .expect("Config should work");
+        assert_eq!(
+            config.database_path,
+            PathBuf::from("/home/test/.local/share/pincer_chat/database.db")
+        );
+    }
+
+    #[test]
+    fn test_invalid_profile_name_fails() {
+        let options = CliOptions {
+            profile: Some(String::from("../work")),
+            ..CliOptions::default()
+        };
+        assert!(Config::resolve(options, env(&[("XDG_DATA_HOME", "/data")])).is_err());
+    }
+
+    #[test]
+    fn test_preparing_database_creates_directory() {
+        let mut database_dir = std::env::temp_dir();
+        database_dir.push(format!("pincer_chat_config_{}", std::process::id()));
+        let config = Config {
+            profile: None,
+            database_path: database_dir.join("nested").join("database.db"),
+            profile_choosable: false,
+        };
+
+        let database_url = config
+            .prepare_database()
+            .expect("Preparing database should work");
+
+        assert!(database_dir.join("nested").is_dir());
+        assert!(database_url.ends_with("database.db"));
+        std::fs::remove_dir_all(&database_dir).expect("Deleting directory should work");
+    }
+}
@@ -1,6 +1,8 @@
 mod assets;
 mod assistant;
+mod cli;
 mod components;
+mod config;
 mod screens;
 
 use gtk::prelude::*;
@@ -11,6 +13,7 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use assistant::{database::Database, Assistant};
+use config::{CliOptions, Config};
 use screens::{
     chat::ChatScreen,
     startup::{StartupScreen, StartupScreenOutputMsg},
@@ -21,7 +24,10 @@ const APP_ID: &str = "org.relm4.PincerChat";
 #[derive(Debug)]
 struct App {
     assistant: Arc<Mutex<Assistant>>,
-    database: Arc<Mutex<Database>>,
+    database: Arc<Database>,
+    options: CliOptions,
+    /// Profiles that can be chosen on the startup screen.
+    profiles: Vec<String>,
     screen: Option<AppScreen>,
 }
 
@@ -34,12 +40,15 @@ enum AppScreen {
 #[derive(Debug)]
 enum AppMsg {
     ShowStartUpScreen,
+    /// Opens the database of the named profile, then starts up again.
+    SwitchProfile(String),
     ShowChatScreen,
 }
 
 #[relm4::component(async)]
 impl AsyncComponent for App {
-    type Init = ();
+    /// Command line options, URL of the database and profiles that can be chosen on startup.
+    type Init = (CliOptions, String, Vec<String>);
     type Input = AppMsg;
     type Output = ();
     type CommandOutput = ();
@@ -68,18 +77,20 @@ impl AsyncComponent for App {
     }
 
     async fn init(
-        _: (),
+        (options, database_url, profiles): Self::Init,
         root: Self::Root,
         sender: AsyncComponentSender<Self>,
     ) -> AsyncComponentParts<Self> {
         let assistant = Assistant::new().await;
-        let database = Database::new(None)
+        let database = Database::new(database_url)
             .await
             .expect("Database connection should work");
 
         let mut model = App {
             assistant: Arc::new(Mutex::new(assistant)),
-            database: Arc::new(Mutex::new(database)),
+            database: Arc::new(database),
+            options,
+            profiles,
             screen: None,
         };
 
@@ -103,15 +114,25 @@ impl AsyncComponent for App {
         match message {
             AppMsg::ShowStartUpScreen => {
                 tracing::info!("Showing startup screen");
-                let assistant = self.assistant.clone();
-                let database = self.database.clone();
-                let controller = StartupScreen::builder()
-                    .launch((assistant, database))
-                    .forward(sender.input_sender(), |output| match output {
-                        StartupScreenOutputMsg::End => AppMsg::ShowChatScreen,
-                    });
-                widgets.container.append(controller.widget());
-                self.screen = Some(AppScreen::StartUp(controller));
+                self.show_startup_screen(widgets, sender);
+            }
+            AppMsg::SwitchProfile(profile) => {
+                tracing::info!("Switching to profile {profile}");
+                let options = CliOptions {
+                    profile: Some(profile),
+                    ..self.options.clone()
+                };
+                let database = Config::load(options).and_then(|config| config.prepare_database());
+                let database = match database {
+                    Ok(database_url) => Database::new(database_url).await,
+                    Err(error) => Err(error),
+                };
+                match database {
+                    Ok(database) => self.database = Arc::new(database),
+                    // Starting up with the default profile is better than not starting at all
+                    Err(error) => tracing::error!("Opening profile failed because of: {error}"),
+                }
+                self.show_startup_screen(widgets, sender);
             }
             AppMsg::ShowChatScreen => {
                 let assistant = self.assistant.clone();
@@ -124,6 +145,27 @@ impl AsyncComponent for App {
     }
 }
 
+impl App {
+    fn show_startup_screen(
+        &mut self,
+        widgets: &mut AppWidgets,
+        sender: AsyncComponentSender<Self>,
+    ) {
+        let assistant = self.assistant.clone();
+        let database = self.database.clone();
+        // The profile is only chosen once
+        let profiles = std::mem::take(&mut self.profiles);
+        let controller = StartupScreen::builder()
+            .launch((assistant, database, profiles))
+            .forward(sender.input_sender(), |output| match output {
+                StartupScreenOutputMsg::SwitchProfile(profile) => AppMsg::SwitchProfile(profile),
+                StartupScreenOutputMsg::End => AppMsg::ShowChatScreen,
+            });
+        widgets.container.append(controller.widget());
+        self.screen = Some(AppScreen::StartUp(controller));
+    }
+}
+
 fn load_css(settings: &gtk::Settings) {
     let theme_name = settings
         .gtk_theme_name()
@@ -149,12 +191,65 @@ fn main() {
         .init();
 
     tracing::info!("Starting application!");
-    let relm_app = RelmApp::new(APP_ID);
+    let (options, gtk_args) = match CliOptions::parse(std::env::args()) {
+        Ok(parsed_args) => parsed_args,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(2);
+        }
+    };
+    if options.list_profiles {
+        match Config::list_profiles() {
+            Ok(profiles) => profiles.iter().for_each(|profile| println!("{profile}")),
+            Err(error) => {
+                eprintln!("Listing profiles failed because of: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let config = match Config::load(options.clone()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Unable to open the database: {error}");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(
+        "Using database {} of profile {}",
+        config.database_path.display(),
+        config.profile.as_deref().unwrap_or("default")
+    );
+    let database_url = match config.prepare_database() {
+        Ok(database_url) => database_url,
+        Err(error) => {
+            eprintln!("Unable to open the database: {error}");
+            std::process::exit(1);
+        }
+    };
+
+    if options.has_database_command() {
+        if let Err(error) = cli::run_database_commands(&options, database_url) {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let relm_app = RelmApp::new(APP_ID).with_args(gtk_args);
 
     let settings = gtk::Settings::default().expect("Accessing settings should work");
     settings.connect_gtk_application_prefer_dark_theme_notify(load_css);
     settings.connect_gtk_theme_name_notify(load_css);
     load_css(&settings);
 
-    relm_app.run_async::<App>(());
+    let profiles = if config.profile_choosable {
+        Config::list_profiles().unwrap_or_else(|error| {
+            tracing::error!("Listing profiles failed because of: {error}");
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    relm_app.run_async::<App>((options, database_url, profiles));
 }
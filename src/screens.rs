@@ -1,2 +1,4 @@
+pub mod backend_settings;
 pub mod chat;
+pub mod playground;
 pub mod startup;
@@ -0,0 +1,497 @@
+use gtk::prelude::*;
+use relm4::component::{AsyncComponent, AsyncComponentParts, AsyncComponentSender};
+use relm4::prelude::*;
+use relm4::RelmRemoveAllExt;
+use std::sync::Arc;
+
+use crate::assistant::backend::{build_backend, BackendKind, Timeouts};
+use crate::assistant::database::models::{Backend, NewBackend};
+use crate::assistant::database::Database;
+use crate::assistant::notification::DatabaseNotifierMessage;
+
+/// Window managing the servers that answers can be generated with.
+#[derive(Debug)]
+pub struct BackendSettingsScreen {
+    database: Arc<Database>,
+    backends: Vec<Backend>,
+    /// Backend shown in the form, `None` when adding a new one.
+    editing: Option<i64>,
+    status: String,
+    backend_list: gtk::ListBox,
+}
+
+#[derive(Debug)]
+pub enum BackendSettingsInputMsg {
+    UpdateBackends(Vec<Backend>),
+    /// Shows the backend at the given index of the list in the form.
+    EditBackend(usize),
+    NewBackend,
+    Save,
+    Delete,
+    Activate,
+    TestConnection,
+}
+
+#[derive(Debug)]
+pub enum BackendSettingsCmdMsg {
+    /// Number of models of the tested backend, or why it could not be reached.
+    ConnectionTested(Result<usize, String>),
+}
+
+impl BackendSettingsScreen {
+    fn editing_backend(&self) -> Option<&Backend> {
+        self.backends
+            .iter()
+            .find(|backend| Some(backend.id) == self.editing)
+    }
+
+    fn show_backends(&self) {
+        self.backend_list.remove_all();
+        for (index, backend) in self.backends.iter().enumerate() {
+            let kind = BackendKind::try_from(backend.kind.clone()).unwrap_or_default();
+            relm4::view! {
+                row = gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_margin_all: 5,
+
+                    gtk::Label {
+                        set_halign: gtk::Align::Start,
+                        set_label: &if backend.active {
+                            format!("{} (active)", backend.name)
+                        } else {
+                            backend.name.clone()
+                        },
+                    },
+                    gtk::Label {
+                        set_halign: gtk::Align::Start,
+                        set_label: &format!("{} · {}", kind.label(), backend.base_url),
+                        set_css_classes: &["dim-label"],
+                    },
+                }
+            }
+            self.backend_list.append(&row);
+            if Some(backend.id) == self.editing {
+                self.backend_list
+                    .select_row(self.backend_list.row_at_index(index as i32).as_ref());
+            }
+        }
+    }
+
+    fn show_form(&self, widgets: &BackendSettingsScreenWidgets) {
+        let backend = self.editing_backend();
+        let kind = backend
+            .and_then(|backend| BackendKind::try_from(backend.kind.clone()).ok())
+            .unwrap_or_default();
+        widgets.name_entry.set_text(
+            backend
+                .map(|backend| backend.name.as_str())
+                .unwrap_or_default(),
+        );
+        widgets.kind_drop_down.set_selected(
+            BackendKind::ALL
+                .iter()
+                .position(|backend_kind| *backend_kind == kind)
+                .unwrap_or_default() as u32,
+        );
+        widgets.base_url_entry.set_text(
+            backend
+                .map(|backend| backend.base_url.as_str())
+                .unwrap_or_default(),
+        );
+        widgets.api_key_entry.set_text(
+            backend
+                .and_then(|backend| backend.api_key.as_deref())
+                .unwrap_or_default(),
+        );
+        let default_timeouts = Timeouts::default();
+        widgets.connect_timeout_spin_button.set_value(
+            backend
+                .map(|backend| backend.connect_timeout_seconds)
+                .unwrap_or(default_timeouts.connect.as_secs() as i64) as f64,
+        );
+        widgets.first_byte_timeout_spin_button.set_value(
+            backend
+                .map(|backend| backend.first_byte_timeout_seconds)
+                .unwrap_or(default_timeouts.first_byte.as_secs() as i64) as f64,
+        );
+        widgets.idle_timeout_spin_button.set_value(
+            backend
+                .map(|backend| backend.idle_timeout_seconds)
+                .unwrap_or(default_timeouts.idle.as_secs() as i64) as f64,
+        );
+        widgets.default_model_entry.set_text(
+            backend
+                .and_then(|backend| backend.default_model.as_deref())
+                .unwrap_or_default(),
+        );
+    }
+
+    /// Returns the backend described by the form, or why it is not valid.
+    fn read_form(widgets: &BackendSettingsScreenWidgets) -> Result<NewBackend, String> {
+        let name = widgets.name_entry.text().trim().to_string();
+        if name.is_empty() {
+            return Err(String::from("The name must not be empty"));
+        }
+        let base_url = widgets.base_url_entry.text().trim().to_string();
+        if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+            return Err(String::from(
+                "The address must start with http:// or https://",
+            ));
+        }
+        let kind = BackendKind::ALL
+            .get(widgets.kind_drop_down.selected() as usize)
+            .copied()
+            .unwrap_or_default();
+        let api_key = widgets.api_key_entry.text().trim().to_string();
+        let default_model = widgets.default_model_entry.text().trim().to_string();
+        Ok(NewBackend {
+            name,
+            kind: <&str>::from(kind).to_string(),
+            base_url,
+            api_key: (!api_key.is_empty()).then_some(api_key),
+            connect_timeout_seconds: widgets.connect_timeout_spin_button.value() as i64,
+            first_byte_timeout_seconds: widgets.first_byte_timeout_spin_button.value() as i64,
+            idle_timeout_seconds: widgets.idle_timeout_spin_button.value() as i64,
+            default_model: (!default_model.is_empty()).then_some(default_model),
+        })
+    }
+}
+
+#[relm4::component(async, pub)]
+impl AsyncComponent for BackendSettingsScreen {
+    type Init = Arc<Database>;
+    type Input = BackendSettingsInputMsg;
+    type Output = ();
+    type CommandOutput = BackendSettingsCmdMsg;
+
+    view! {
+        gtk::Window {
+            set_title: Some("Servers"),
+            set_default_size: (640, 460),
+            set_hide_on_close: true,
+
+            gtk::Paned {
+                set_position: 240,
+
+                #[wrap(Some)]
+                set_start_child = &gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_margin_all: 5,
+                    set_spacing: 5,
+
+                    gtk::ScrolledWindow {
+                        set_vexpand: true,
+                        set_hscrollbar_policy: gtk::PolicyType::Never,
+
+                        #[local_ref]
+                        backend_list -> gtk::ListBox {
+                            set_selection_mode: gtk::SelectionMode::Single,
+                            set_css_classes: &["backend_list"],
+                            connect_row_activated[sender] => move |_, row| {
+                                sender.input(BackendSettingsInputMsg::EditBackend(row.index() as usize));
+                            },
+                        },
+                    },
+
+                    gtk::Button {
+                        set_icon_name: "list-add-symbolic",
+                        set_tooltip_text: Some("Add a server"),
+                        set_css_classes: &["button"],
+                        connect_clicked => BackendSettingsInputMsg::NewBackend,
+                    },
+                },
+
+                #[wrap(Some)]
+                set_end_child = &gtk::Grid {
+                    set_margin_all: 10,
+                    set_row_spacing: 5,
+                    set_column_spacing: 10,
+
+                    attach[0, 0, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "Name",
+                    },
+                    #[name = "name_entry"]
+                    attach[1, 0, 1, 1] = &gtk::Entry {
+                        set_hexpand: true,
+                        set_placeholder_text: Some("Team GPU server"),
+                    },
+
+                    attach[0, 1, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "Kind",
+                    },
+                    #[name = "kind_drop_down"]
+                    attach[1, 1, 1, 1] = &gtk::DropDown::from_strings(
+                        &BackendKind::ALL.map(|kind| kind.label()),
+                    ) {
+                        set_css_classes: &["dropdown"],
+                    },
+
+                    attach[0, 2, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "Address",
+                    },
+                    #[name = "base_url_entry"]
+                    attach[1, 2, 1, 1] = &gtk::Entry {
+                        set_placeholder_text: Some("http://localhost:11434"),
+                    },
+
+                    attach[0, 3, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "API key",
+                    },
+                    #[name = "api_key_entry"]
+                    attach[1, 3, 1, 1] = &gtk::PasswordEntry {
+                        set_show_peek_icon: true,
+                        set_placeholder_text: Some("Optional"),
+                    },
+
+                    attach[0, 4, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "Connection timeout (s)",
+                    },
+                    #[name = "connect_timeout_spin_button"]
+                    attach[1, 4, 1, 1] = &gtk::SpinButton::with_range(1.0, 600.0, 1.0) {
+                        set_halign: gtk::Align::Start,
+                        set_tooltip_text: Some("Time waited to reach the server"),
+                    },
+
+                    attach[0, 5, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "First answer timeout (s)",
+                    },
+                    #[name = "first_byte_timeout_spin_button"]
+                    attach[1, 5, 1, 1] = &gtk::SpinButton::with_range(1.0, 3600.0, 1.0) {
+                        set_halign: gtk::Align::Start,
+                        set_tooltip_text: Some("Time waited for an answer to start, including loading the model"),
+                    },
+
+                    attach[0, 6, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "Idle timeout (s)",
+                    },
+                    #[name = "idle_timeout_spin_button"]
+                    attach[1, 6, 1, 1] = &gtk::SpinButton::with_range(1.0, 3600.0, 1.0) {
+                        set_halign: gtk::Align::Start,
+                        set_tooltip_text: Some("Time waited between two parts of an answer"),
+                    },
+
+                    attach[0, 7, 1, 1] = &gtk::Label {
+                        set_halign: gtk::Align::End,
+                        set_label: "Default model",
+                    },
+                    #[name = "default_model_entry"]
+                    attach[1, 7, 1, 1] = &gtk::Entry {
+                        set_placeholder_text: Some("First model of the server"),
+                    },
+
+                    attach[0, 8, 2, 1] = &gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_halign: gtk::Align::End,
+                        set_margin_top: 10,
+                        set_spacing: 5,
+
+                        gtk::Button {
+                            set_label: "Test connection",
+                            set_css_classes: &["button"],
+                            connect_clicked => BackendSettingsInputMsg::TestConnection,
+                        },
+                        gtk::Button {
+                            set_label: "Use for new threads",
+                            set_css_classes: &["button"],
+                            #[watch]
+                            set_sensitive: model
+                                .editing_backend()
+                                .is_some_and(|backend| !backend.active),
+                            connect_clicked => BackendSettingsInputMsg::Activate,
+                        },
+                        gtk::Button {
+                            set_label: "Delete",
+                            set_css_classes: &["button", "destructive-action"],
+                            #[watch]
+                            set_sensitive: model.editing.is_some() && model.backends.len() > 1,
+                            connect_clicked => BackendSettingsInputMsg::Delete,
+                        },
+                        gtk::Button {
+                            set_label: "Save",
+                            set_css_classes: &["button", "suggested-action"],
+                            connect_clicked => BackendSettingsInputMsg::Save,
+                        },
+                    },
+
+                    attach[0, 9, 2, 1] = &gtk::Label {
+                        set_wrap: true,
+                        set_halign: gtk::Align::End,
+                        #[watch]
+                        set_label: &model.status,
+                    },
+                },
+            },
+        }
+    }
+
+    async fn init(
+        database: Self::Init,
+        root: Self::Root,
+        sender: AsyncComponentSender<Self>,
+    ) -> AsyncComponentParts<Self> {
+        let backends = database
+            .get_backends()
+            .await
+            .expect("Getting backends should work");
+        let model = BackendSettingsScreen {
+            database,
+            editing: backends.first().map(|backend| backend.id),
+            backends,
+            status: String::new(),
+            backend_list: gtk::ListBox::default(),
+        };
+
+        // References used in the view macro
+        let backend_list = &model.backend_list;
+
+        let widgets = view_output!();
+
+        model.show_backends();
+        model.show_form(&widgets);
+
+        model.database.notifier.subscribe(
+            sender.input_sender(),
+            |notifier_message: DatabaseNotifierMessage| match notifier_message {
+                DatabaseNotifierMessage::UpdateBackends(backends) => {
+                    Some(BackendSettingsInputMsg::UpdateBackends(backends))
+                }
+                _ => None,
+            },
+        );
+
+        AsyncComponentParts { model, widgets }
+    }
+
+    async fn update_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::Input,
+        sender: AsyncComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            BackendSettingsInputMsg::UpdateBackends(backends) => {
+                self.backends = backends;
+                if self.editing_backend().is_none() {
+                    self.editing = None;
+                }
+                self.show_backends();
+            }
+            BackendSettingsInputMsg::EditBackend(index) => {
+                self.editing = self.backends.get(index).map(|backend| backend.id);
+                self.status = String::new();
+                self.show_form(widgets);
+            }
+            BackendSettingsInputMsg::NewBackend => {
+                self.editing = None;
+                self.status = String::new();
+                self.backend_list.unselect_all();
+                self.show_form(widgets);
+                widgets.name_entry.grab_focus();
+            }
+            BackendSettingsInputMsg::Save => match Self::read_form(widgets) {
+                Ok(new_backend) => {
+                    let result = match self.editing {
+                        Some(id) => self.database.update_backend(id, &new_backend).await,
+                        None => match self.database.create_backend(&new_backend).await {
+                            Ok(backend) => {
+                                self.editing = Some(backend.id);
+                                Ok(())
+                            }
+                            Err(error) => Err(error),
+                        },
+                    };
+                    self.status = match result {
+                        Ok(_) => format!("Saved {}", new_backend.name),
+                        Err(error) => {
+                            tracing::error!("Saving backend failed because of: {error}");
+                            format!("Saving failed: {error}")
+                        }
+                    };
+                }
+                Err(error) => self.status = error,
+            },
+            BackendSettingsInputMsg::Delete => {
+                if let Some(id) = self.editing {
+                    tracing::info!("Deleting backend with id {id}");
+                    match self.database.delete_backend(id).await {
+                        Ok(_) => {
+                            self.editing = None;
+                            self.status = String::from("Deleted");
+                            self.show_form(widgets);
+                        }
+                        Err(error) => {
+                            tracing::error!("Deleting backend failed because of: {error}");
+                            self.status = format!("Deleting failed: {error}");
+                        }
+                    }
+                }
+            }
+            BackendSettingsInputMsg::Activate => {
+                if let Some(id) = self.editing {
+                    tracing::info!("Activating backend with id {id}");
+                    if let Err(error) = self.database.set_active_backend(id).await {
+                        tracing::error!("Activating backend failed because of: {error}");
+                        self.status = format!("Activating failed: {error}");
+                    }
+                }
+            }
+            BackendSettingsInputMsg::TestConnection => {
+                let backend = Self::read_form(widgets).and_then(|new_backend| {
+                    build_backend(
+                        &new_backend.kind,
+                        &new_backend.base_url,
+                        new_backend.api_key,
+                        Timeouts::from_seconds(
+                            new_backend.connect_timeout_seconds,
+                            new_backend.first_byte_timeout_seconds,
+                            new_backend.idle_timeout_seconds,
+                        ),
+                    )
+                    .map_err(|error| error.to_string())
+                });
+                match backend {
+                    Ok(backend) => {
+                        self.status = String::from("Connecting...");
+                        sender.oneshot_command(async move {
+                            let result = match backend.health_check().await {
+                                Ok(_) => backend.list_models().await,
+                                Err(error) => Err(error),
+                            };
+                            BackendSettingsCmdMsg::ConnectionTested(
+                                result
+                                    .map(|models| models.len())
+                                    .map_err(|error| error.to_string()),
+                            )
+                        });
+                    }
+                    Err(error) => self.status = error,
+                }
+            }
+        }
+        self.update_view(widgets, sender);
+    }
+
+    async fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        _: AsyncComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            BackendSettingsCmdMsg::ConnectionTested(result) => {
+                self.status = match result {
+                    Ok(n_models) => format!("Connected! The server has {n_models} models"),
+                    Err(error) => format!("Connecting failed: {error}"),
+                };
+            }
+        }
+    }
+}
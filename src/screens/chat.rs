@@ -3,61 +3,324 @@ use futures::{FutureExt, StreamExt};
 use gtk::prelude::*;
 use relm4::component::{AsyncComponent, AsyncComponentParts, AsyncComponentSender};
 use relm4::prelude::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
+use tokio::time::{sleep, Duration};
 use tracing;
 
+use crate::assistant::backend::{KeepAlive, LoadedModel};
+use crate::assistant::database::backup::BackupFile;
+use crate::assistant::database::message_writer::MessageWriter;
+use crate::assistant::database::models::{Backend, NewPromptTemplate};
+use crate::assistant::database::{Database, ThreadFilterOptions};
 use crate::assistant::ollama::types::{Message, Role};
-use crate::assistant::{database::Database, notification::DatabaseNotifierMessage, Assistant};
+use crate::assistant::retry::{is_transient, RetryAttempt};
+use crate::assistant::structured_output::ResponseFormat;
+use crate::assistant::templates::{
+    normalize_template_name, read_templates_file, write_templates_file,
+};
+use crate::assistant::{
+    notification::DatabaseNotifierMessage, Assistant, AssistantParameters, ModelChoice,
+};
 use crate::components::assistant_parameters::{
-    AssistantParametersComponent, AssistantParametersOutputMsg,
+    AssistantParametersComponent, AssistantParametersInputMsg, AssistantParametersOutputMsg,
+};
+use crate::components::chat_input::{ChatInputComponent, ChatInputInputMsg, ChatInputOutputMsg};
+use crate::components::encryption_settings::{
+    EncryptionSettingsComponent, EncryptionSettingsInputMsg, EncryptionSettingsOutputMsg,
 };
-use crate::components::chat_input::{ChatInputComponent, ChatInputOutputMsg};
 use crate::components::message_bubble::{
     MessageBubbleContainerComponent, MessageBubbleContainerInputMsg,
+    MessageBubbleContainerOutputMsg, MESSAGES_PAGE_SIZE,
+};
+use crate::components::model_comparison::{
+    ModelComparisonComponent, ModelComparisonInputMsg, ModelComparisonOutputMsg,
+};
+use crate::components::response_format::{
+    ResponseFormatComponent, ResponseFormatInputMsg, ResponseFormatOutputMsg,
 };
 use crate::components::thread_list::{
     ThreadListContainerComponent, ThreadListContainerInputMsg, ThreadListContainerOutputMsg,
 };
+use crate::screens::backend_settings::BackendSettingsScreen;
+use crate::screens::playground::{PlaygroundInputMsg, PlaygroundScreen};
+
+/// Number of previous user prompts that can be recalled in the chat input.
+const PROMPT_HISTORY_SIZE: i64 = 100;
+/// Time between checks of whether a scheduled backup of the database is due.
+const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Time between checks of whether the server can be reached.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// State of an answer being generated in the background for a thread.
+#[derive(Debug)]
+struct Generation {
+    message_id: i64,
+    status: GenerationStatus,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GenerationStatus {
+    Generating,
+    /// The answer waits for the model to be loaded in memory.
+    LoadingModel,
+    /// The server could not be reached and the request is about to be sent again.
+    Reconnecting(RetryAttempt),
+}
 
 #[derive(Debug)]
 pub struct ChatScreen {
     assistant: Arc<Mutex<Assistant>>,
-    database: Arc<Mutex<Database>>,
+    database: Arc<Database>,
     current_thread_id: i64,
+    /// Thread whose messages are displayed, shared with the database notifier subscription.
+    displayed_thread_id: Arc<AtomicI64>,
+    /// Answers being generated, by thread id.
+    generations: HashMap<i64, Generation>,
+    /// Models of every backend, grouped by backend.
+    models: Vec<ModelChoice>,
     // Components
     assistant_parameters: Controller<AssistantParametersComponent>,
     thread_list: AsyncController<ThreadListContainerComponent>,
     chat_input: Controller<ChatInputComponent>,
     message_bubbles: AsyncController<MessageBubbleContainerComponent>,
+    model_comparison: Controller<ModelComparisonComponent>,
+    encryption_settings: Controller<EncryptionSettingsComponent>,
+    response_format: Controller<ResponseFormatComponent>,
+    backend_settings: AsyncController<BackendSettingsScreen>,
+    playground: AsyncController<PlaygroundScreen>,
+    comparing: bool,
+    last_backup: Option<BackupFile>,
+    /// Whether the server of the selected backend answered the last health check.
+    backend_reachable: bool,
+    /// Whether inputs are disabled until a model is downloaded.
+    pulling_model: bool,
+    /// Whether inputs are disabled until the models being compared answered.
+    comparison_running: bool,
 }
 
 #[derive(Debug)]
 pub enum ChatScreenInputMsg {
     CreateNewThread,
     GetThreadMessages(i64),
+    LoadOlderMessages(i64),
     SubmitUserInput(String),
+    SaveDraft(i64, String),
     DeleteThread(i64),
-    AssistantAnswer,
+    RenameThread(i64, String),
+    PinThread(i64, bool),
+    ArchiveThread(i64, bool),
+    RestoreThread(i64),
+    PurgeThread(i64),
+    CreateTag(String),
+    DeleteTag(i64),
+    AddTagToThread(i64, i64),
+    RemoveTagFromThread(i64, i64),
+    CreateFolder(String),
+    DeleteFolder(i64),
+    MoveThreadToFolder(i64, Option<i64>),
+    SavePromptTemplate(String, String),
+    DeletePromptTemplate(i64),
+    ImportPromptTemplates(PathBuf),
+    ExportPromptTemplates(PathBuf),
+    EditMessage(i64, String),
+    DeleteMessage(i64),
+    /// Replaces an answer that did not follow the format of its thread.
+    RetryAnswer(i64),
+    AssistantAnswer(i64),
+    SetResponseFormat(Option<ResponseFormat>),
+    // Database encryption
+    EnableEncryption(String),
+    ChangePassphrase(String, String),
+    DisableEncryption(String),
+    BackUpDatabase,
+    // Backends
+    ShowBackendSettings,
+    ShowPlayground,
+    UpdateBackends(Vec<Backend>),
+    // Model comparison
+    CompareModels(bool),
+    /// Backend id and name of the picked model, and its answer.
+    PickComparisonAnswer(i64, String, String),
+    ComparisonEnd,
     // Assistant Parameters
-    SelectModel(String),
+    /// Backend id and name of the selected model.
+    SelectModel(i64, String),
     Temperature(f64),
     TopK(u64),
     TopP(f64),
     ResetParameters,
+    KeepAlive(Option<KeepAlive>),
+    ListLoadedModels,
+    PreloadModel,
+    UnloadModel(String),
 }
 
 #[derive(Debug)]
 pub enum ChatScreenCmdMsg {
-    PullModelEnd,
-    AnswerEnd,
+    /// Name of the downloaded model, `None` if it could not be downloaded.
+    PullModelEnd(Option<String>),
+    /// Models in the memory of the selected backend, or why they could not be listed.
+    LoadedModelsListed(Result<Vec<LoadedModel>, String>),
+    /// Thread and message ids of an answer, and its new status.
+    GenerationStatus(i64, i64, GenerationStatus),
+    /// Whether the server of the selected backend can be reached.
+    BackendHealth(bool),
+    AnswerEnd(i64, i64),
+    BackedUp(BackupFile),
+    ModelsListed(Vec<ModelChoice>),
 }
 
 impl ChatScreen {
+    fn set_current_thread(&mut self, thread_id: i64) {
+        self.current_thread_id = thread_id;
+        self.displayed_thread_id.store(thread_id, Ordering::SeqCst);
+    }
+
+    /// Returns what the answer of the displayed thread is waiting for, if anything.
+    fn status_text(&self) -> Option<String> {
+        if !self.backend_reachable {
+            return Some(String::from(
+                "The server cannot be reached, waiting for it to come back...",
+            ));
+        }
+        match &self.generations.get(&self.current_thread_id)?.status {
+            GenerationStatus::Generating => None,
+            GenerationStatus::LoadingModel => Some(String::from(
+                "Loading the model, the answer will start once it is in memory...",
+            )),
+            GenerationStatus::Reconnecting(retry_attempt) => Some(retry_attempt.to_string()),
+        }
+    }
+
+    /// Tells the encryption settings whether the database is now encrypted, or why it failed.
+    fn report_encryption_change(&self, result: anyhow::Result<bool>) {
+        let result = result.map_err(|error| {
+            tracing::error!("Changing database encryption failed because of: {error}");
+            error.to_string()
+        });
+        self.encryption_settings
+            .emit(EncryptionSettingsInputMsg::ChangeApplied(result));
+    }
+
+    /// Generates the next answers with the given backend, `None` meaning the active one.
+    ///
+    /// When the backend changes, its preferred model is selected.
+    async fn use_backend(&self, backend_id: Option<i64>) {
+        let mut assistant = self.assistant.lock().await;
+        let previous_backend_id = assistant.backend_id();
+        assistant.set_backend(backend_id);
+        if let Some(backend_id) = assistant.backend_id() {
+            if Some(backend_id) != previous_backend_id {
+                self.use_preferred_model(&mut assistant, backend_id);
+            }
+        }
+    }
+
+    /// Selects the preferred model of the given backend, without pulling it.
+    fn use_preferred_model(&self, assistant: &mut Assistant, backend_id: i64) {
+        let models: Vec<String> = self
+            .models
+            .iter()
+            .filter(|choice| choice.backend_id == backend_id)
+            .map(|choice| choice.model.clone())
+            .collect();
+        if let Some(model) = assistant.preferred_model(&models) {
+            tracing::info!("Using model {model} of backend with id {backend_id}");
+            assistant.set_model(model.clone());
+            self.assistant_parameters
+                .emit(AssistantParametersInputMsg::ShowModel(backend_id, model));
+        }
+    }
+
+    /// Lists the models of every backend in the background, as some may be slow to answer.
+    async fn refresh_models(&self, sender: &AsyncComponentSender<Self>) {
+        let backends = self.assistant.lock().await.backends().to_vec();
+        sender.oneshot_command(async move {
+            ChatScreenCmdMsg::ModelsListed(Assistant::list_all_models(backends).await)
+        });
+    }
+
+    /// Returns the messages of a thread in the format expected by the assistant.
+    async fn get_thread_messages(&self, thread_id: i64) -> Vec<Message> {
+        let messages = self
+            .database
+            .get_messages(thread_id)
+            .await
+            .expect("Getting messages should work");
+
+        messages
+            .into_iter()
+            .map(|m| Message {
+                content: m.content,
+                role: Role::try_from(m.role).expect("Role string to enum conversion should work"),
+            })
+            .collect()
+    }
+
+    /// Generates the title of a thread if the messages only contain the first user message.
+    async fn generate_thread_title(
+        database: &Database,
+        parameters: AssistantParameters,
+        thread_id: i64,
+        messages: &[Message],
+    ) {
+        if messages.len() != 2 {
+            return;
+        }
+        tracing::info!("Generating thread title for thread after first user message");
+        let thread_title =
+            match Assistant::generate_thread_title(parameters, messages[1].clone()).await {
+                Ok(thread_title) => thread_title,
+                Err(error) => {
+                    tracing::error!("Generating thread title failed because of: {error}");
+                    return;
+                }
+            };
+        database
+            .update_thread_title(thread_id, thread_title)
+            .await
+            .expect("Updating thread title should work");
+    }
+
+    /// Pretty-prints an answer following the format of its thread,
+    /// or marks it with what is wrong with it.
+    async fn check_answer_format(
+        database: &Database,
+        message_id: i64,
+        response_format: &ResponseFormat,
+        answer: &str,
+    ) {
+        let result = match response_format.check_answer(answer) {
+            Ok(formatted_answer) => database.edit_message(message_id, formatted_answer).await,
+            Err(format_error) => {
+                tracing::warn!("Answer {message_id} does not follow the format: {format_error}");
+                database
+                    .set_message_format_error(message_id, &format_error)
+                    .await
+            }
+        };
+        if let Err(error) = result {
+            tracing::error!("Checking answer format failed because of: {error}");
+        }
+    }
+
+    /// The chat input is disabled while an answer is being generated for the current thread.
+    fn update_chat_input_state(&self) {
+        let enabled =
+            self.backend_reachable && !self.generations.contains_key(&self.current_thread_id);
+        self.chat_input.emit(ChatInputInputMsg::SetEnabled(enabled));
+    }
+
     fn enable_inputs(&mut self) {
         self.assistant_parameters.widget().set_sensitive(true);
         self.thread_list.widget().set_sensitive(true);
         self.chat_input.widget().set_sensitive(true);
+        self.update_chat_input_state();
     }
 
     fn disable_inputs(&mut self) {
@@ -69,7 +332,7 @@ impl ChatScreen {
 
 #[relm4::component(async, pub)]
 impl AsyncComponent for ChatScreen {
-    type Init = (Arc<Mutex<Assistant>>, Arc<Mutex<Database>>);
+    type Init = (Arc<Mutex<Assistant>>, Arc<Database>);
     type Input = ChatScreenInputMsg;
     type Output = ();
     type CommandOutput = ChatScreenCmdMsg;
@@ -104,13 +367,90 @@ impl AsyncComponent for ChatScreen {
                 set_valign: gtk::Align::Fill,
                 set_halign: gtk::Align::Fill,
 
-                // Assistant Parameters
-                #[local_ref]
-                assistant_parameters -> gtk::Box {},
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+
+                    // Assistant Parameters
+                    #[local_ref]
+                    assistant_parameters -> gtk::Box {},
+
+                    gtk::ToggleButton {
+                        set_label: "Compare",
+                        set_tooltip_text: Some("Compare the answers of several models"),
+                        set_valign: gtk::Align::Center,
+                        set_css_classes: &["button", "compare_models_button"],
+                        #[watch]
+                        set_active: model.comparing,
+
+                        connect_toggled[sender] => move |button| {
+                            sender.input(ChatScreenInputMsg::CompareModels(button.is_active()));
+                        },
+                    },
+
+                    gtk::Button {
+                        set_icon_name: "document-save-symbolic",
+                        set_valign: gtk::Align::Center,
+                        set_css_classes: &["button", "backup_button"],
+                        #[watch]
+                        set_tooltip_text: Some(&match &model.last_backup {
+                            Some(backup) => format!(
+                                "Back up the chat history now\nLast backup: {}",
+                                backup.created_at.format("%Y-%m-%d %H:%M")
+                            ),
+                            None => String::from("Back up the chat history now"),
+                        }),
+                        connect_clicked => ChatScreenInputMsg::BackUpDatabase,
+                    },
+
+                    gtk::Button {
+                        set_icon_name: "network-server-symbolic",
+                        set_tooltip_text: Some("Servers"),
+                        set_valign: gtk::Align::Center,
+                        set_css_classes: &["button", "backend_settings_button"],
+                        connect_clicked => ChatScreenInputMsg::ShowBackendSettings,
+                    },
+
+                    gtk::Button {
+                        set_icon_name: "text-editor-symbolic",
+                        set_tooltip_text: Some("Completion playground"),
+                        set_valign: gtk::Align::Center,
+                        set_css_classes: &["button", "playground_button"],
+                        connect_clicked => ChatScreenInputMsg::ShowPlayground,
+                    },
+
+                    #[local_ref]
+                    response_format -> gtk::MenuButton {
+                        set_valign: gtk::Align::Center,
+                    },
+
+                    #[local_ref]
+                    encryption_settings -> gtk::MenuButton {
+                        set_valign: gtk::Align::Center,
+                    },
+                },
 
                 // Message bubbles
                 #[local_ref]
-                message_bubbles -> gtk::Box {},
+                message_bubbles -> gtk::Box {
+                    #[watch]
+                    set_visible: !model.comparing,
+                },
+
+                gtk::Label {
+                    set_css_classes: &["dim-label"],
+                    #[watch]
+                    set_label: &model.status_text().unwrap_or_default(),
+                    #[watch]
+                    set_visible: !model.comparing && model.status_text().is_some(),
+                },
+
+                // Answers of the compared models
+                #[local_ref]
+                model_comparison -> gtk::Box {
+                    #[watch]
+                    set_visible: model.comparing,
+                },
 
                 // User Chat Input Fields
                 #[local_ref]
@@ -128,9 +468,8 @@ impl AsyncComponent for ChatScreen {
         let database = init.1;
 
         let threads = {
-            let mut database = database.lock().await;
             let mut threads = database
-                .get_threads()
+                .get_threads(ThreadFilterOptions::default())
                 .await
                 .expect("Getting all thread should work");
 
@@ -142,37 +481,80 @@ impl AsyncComponent for ChatScreen {
                     .expect("Creating thread should work");
                 threads.push(thread);
             }
+
+            // Deleted threads are shown in the thread list's trash
+            let trashed_threads = database
+                .get_threads(ThreadFilterOptions {
+                    trashed: true,
+                    ..ThreadFilterOptions::default()
+                })
+                .await
+                .expect("Getting deleted threads should work");
+            threads.extend(trashed_threads);
             threads
         };
 
+        let (tags, thread_tags) = {
+            let tags = database.get_tags().await.expect("Getting tags should work");
+            let thread_tags = database
+                .get_all_thread_tags()
+                .await
+                .expect("Getting thread tags should work");
+            (tags, thread_tags)
+        };
+        let folders = database
+            .get_folders()
+            .await
+            .expect("Getting folders should work");
+
         let latest_thread = threads.first().expect("First thread must exist");
         let latest_thread_id = latest_thread.id;
+        let latest_thread_backend_id = latest_thread.backend_id;
 
-        let messages = {
-            let mut database = database.lock().await;
-            database
-                .get_messages(latest_thread_id)
-                .await
-                .expect("Getting messages should work")
-        };
+        let messages = database
+            .get_messages_page(latest_thread_id, None, MESSAGES_PAGE_SIZE)
+            .await
+            .expect("Getting messages should work");
 
         let message_bubbles = MessageBubbleContainerComponent::builder()
             .launch(messages)
-            .detach();
+            .forward(sender.input_sender(), |output| match output {
+                MessageBubbleContainerOutputMsg::LoadOlderMessages(before_id) => {
+                    ChatScreenInputMsg::LoadOlderMessages(before_id)
+                }
+                MessageBubbleContainerOutputMsg::EditMessage(message_id, content) => {
+                    ChatScreenInputMsg::EditMessage(message_id, content)
+                }
+                MessageBubbleContainerOutputMsg::DeleteMessage(message_id) => {
+                    ChatScreenInputMsg::DeleteMessage(message_id)
+                }
+                MessageBubbleContainerOutputMsg::RetryMessage(message_id) => {
+                    ChatScreenInputMsg::RetryAnswer(message_id)
+                }
+            });
 
-        let local_models = {
+        let (models, selected_model) = {
             let assistant = assistant.lock().await;
-            match assistant.list_models().await {
-                Ok(models) => models,
-                Err(err) => {
-                    tracing::error!("Could not retrieve list of local models because of: {err}");
-                    Vec::new()
-                }
-            }
+            let models = Assistant::list_all_models(assistant.backends().to_vec()).await;
+            let selected_model = assistant.backend_id().zip(assistant.parameters().model);
+            (models, selected_model)
         };
 
+        let model_comparison = ModelComparisonComponent::builder()
+            .launch(models.clone())
+            .forward(sender.input_sender(), |output| match output {
+                ModelComparisonOutputMsg::PickAnswer(backend_id, model, content) => {
+                    ChatScreenInputMsg::PickComparisonAnswer(backend_id, model, content)
+                }
+                ModelComparisonOutputMsg::Finished => ChatScreenInputMsg::ComparisonEnd,
+            });
+
+        let playground = PlaygroundScreen::builder()
+            .launch((models.clone(), selected_model.clone()))
+            .detach();
+
         let assistant_parameters = AssistantParametersComponent::builder()
-            .launch(local_models)
+            .launch((models.clone(), selected_model))
             .forward(sender.input_sender(), |output| match output {
                 AssistantParametersOutputMsg::Temperature(value) => {
                     ChatScreenInputMsg::Temperature(value)
@@ -182,22 +564,85 @@ impl AsyncComponent for ChatScreen {
                 AssistantParametersOutputMsg::ResetParameters => {
                     ChatScreenInputMsg::ResetParameters
                 }
-                AssistantParametersOutputMsg::SelectModel(value) => {
-                    ChatScreenInputMsg::SelectModel(value)
+                AssistantParametersOutputMsg::SelectModel(backend_id, model) => {
+                    ChatScreenInputMsg::SelectModel(backend_id, model)
+                }
+                AssistantParametersOutputMsg::KeepAlive(keep_alive) => {
+                    ChatScreenInputMsg::KeepAlive(keep_alive)
+                }
+                AssistantParametersOutputMsg::ListLoadedModels => {
+                    ChatScreenInputMsg::ListLoadedModels
+                }
+                AssistantParametersOutputMsg::PreloadModel => ChatScreenInputMsg::PreloadModel,
+                AssistantParametersOutputMsg::UnloadModel(model) => {
+                    ChatScreenInputMsg::UnloadModel(model)
                 }
             });
 
-        let chat_input =
-            ChatInputComponent::builder()
-                .launch(())
-                .forward(sender.input_sender(), |output| match output {
-                    ChatInputOutputMsg::SubmitUserInput(message) => {
-                        ChatScreenInputMsg::SubmitUserInput(message)
-                    }
-                });
+        let backend_settings = BackendSettingsScreen::builder()
+            .launch(database.clone())
+            .detach();
+
+        let encryption_settings = EncryptionSettingsComponent::builder()
+            .launch(database.is_encrypted().unwrap_or_default())
+            .forward(sender.input_sender(), |output| match output {
+                EncryptionSettingsOutputMsg::EnableEncryption(passphrase) => {
+                    ChatScreenInputMsg::EnableEncryption(passphrase)
+                }
+                EncryptionSettingsOutputMsg::ChangePassphrase(passphrase, new_passphrase) => {
+                    ChatScreenInputMsg::ChangePassphrase(passphrase, new_passphrase)
+                }
+                EncryptionSettingsOutputMsg::DisableEncryption(passphrase) => {
+                    ChatScreenInputMsg::DisableEncryption(passphrase)
+                }
+            });
+
+        let response_format = ResponseFormatComponent::builder().launch(()).forward(
+            sender.input_sender(),
+            |output| match output {
+                ResponseFormatOutputMsg::SetFormat(response_format) => {
+                    ChatScreenInputMsg::SetResponseFormat(response_format)
+                }
+            },
+        );
+
+        let (prompt_history, prompt_templates) = {
+            let prompt_history = database
+                .get_user_prompts(PROMPT_HISTORY_SIZE)
+                .await
+                .expect("Getting previous user prompts should work");
+            let prompt_templates = database
+                .get_prompt_templates()
+                .await
+                .expect("Getting prompt templates should work");
+            (prompt_history, prompt_templates)
+        };
+
+        let chat_input = ChatInputComponent::builder()
+            .launch((prompt_history, prompt_templates))
+            .forward(sender.input_sender(), |output| match output {
+                ChatInputOutputMsg::SubmitUserInput(message) => {
+                    ChatScreenInputMsg::SubmitUserInput(message)
+                }
+                ChatInputOutputMsg::SaveDraft(thread_id, draft) => {
+                    ChatScreenInputMsg::SaveDraft(thread_id, draft)
+                }
+                ChatInputOutputMsg::SaveTemplate(name, content) => {
+                    ChatScreenInputMsg::SavePromptTemplate(name, content)
+                }
+                ChatInputOutputMsg::DeleteTemplate(template_id) => {
+                    ChatScreenInputMsg::DeletePromptTemplate(template_id)
+                }
+                ChatInputOutputMsg::ImportTemplates(path) => {
+                    ChatScreenInputMsg::ImportPromptTemplates(path)
+                }
+                ChatInputOutputMsg::ExportTemplates(path) => {
+                    ChatScreenInputMsg::ExportPromptTemplates(path)
+                }
+            });
 
         let thread_list = ThreadListContainerComponent::builder()
-            .launch(threads)
+            .launch((threads, tags, thread_tags, folders))
             .forward(sender.input_sender(), |output| match output {
                 ThreadListContainerOutputMsg::CreateNewThread => {
                     ChatScreenInputMsg::CreateNewThread
@@ -208,41 +653,121 @@ impl AsyncComponent for ChatScreen {
                 ThreadListContainerOutputMsg::DeleteThread(thread_id) => {
                     ChatScreenInputMsg::DeleteThread(thread_id)
                 }
+                ThreadListContainerOutputMsg::RenameThread(thread_id, title) => {
+                    ChatScreenInputMsg::RenameThread(thread_id, title)
+                }
+                ThreadListContainerOutputMsg::PinThread(thread_id, pinned) => {
+                    ChatScreenInputMsg::PinThread(thread_id, pinned)
+                }
+                ThreadListContainerOutputMsg::ArchiveThread(thread_id, archived) => {
+                    ChatScreenInputMsg::ArchiveThread(thread_id, archived)
+                }
+                ThreadListContainerOutputMsg::RestoreThread(thread_id) => {
+                    ChatScreenInputMsg::RestoreThread(thread_id)
+                }
+                ThreadListContainerOutputMsg::PurgeThread(thread_id) => {
+                    ChatScreenInputMsg::PurgeThread(thread_id)
+                }
+                ThreadListContainerOutputMsg::CreateTag(name) => {
+                    ChatScreenInputMsg::CreateTag(name)
+                }
+                ThreadListContainerOutputMsg::DeleteTag(tag_id) => {
+                    ChatScreenInputMsg::DeleteTag(tag_id)
+                }
+                ThreadListContainerOutputMsg::AddTagToThread(thread_id, tag_id) => {
+                    ChatScreenInputMsg::AddTagToThread(thread_id, tag_id)
+                }
+                ThreadListContainerOutputMsg::RemoveTagFromThread(thread_id, tag_id) => {
+                    ChatScreenInputMsg::RemoveTagFromThread(thread_id, tag_id)
+                }
+                ThreadListContainerOutputMsg::CreateFolder(name) => {
+                    ChatScreenInputMsg::CreateFolder(name)
+                }
+                ThreadListContainerOutputMsg::DeleteFolder(folder_id) => {
+                    ChatScreenInputMsg::DeleteFolder(folder_id)
+                }
+                ThreadListContainerOutputMsg::MoveThreadToFolder(thread_id, folder_id) => {
+                    ChatScreenInputMsg::MoveThreadToFolder(thread_id, folder_id)
+                }
             });
 
+        let last_backup = database
+            .list_backups()
+            .ok()
+            .and_then(|backups| backups.into_iter().next());
+
         let model = ChatScreen {
             assistant,
             database,
             current_thread_id: latest_thread_id,
+            displayed_thread_id: Arc::new(AtomicI64::new(latest_thread_id)),
+            generations: HashMap::new(),
+            models,
             thread_list,
             assistant_parameters,
             chat_input,
             message_bubbles,
+            model_comparison,
+            encryption_settings,
+            response_format,
+            backend_settings,
+            playground,
+            comparing: false,
+            last_backup,
+            backend_reachable: true,
+            pulling_model: false,
+            comparison_running: false,
         };
+        model.use_backend(latest_thread_backend_id).await;
 
-        // Connect chat history notifier to message bubbles
+        // Connect chat history notifier to message bubbles, ignoring messages of other threads
         {
-            let database = model.database.lock().await;
-            database.notifier.subscribe(
+            let displayed_thread_id = model.displayed_thread_id.clone();
+            model.database.notifier.subscribe(
                 model.message_bubbles.sender(),
-                |notifier_message: DatabaseNotifierMessage| match notifier_message {
-                    DatabaseNotifierMessage::NewMessage(message) => {
-                        Some(MessageBubbleContainerInputMsg::AddNewMessage(message))
+                move |notifier_message: DatabaseNotifierMessage| {
+                    let displayed_thread_id = displayed_thread_id.load(Ordering::SeqCst);
+                    match notifier_message {
+                        DatabaseNotifierMessage::NewMessage(message)
+                            if message.thread_id == displayed_thread_id =>
+                        {
+                            Some(MessageBubbleContainerInputMsg::AddNewMessage(message))
+                        }
+                        DatabaseNotifierMessage::UpdateMessage(thread_id, message_id, content)
+                            if thread_id == displayed_thread_id =>
+                        {
+                            Some(MessageBubbleContainerInputMsg::AppendToMessage(
+                                message_id, content,
+                            ))
+                        }
+                        DatabaseNotifierMessage::EditMessage(message)
+                            if message.thread_id == displayed_thread_id =>
+                        {
+                            Some(MessageBubbleContainerInputMsg::EditMessage(message))
+                        }
+                        DatabaseNotifierMessage::DeleteMessage(thread_id, message_id)
+                            if thread_id == displayed_thread_id =>
+                        {
+                            Some(MessageBubbleContainerInputMsg::DeleteMessage(message_id))
+                        }
+                        DatabaseNotifierMessage::GetThreadMessages(thread_id, messages)
+                            if thread_id == displayed_thread_id =>
+                        {
+                            Some(MessageBubbleContainerInputMsg::RefreshMessages(messages))
+                        }
+                        DatabaseNotifierMessage::GetOlderThreadMessages(thread_id, messages)
+                            if thread_id == displayed_thread_id =>
+                        {
+                            Some(MessageBubbleContainerInputMsg::PrependMessages(messages))
+                        }
+                        _ => None,
                     }
-                    DatabaseNotifierMessage::UpdateMessage(message_update) => Some(
-                        MessageBubbleContainerInputMsg::AppendToLastMessage(message_update),
-                    ),
-                    DatabaseNotifierMessage::GetThreadMessages(messages) => {
-                        Some(MessageBubbleContainerInputMsg::RefreshMessages(messages))
-                    }
-                    _ => None,
                 },
             );
         }
         // Connect chat history notifier to thread list
         {
-            let database = model.database.lock().await;
-            database.notifier.subscribe(
+            model.database.notifier.subscribe(
                 model.thread_list.sender(),
                 |notifier_message: DatabaseNotifierMessage| match notifier_message {
                     DatabaseNotifierMessage::NewThread(thread) => {
@@ -251,16 +776,108 @@ impl AsyncComponent for ChatScreen {
                     DatabaseNotifierMessage::UpdateThread(thread) => {
                         Some(ThreadListContainerInputMsg::UpdateThread(thread))
                     }
+                    DatabaseNotifierMessage::UpdateThreadTitle(thread_id, title) => Some(
+                        ThreadListContainerInputMsg::SetThreadTitle(thread_id, title),
+                    ),
+                    DatabaseNotifierMessage::DeleteThread(thread_id) => {
+                        Some(ThreadListContainerInputMsg::RemoveThread(thread_id))
+                    }
+                    DatabaseNotifierMessage::NewTag(tag) => {
+                        Some(ThreadListContainerInputMsg::AddTag(tag))
+                    }
+                    DatabaseNotifierMessage::DeleteTag(tag_id) => {
+                        Some(ThreadListContainerInputMsg::RemoveTag(tag_id))
+                    }
+                    DatabaseNotifierMessage::UpdateThreadTags(thread_id, tags) => Some(
+                        ThreadListContainerInputMsg::UpdateThreadTags(thread_id, tags),
+                    ),
+                    DatabaseNotifierMessage::NewFolder(folder) => {
+                        Some(ThreadListContainerInputMsg::AddFolder(folder))
+                    }
+                    DatabaseNotifierMessage::DeleteFolder(folder_id) => {
+                        Some(ThreadListContainerInputMsg::RemoveFolder(folder_id))
+                    }
+                    _ => None,
+                },
+            );
+        }
+
+        // Connect chat history notifier to chat input
+        {
+            model.database.notifier.subscribe(
+                model.chat_input.sender(),
+                |notifier_message: DatabaseNotifierMessage| match notifier_message {
+                    DatabaseNotifierMessage::UpdatePromptTemplates(prompt_templates) => {
+                        Some(ChatInputInputMsg::UpdateTemplates(prompt_templates))
+                    }
+                    _ => None,
+                },
+            );
+        }
+
+        // Connect chat history notifier to the chat screen, to use the updated backends
+        {
+            model.database.notifier.subscribe(
+                sender.input_sender(),
+                |notifier_message: DatabaseNotifierMessage| match notifier_message {
+                    DatabaseNotifierMessage::UpdateBackends(backends) => {
+                        Some(ChatScreenInputMsg::UpdateBackends(backends))
+                    }
                     _ => None,
                 },
             );
         }
 
+        // Check whether the server can be reached, to disable the chat input while it cannot
+        {
+            let assistant = model.assistant.clone();
+            sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
+                shutdown
+                    .register(async move {
+                        loop {
+                            sleep(HEALTH_CHECK_INTERVAL).await;
+                            let backend = assistant.lock().await.parameters().backend;
+                            let reachable = backend.health_check().await.is_ok();
+                            out.emit(ChatScreenCmdMsg::BackendHealth(reachable));
+                        }
+                    })
+                    .drop_on_shutdown()
+                    .boxed()
+            });
+        }
+
+        // Back up the database regularly, the first backup being made on startup
+        {
+            let database = model.database.clone();
+            sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
+                shutdown
+                    .register(async move {
+                        loop {
+                            sleep(BACKUP_CHECK_INTERVAL).await;
+                            match database.backup_if_due().await {
+                                Ok(Some(backup)) => out.emit(ChatScreenCmdMsg::BackedUp(backup)),
+                                Ok(None) => {}
+                                Err(error) => {
+                                    tracing::error!(
+                                        "Backing up database failed because of: {error}"
+                                    )
+                                }
+                            }
+                        }
+                    })
+                    .drop_on_shutdown()
+                    .boxed()
+            });
+        }
+
         // References used in the view macro
         let assistant_parameters = model.assistant_parameters.widget();
         let thread_list = model.thread_list.widget();
         let message_bubbles = model.message_bubbles.widget();
         let chat_input = model.chat_input.widget();
+        let model_comparison = model.model_comparison.widget();
+        let encryption_settings = model.encryption_settings.widget();
+        let response_format = model.response_format.widget();
 
         let widgets = view_output!();
 
@@ -271,7 +888,7 @@ impl AsyncComponent for ChatScreen {
         &mut self,
         message: Self::Input,
         sender: AsyncComponentSender<Self>,
-        _: &Self::Root,
+        root: &Self::Root,
     ) {
         match message {
             ChatScreenInputMsg::Temperature(value) => {
@@ -288,39 +905,96 @@ impl AsyncComponent for ChatScreen {
                 let mut assistant = self.assistant.lock().await;
                 assistant.reset_parameters();
             }
-            ChatScreenInputMsg::SelectModel(model) => {
-                tracing::info!("Pulling model {model}");
+            ChatScreenInputMsg::KeepAlive(keep_alive) => {
+                tracing::info!(
+                    "Keeping models in memory for {}",
+                    KeepAlive::label(keep_alive)
+                );
+                self.assistant.lock().await.set_keep_alive(keep_alive);
+            }
+            ChatScreenInputMsg::ListLoadedModels => {
+                let parameters = self.assistant.lock().await.parameters();
+                sender.oneshot_command(async move {
+                    ChatScreenCmdMsg::LoadedModelsListed(list_loaded_models(&parameters).await)
+                });
+            }
+            ChatScreenInputMsg::PreloadModel => {
+                let parameters = self.assistant.lock().await.parameters();
+                tracing::info!("Preloading model {:?}", parameters.model);
+                sender.oneshot_command(async move {
+                    let loaded_models = match Assistant::preload_model(&parameters).await {
+                        Ok(_) => list_loaded_models(&parameters).await,
+                        Err(error) => {
+                            tracing::error!("Preloading model failed because of: {error}");
+                            Err(format!("Loading the model failed: {error}"))
+                        }
+                    };
+                    ChatScreenCmdMsg::LoadedModelsListed(loaded_models)
+                });
+            }
+            ChatScreenInputMsg::UnloadModel(model) => {
+                let parameters = self.assistant.lock().await.parameters();
+                tracing::info!("Unloading model {model}");
+                sender.oneshot_command(async move {
+                    let loaded_models = match Assistant::unload_model(&parameters, model).await {
+                        Ok(_) => list_loaded_models(&parameters).await,
+                        Err(error) => {
+                            tracing::error!("Unloading model failed because of: {error}");
+                            Err(format!("Unloading the model failed: {error}"))
+                        }
+                    };
+                    ChatScreenCmdMsg::LoadedModelsListed(loaded_models)
+                });
+            }
+            ChatScreenInputMsg::SelectModel(backend_id, model) => {
+                tracing::info!("Selecting model {model} of backend with id {backend_id}");
+                if self.assistant.lock().await.backend_id() != Some(backend_id) {
+                    tracing::info!(
+                        "Using backend with id {backend_id} for thread with id {}",
+                        self.current_thread_id
+                    );
+                    if let Err(error) = self
+                        .database
+                        .set_thread_backend(self.current_thread_id, Some(backend_id))
+                        .await
+                    {
+                        tracing::error!("Setting thread backend failed because of: {error}");
+                    }
+                    self.assistant.lock().await.set_backend(Some(backend_id));
+                }
                 self.disable_inputs();
-                let assistant = self.assistant.clone();
-                sender.command(|out, shutdown: relm4::ShutdownReceiver| {
+                self.pulling_model = true;
+                let (parameters, can_pull_models) = {
+                    let assistant = self.assistant.lock().await;
+                    (assistant.parameters(), assistant.can_pull_models())
+                };
+                sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
                     shutdown
                         .register(async move {
-                            let mut assistant = assistant.lock().await;
-                            let mut response_stream = match assistant.pull_model(model.clone()).await {
-                                Ok(stream) => stream,
-                                Err(error) => {
-                                    tracing::error!(
-                                        "Error receiving pull model response because of: {error}"
-                                    );
-                                    return;
-                                }
-                            };
-
-                            while let Some(result) = response_stream.next().await {
-                                match result {
-                                    Ok(pull_model_response) => {
-                                        tracing::info!("Received pull model response: {:?}", pull_model_response);
-                                    }
-                                    Err(error) => {
-                                        tracing::error!(
-                                            "Error receiving pull model response because of: {error}"
+                            let result: anyhow::Result<()> = async {
+                                // Models of other backends are managed by their server
+                                if can_pull_models {
+                                    let mut response_stream =
+                                        Assistant::pull_model(&parameters, model.clone()).await?;
+                                    while let Some(result) = response_stream.next().await {
+                                        tracing::info!(
+                                            "Received pull model response: {:?}",
+                                            result?
                                         );
-                                        return;
                                     }
                                 }
+                                Ok(())
                             }
-                            assistant.set_model(model);
-                            out.emit(ChatScreenCmdMsg::PullModelEnd);
+                            .await;
+                            // The inputs are enabled again even if the download failed
+                            let pulled_model = match result {
+                                Ok(()) => Some(model),
+                                Err(error) => {
+                                    tracing::error!("Pulling model failed because of: {error}");
+                                    None
+                                }
+                            };
+                            out.emit(ChatScreenCmdMsg::PullModelEnd(pulled_model));
                         })
                         // Perform task until a shutdown interrupts it
                         .drop_on_shutdown()
@@ -330,131 +1004,548 @@ impl AsyncComponent for ChatScreen {
             }
             ChatScreenInputMsg::GetThreadMessages(thread_id) => {
                 tracing::info!("Getting messages for thread with id {thread_id}");
-                {
-                    let mut database = self.database.lock().await;
-                    database
-                        .get_messages(thread_id)
-                        .await
-                        .expect("Getting thread messages should work");
-                    self.current_thread_id = thread_id;
+                self.set_current_thread(thread_id);
+                match self.database.get_thread(thread_id).await {
+                    Ok(thread) => {
+                        self.use_backend(thread.backend_id).await;
+                        self.response_format
+                            .emit(ResponseFormatInputMsg::ShowFormat(
+                                thread
+                                    .response_format
+                                    .as_deref()
+                                    .and_then(|format| ResponseFormat::parse(format).ok()),
+                            ));
+                    }
+                    Err(error) => tracing::error!("Getting thread failed because of: {error}"),
                 }
+                self.database
+                    .get_messages_page(thread_id, None, MESSAGES_PAGE_SIZE)
+                    .await
+                    .expect("Getting thread messages should work");
+                let draft = self
+                    .database
+                    .get_draft(thread_id)
+                    .await
+                    .expect("Getting thread draft should work")
+                    .unwrap_or_default();
+                self.chat_input
+                    .emit(ChatInputInputMsg::LoadDraft(thread_id, draft));
+                self.update_chat_input_state();
+            }
+            ChatScreenInputMsg::LoadOlderMessages(before_id) => {
+                tracing::debug!("Loading messages sent before message with id {before_id}");
+                self.database
+                    .get_messages_page(self.current_thread_id, Some(before_id), MESSAGES_PAGE_SIZE)
+                    .await
+                    .expect("Getting older thread messages should work");
             }
             ChatScreenInputMsg::CreateNewThread => {
                 tracing::info!("Creating new thread");
-                let mut database = self.database.lock().await;
-                let thread = database
+                let thread = self
+                    .database
                     .create_thread("New Thread")
                     .await
                     .expect("Creating new thread should work");
-                self.current_thread_id = thread.id;
+                // New threads keep using the selected backend
+                let backend = self.assistant.lock().await.backend_config().cloned();
+                if let Some(backend) = backend.filter(|backend| !backend.active) {
+                    self.database
+                        .set_thread_backend(thread.id, Some(backend.id))
+                        .await
+                        .expect("Setting thread backend should work");
+                }
+                self.set_current_thread(thread.id);
+                self.response_format
+                    .emit(ResponseFormatInputMsg::ShowFormat(None));
+                self.update_chat_input_state();
             }
             ChatScreenInputMsg::DeleteThread(thread_id) => {
                 tracing::info!("Deleting thread with id {thread_id}");
-                let mut database = self.database.lock().await;
-                database
+                self.database
                     .delete_thread(thread_id)
                     .await
                     .expect("Deleting thread should work");
             }
+            ChatScreenInputMsg::RenameThread(thread_id, title) => {
+                tracing::info!("Renaming thread with id {thread_id}");
+                self.database
+                    .update_thread_title(thread_id, title)
+                    .await
+                    .expect("Updating thread title should work");
+            }
+            ChatScreenInputMsg::PinThread(thread_id, pinned) => {
+                tracing::info!("Setting pinned to {pinned} for thread with id {thread_id}");
+                self.database
+                    .set_thread_pinned(thread_id, pinned)
+                    .await
+                    .expect("Pinning thread should work");
+            }
+            ChatScreenInputMsg::ArchiveThread(thread_id, archived) => {
+                tracing::info!("Setting archived to {archived} for thread with id {thread_id}");
+                self.database
+                    .set_thread_archived(thread_id, archived)
+                    .await
+                    .expect("Archiving thread should work");
+            }
+            ChatScreenInputMsg::RestoreThread(thread_id) => {
+                tracing::info!("Restoring thread with id {thread_id}");
+                self.database
+                    .restore_thread(thread_id)
+                    .await
+                    .expect("Restoring thread should work");
+            }
+            ChatScreenInputMsg::PurgeThread(thread_id) => {
+                tracing::info!("Permanently deleting thread with id {thread_id}");
+                self.database
+                    .purge_thread(thread_id)
+                    .await
+                    .expect("Purging thread should work");
+            }
+            ChatScreenInputMsg::CreateTag(name) => {
+                tracing::info!("Creating tag {name}");
+                if let Err(error) = self.database.create_tag(&name).await {
+                    tracing::error!("Creating tag failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::DeleteTag(tag_id) => {
+                tracing::info!("Deleting tag with id {tag_id}");
+                self.database
+                    .delete_tag(tag_id)
+                    .await
+                    .expect("Deleting tag should work");
+            }
+            ChatScreenInputMsg::AddTagToThread(thread_id, tag_id) => {
+                tracing::info!("Adding tag with id {tag_id} to thread with id {thread_id}");
+                self.database
+                    .add_tag_to_thread(thread_id, tag_id)
+                    .await
+                    .expect("Tagging thread should work");
+            }
+            ChatScreenInputMsg::RemoveTagFromThread(thread_id, tag_id) => {
+                tracing::info!("Removing tag with id {tag_id} from thread with id {thread_id}");
+                self.database
+                    .remove_tag_from_thread(thread_id, tag_id)
+                    .await
+                    .expect("Untagging thread should work");
+            }
+            ChatScreenInputMsg::CreateFolder(name) => {
+                tracing::info!("Creating folder {name}");
+                if let Err(error) = self.database.create_folder(&name).await {
+                    tracing::error!("Creating folder failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::DeleteFolder(folder_id) => {
+                tracing::info!("Deleting folder with id {folder_id}");
+                self.database
+                    .delete_folder(folder_id)
+                    .await
+                    .expect("Deleting folder should work");
+            }
+            ChatScreenInputMsg::MoveThreadToFolder(thread_id, folder_id) => {
+                tracing::info!("Moving thread with id {thread_id} to folder {folder_id:?}");
+                self.database
+                    .set_thread_folder(thread_id, folder_id)
+                    .await
+                    .expect("Moving thread to folder should work");
+            }
+            ChatScreenInputMsg::SavePromptTemplate(name, content) => {
+                tracing::info!("Saving prompt template {name}");
+                let new_prompt_template = NewPromptTemplate {
+                    name: &name,
+                    content: &content,
+                };
+                if let Err(error) = self
+                    .database
+                    .save_prompt_templates(&[new_prompt_template])
+                    .await
+                {
+                    tracing::error!("Saving prompt template failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::DeletePromptTemplate(template_id) => {
+                tracing::info!("Deleting prompt template with id {template_id}");
+                self.database
+                    .delete_prompt_template(template_id)
+                    .await
+                    .expect("Deleting prompt template should work");
+            }
+            ChatScreenInputMsg::ImportPromptTemplates(path) => {
+                tracing::info!("Importing prompt templates from {}", path.display());
+                let entries = match read_templates_file(&path) {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        tracing::error!("Reading prompt templates file failed because of: {error}");
+                        return;
+                    }
+                };
+                let names: Vec<String> = entries
+                    .iter()
+                    .map(|entry| normalize_template_name(&entry.name))
+                    .collect();
+                let new_prompt_templates: Vec<NewPromptTemplate> = entries
+                    .iter()
+                    .zip(&names)
+                    .filter(|(entry, name)| !name.is_empty() && !entry.content.trim().is_empty())
+                    .map(|(entry, name)| NewPromptTemplate {
+                        name,
+                        content: &entry.content,
+                    })
+                    .collect();
+                if let Err(error) = self
+                    .database
+                    .save_prompt_templates(&new_prompt_templates)
+                    .await
+                {
+                    tracing::error!("Importing prompt templates failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::EditMessage(message_id, content) => {
+                tracing::info!("Editing message with id {message_id}");
+                if let Err(error) = self.database.edit_message(message_id, content).await {
+                    tracing::error!("Editing message failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::DeleteMessage(message_id) => {
+                tracing::info!("Deleting message with id {message_id}");
+                if let Err(error) = self.database.delete_message(message_id).await {
+                    tracing::error!("Deleting message failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::RetryAnswer(message_id) => {
+                let thread_id = self.current_thread_id;
+                if self.generations.contains_key(&thread_id) {
+                    return;
+                }
+                // Only the last answer can be replaced without losing the messages after it
+                let messages = self
+                    .database
+                    .get_messages(thread_id)
+                    .await
+                    .expect("Getting thread messages should work");
+                if messages.last().map(|message| message.id) != Some(message_id) {
+                    tracing::warn!("Not retrying message {message_id}, it is not the last one");
+                    return;
+                }
+                tracing::info!("Retrying answer with id {message_id}");
+                if let Err(error) = self.database.delete_message(message_id).await {
+                    tracing::error!("Deleting message failed because of: {error}");
+                    return;
+                }
+                sender.input(ChatScreenInputMsg::AssistantAnswer(thread_id));
+            }
+            ChatScreenInputMsg::SetResponseFormat(response_format) => {
+                tracing::info!(
+                    "Setting response format of thread {}",
+                    self.current_thread_id
+                );
+                let result = self
+                    .database
+                    .set_thread_response_format(
+                        self.current_thread_id,
+                        response_format.as_ref().map(ToString::to_string).as_deref(),
+                    )
+                    .await
+                    .map_err(|error| error.to_string());
+                self.response_format
+                    .emit(ResponseFormatInputMsg::ChangeApplied(result));
+            }
+            ChatScreenInputMsg::EnableEncryption(passphrase) => {
+                tracing::info!("Encrypting database");
+                let result = self.database.enable_encryption(&passphrase).await;
+                self.report_encryption_change(result.map(|_| true));
+            }
+            ChatScreenInputMsg::ChangePassphrase(passphrase, new_passphrase) => {
+                tracing::info!("Changing database passphrase");
+                let result = self
+                    .database
+                    .change_passphrase(&passphrase, &new_passphrase)
+                    .await;
+                self.report_encryption_change(result.map(|_| true));
+            }
+            ChatScreenInputMsg::DisableEncryption(passphrase) => {
+                tracing::info!("Decrypting database");
+                let result = self.database.disable_encryption(&passphrase).await;
+                self.report_encryption_change(result.map(|_| false));
+            }
+            ChatScreenInputMsg::BackUpDatabase => match self.database.backup().await {
+                Ok(backup) => self.last_backup = Some(backup),
+                Err(error) => tracing::error!("Backing up database failed because of: {error}"),
+            },
+            ChatScreenInputMsg::ShowBackendSettings => {
+                let window = self.backend_settings.widget();
+                window.set_transient_for(root.root().and_downcast_ref::<gtk::Window>());
+                window.present();
+            }
+            ChatScreenInputMsg::ShowPlayground => {
+                let window = self.playground.widget();
+                window.set_transient_for(root.root().and_downcast_ref::<gtk::Window>());
+                window.present();
+            }
+            ChatScreenInputMsg::UpdateBackends(backends) => {
+                tracing::info!("Using {} updated backends", backends.len());
+                self.assistant.lock().await.set_backends(backends);
+                self.refresh_models(&sender).await;
+            }
+            ChatScreenInputMsg::ExportPromptTemplates(path) => {
+                tracing::info!("Exporting prompt templates to {}", path.display());
+                let prompt_templates = self
+                    .database
+                    .get_prompt_templates()
+                    .await
+                    .expect("Getting prompt templates should work");
+                if let Err(error) = write_templates_file(&path, prompt_templates) {
+                    tracing::error!("Writing prompt templates file failed because of: {error}");
+                }
+            }
             ChatScreenInputMsg::SubmitUserInput(user_input) => {
                 tracing::info!("Submitting user input");
                 let message = Message {
                     content: user_input,
                     role: Role::User,
                 };
-                {
-                    let mut database = self.database.lock().await;
-                    let thread_id = self.current_thread_id;
-                    database
-                        .create_message(thread_id, message.content, message.role)
-                        .await
-                        .expect("Message should be created");
+                let thread_id = self.current_thread_id;
+                self.database
+                    .create_message(thread_id, message.content, message.role)
+                    .await
+                    .expect("Message should be created");
+                self.database
+                    .save_draft(thread_id, "")
+                    .await
+                    .expect("Clearing thread draft should work");
+                if self.comparing {
+                    let messages = self.get_thread_messages(thread_id).await;
+                    let parameters = self.assistant.lock().await.parameters();
+                    let database = self.database.clone();
+                    let title_messages = messages.clone();
+                    sender.command(move |_, shutdown: relm4::ShutdownReceiver| {
+                        shutdown
+                            .register(async move {
+                                Self::generate_thread_title(
+                                    &database,
+                                    parameters,
+                                    thread_id,
+                                    &title_messages,
+                                )
+                                .await;
+                            })
+                            .drop_on_shutdown()
+                            .boxed()
+                    });
+                    self.model_comparison
+                        .emit(ModelComparisonInputMsg::Compare(messages));
+                    self.disable_inputs();
+                    self.comparison_running = true;
+                } else {
+                    sender
+                        .input_sender()
+                        .emit(ChatScreenInputMsg::AssistantAnswer(thread_id));
                 }
+            }
+            ChatScreenInputMsg::CompareModels(comparing) => {
+                self.comparing = comparing;
+            }
+            ChatScreenInputMsg::PickComparisonAnswer(backend_id, model, content) => {
+                tracing::info!("Continuing thread with the answer of model {model}");
+                self.database
+                    .create_message(self.current_thread_id, content, Role::Assistant)
+                    .await
+                    .expect("Creating assistant message should work");
+                self.assistant_parameters
+                    .emit(AssistantParametersInputMsg::ShowModel(
+                        backend_id,
+                        model.clone(),
+                    ));
                 sender
                     .input_sender()
-                    .emit(ChatScreenInputMsg::AssistantAnswer);
-
-                self.disable_inputs();
+                    .emit(ChatScreenInputMsg::SelectModel(backend_id, model));
+                self.comparing = false;
             }
-            ChatScreenInputMsg::AssistantAnswer => {
-                let thread_id = self.current_thread_id;
+            ChatScreenInputMsg::ComparisonEnd => {
+                self.comparison_running = false;
+                self.enable_inputs();
+            }
+            ChatScreenInputMsg::SaveDraft(thread_id, draft) => {
+                tracing::debug!("Saving draft for thread with id {thread_id}");
+                if let Err(error) = self.database.save_draft(thread_id, &draft).await {
+                    tracing::error!("Saving draft failed because of: {error}");
+                }
+            }
+            ChatScreenInputMsg::AssistantAnswer(thread_id) => {
                 let database = self.database.clone();
 
-                let messages: Vec<Message> = {
-                    let mut database = database.lock().await;
-                    let messages = database
-                        .get_messages(thread_id)
-                        .await
-                        .expect("Getting messages should work");
-
-                    messages
-                        .into_iter()
-                        .map(|m| Message {
-                            content: m.content,
-                            role: Role::try_from(m.role)
-                                .expect("Role string to enum conversion should work"),
-                        })
-                        .collect()
-                };
-
-                if messages.len() == 2 {
-                    tracing::info!("Generating thread title for thread after first user message");
-                    let mut assistant = self.assistant.lock().await;
-                    let thread_title = assistant
-                        .generate_thread_title(messages[1].clone())
-                        .await
-                        .unwrap();
-                    let mut database = database.lock().await;
-                    database
-                        .update_thread_title(self.current_thread_id, thread_title)
-                        .await
-                        .expect("Updating thread title should work");
+                let messages = self.get_thread_messages(thread_id).await;
+                // Each answer uses the parameters selected when it was requested
+                let mut parameters = self.assistant.lock().await.parameters();
+                match database.get_thread(thread_id).await {
+                    Ok(thread) => {
+                        parameters.response_format =
+                            thread.response_format.as_deref().and_then(|format| {
+                                match ResponseFormat::parse(format) {
+                                    Ok(response_format) => Some(response_format),
+                                    Err(error) => {
+                                        tracing::error!(
+                                            "Reading response format failed because of: {error}"
+                                        );
+                                        None
+                                    }
+                                }
+                            });
+                    }
+                    Err(error) => tracing::error!("Getting thread failed because of: {error}"),
                 }
 
                 let assistant_message_id = {
-                    let mut database = database.lock().await;
                     let message = database
-                        .create_message(thread_id, String::new(), Role::Assistant)
+                        .create_partial_message(thread_id, Role::Assistant)
                         .await
                         .expect("Creating an empty assistant message should work");
                     message.id
                 };
+                self.generations.insert(
+                    thread_id,
+                    Generation {
+                        message_id: assistant_message_id,
+                        status: GenerationStatus::Generating,
+                    },
+                );
+                self.thread_list
+                    .emit(ThreadListContainerInputMsg::SetThreadGenerating(
+                        thread_id, true,
+                    ));
+                self.update_chat_input_state();
 
-                let assistant = self.assistant.clone();
                 sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
                     shutdown
                         .register(async move {
-                            let mut assistant = assistant.lock().await;
-                            let mut message_stream = match assistant.generate_answer(messages).await
-                            {
-                                Ok(stream) => stream,
+                            let set_status = |status| {
+                                out.emit(ChatScreenCmdMsg::GenerationStatus(
+                                    thread_id,
+                                    assistant_message_id,
+                                    status,
+                                ))
+                            };
+                            // Whether the answer has not started yet while another status is shown
+                            let waiting =
+                                AtomicBool::new(Assistant::model_needs_loading(&parameters).await);
+                            if waiting.load(Ordering::SeqCst) {
+                                set_status(GenerationStatus::LoadingModel);
+                            }
+
+                            Self::generate_thread_title(
+                                &database,
+                                parameters.clone(),
+                                thread_id,
+                                &messages,
+                            )
+                            .await;
+
+                            let response_format = parameters.response_format.clone();
+                            let mut answer_text = String::new();
+                            let mut writer = MessageWriter::new(thread_id, assistant_message_id);
+                            let answer = Assistant::generate_answer_reporting_retries(
+                                parameters,
+                                messages,
+                                |retry_attempt| {
+                                    waiting.store(true, Ordering::SeqCst);
+                                    set_status(GenerationStatus::Reconnecting(
+                                        retry_attempt.clone(),
+                                    ));
+                                },
+                            )
+                            .await;
+                            match answer {
+                                Ok(mut message_stream) => loop {
+                                    let result = match writer
+                                        .next_chunk(&database, &mut message_stream)
+                                        .await
+                                    {
+                                        Ok(Some(result)) => result,
+                                        Ok(None) => break,
+                                        Err(error) => {
+                                            tracing::error!(
+                                                "Updating message in database failed because of: {error}"
+                                            );
+                                            break;
+                                        }
+                                    };
+                                    if waiting.swap(false, Ordering::SeqCst) {
+                                        set_status(GenerationStatus::Generating);
+                                    }
+                                    match result {
+                                        Ok(chunk) => {
+                                            tracing::debug!(
+                                                "Received assistant answer: {:?}",
+                                                chunk
+                                            );
+                                            if chunk.discard_previous {
+                                                answer_text.clear();
+                                                if let Err(error) =
+                                                    writer.discard(&database).await
+                                                {
+                                                    tracing::error!(
+                                                        "Discarding reasoning in database failed because of: {error}"
+                                                    );
+                                                    break;
+                                                }
+                                            }
+                                            answer_text.push_str(&chunk.content);
+                                            if let Err(error) =
+                                                writer.push(&database, chunk.content).await
+                                            {
+                                                tracing::error!(
+                                                    "Updating message in database failed because of: {error}"
+                                                );
+                                                break;
+                                            }
+                                        }
+                                        Err(error) => {
+                                            tracing::error!(
+                                                "Error receiving assistant answer because of: {error}"
+                                            );
+                                            break;
+                                        }
+                                    }
+                                }
                                 Err(error) => {
                                     tracing::error!(
                                         "Error receiving assistant answer because of: {error}"
                                     );
-                                    return;
-                                }
-                            };
-
-                            while let Some(result) = message_stream.next().await {
-                                match result {
-                                    Ok(message) => {
-                                        tracing::info!("Received assistant answer: {:?}", message);
-                                        let mut database = database.lock().await;
-                                        database
-                                            .update_message(assistant_message_id, message.content)
-                                            .await
-                                            .expect("Updating message in database should work");
+                                    // The health checks tell when the server is back
+                                    if is_transient(&error) {
+                                        out.emit(ChatScreenCmdMsg::BackendHealth(false));
                                     }
-                                    Err(error) => {
+                                    // Nothing was generated, so the placeholder of the answer is removed
+                                    if let Err(error) =
+                                        database.delete_message(assistant_message_id).await
+                                    {
                                         tracing::error!(
-                                            "Error receiving assistant answer because of: {error}"
+                                            "Deleting empty answer failed because of: {error}"
                                         );
-                                        return;
+                                    }
+                                    out.emit(ChatScreenCmdMsg::AnswerEnd(
+                                        thread_id,
+                                        assistant_message_id,
+                                    ));
+                                    return;
+                                }
+                            }
+                            match writer.finish(&database).await {
+                                Ok(()) => {
+                                    if let Some(response_format) = &response_format {
+                                        Self::check_answer_format(
+                                            &database,
+                                            assistant_message_id,
+                                            response_format,
+                                            &answer_text,
+                                        )
+                                        .await;
                                     }
                                 }
+                                Err(error) => tracing::error!(
+                                    "Writing assistant answer failed because of: {error}"
+                                ),
                             }
-                            out.emit(ChatScreenCmdMsg::AnswerEnd);
+                            out.emit(ChatScreenCmdMsg::AnswerEnd(thread_id, assistant_message_id));
                         })
                         // Perform task until a shutdown interrupts it
                         .drop_on_shutdown()
@@ -468,16 +1559,103 @@ impl AsyncComponent for ChatScreen {
     async fn update_cmd(
         &mut self,
         message: Self::CommandOutput,
-        _: AsyncComponentSender<Self>,
+        sender: AsyncComponentSender<Self>,
         _: &Self::Root,
     ) {
         match message {
-            ChatScreenCmdMsg::PullModelEnd => {
+            ChatScreenCmdMsg::PullModelEnd(model) => {
+                self.pulling_model = false;
                 self.enable_inputs();
+                if let Some(model) = model {
+                    self.assistant.lock().await.set_model(model);
+                    // The first answer then does not wait for the model to be loaded
+                    sender.input(ChatScreenInputMsg::PreloadModel);
+                }
             }
-            ChatScreenCmdMsg::AnswerEnd => {
-                self.enable_inputs();
+            ChatScreenCmdMsg::LoadedModelsListed(loaded_models) => {
+                self.assistant_parameters
+                    .emit(AssistantParametersInputMsg::SetLoadedModels(loaded_models));
+            }
+            ChatScreenCmdMsg::GenerationStatus(thread_id, message_id, status) => {
+                if let Some(generation) = self
+                    .generations
+                    .get_mut(&thread_id)
+                    .filter(|generation| generation.message_id == message_id)
+                {
+                    generation.status = status;
+                }
+            }
+            ChatScreenCmdMsg::BackendHealth(reachable) => {
+                if reachable != self.backend_reachable {
+                    self.backend_reachable = reachable;
+                    if reachable {
+                        tracing::info!("The server can be reached again");
+                        // Models may have been pulled or removed while it was down
+                        self.refresh_models(&sender).await;
+                        if !self.pulling_model && !self.comparison_running {
+                            self.enable_inputs();
+                        }
+                    } else {
+                        tracing::warn!("The server cannot be reached");
+                    }
+                    self.update_chat_input_state();
+                }
+            }
+            ChatScreenCmdMsg::AnswerEnd(thread_id, message_id) => {
+                tracing::info!("Answer generation ended for thread with id {thread_id}");
+                if self
+                    .generations
+                    .get(&thread_id)
+                    .is_some_and(|generation| generation.message_id == message_id)
+                {
+                    self.generations.remove(&thread_id);
+                    self.thread_list
+                        .emit(ThreadListContainerInputMsg::SetThreadGenerating(
+                            thread_id, false,
+                        ));
+                }
+                self.update_chat_input_state();
+            }
+            ChatScreenCmdMsg::BackedUp(backup) => {
+                self.last_backup = Some(backup);
+            }
+            ChatScreenCmdMsg::ModelsListed(models) => {
+                tracing::info!("Listed {} models of every backend", models.len());
+                self.models = models;
+                self.assistant_parameters
+                    .emit(AssistantParametersInputMsg::SetModels(self.models.clone()));
+                self.model_comparison
+                    .emit(ModelComparisonInputMsg::SetModels(self.models.clone()));
+                self.playground
+                    .emit(PlaygroundInputMsg::SetModels(self.models.clone()));
+
+                // The selected model may not be served by the backend anymore
+                let mut assistant = self.assistant.lock().await;
+                if let Some(backend_id) = assistant.backend_id() {
+                    let model = assistant.parameters().model.unwrap_or_default();
+                    if self
+                        .models
+                        .iter()
+                        .any(|choice| choice.is(backend_id, &model))
+                    {
+                        self.assistant_parameters
+                            .emit(AssistantParametersInputMsg::ShowModel(backend_id, model));
+                    } else {
+                        self.use_preferred_model(&mut assistant, backend_id);
+                    }
+                }
             }
         }
     }
 }
+
+/// Returns the models in the memory of the backend of the given parameters,
+/// or why they could not be listed.
+pub async fn list_loaded_models(
+    parameters: &AssistantParameters,
+) -> Result<Vec<LoadedModel>, String> {
+    Assistant::loaded_models(parameters).await.map_err(|error| {
+        tracing::warn!("Listing loaded models failed because of: {error}");
+        error.to_string()
+    })
+}
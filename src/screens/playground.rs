@@ -0,0 +1,415 @@
+use futures::{FutureExt, StreamExt};
+use gtk::prelude::*;
+use relm4::component::{AsyncComponent, AsyncComponentParts, AsyncComponentSender};
+use relm4::prelude::*;
+
+use crate::assistant::backend::{CompletionPrompt, KeepAlive, LoadedModel};
+use crate::assistant::{Assistant, AssistantParameters, GenerationStats, ModelChoice};
+use crate::components::assistant_parameters::{
+    AssistantParametersComponent, AssistantParametersInputMsg, AssistantParametersOutputMsg,
+};
+use crate::screens::chat::list_loaded_models;
+
+/// Window completing raw prompts, with custom templates or a suffix to fill in the middle.
+#[derive(Debug)]
+pub struct PlaygroundScreen {
+    /// Parameters of the completions, independent from the ones of the chat.
+    parameters: AssistantParameters,
+    /// Models of every backend, grouped by backend.
+    models: Vec<ModelChoice>,
+    assistant_parameters: Controller<AssistantParametersComponent>,
+    prompt_buffer: gtk::TextBuffer,
+    suffix_buffer: gtk::TextBuffer,
+    template_buffer: gtk::TextBuffer,
+    completion_buffer: gtk::TextBuffer,
+    raw: bool,
+    generating: bool,
+    status: String,
+}
+
+#[derive(Debug)]
+pub enum PlaygroundInputMsg {
+    /// Replaces the models that can be selected, when backends change.
+    SetModels(Vec<ModelChoice>),
+    Raw(bool),
+    Complete,
+    // Assistant Parameters
+    /// Backend id and name of the selected model.
+    SelectModel(i64, String),
+    Temperature(f64),
+    TopK(u64),
+    TopP(f64),
+    ResetParameters,
+    KeepAlive(Option<KeepAlive>),
+    ListLoadedModels,
+    PreloadModel,
+    UnloadModel(String),
+}
+
+#[derive(Debug)]
+pub enum PlaygroundCmdMsg {
+    CompletionChunk(String),
+    /// Statistics of the completion, or why it failed.
+    CompletionEnd(Result<Option<GenerationStats>, String>),
+    /// Models in the memory of the selected backend, or why they could not be listed.
+    LoadedModelsListed(Result<Vec<LoadedModel>, String>),
+}
+
+impl PlaygroundScreen {
+    fn select_model(&mut self, backend_id: i64, model: &str) {
+        if let Some(choice) = self
+            .models
+            .iter()
+            .find(|choice| choice.is(backend_id, model))
+        {
+            self.parameters.backend = choice.backend.clone();
+            self.parameters.model = Some(choice.model.clone());
+        }
+    }
+
+    /// Returns the prompt described by the editors.
+    fn read_prompt(&self) -> CompletionPrompt {
+        let text = |buffer: &gtk::TextBuffer| {
+            let (start, end) = buffer.bounds();
+            buffer.text(&start, &end, false).to_string()
+        };
+        let suffix = text(&self.suffix_buffer);
+        let template = text(&self.template_buffer);
+        CompletionPrompt {
+            prompt: text(&self.prompt_buffer),
+            suffix: (!suffix.is_empty()).then_some(suffix),
+            template: (!template.trim().is_empty()).then_some(template),
+            raw: self.raw,
+        }
+    }
+}
+
+#[relm4::component(async, pub)]
+impl AsyncComponent for PlaygroundScreen {
+    /// Models of every backend, and backend id and name of the selected model.
+    type Init = (Vec<ModelChoice>, Option<(i64, String)>);
+    type Input = PlaygroundInputMsg;
+    type Output = ();
+    type CommandOutput = PlaygroundCmdMsg;
+
+    view! {
+        gtk::Window {
+            set_title: Some("Completion playground"),
+            set_default_size: (800, 640),
+            set_hide_on_close: true,
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 10,
+                set_spacing: 5,
+
+                #[local_ref]
+                assistant_parameters -> gtk::Box {},
+
+                gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_label: "Prompt",
+                    set_css_classes: &["heading"],
+                },
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_hscrollbar_policy: gtk::PolicyType::Never,
+
+                    gtk::TextView {
+                        set_buffer: Some(&model.prompt_buffer),
+                        set_monospace: true,
+                        set_wrap_mode: gtk::WrapMode::WordChar,
+                        set_css_classes: &["prompt_input"],
+                    },
+                },
+
+                gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_label: "Suffix",
+                    set_tooltip_text: Some("Text after the completion, for models that fill in the middle"),
+                    set_css_classes: &["heading"],
+                },
+                gtk::ScrolledWindow {
+                    set_min_content_height: 60,
+                    set_hscrollbar_policy: gtk::PolicyType::Never,
+
+                    gtk::TextView {
+                        set_buffer: Some(&model.suffix_buffer),
+                        set_monospace: true,
+                        set_wrap_mode: gtk::WrapMode::WordChar,
+                        set_css_classes: &["suffix_input"],
+                    },
+                },
+
+                gtk::Expander {
+                    set_label: Some("Template"),
+                    set_tooltip_text: Some("Prompt template replacing the one of the model, ignored for raw prompts"),
+
+                    gtk::ScrolledWindow {
+                        set_min_content_height: 80,
+                        set_hscrollbar_policy: gtk::PolicyType::Never,
+
+                        gtk::TextView {
+                            set_buffer: Some(&model.template_buffer),
+                            set_monospace: true,
+                            set_wrap_mode: gtk::WrapMode::WordChar,
+                            set_css_classes: &["template_input"],
+                        },
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 10,
+
+                    gtk::CheckButton {
+                        set_label: Some("Raw"),
+                        set_tooltip_text: Some("Send the prompt to the model as is, without any template"),
+                        connect_toggled[sender] => move |button| {
+                            sender.input(PlaygroundInputMsg::Raw(button.is_active()));
+                        },
+                    },
+                    gtk::Label {
+                        set_hexpand: true,
+                        set_halign: gtk::Align::Start,
+                        set_wrap: true,
+                        set_css_classes: &["dim-label"],
+                        #[watch]
+                        set_label: &model.status,
+                    },
+                    gtk::Button {
+                        set_label: "Complete",
+                        set_css_classes: &["button", "suggested-action"],
+                        #[watch]
+                        set_sensitive: !model.generating,
+                        connect_clicked => PlaygroundInputMsg::Complete,
+                    },
+                },
+
+                gtk::Label {
+                    set_halign: gtk::Align::Start,
+                    set_label: "Completion",
+                    set_css_classes: &["heading"],
+                },
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_hscrollbar_policy: gtk::PolicyType::Never,
+
+                    gtk::TextView {
+                        set_buffer: Some(&model.completion_buffer),
+                        set_editable: false,
+                        set_monospace: true,
+                        set_wrap_mode: gtk::WrapMode::WordChar,
+                        set_css_classes: &["completion_output"],
+                    },
+                },
+            },
+        }
+    }
+
+    async fn init(
+        (models, selected_model): Self::Init,
+        root: Self::Root,
+        sender: AsyncComponentSender<Self>,
+    ) -> AsyncComponentParts<Self> {
+        let assistant_parameters = AssistantParametersComponent::builder()
+            .launch((models.clone(), selected_model.clone()))
+            .forward(sender.input_sender(), |output| match output {
+                AssistantParametersOutputMsg::SelectModel(backend_id, model) => {
+                    PlaygroundInputMsg::SelectModel(backend_id, model)
+                }
+                AssistantParametersOutputMsg::Temperature(value) => {
+                    PlaygroundInputMsg::Temperature(value)
+                }
+                AssistantParametersOutputMsg::TopK(value) => PlaygroundInputMsg::TopK(value),
+                AssistantParametersOutputMsg::TopP(value) => PlaygroundInputMsg::TopP(value),
+                AssistantParametersOutputMsg::ResetParameters => {
+                    PlaygroundInputMsg::ResetParameters
+                }
+                AssistantParametersOutputMsg::KeepAlive(keep_alive) => {
+                    PlaygroundInputMsg::KeepAlive(keep_alive)
+                }
+                AssistantParametersOutputMsg::ListLoadedModels => {
+                    PlaygroundInputMsg::ListLoadedModels
+                }
+                AssistantParametersOutputMsg::PreloadModel => PlaygroundInputMsg::PreloadModel,
+                AssistantParametersOutputMsg::UnloadModel(model) => {
+                    PlaygroundInputMsg::UnloadModel(model)
+                }
+            });
+
+        let mut model = PlaygroundScreen {
+            parameters: AssistantParameters::default(),
+            models,
+            assistant_parameters,
+            prompt_buffer: gtk::TextBuffer::default(),
+            suffix_buffer: gtk::TextBuffer::default(),
+            template_buffer: gtk::TextBuffer::default(),
+            completion_buffer: gtk::TextBuffer::default(),
+            raw: false,
+            generating: false,
+            status: String::new(),
+        };
+        if let Some((backend_id, selected_model)) = selected_model {
+            model.select_model(backend_id, &selected_model);
+        }
+
+        // References used in the view macro
+        let assistant_parameters = model.assistant_parameters.widget();
+
+        let widgets = view_output!();
+
+        AsyncComponentParts { model, widgets }
+    }
+
+    async fn update(
+        &mut self,
+        message: Self::Input,
+        sender: AsyncComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            PlaygroundInputMsg::SetModels(models) => {
+                self.models = models.clone();
+                self.assistant_parameters
+                    .emit(AssistantParametersInputMsg::SetModels(models));
+            }
+            PlaygroundInputMsg::Raw(raw) => self.raw = raw,
+            PlaygroundInputMsg::Complete => {
+                let prompt = self.read_prompt();
+                if prompt.prompt.is_empty() {
+                    self.status = String::from("The prompt must not be empty");
+                    return;
+                }
+                tracing::info!("Completing prompt with model {:?}", self.parameters.model);
+                self.completion_buffer.set_text("");
+                self.status = String::from("Completing...");
+                self.generating = true;
+                let parameters = self.parameters.clone();
+                sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
+                    shutdown
+                        .register(async move {
+                            let mut chunk_stream = match Assistant::complete(parameters, prompt)
+                                .await
+                            {
+                                Ok(chunk_stream) => chunk_stream.boxed(),
+                                Err(error) => {
+                                    tracing::error!("Completing prompt failed because of: {error}");
+                                    out.emit(PlaygroundCmdMsg::CompletionEnd(Err(
+                                        error.to_string()
+                                    )));
+                                    return;
+                                }
+                            };
+                            let mut stats = None;
+                            while let Some(chunk) = chunk_stream.next().await {
+                                match chunk {
+                                    Ok(chunk) => {
+                                        stats = chunk.stats;
+                                        out.emit(PlaygroundCmdMsg::CompletionChunk(chunk.text));
+                                        if chunk.done {
+                                            break;
+                                        }
+                                    }
+                                    Err(error) => {
+                                        out.emit(PlaygroundCmdMsg::CompletionEnd(Err(
+                                            error.to_string()
+                                        )));
+                                        return;
+                                    }
+                                }
+                            }
+                            out.emit(PlaygroundCmdMsg::CompletionEnd(Ok(stats)));
+                        })
+                        // Perform task until a shutdown interrupts it
+                        .drop_on_shutdown()
+                        // Wrap into a `Pin<Box<Future>>` for return
+                        .boxed()
+                })
+            }
+            PlaygroundInputMsg::SelectModel(backend_id, model) => {
+                tracing::info!(
+                    "Selecting model {model} of backend with id {backend_id} for completions"
+                );
+                self.select_model(backend_id, &model);
+            }
+            PlaygroundInputMsg::Temperature(value) => self.parameters.temperature = value,
+            PlaygroundInputMsg::TopK(value) => self.parameters.top_k = value,
+            PlaygroundInputMsg::TopP(value) => self.parameters.top_p = value,
+            PlaygroundInputMsg::ResetParameters => {
+                self.parameters = AssistantParameters {
+                    backend: self.parameters.backend.clone(),
+                    model: self.parameters.model.clone(),
+                    keep_alive: self.parameters.keep_alive,
+                    ..AssistantParameters::default()
+                }
+            }
+            PlaygroundInputMsg::KeepAlive(keep_alive) => self.parameters.keep_alive = keep_alive,
+            PlaygroundInputMsg::ListLoadedModels => {
+                let parameters = self.parameters.clone();
+                sender.oneshot_command(async move {
+                    PlaygroundCmdMsg::LoadedModelsListed(list_loaded_models(&parameters).await)
+                });
+            }
+            PlaygroundInputMsg::PreloadModel => {
+                let parameters = self.parameters.clone();
+                tracing::info!("Preloading model {:?}", parameters.model);
+                sender.oneshot_command(async move {
+                    let loaded_models = match Assistant::preload_model(&parameters).await {
+                        Ok(_) => list_loaded_models(&parameters).await,
+                        Err(error) => {
+                            tracing::error!("Preloading model failed because of: {error}");
+                            Err(format!("Loading the model failed: {error}"))
+                        }
+                    };
+                    PlaygroundCmdMsg::LoadedModelsListed(loaded_models)
+                });
+            }
+            PlaygroundInputMsg::UnloadModel(model) => {
+                let parameters = self.parameters.clone();
+                tracing::info!("Unloading model {model}");
+                sender.oneshot_command(async move {
+                    let loaded_models = match Assistant::unload_model(&parameters, model).await {
+                        Ok(_) => list_loaded_models(&parameters).await,
+                        Err(error) => {
+                            tracing::error!("Unloading model failed because of: {error}");
+                            Err(format!("Unloading the model failed: {error}"))
+                        }
+                    };
+                    PlaygroundCmdMsg::LoadedModelsListed(loaded_models)
+                });
+            }
+        }
+    }
+
+    async fn update_cmd(
+        &mut self,
+        message: Self::CommandOutput,
+        _: AsyncComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            PlaygroundCmdMsg::CompletionChunk(text) => {
+                self.completion_buffer
+                    .insert(&mut self.completion_buffer.end_iter(), &text);
+            }
+            PlaygroundCmdMsg::CompletionEnd(result) => {
+                self.generating = false;
+                self.status = match result {
+                    Ok(Some(stats)) => format!(
+                        "{} tokens · {:.1} tokens/s · {:.2}s total",
+                        stats.eval_count,
+                        stats.tokens_per_second(),
+                        stats.total_duration.as_secs_f64(),
+                    ),
+                    Ok(None) => String::new(),
+                    Err(error) => format!("Completing failed: {error}"),
+                };
+            }
+            PlaygroundCmdMsg::LoadedModelsListed(loaded_models) => {
+                self.assistant_parameters
+                    .emit(AssistantParametersInputMsg::SetLoadedModels(loaded_models));
+            }
+        }
+    }
+}
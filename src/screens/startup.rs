@@ -1,3 +1,4 @@
+use futures::{FutureExt, StreamExt};
 use gtk::prelude::*;
 use relm4::component::{AsyncComponent, AsyncComponentParts, AsyncComponentSender};
 use relm4::prelude::*;
@@ -7,49 +8,112 @@ use tokio::time::{sleep, Duration};
 use tracing;
 
 use crate::assets::LOGO_SVG;
+use crate::assistant::database::backup::{self, BackupFile};
+use crate::assistant::ollama::RECOMMENDED_MODELS;
 use crate::assistant::{database::Database, Assistant};
 
+/// Number of days after which deleted threads are permanently removed,
+/// unless overridden with the `PINCER_CHAT_TRASH_RETENTION_DAYS` environment variable.
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+fn trash_retention_days() -> i64 {
+    match std::env::var("PINCER_CHAT_TRASH_RETENTION_DAYS") {
+        Ok(value) => match value.parse::<i64>() {
+            Ok(days) if days >= 0 => days,
+            _ => {
+                tracing::warn!(
+                    "Invalid trash retention period '{value}'. Using {DEFAULT_TRASH_RETENTION_DAYS} days instead"
+                );
+                DEFAULT_TRASH_RETENTION_DAYS
+            }
+        },
+        Err(_) => DEFAULT_TRASH_RETENTION_DAYS,
+    }
+}
+
 #[derive(Debug)]
 pub struct StartupScreen {
     assistant: Arc<Mutex<Assistant>>,
-    database: Arc<Mutex<Database>>,
+    database: Arc<Database>,
     state: StartupScreenState,
+    /// Backups that can be restored, in the order shown in the backup selection.
+    backups: Vec<BackupFile>,
+    /// Named profiles that can be chosen instead of the default one before starting up.
+    profiles: Vec<String>,
 }
 
 #[derive(Debug)]
 pub enum StartupScreenState {
     Start,
-    CheckOllama,
-    OllamaNotRunning,
-    ListModels,
+    ChoosingProfile,
+    DatabaseLocked,
+    WrongPassphrase,
     RunningDatabaseMigrations,
     DatabaseMigrationsFailed,
+    PurgingTrash,
+    RecoveringMessages,
+    BackingUpDatabase,
+    RestoreFailed(String),
+    LoadingBackends,
+    /// Name of the active backend.
+    CheckBackend(String),
+    BackendNotRunning(String),
+    ListModels,
+    ListModelsFailed(String),
+    /// The backend has no models, and one of the recommended ones can be downloaded.
+    NoModels(String),
+    /// The backend has no models, and they cannot be downloaded from the application.
+    NoModelsToPull(String),
+    /// Name of the model being downloaded.
+    PullingModel(String),
+    /// Name of the model and why it could not be downloaded.
+    PullFailed(String, String),
     End,
 }
 
 #[derive(Debug)]
 pub enum StartupScreenInputMsg {
     Start,
-    CheckOllamaIsRunning,
-    OllamaNotRunning,
-    Retry,
-    ListModels,
+    ChooseProfile,
+    UnlockDatabase,
+    RestoreBackup,
     RunDatabaseMigrations,
     DatabaseMigrationsFailed,
+    PurgeTrash,
+    RecoverMessages,
+    BackUpDatabase,
+    LoadBackends,
+    CheckBackendIsRunning,
+    BackendNotRunning,
+    Retry,
+    ChangeBackendAddress,
+    ListModels,
+    PullModel,
+    SkipModelSetup,
     End,
 }
 
+#[derive(Debug)]
+pub enum StartupScreenCmdMsg {
+    /// Status of the download, and downloaded fraction of the current file if known.
+    PullProgress(String, Option<f64>),
+    /// Name of the downloaded model, or why it could not be downloaded.
+    PullEnded(Result<String, String>),
+}
+
 #[derive(Debug)]
 pub enum StartupScreenOutputMsg {
+    /// Name of the profile to start up with instead of the default one.
+    SwitchProfile(String),
     End,
 }
 
 #[relm4::component(async, pub)]
 impl AsyncComponent for StartupScreen {
-    type Init = (Arc<Mutex<Assistant>>, Arc<Mutex<Database>>);
+    type Init = (Arc<Mutex<Assistant>>, Arc<Database>, Vec<String>);
     type Input = StartupScreenInputMsg;
     type Output = StartupScreenOutputMsg;
-    type CommandOutput = ();
+    type CommandOutput = StartupScreenCmdMsg;
 
     view! {
         gtk::Box {
@@ -95,6 +159,141 @@ impl AsyncComponent for StartupScreen {
                     },
                 },
 
+                #[name = "profile_box"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                    set_margin_top: 10,
+                    set_visible: false,
+
+                    #[name = "profile_drop_down"]
+                    gtk::DropDown {
+                        set_hexpand: true,
+                        set_css_classes: &["dropdown"],
+                    },
+                    gtk::Button {
+                        set_label: "Continue",
+                        connect_clicked => StartupScreenInputMsg::ChooseProfile,
+                    },
+                },
+
+                #[name = "passphrase_box"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                    set_margin_top: 10,
+                    set_visible: false,
+
+                    #[name = "passphrase_entry"]
+                    gtk::PasswordEntry {
+                        set_hexpand: true,
+                        set_show_peek_icon: true,
+                        set_placeholder_text: Some("Passphrase"),
+                        connect_activate => StartupScreenInputMsg::UnlockDatabase,
+                    },
+                    gtk::Button {
+                        set_label: "Unlock",
+                        connect_clicked => StartupScreenInputMsg::UnlockDatabase,
+                    },
+                },
+
+                #[name = "restore_box"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 5,
+                    set_margin_top: 20,
+                    set_visible: false,
+
+                    gtk::Label {
+                        set_label: "Or restore a backup of the chat history",
+                    },
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_spacing: 5,
+
+                        #[name = "backup_drop_down"]
+                        gtk::DropDown {
+                            set_hexpand: true,
+                            set_css_classes: &["dropdown"],
+                        },
+                        #[name = "backup_passphrase_entry"]
+                        gtk::PasswordEntry {
+                            set_show_peek_icon: true,
+                            set_placeholder_text: Some("Passphrase, if encrypted"),
+                            connect_activate => StartupScreenInputMsg::RestoreBackup,
+                        },
+                        gtk::Button {
+                            set_label: "Restore",
+                            connect_clicked => StartupScreenInputMsg::RestoreBackup,
+                        },
+                    },
+                },
+
+                #[name = "address_box"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 5,
+                    set_margin_top: 10,
+                    set_visible: false,
+
+                    gtk::Label {
+                        set_label: "Server address",
+                    },
+                    #[name = "address_entry"]
+                    gtk::Entry {
+                        set_hexpand: true,
+                        set_placeholder_text: Some("http://localhost:11434"),
+                        connect_activate => StartupScreenInputMsg::ChangeBackendAddress,
+                    },
+                    gtk::Button {
+                        set_label: "Connect",
+                        connect_clicked => StartupScreenInputMsg::ChangeBackendAddress,
+                    },
+                },
+
+                #[name = "model_setup_box"]
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 10,
+                    set_margin_top: 20,
+                    set_visible: false,
+
+                    gtk::Label {
+                        set_label: "Answers are generated by a model running on your computer. \
+                            Pick one to download, more can be added later from the model selection.",
+                        set_wrap: true,
+                        set_max_width_chars: 60,
+                    },
+                    #[name = "recommended_model_list"]
+                    gtk::ListBox {
+                        set_selection_mode: gtk::SelectionMode::Single,
+                        set_css_classes: &["boxed-list"],
+                    },
+                    gtk::Box {
+                        set_orientation: gtk::Orientation::Horizontal,
+                        set_spacing: 5,
+                        set_halign: gtk::Align::End,
+
+                        gtk::Button {
+                            set_label: "Skip",
+                            set_tooltip_text: Some("Continue without a model"),
+                            connect_clicked => StartupScreenInputMsg::SkipModelSetup,
+                        },
+                        gtk::Button {
+                            set_label: "Download",
+                            set_css_classes: &["suggested-action"],
+                            connect_clicked => StartupScreenInputMsg::PullModel,
+                        },
+                    },
+                },
+
+                #[name = "pull_progress_bar"]
+                gtk::ProgressBar {
+                    set_margin_top: 10,
+                    set_show_text: true,
+                    set_visible: false,
+                },
+
                 #[name = "retry_button"]
                 gtk::Button {
                     set_hexpand: false,
@@ -118,9 +317,12 @@ impl AsyncComponent for StartupScreen {
             assistant: init.0,
             database: init.1,
             state: StartupScreenState::Start,
+            backups: Vec::new(),
+            profiles: init.2,
         };
 
         let mut widgets = view_output!();
+        show_recommended_models(&widgets);
 
         model
             .update_with_view(&mut widgets, StartupScreenInputMsg::Start, sender, &root)
@@ -138,93 +340,326 @@ impl AsyncComponent for StartupScreen {
     ) {
         sleep(Duration::from_millis(100)).await;
         match message {
+            StartupScreenInputMsg::Start if !self.profiles.is_empty() => {
+                tracing::info!("Waiting for user to choose a profile");
+                self.state = StartupScreenState::ChoosingProfile;
+            }
             StartupScreenInputMsg::Start => {
                 tracing::info!("Start up screen initialization");
+                match self.database.is_encrypted() {
+                    Ok(true) => {
+                        tracing::info!("Database is encrypted. Waiting for passphrase");
+                        self.state = StartupScreenState::DatabaseLocked;
+                    }
+                    Ok(false) => {
+                        sender
+                            .input_sender()
+                            .emit(StartupScreenInputMsg::RunDatabaseMigrations);
+                        self.state = StartupScreenState::RunningDatabaseMigrations;
+                    }
+                    Err(error) => {
+                        tracing::error!(
+                            "Reading database encryption settings failed because of {error}"
+                        );
+                        self.state = StartupScreenState::DatabaseMigrationsFailed;
+                    }
+                }
+            }
+            StartupScreenInputMsg::ChooseProfile => {
+                widgets.profile_box.set_visible(false);
+                widgets.spinner.set_spinning(true);
+                let selected = widgets.profile_drop_down.selected() as usize;
+                let profiles = std::mem::take(&mut self.profiles);
+                // The default profile is listed first
+                match selected
+                    .checked_sub(1)
+                    .and_then(|index| profiles.get(index))
+                {
+                    Some(profile) => {
+                        tracing::info!("User chose profile {profile}");
+                        sender
+                            .output_sender()
+                            .emit(StartupScreenOutputMsg::SwitchProfile(profile.clone()));
+                    }
+                    None => {
+                        tracing::info!("User chose the default profile");
+                        sender.input_sender().emit(StartupScreenInputMsg::Start);
+                    }
+                }
+                self.state = StartupScreenState::Start;
+            }
+            StartupScreenInputMsg::UnlockDatabase => {
+                tracing::info!("Unlocking database");
+                let passphrase = widgets.passphrase_entry.text().to_string();
+                widgets.passphrase_entry.set_text("");
+                match self.database.unlock(&passphrase).await {
+                    Ok(_) => {
+                        widgets.passphrase_box.set_visible(false);
+                        sender
+                            .input_sender()
+                            .emit(StartupScreenInputMsg::RunDatabaseMigrations);
+                        self.state = StartupScreenState::RunningDatabaseMigrations;
+                    }
+                    Err(error) => {
+                        tracing::error!("Unlocking database failed because of {error}");
+                        self.state = StartupScreenState::WrongPassphrase;
+                    }
+                }
+            }
+            StartupScreenInputMsg::RestoreBackup => {
+                let selected = widgets.backup_drop_down.selected() as usize;
+                let passphrase = widgets.backup_passphrase_entry.text().to_string();
+                widgets.backup_passphrase_entry.set_text("");
+                if let Some(backup) = self.backups.get(selected) {
+                    tracing::info!("Restoring backup {}", backup.path.display());
+                    let passphrase = (!passphrase.is_empty()).then_some(passphrase);
+                    match self
+                        .database
+                        .restore_backup(backup.path.clone(), passphrase.as_deref())
+                        .await
+                    {
+                        Ok(_) => {
+                            widgets.passphrase_box.set_visible(false);
+                            hide_setup(widgets);
+                            sender
+                                .input_sender()
+                                .emit(StartupScreenInputMsg::RunDatabaseMigrations);
+                            self.state = StartupScreenState::RunningDatabaseMigrations;
+                        }
+                        Err(error) => {
+                            tracing::error!("Restoring backup failed because of {error}");
+                            self.state = StartupScreenState::RestoreFailed(error.to_string());
+                        }
+                    }
+                }
+            }
+            StartupScreenInputMsg::RunDatabaseMigrations => {
+                tracing::info!("Running database migrations");
+                match self.database.run_migrations().await {
+                    Ok(_) => {
+                        tracing::info!("Database migrations successful");
+                        sender
+                            .input_sender()
+                            .emit(StartupScreenInputMsg::PurgeTrash);
+                        self.state = StartupScreenState::PurgingTrash;
+                    }
+                    Err(error) => {
+                        tracing::error!("Database migrations failed because of {error}");
+                        sender
+                            .input_sender()
+                            .emit(StartupScreenInputMsg::DatabaseMigrationsFailed);
+                        self.state = StartupScreenState::DatabaseMigrationsFailed;
+                    }
+                }
+            }
+            StartupScreenInputMsg::DatabaseMigrationsFailed => {
+                tracing::info!("Database migrations failed");
+            }
+            StartupScreenInputMsg::PurgeTrash => {
+                let retention_days = trash_retention_days();
+                tracing::info!("Purging threads deleted more than {retention_days} days ago");
+                match self
+                    .database
+                    .purge_deleted_threads(chrono::Duration::days(retention_days))
+                    .await
+                {
+                    Ok(n_purged) => tracing::info!("Purged {n_purged} deleted threads"),
+                    // Not being able to empty the trash should not prevent using the application
+                    Err(error) => {
+                        tracing::error!("Purging deleted threads failed because of {error}")
+                    }
+                }
+                sender
+                    .input_sender()
+                    .emit(StartupScreenInputMsg::RecoverMessages);
+                self.state = StartupScreenState::RecoveringMessages;
+            }
+            StartupScreenInputMsg::RecoverMessages => {
+                tracing::info!("Recovering messages interrupted while being generated");
+                match self.database.recover_partial_messages().await {
+                    Ok(n_recovered) => tracing::info!("Recovered {n_recovered} partial messages"),
+                    Err(error) => {
+                        tracing::error!("Recovering partial messages failed because of {error}")
+                    }
+                }
+                sender
+                    .input_sender()
+                    .emit(StartupScreenInputMsg::BackUpDatabase);
+                self.state = StartupScreenState::BackingUpDatabase;
+            }
+            StartupScreenInputMsg::BackUpDatabase => {
+                match self.database.backup_if_due().await {
+                    Ok(Some(backup)) => {
+                        tracing::info!("Backed up database to {}", backup.path.display())
+                    }
+                    Ok(None) => tracing::info!("Last database backup is recent enough"),
+                    Err(error) => {
+                        tracing::error!("Backing up database failed because of {error}")
+                    }
+                }
+                sender
+                    .input_sender()
+                    .emit(StartupScreenInputMsg::LoadBackends);
+                self.state = StartupScreenState::LoadingBackends;
+            }
+            StartupScreenInputMsg::LoadBackends => {
+                tracing::info!("Loading backends");
+                match self.database.get_backends().await {
+                    Ok(backends) => self.assistant.lock().await.set_backends(backends),
+                    // The default Ollama backend is used instead
+                    Err(error) => tracing::error!("Loading backends failed because of {error}"),
+                }
                 sender
                     .input_sender()
-                    .emit(StartupScreenInputMsg::CheckOllamaIsRunning);
-                self.state = StartupScreenState::CheckOllama;
+                    .emit(StartupScreenInputMsg::CheckBackendIsRunning);
+                self.state =
+                    StartupScreenState::CheckBackend(self.assistant.lock().await.backend_name());
             }
-            StartupScreenInputMsg::CheckOllamaIsRunning => {
-                tracing::info!("Checking if Ollama is running");
-                match self.assistant.lock().await.is_ollama_running().await {
+            StartupScreenInputMsg::CheckBackendIsRunning => {
+                let assistant = self.assistant.lock().await;
+                let backend_name = assistant.backend_name();
+                tracing::info!("Checking if backend {backend_name} is running");
+                match assistant.is_backend_running().await {
                     true => {
-                        tracing::info!("Ollama is running. Continuing");
+                        tracing::info!("Backend {backend_name} is running. Continuing");
                         sender
                             .input_sender()
                             .emit(StartupScreenInputMsg::ListModels);
                         self.state = StartupScreenState::ListModels;
                     }
                     false => {
-                        tracing::error!("Ollama is not running. Stopping");
+                        tracing::error!("Backend {backend_name} is not running. Stopping");
                         sender
                             .input_sender()
-                            .emit(StartupScreenInputMsg::OllamaNotRunning);
-                        self.state = StartupScreenState::OllamaNotRunning;
+                            .emit(StartupScreenInputMsg::BackendNotRunning);
+                        self.state = StartupScreenState::BackendNotRunning(backend_name);
                     }
                 }
             }
-            StartupScreenInputMsg::OllamaNotRunning => {
-                tracing::error!("Ollama is not running. Waiting for user input");
+            StartupScreenInputMsg::BackendNotRunning => {
+                tracing::error!("Backend is not running. Waiting for user input");
             }
             StartupScreenInputMsg::Retry => {
                 tracing::error!("User clicked retry button");
-                widgets.retry_button.set_visible(false);
-                widgets.spinner.set_spinning(true);
+                hide_setup(widgets);
                 sender
                     .input_sender()
-                    .emit(StartupScreenInputMsg::CheckOllamaIsRunning);
-                self.state = StartupScreenState::CheckOllama;
+                    .emit(StartupScreenInputMsg::CheckBackendIsRunning);
+                self.state =
+                    StartupScreenState::CheckBackend(self.assistant.lock().await.backend_name());
+            }
+            StartupScreenInputMsg::ChangeBackendAddress => {
+                let base_url = widgets.address_entry.text().trim().to_string();
+                let backend_id = self
+                    .assistant
+                    .lock()
+                    .await
+                    .backend_config()
+                    .map(|config| config.id);
+                match backend_id {
+                    Some(backend_id) if !base_url.is_empty() => {
+                        tracing::info!("Changing address of backend {backend_id} to {base_url}");
+                        match self
+                            .database
+                            .set_backend_base_url(backend_id, &base_url)
+                            .await
+                        {
+                            Ok(_) => {
+                                hide_setup(widgets);
+                                sender
+                                    .input_sender()
+                                    .emit(StartupScreenInputMsg::LoadBackends);
+                                self.state = StartupScreenState::LoadingBackends;
+                            }
+                            Err(error) => {
+                                tracing::error!(
+                                    "Changing backend address failed because of {error}"
+                                )
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        tracing::error!("No backend is configured, its address cannot be changed")
+                    }
+                }
             }
             StartupScreenInputMsg::ListModels => {
-                tracing::info!("Listing local models");
+                tracing::info!("Listing models");
                 let mut assistant = self.assistant.lock().await;
-                let models = match assistant.list_models().await {
-                    Ok(models) => models,
+                match assistant.list_models().await {
+                    Ok(models) => match assistant.preferred_model(&models) {
+                        Some(model) => {
+                            tracing::info!("Found {} models. Using {model} as model", models.len());
+                            assistant.set_model(model);
+                            sender.input_sender().emit(StartupScreenInputMsg::End);
+                            self.state = StartupScreenState::End;
+                        }
+                        None if assistant.can_pull_models() => {
+                            tracing::info!("No models found. Waiting for user to pick one");
+                            self.state = StartupScreenState::NoModels(assistant.backend_name());
+                        }
+                        None => {
+                            tracing::info!("No models found. Waiting for user to load one");
+                            self.state =
+                                StartupScreenState::NoModelsToPull(assistant.backend_name());
+                        }
+                    },
                     Err(err) => {
-                        tracing::error!(
-                            "Could not retrieve list of local models because of: {err}"
-                        );
-                        Vec::new()
+                        tracing::error!("Could not retrieve list of models because of: {err}");
+                        self.state = StartupScreenState::ListModelsFailed(err.to_string());
                     }
-                };
-                let mut model = String::from("llama3.2:1b");
-                if models.is_empty() {
-                    tracing::info!("No local model found. Using {model} as default model");
-                } else {
-                    tracing::info!(
-                        "Found {} local models. Using {} as default model",
-                        models.len(),
-                        models[0]
-                    );
-                    model = models[0].clone();
                 }
-                assistant.set_model(model);
-                sender
-                    .input_sender()
-                    .emit(StartupScreenInputMsg::RunDatabaseMigrations);
-                self.state = StartupScreenState::RunningDatabaseMigrations;
             }
-            StartupScreenInputMsg::RunDatabaseMigrations => {
-                tracing::info!("Running database migrations");
-                let database = self.database.lock().await;
-                match database.run_migrations().await {
-                    Ok(_) => {
-                        tracing::info!("Database migrations successful");
-                        sender.input_sender().emit(StartupScreenInputMsg::End);
-                        self.state = StartupScreenState::End;
-                    }
-                    Err(error) => {
-                        tracing::error!("Database migrations failed because of {error}");
-                        sender
-                            .input_sender()
-                            .emit(StartupScreenInputMsg::DatabaseMigrationsFailed);
-                        self.state = StartupScreenState::DatabaseMigrationsFailed;
-                    }
-                }
+            StartupScreenInputMsg::PullModel => {
+                let Some(row) = widgets.recommended_model_list.selected_row() else {
+                    return;
+                };
+                let Some(recommended_model) = RECOMMENDED_MODELS.get(row.index() as usize) else {
+                    return;
+                };
+                let model = recommended_model.name.to_string();
+                tracing::info!("Pulling model {model}");
+                let parameters = self.assistant.lock().await.parameters();
+                let model_to_pull = model.clone();
+                sender.command(move |out, shutdown: relm4::ShutdownReceiver| {
+                    shutdown
+                        .register(async move {
+                            let result: anyhow::Result<()> = async {
+                                let mut stream =
+                                    Assistant::pull_model(&parameters, model_to_pull.clone())
+                                        .await?;
+                                while let Some(response) = stream.next().await {
+                                    let response = response?;
+                                    let progress = response.progress();
+                                    out.emit(StartupScreenCmdMsg::PullProgress(
+                                        response.status,
+                                        progress,
+                                    ));
+                                }
+                                Ok(())
+                            }
+                            .await;
+                            out.emit(StartupScreenCmdMsg::PullEnded(
+                                result
+                                    .map(|_| model_to_pull)
+                                    .map_err(|error| error.to_string()),
+                            ));
+                        })
+                        .drop_on_shutdown()
+                        .boxed()
+                });
+                widgets.model_setup_box.set_visible(false);
+                widgets.restore_box.set_visible(false);
+                widgets.pull_progress_bar.set_fraction(0.0);
+                widgets.pull_progress_bar.set_visible(true);
+                self.state = StartupScreenState::PullingModel(model);
             }
-            StartupScreenInputMsg::DatabaseMigrationsFailed => {
-                tracing::info!("Database migrations failed");
+            StartupScreenInputMsg::SkipModelSetup => {
+                tracing::info!("User skipped model setup");
+                hide_setup(widgets);
+                sender.input_sender().emit(StartupScreenInputMsg::End);
+                self.state = StartupScreenState::End;
             }
             StartupScreenInputMsg::End => {
                 tracing::info!("Finished application startup");
@@ -233,34 +668,234 @@ impl AsyncComponent for StartupScreen {
             }
         }
 
-        match self.state {
+        match &self.state {
             StartupScreenState::Start => {
                 widgets.status_label.set_label("Starting up application...");
             }
-            StartupScreenState::CheckOllama => {
+            StartupScreenState::ChoosingProfile => {
                 widgets
                     .status_label
-                    .set_label("Checking if Ollama is running...");
+                    .set_label("Which profile do you want to use?");
+                widgets.spinner.set_spinning(false);
+                let profile_list = gtk::StringList::new(&["Default"]);
+                for profile in &self.profiles {
+                    profile_list.append(profile);
+                }
+                widgets.profile_drop_down.set_model(Some(&profile_list));
+                widgets.profile_box.set_visible(true);
             }
-            StartupScreenState::OllamaNotRunning => {
+            StartupScreenState::DatabaseLocked => {
                 widgets
                     .status_label
-                    .set_label("Ollama is not running :( Please start it and try again ");
-                widgets.retry_button.set_visible(true);
+                    .set_label("The database is encrypted, please enter its passphrase");
                 widgets.spinner.set_spinning(false);
+                widgets.passphrase_box.set_visible(true);
+                widgets.passphrase_entry.grab_focus();
+                self.show_backups(widgets);
             }
-            StartupScreenState::ListModels => {
-                widgets.status_label.set_label("Listing local models...");
+            StartupScreenState::WrongPassphrase => {
+                widgets
+                    .status_label
+                    .set_label("Wrong passphrase :( Please try again");
+                widgets.passphrase_entry.grab_focus();
             }
             StartupScreenState::RunningDatabaseMigrations => {
+                widgets.spinner.set_spinning(true);
                 widgets.status_label.set_label("Running migrations...");
             }
-            StartupScreenState::DatabaseMigrationsFailed => widgets
-                .status_label
-                .set_label("Database migrations failed :("),
+            StartupScreenState::DatabaseMigrationsFailed => {
+                widgets
+                    .status_label
+                    .set_label("Database migrations failed :(");
+                widgets.spinner.set_spinning(false);
+                self.show_backups(widgets);
+            }
+            StartupScreenState::RestoreFailed(error) => {
+                widgets
+                    .status_label
+                    .set_label(&format!("Restoring the backup failed: {error}"));
+                widgets.spinner.set_spinning(false);
+            }
+            StartupScreenState::PurgingTrash => {
+                widgets.status_label.set_label("Emptying trash...");
+            }
+            StartupScreenState::RecoveringMessages => {
+                widgets
+                    .status_label
+                    .set_label("Recovering interrupted messages...");
+            }
+            StartupScreenState::BackingUpDatabase => {
+                widgets.status_label.set_label("Backing up chat history...");
+            }
+            StartupScreenState::LoadingBackends => {
+                widgets.status_label.set_label("Loading servers...");
+            }
+            StartupScreenState::CheckBackend(backend_name) => {
+                widgets
+                    .status_label
+                    .set_label(&format!("Checking if {backend_name} is running..."));
+            }
+            StartupScreenState::BackendNotRunning(backend_name) => {
+                widgets.status_label.set_label(&format!(
+                    "{backend_name} is not running :( Please start it and try again "
+                ));
+                widgets.retry_button.set_visible(true);
+                widgets.spinner.set_spinning(false);
+                let assistant = self.assistant.lock().await;
+                if let Some(config) = assistant.backend_config() {
+                    widgets.address_entry.set_text(&config.base_url);
+                    widgets.address_box.set_visible(true);
+                }
+                drop(assistant);
+                self.show_backups(widgets);
+            }
+            StartupScreenState::ListModels => {
+                widgets.status_label.set_label("Listing models...");
+            }
+            StartupScreenState::ListModelsFailed(error) => {
+                widgets
+                    .status_label
+                    .set_label(&format!("Listing models failed: {error}"));
+                widgets.retry_button.set_visible(true);
+                widgets.spinner.set_spinning(false);
+                self.show_backups(widgets);
+            }
+            StartupScreenState::NoModels(backend_name) => {
+                widgets
+                    .status_label
+                    .set_label(&format!("{backend_name} has no models yet"));
+                widgets.spinner.set_spinning(false);
+                widgets.model_setup_box.set_visible(true);
+                self.show_backups(widgets);
+            }
+            StartupScreenState::NoModelsToPull(backend_name) => {
+                widgets.status_label.set_label(&format!(
+                    "{backend_name} has no models :( Please load one on the server and try again"
+                ));
+                widgets.retry_button.set_visible(true);
+                widgets.spinner.set_spinning(false);
+                self.show_backups(widgets);
+            }
+            StartupScreenState::PullingModel(model) => {
+                widgets
+                    .status_label
+                    .set_label(&format!("Downloading {model}..."));
+                widgets.spinner.set_spinning(true);
+            }
+            StartupScreenState::PullFailed(model, error) => {
+                widgets
+                    .status_label
+                    .set_label(&format!("Downloading {model} failed: {error}"));
+                widgets.spinner.set_spinning(false);
+                widgets.pull_progress_bar.set_visible(false);
+                widgets.model_setup_box.set_visible(true);
+            }
             StartupScreenState::End => {
                 widgets.status_label.set_label("Application is ready!");
             }
         }
     }
+
+    async fn update_cmd_with_view(
+        &mut self,
+        widgets: &mut Self::Widgets,
+        message: Self::CommandOutput,
+        sender: AsyncComponentSender<Self>,
+        _: &Self::Root,
+    ) {
+        match message {
+            StartupScreenCmdMsg::PullProgress(status, progress) => {
+                match progress {
+                    Some(progress) => widgets.pull_progress_bar.set_fraction(progress),
+                    None => widgets.pull_progress_bar.pulse(),
+                }
+                widgets.pull_progress_bar.set_text(Some(&status));
+            }
+            StartupScreenCmdMsg::PullEnded(Ok(model)) => {
+                tracing::info!("Pulled model {model}");
+                widgets.pull_progress_bar.set_visible(false);
+                self.assistant.lock().await.set_model(model);
+                sender.input_sender().emit(StartupScreenInputMsg::End);
+            }
+            StartupScreenCmdMsg::PullEnded(Err(error)) => {
+                tracing::error!("Pulling model failed because of {error}");
+                if let StartupScreenState::PullingModel(model) = &self.state {
+                    widgets
+                        .status_label
+                        .set_label(&format!("Downloading {model} failed: {error}"));
+                    self.state = StartupScreenState::PullFailed(model.clone(), error);
+                }
+                widgets.spinner.set_spinning(false);
+                widgets.pull_progress_bar.set_visible(false);
+                widgets.model_setup_box.set_visible(true);
+                self.show_backups(widgets);
+            }
+        }
+    }
+}
+
+/// Lists the models that can be downloaded on first run, the smallest one being selected.
+fn show_recommended_models(widgets: &StartupScreenWidgets) {
+    for recommended_model in RECOMMENDED_MODELS {
+        relm4::view! {
+            row = gtk::ListBoxRow {
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 2,
+                    set_margin_all: 8,
+
+                    gtk::Label {
+                        set_halign: gtk::Align::Start,
+                        set_markup: &format!(
+                            "<b>{}</b>  {}",
+                            recommended_model.name, recommended_model.size
+                        ),
+                    },
+                    gtk::Label {
+                        set_halign: gtk::Align::Start,
+                        set_label: recommended_model.description,
+                        set_css_classes: &["dim-label"],
+                    },
+                },
+            }
+        }
+        widgets.recommended_model_list.append(&row);
+    }
+    widgets
+        .recommended_model_list
+        .select_row(widgets.recommended_model_list.row_at_index(0).as_ref());
+}
+
+/// Hides the widgets used to set up the backend and its models, or to restore a backup.
+fn hide_setup(widgets: &StartupScreenWidgets) {
+    widgets.restore_box.set_visible(false);
+    widgets.retry_button.set_visible(false);
+    widgets.address_box.set_visible(false);
+    widgets.model_setup_box.set_visible(false);
+    widgets.pull_progress_bar.set_visible(false);
+    widgets.spinner.set_spinning(true);
+}
+
+impl StartupScreen {
+    /// Shows the backups of the database so that one of them can be restored.
+    fn show_backups(&mut self, widgets: &StartupScreenWidgets) {
+        self.backups = match self.database.list_backups() {
+            Ok(backups) => backups,
+            Err(error) => {
+                tracing::error!("Listing backups failed because of {error}");
+                Vec::new()
+            }
+        };
+        let backup_list = gtk::StringList::default();
+        for backup in &self.backups {
+            let encrypted = backup::is_backup_encrypted(&backup.path).unwrap_or_default();
+            backup_list.append(&format!(
+                "{}{}",
+                backup.created_at.format("%Y-%m-%d %H:%M:%S"),
+                if encrypted { " (encrypted)" } else { "" }
+            ));
+        }
+        widgets.backup_drop_down.set_model(Some(&backup_list));
+        widgets.restore_box.set_visible(!self.backups.is_empty());
+    }
 }